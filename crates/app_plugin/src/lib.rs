@@ -1,4 +1,5 @@
 use bevy::{
+    pbr::DirectionalLightShadowMap,
     prelude::*,
     reflect::TypeUuid,
     render::{camera::Projection, render_resource::*},
@@ -7,11 +8,14 @@ use bevy::{
 use bevy_atmosphere::prelude::*;
 use bevy_terrain::{debug::DebugTerrain, prelude::*};
 use std::{f32::consts::TAU, time::Instant};
-use terrain_settings::load_settings;
+use terrain_settings::{load_settings, Settings, SettingsWatcher};
 
 const TERRAIN_SHADER: HandleUntyped =
     HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 24380770943559);
 
+/// The half extent of the area around the camera, in which the sun casts shadows.
+const SHADOW_SIZE: f32 = 5000.0;
+
 #[derive(AsBindGroup, TypeUuid, Clone)]
 #[uuid = "003e1d5d-241c-45a6-8c25-731dee22d820"]
 pub struct TerrainMaterial {}
@@ -42,6 +46,7 @@ impl Plugin for AppPlugin {
             ..default()
         }))
         .insert_resource(Msaa { samples: 4 })
+        .insert_resource(DirectionalLightShadowMap { size: 4096 })
         .insert_resource(Atmosphere {
             sun_intensity: 10.0,
             ..default()
@@ -49,13 +54,16 @@ impl Plugin for AppPlugin {
         .insert_resource(AtmosphereSettings { resolution: 64 })
         .add_plugin(AtmospherePlugin {})
         .add_plugin(TerrainPlugin {
-            attachment_count: 3,
+            attachment_count: 4,
+            ..default()
         })
         .add_plugin(TerrainDebugPlugin)
         .add_plugin(TerrainMaterialPlugin::<TerrainMaterial>::default())
         .add_startup_system(setup)
         .add_system(daylight_cycle)
-        .add_system(toggle_camera_and_height_data);
+        .add_system(sun_follow_camera.after(daylight_cycle))
+        .add_system(toggle_camera)
+        .add_system(reload_settings);
 
         app.world.resource_mut::<Assets<_>>().set_untracked(
             TERRAIN_SHADER,
@@ -72,9 +80,12 @@ fn setup(
     mut materials: ResMut<Assets<TerrainMaterial>>,
     mut quadtrees: ResMut<TerrainViewComponents<Quadtree>>,
     mut terrain_view_configs: ResMut<TerrainViewComponents<TerrainViewConfig>>,
+    mut render_settings: ResMut<TerrainRenderSettings>,
 ) {
     let settings = load_settings().unwrap();
 
+    render_settings.horizon_culling = settings.horizon_culling;
+
     let mut preprocessor = Preprocessor::default();
     let mut loader = AttachmentFromDiskLoader::default();
 
@@ -85,61 +96,22 @@ fn setup(
         settings.node_atlas_size,
         settings.terrain_path.clone(),
     );
-    config.leaf_node_size = settings.texture_size - 2 * settings.border_size;
-    config.add_attachment_from_disk(
+
+    let mut base = BaseConfig::new(settings.texture_size, settings.mip_level_count);
+    base.border_size = settings.border_size;
+    // missing source tiles leave holes, which are not rendered
+    base.nodata = Nodata::Discard;
+
+    config.add_base_attachment_from_disk(
         &mut preprocessor,
         &mut loader,
-        AttachmentConfig::new(
-            "dtm".to_string(),
-            settings.texture_size,
-            settings.border_size,
-            settings.mip_level_count,
-            AttachmentFormat::R16,
-        ),
+        base,
         TileConfig {
             path: format!("{}/source/dtm", &settings.terrain_path),
             size: settings.tile_size,
             file_format: FileFormat::DTM,
         },
     );
-    if settings.enable_dsm {
-        config.add_attachment_from_disk(
-            &mut preprocessor,
-            &mut loader,
-            AttachmentConfig::new(
-                "dsm".to_string(),
-                settings.texture_size,
-                settings.border_size,
-                settings.mip_level_count,
-                AttachmentFormat::R16,
-            ),
-            TileConfig {
-                path: format!("{}/source/dsm", &settings.terrain_path),
-                size: settings.tile_size,
-                file_format: FileFormat::DTM,
-            },
-        );
-    } else {
-        // parse and load dtm twice
-        // a little hacky I know, but it does the trick
-        config.add_attachment_from_disk(
-            &mut preprocessor,
-            &mut loader,
-            AttachmentConfig::new(
-                "dsm".to_string(),
-                settings.texture_size,
-                settings.border_size,
-                settings.mip_level_count,
-                AttachmentFormat::R16,
-            ),
-            TileConfig {
-                path: format!("{}/source/dtm", &settings.terrain_path),
-                size: settings.tile_size,
-                file_format: FileFormat::DTM,
-            },
-        );
-    }
-
     config.add_attachment_from_disk(
         &mut preprocessor,
         &mut loader,
@@ -157,6 +129,25 @@ fn setup(
         },
     );
 
+    // the buildings and vegetation are extruded from the difference between the DSM and the DTM
+    let surface = SurfaceConfig::new(&base);
+
+    if settings.enable_dsm {
+        config.add_surface_from_disk(
+            &mut preprocessor,
+            &mut loader,
+            surface,
+            TileConfig {
+                path: format!("{}/source/dsm", &settings.terrain_path),
+                size: settings.tile_size,
+                file_format: FileFormat::DTM,
+            },
+        );
+    } else {
+        // keep the attachment layout of the material, without any surface data
+        config.add_attachment(surface.attachment());
+    }
+
     if settings.preprocess {
         println!("Started preprocessing the terrain data. This might take a while ...");
         let start = Instant::now();
@@ -191,34 +182,98 @@ fn setup(
         ))
         .id();
 
-    let view_config = TerrainViewConfig {
-        node_count: settings.node_count,
-        load_distance: settings.load_distance,
-        view_distance: settings.view_distance,
-        ..default()
-    };
+    let view_settings = view_settings_from(&settings);
+
+    let mut view_config = TerrainViewConfig::default();
+    view_settings.apply(&mut view_config);
     let quadtree = Quadtree::from_configs(&config, &view_config);
 
     terrain_view_configs.insert((terrain, view), view_config);
     quadtrees.insert((terrain, view), quadtree);
 
-    commands.spawn((
-        DirectionalLightBundle {
-            directional_light: DirectionalLight {
-                illuminance: 15000.0,
+    let sun = commands
+        .spawn((
+            TerrainView,
+            DirectionalLightBundle {
+                directional_light: DirectionalLight {
+                    illuminance: 15000.0,
+                    shadows_enabled: true,
+                    shadow_projection: OrthographicProjection {
+                        left: -SHADOW_SIZE,
+                        right: SHADOW_SIZE,
+                        bottom: -SHADOW_SIZE,
+                        top: SHADOW_SIZE,
+                        near: -2.0 * SHADOW_SIZE,
+                        far: 2.0 * SHADOW_SIZE,
+                        ..default()
+                    },
+                    ..default()
+                },
+                transform: Transform::from_xyz(0.0, 4.0, -1.0).looking_at(Vec3::ZERO, Vec3::Y),
                 ..default()
             },
-            transform: Transform::from_xyz(0.0, 4.0, -1.0).looking_at(Vec3::ZERO, Vec3::Y),
-            ..default()
-        },
-        Sun::default(),
-    ));
+            Sun::default(),
+        ))
+        .id();
+
+    // the shadows are rendered with a coarser level of detail than the camera view
+    let mut sun_view_config = TerrainViewConfig {
+        view_distance_scale: 0.5,
+        ..default()
+    };
+    view_settings.apply(&mut sun_view_config);
+    let sun_quadtree = Quadtree::from_configs(&config, &sun_view_config);
+
+    terrain_view_configs.insert((terrain, sun), sun_view_config);
+    quadtrees.insert((terrain, sun), sun_quadtree);
+    commands.insert_resource(view_settings);
     commands.insert_resource(AmbientLight {
         brightness: 0.2,
         ..default()
     });
 }
 
+/// The quality settings of the terrain views, that can be changed at runtime.
+fn view_settings_from(settings: &Settings) -> TerrainViewSettings {
+    TerrainViewSettings {
+        node_count: settings.node_count,
+        load_distance: settings.load_distance,
+        view_distance: settings.view_distance,
+        tile_scale: settings.tile_scale,
+        grid_size: settings.grid_size,
+        morph_range: settings.morph_range,
+        blend_range: settings.blend_range,
+        ..default()
+    }
+}
+
+/// Applies the quality settings of the terrain views and the horizon culling,
+/// whenever the `config.toml` changes.
+fn reload_settings(
+    mut watcher: Local<Option<SettingsWatcher>>,
+    mut view_settings: ResMut<TerrainViewSettings>,
+    mut render_settings: ResMut<TerrainRenderSettings>,
+) {
+    if watcher.is_none() {
+        *watcher = SettingsWatcher::new().ok();
+    }
+
+    let watcher = match watcher.as_mut() {
+        Some(watcher) => watcher,
+        None => return,
+    };
+
+    match watcher.poll() {
+        Some(Ok(settings)) => {
+            *view_settings = view_settings_from(&settings);
+            render_settings.horizon_culling = settings.horizon_culling;
+            println!("Reloaded the terrain view settings.");
+        }
+        Some(Err(error)) => println!("Could not reload the settings: {error}."),
+        None => {}
+    }
+}
+
 #[derive(Component)]
 struct Sun {
     rotating: bool,
@@ -272,17 +327,29 @@ fn daylight_cycle(
         sun.angle += TAU * time.delta_seconds() / sun.period_duration;
         atmosphere.sun_position = Vec3::new(sun.angle.cos(), sun.angle.sin(), 0.0);
 
-        *transform = Transform::from_xyz(-sun.angle.cos(), sun.angle.sin(), 0.0)
-            .looking_at(Vec3::ZERO, Vec3::Y);
+        let sun_position = Vec3::new(-sun.angle.cos(), sun.angle.sin(), 0.0);
+        let target = transform.translation - sun_position;
+        transform.look_at(target, Vec3::Y);
         light.illuminance = sun.angle.sin().max(0.0).powf(2.0) * sun.illuminance;
     }
 }
 
-fn toggle_camera_and_height_data(
-    input: Res<Input<KeyCode>>,
-    mut camera_query: Query<&mut DebugCamera>,
-    mut debug: ResMut<DebugTerrain>,
+/// Moves the sun along with the camera, so that the shadows are rendered around the viewer.
+fn sun_follow_camera(
+    camera_query: Query<&Transform, (With<DebugCamera>, Without<Sun>)>,
+    mut sun_query: Query<&mut Transform, With<Sun>>,
 ) {
+    // the camera or the sun might not exist (yet)
+    let (camera_transform, mut sun_transform) =
+        match (camera_query.get_single(), sun_query.get_single_mut()) {
+            (Ok(camera_transform), Ok(sun_transform)) => (camera_transform, sun_transform),
+            _ => return,
+        };
+
+    sun_transform.translation = camera_transform.translation;
+}
+
+fn toggle_camera(input: Res<Input<KeyCode>>, mut camera_query: Query<&mut DebugCamera>) {
     let mut camera = camera_query.single_mut();
     if input.just_pressed(KeyCode::T) {
         camera.active = !camera.active;
@@ -291,12 +358,4 @@ fn toggle_camera_and_height_data(
             if camera.active { "on" } else { "off" }
         )
     }
-
-    if input.just_pressed(KeyCode::V) {
-        debug.test1 = !debug.test1;
-        println!(
-            "The {} is now used as the terrain height.",
-            if debug.test1 { "DSM" } else { "DTM" }
-        )
-    }
 }
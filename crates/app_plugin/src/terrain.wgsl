@@ -9,15 +9,26 @@ struct TerrainConfig {
     height_size: f32,
     minmax_size: f32,
     albedo_size: f32,
-    _empty: f32,
+    surface_size: f32,
     height_scale: f32,
     minmax_scale: f32,
     albedo_scale: f32,
-    _empty: u32,
+    surface_scale: f32,
     height_offset: f32,
     minmax_offset: f32,
     albedo_offset: f32,
-    _empty: u32,
+    surface_offset: f32,
+    height_min_lod: u32,
+    minmax_min_lod: u32,
+    albedo_min_lod: u32,
+    surface_min_lod: u32,
+    height_max_lod: u32,
+    minmax_max_lod: u32,
+    albedo_max_lod: u32,
+    surface_max_lod: u32,
+
+    planet_radius: f32,
+    nodata: u32,
 }
 
 // view bindings
@@ -36,21 +47,18 @@ var<storage> tiles: TileList;
 var<uniform> config: TerrainConfig;
 @group(2) @binding(1)
 var atlas_sampler: sampler;
-#ifndef TEST1
 @group(2) @binding(2)
 var height_atlas: texture_2d_array<f32>;
 @group(2) @binding(3)
 var minmax_atlas: texture_2d_array<f32>;
-#else
-@group(2) @binding(3)
-var height_atlas: texture_2d_array<f32>;
-@group(2) @binding(2)
-var minmax_atlas: texture_2d_array<f32>;
-#endif
 #ifdef ALBEDO
 @group(2) @binding(4)
 var albedo_atlas: texture_2d_array<f32>;
 #endif
+#ifdef SURFACE
+@group(2) @binding(5)
+var surface_atlas: texture_2d_array<f32>;
+#endif
 
 #import bevy_pbr::mesh_types
 #import bevy_pbr::pbr_types
@@ -63,26 +71,29 @@ var albedo_atlas: texture_2d_array<f32>;
 #import bevy_terrain::node
 #import bevy_terrain::functions
 #import bevy_terrain::debug
+#import bevy_terrain::surface
+
+// the albedo of the terrain, where no albedo data is available (e.g. past the albedo's coarsest lod)
+let DEFAULT_ALBEDO: vec4<f32> = vec4<f32>(0.5, 0.5, 0.5, 1.0);
 
 struct FragmentData {
     world_normal: vec3<f32>,
     color: vec4<f32>,
+    // the height of the extruded surface above the terrain
+    surface: f32,
 }
 
 fn vertex_height(lookup: NodeLookup) -> f32 {
     let height_coords = lookup.atlas_coords * config.height_scale + config.height_offset;
     var height = textureSampleLevel(height_atlas, atlas_sampler, height_coords, lookup.atlas_index, 0.0).x;
 
-#ifdef TEST3
-    // still produces bugs for nodes of mip 1 and above (preprocessing)
-    let gather = textureGather(0, height_atlas, atlas_sampler, height_coords, lookup.atlas_index);
+    height = height * config.height;
 
-    if (gather.x == 0.0 || gather.y == 0.0 || gather.z == 0.0 || gather.w == 0.0) {
-        height = height / 0.0;
-    }
+#ifdef SURFACE
+    height = height + surface_height(surface_atlas, lookup, config.surface_size, config.surface_scale, config.surface_offset);
 #endif
 
-    return height * config.height;
+    return height;
 }
 
 fn lookup_fragment_data(input: FragmentInput, lookup: NodeLookup, ddx: vec2<f32>, ddy: vec2<f32>) -> FragmentData {
@@ -95,22 +106,36 @@ fn lookup_fragment_data(input: FragmentInput, lookup: NodeLookup, ddx: vec2<f32>
     let height_coords = atlas_coords * config.height_scale + config.height_offset;
     let height_ddx = ddx / config.height_size;
     let height_ddy = ddy / config.height_size;
-    let albedo_coords = atlas_coords * config.albedo_scale + config.albedo_offset;
-    let albedo_ddx = ddx / config.albedo_size;
-    let albedo_ddy = ddy / config.albedo_size;
 
     let world_normal = calculate_normal(height_coords, atlas_index, atlas_lod, height_ddx, height_ddy);
 
-    var color = vec4<f32>(0.0);
+    var color = DEFAULT_ALBEDO;
+    var surface = 0.0;
+
+#ifdef SURFACE
+    surface = surface_height(surface_atlas, lookup, config.surface_size, config.surface_scale, config.surface_offset);
+#endif
 
 #ifdef ALBEDO
+    // the albedo might only exist for a subset of the lods, thus it is looked up independently
+    // and only overrides the default albedo, where it is available
+    let albedo_lookup = lookup_attachment_node(atlas_lod, input.local_position, config.albedo_min_lod, config.albedo_max_lod);
+
+    if (albedo_lookup.atlas_index >= 0) {
+        let albedo_index = albedo_lookup.atlas_index;
+        let albedo_lod_scale = f32(1u << atlas_lod) / f32(1u << albedo_lookup.atlas_lod);
+        let albedo_coords = albedo_lookup.atlas_coords * config.albedo_scale + config.albedo_offset;
+        let albedo_ddx = ddx * albedo_lod_scale / config.albedo_size;
+        let albedo_ddy = ddy * albedo_lod_scale / config.albedo_size;
+
 #ifdef SAMPLE_GRAD
-    color = textureSampleGrad(albedo_atlas, atlas_sampler, albedo_coords, atlas_index, albedo_ddx, albedo_ddy);
+        color = textureSampleGrad(albedo_atlas, atlas_sampler, albedo_coords, albedo_index, albedo_ddx, albedo_ddy);
 #else
-    // var color = textureSampleBias(albedo_atlas, atlas_sampler, albedo_coords, atlas_index, 3.0);
-    // var color = textureSample(albedo_atlas, atlas_sampler, albedo_coords, atlas_index);
-    color = textureSampleLevel(albedo_atlas, atlas_sampler, albedo_coords, atlas_index, 0.0);
+        // var color = textureSampleBias(albedo_atlas, atlas_sampler, albedo_coords, albedo_index, 3.0);
+        // var color = textureSample(albedo_atlas, atlas_sampler, albedo_coords, albedo_index);
+        color = textureSampleLevel(albedo_atlas, atlas_sampler, albedo_coords, albedo_index, 0.0);
 #endif
+    }
 #endif
 
 #ifdef BRIGHT
@@ -125,18 +150,26 @@ fn lookup_fragment_data(input: FragmentInput, lookup: NodeLookup, ddx: vec2<f32>
     color = mix(color, vec4<f32>(atlas_coords.x, atlas_coords.y, 0.0, 1.0), 0.5);
 #endif
 
-    return FragmentData(world_normal, color);
+    return FragmentData(world_normal, color, surface);
 }
 
 fn blend_fragment_data(data1: FragmentData, data2: FragmentData, blend_ratio: f32) -> FragmentData {
     let world_normal = mix(data2.world_normal, data1.world_normal, blend_ratio);
     let color = mix(data2.color, data1.color, blend_ratio);
+    let surface = mix(data2.surface, data1.surface, blend_ratio);
 
-    return FragmentData(world_normal, color);
+    return FragmentData(world_normal, color, surface);
 }
 
 fn process_fragment(input: FragmentInput, data: FragmentData) -> Fragment {
     var color = mix(data.color, vec4<f32>(input.debug_color.xyz, 1.0), input.debug_color.w);
+    var world_normal = data.world_normal;
+
+#ifdef SURFACE
+    // the extruded buildings and vegetation are shaded with the normals of their geometry
+    let surface_normal = extruded_normal(input.world_position.xyz);
+    world_normal = select(world_normal, surface_normal, data.surface > 0.0);
+#endif
 
 #ifdef LIGHTING
     var pbr_input: PbrInput = pbr_input_new();
@@ -145,9 +178,9 @@ fn process_fragment(input: FragmentInput, data: FragmentData) -> Fragment {
     pbr_input.material.reflectance = 0.0;
     pbr_input.frag_coord = input.frag_coord;
     pbr_input.world_position = input.world_position;
-    pbr_input.world_normal = data.world_normal;
+    pbr_input.world_normal = world_normal;
     pbr_input.is_orthographic = view.projection[3].w == 1.0;
-    pbr_input.N = data.world_normal;
+    pbr_input.N = world_normal;
     pbr_input.V = calculate_view(input.world_position, pbr_input.is_orthographic);
 
     color = tone_mapping(pbr(pbr_input));
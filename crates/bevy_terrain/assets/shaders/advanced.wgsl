@@ -10,16 +10,27 @@ struct TerrainConfig {
 
     height_size: f32,
     minmax_size: f32,
+    occlusion_size: f32,
     albedo_size: f32,
-    _empty: f32,
     height_scale: f32,
     minmax_scale: f32,
+    occlusion_scale: f32,
     albedo_scale: f32,
-    _empty: f32,
     height_offset: f32,
     minmax_offset: f32,
+    occlusion_offset: f32,
     albedo_offset: f32,
-    _empty: f32,
+    height_min_lod: u32,
+    minmax_min_lod: u32,
+    occlusion_min_lod: u32,
+    albedo_min_lod: u32,
+    height_max_lod: u32,
+    minmax_max_lod: u32,
+    occlusion_max_lod: u32,
+    albedo_max_lod: u32,
+
+    planet_radius: f32,
+    nodata: u32,
 }
 
 // view bindings
@@ -43,8 +54,10 @@ var atlas_sampler: sampler;
 var height_atlas: texture_2d_array<f32>;
 @group(2) @binding(3)
 var minmax_atlas: texture_2d_array<f32>;
-#ifdef ALBEDO
 @group(2) @binding(4)
+var occlusion_atlas: texture_2d_array<f32>;
+#ifdef ALBEDO
+@group(2) @binding(5)
 var albedo_atlas: texture_2d_array<f32>;
 #endif
 
@@ -72,6 +85,7 @@ var array_sampler: sampler;
 struct FragmentData {
     world_normal: vec3<f32>,
     color: vec4<f32>,
+    occlusion: f32,
 }
 
 // Lookup the terrain data required by your `fragment_color` function.
@@ -94,6 +108,9 @@ fn lookup_fragment_data(input: FragmentInput, lookup: NodeLookup, ddx: vec2<f32>
     // Calculate the normal from the heightmap.
     let world_normal = calculate_normal(height_coords, atlas_index, atlas_lod, height_ddx, height_ddy);
 
+    // Sample the baked ambient occlusion.
+    let occlusion = ambient_occlusion(occlusion_atlas, lookup, config.occlusion_scale, config.occlusion_offset);
+
 #ifdef ALBEDO
 #ifdef SAMPLE_GRAD
     var color = textureSampleGrad(albedo_atlas, atlas_sampler, albedo_coords, atlas_index, albedo_ddx, albedo_ddy);
@@ -110,15 +127,16 @@ fn lookup_fragment_data(input: FragmentInput, lookup: NodeLookup, ddx: vec2<f32>
     color = mix(color, show_lod(atlas_lod, input.world_position.xyz), 0.4);
 #endif
 
-    return FragmentData(world_normal, color);
+    return FragmentData(world_normal, color, occlusion);
 }
 
 // Blend the terrain data at the fringe between two lods.
 fn blend_fragment_data(data1: FragmentData, data2: FragmentData, blend_ratio: f32) -> FragmentData {
     let world_normal =  mix(data2.world_normal, data1.world_normal, blend_ratio);
     let color = mix(data2.color, data1.color, blend_ratio);
+    let occlusion = mix(data2.occlusion, data1.occlusion, blend_ratio);
 
-    return FragmentData(world_normal, color);
+    return FragmentData(world_normal, color, occlusion);
 }
 
 // The function that evaluates the color of the fragment.
@@ -155,6 +173,7 @@ fn process_fragment(in: FragmentInput, data: FragmentData) -> Fragment {
     pbr_input.material.base_color = color;
     pbr_input.material.perceptual_roughness = 0.6;
     pbr_input.material.reflectance = 0.1;
+    pbr_input.occlusion = data.occlusion;
     pbr_input.frag_coord = in.frag_coord;
     pbr_input.world_position = in.world_position;
     pbr_input.world_normal = world_normal;
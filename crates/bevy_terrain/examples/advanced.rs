@@ -35,7 +35,8 @@ fn main() {
             ..default()
         }))
         .add_plugin(TerrainPlugin {
-            attachment_count: 3, // has to match the attachments of the terrain
+            attachment_count: 4, // has to match the attachments of the terrain
+            ..default()
         })
         .add_plugin(TerrainDebugPlugin)
         .add_plugin(TerrainMaterialPlugin::<TerrainMaterial>::default())
@@ -73,7 +74,11 @@ fn setup(
     config.add_base_attachment_from_disk(
         &mut preprocessor,
         &mut loader,
-        BaseConfig::new(TEXTURE_SIZE, MIP_LEVEL_COUNT),
+        BaseConfig {
+            // bakes the ambient occlusion, which is applied in the fragment shader
+            occlusion: Some(OcclusionConfig::default()),
+            ..BaseConfig::new(TEXTURE_SIZE, MIP_LEVEL_COUNT)
+        },
         TileConfig {
             path: "assets/terrain/source/height".to_string(),
             size: TERRAIN_SIZE,
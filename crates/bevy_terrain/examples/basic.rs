@@ -20,6 +20,7 @@ fn main() {
         .add_plugins(DefaultPlugins)
         .add_plugin(TerrainPlugin {
             attachment_count: 2, // has to match the attachments of the terrain
+            ..default()
         })
         .add_plugin(TerrainDebugPlugin) // enable debug settings and controls
         .add_plugin(TerrainMaterialPlugin::<TerrainMaterial>::default())
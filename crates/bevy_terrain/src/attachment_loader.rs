@@ -1,7 +1,8 @@
 //! The default attachment loader, which loads node data from disk.
 
 use crate::terrain_data::{
-    node_atlas::NodeAtlas, AttachmentConfig, AttachmentIndex, FileFormat, NodeId,
+    contains_lod, node_atlas::NodeAtlas, AttachmentConfig, AttachmentIndex, FileFormat,
+    NodeCoordinate, NodeId,
 };
 use bevy::{
     asset::{AssetServer, HandleId, LoadState},
@@ -14,6 +15,8 @@ pub(crate) struct AttachmentFromDisk {
     pub(crate) path: String,
     pub(crate) format: TextureFormat,
     pub(crate) file_format: FileFormat,
+    pub(crate) min_lod: u32,
+    pub(crate) max_lod: u32,
 }
 
 impl AttachmentFromDisk {
@@ -22,6 +25,8 @@ impl AttachmentFromDisk {
             path: format!("{}/data/{}", path, attachment.name),
             format: attachment.format.into(),
             file_format: attachment.file_format,
+            min_lod: attachment.min_lod,
+            max_lod: attachment.max_lod,
         }
     }
 }
@@ -52,16 +57,24 @@ pub(crate) fn start_loading_attachment_from_disk(
 
         for &node_id in load_events.iter() {
             let node = loading_nodes.get_mut(&node_id).unwrap();
+            let lod = NodeCoordinate::from(node_id).lod;
 
             for (
                 attachment_index,
                 AttachmentFromDisk {
                     ref path,
                     ref file_format,
+                    min_lod,
+                    max_lod,
                     ..
                 },
             ) in attachments.iter()
             {
+                // the attachment does not exist for this lod
+                if !contains_lod(*min_lod, *max_lod, lod) {
+                    continue;
+                }
+
                 let handle: Handle<Image> =
                     asset_server.load(&format!("{path}/{node_id}.{}", file_format.extension()));
 
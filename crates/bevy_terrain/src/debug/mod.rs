@@ -1,6 +1,9 @@
 //! Contains a debug resource and systems controlling it to visualize different internal
 //! data of the plugin.
-use crate::{debug::camera::debug_camera_control, TerrainViewComponents, TerrainViewConfig};
+use crate::{
+    debug::camera::debug_camera_control, render::TerrainRenderSettings,
+    terrain_view::TerrainViewSettings, TerrainViewComponents, TerrainViewConfig,
+};
 use bevy::{
     prelude::*,
     render::{Extract, RenderApp, RenderStage},
@@ -37,6 +40,9 @@ pub struct DebugTerrain {
     pub bright: bool,
     pub lighting: bool,
     pub sample_grad: bool,
+    /// Extrudes the surface attachment (buildings and vegetation) on top of the terrain.
+    /// Only affects terrains, that have a surface attachment.
+    pub surface: bool,
     pub freeze: bool,
     pub test1: bool,
     pub test2: bool,
@@ -58,6 +64,7 @@ impl Default for DebugTerrain {
             bright: false,
             lighting: true,
             sample_grad: true,
+            surface: true,
             freeze: false,
             test1: false,
             test2: false,
@@ -70,7 +77,11 @@ pub fn extract_debug(mut debug: ResMut<DebugTerrain>, extracted_debug: Extract<R
     *debug = extracted_debug.clone();
 }
 
-pub fn toggle_debug(input: Res<Input<KeyCode>>, mut debug: ResMut<DebugTerrain>) {
+pub fn toggle_debug(
+    input: Res<Input<KeyCode>>,
+    mut debug: ResMut<DebugTerrain>,
+    mut settings: ResMut<TerrainRenderSettings>,
+) {
     if input.just_pressed(KeyCode::W) {
         debug.wireframe = !debug.wireframe;
         println!(
@@ -155,6 +166,42 @@ pub fn toggle_debug(input: Res<Input<KeyCode>>, mut debug: ResMut<DebugTerrain>)
             if debug.sample_grad { "on" } else { "off" }
         )
     }
+    if input.just_pressed(KeyCode::Key5) {
+        debug.surface = !debug.surface;
+        println!(
+            "Toggled the surface extrusion {}.",
+            if debug.surface { "on" } else { "off" }
+        )
+    }
+    if input.just_pressed(KeyCode::R) {
+        settings.depth_prepass = !settings.depth_prepass;
+        println!(
+            "Toggled the depth prepass {}.",
+            if settings.depth_prepass { "on" } else { "off" }
+        )
+    }
+    if input.just_pressed(KeyCode::K) {
+        settings.occlusion_culling = !settings.occlusion_culling;
+        println!(
+            "Toggled the occlusion culling {}.",
+            if settings.occlusion_culling {
+                "on"
+            } else {
+                "off"
+            }
+        )
+    }
+    if input.just_pressed(KeyCode::Key4) {
+        settings.horizon_culling = !settings.horizon_culling;
+        println!(
+            "Toggled the horizon culling {}.",
+            if settings.horizon_culling {
+                "on"
+            } else {
+                "off"
+            }
+        )
+    }
     if input.just_pressed(KeyCode::F) {
         debug.freeze = !debug.freeze;
         println!(
@@ -185,42 +232,84 @@ pub fn toggle_debug(input: Res<Input<KeyCode>>, mut debug: ResMut<DebugTerrain>)
     }
 }
 
+/// Adjusts the tile scale, the view distance and the grid size of the terrain views.
+/// If the [`TerrainViewSettings`] are present, they are adjusted instead,
+/// so that they stay in sync with the views.
 pub fn change_config(
     input: Res<Input<KeyCode>>,
+    settings: Option<ResMut<TerrainViewSettings>>,
     mut view_configs: ResMut<TerrainViewComponents<TerrainViewConfig>>,
 ) {
-    for mut view_config in &mut view_configs.0.values_mut() {
-        if input.just_pressed(KeyCode::H) && view_config.tile_scale > 0.25 {
-            view_config.tile_scale /= 2.0;
-            println!("Decreased the tile scale to {}.", view_config.tile_scale);
-        }
-        if input.just_pressed(KeyCode::J) {
-            view_config.tile_scale *= 2.0;
-            println!("Increased the tile scale to {}.", view_config.tile_scale)
-        }
+    // the resources are only borrowed mutably on a change, to not trigger their change detection
+    if !input.any_just_pressed(QUALITY_KEYS) {
+        return;
+    }
 
-        if input.just_pressed(KeyCode::I) {
-            view_config.view_distance -= 0.25;
-            println!(
-                "Decreased the view distance to {}.",
-                view_config.view_distance
-            );
-        }
-        if input.just_pressed(KeyCode::O) {
-            view_config.view_distance += 0.25;
-            println!(
-                "Increased the view distance to {}.",
-                view_config.view_distance
-            );
-        }
+    if let Some(mut settings) = settings {
+        let TerrainViewSettings {
+            tile_scale,
+            view_distance,
+            grid_size,
+            ..
+        } = &mut *settings;
 
-        if input.just_pressed(KeyCode::N) && view_config.grid_size > 2 {
-            view_config.grid_size -= 2;
-            println!("Decreased the grid size to {}.", view_config.grid_size);
-        }
-        if input.just_pressed(KeyCode::E) {
-            view_config.grid_size += 2;
-            println!("Increased the grid size to {}.", view_config.grid_size);
-        }
+        change_quality(&input, tile_scale, view_distance, grid_size);
+
+        return;
+    }
+
+    for view_config in view_configs.0.values_mut() {
+        let TerrainViewConfig {
+            tile_scale,
+            view_distance,
+            grid_size,
+            ..
+        } = view_config;
+
+        change_quality(&input, tile_scale, view_distance, grid_size);
+    }
+}
+
+/// The keys, which change the quality of the terrain views.
+const QUALITY_KEYS: [KeyCode; 6] = [
+    KeyCode::H,
+    KeyCode::J,
+    KeyCode::I,
+    KeyCode::O,
+    KeyCode::N,
+    KeyCode::E,
+];
+
+fn change_quality(
+    input: &Input<KeyCode>,
+    tile_scale: &mut f32,
+    view_distance: &mut f32,
+    grid_size: &mut u32,
+) {
+    if input.just_pressed(KeyCode::H) && *tile_scale > 0.25 {
+        *tile_scale /= 2.0;
+        println!("Decreased the tile scale to {}.", tile_scale);
+    }
+    if input.just_pressed(KeyCode::J) {
+        *tile_scale *= 2.0;
+        println!("Increased the tile scale to {}.", tile_scale)
+    }
+
+    if input.just_pressed(KeyCode::I) {
+        *view_distance -= 0.25;
+        println!("Decreased the view distance to {}.", view_distance);
+    }
+    if input.just_pressed(KeyCode::O) {
+        *view_distance += 0.25;
+        println!("Increased the view distance to {}.", view_distance);
+    }
+
+    if input.just_pressed(KeyCode::N) && *grid_size > 2 {
+        *grid_size -= 2;
+        println!("Decreased the grid size to {}.", grid_size);
+    }
+    if input.just_pressed(KeyCode::E) {
+        *grid_size += 2;
+        println!("Increased the grid size to {}.", grid_size);
     }
 }
@@ -3,16 +3,31 @@ use anyhow::Result;
 use bincode::{config, Decode, Encode};
 use std::{fs, path::Path};
 
+/// The layout of an attachment, that can not be reconstructed from its nodes.
+#[derive(Encode, Decode, Clone, Debug)]
+pub struct TCAttachment {
+    pub name: String,
+    pub border_size: u32,
+}
+
 #[derive(Encode, Decode, Debug)]
 pub struct TC {
     pub nodes: Vec<NodeId>,
+    /// The attachments of the terrain, which are missing in files written by older versions.
+    pub attachments: Vec<TCAttachment>,
 }
 
 impl TC {
     pub fn decode_alloc(encoded: &[u8]) -> Result<Self> {
         let config = config::standard();
-        let decoded = bincode::decode_from_slice(encoded, config)?;
-        Ok(decoded.0)
+        let (nodes, length) = bincode::decode_from_slice(encoded, config)?;
+
+        let attachments = match &encoded[length..] {
+            [] => Vec::new(),
+            remaining => bincode::decode_from_slice(remaining, config)?.0,
+        };
+
+        Ok(Self { nodes, attachments })
     }
 
     pub fn encode_alloc(&self) -> Result<Vec<u8>> {
@@ -1,3 +1,4 @@
+use crate::preprocess::nodata::NODATA;
 use anyhow::{anyhow, Result};
 use dtm::DTM;
 use itertools::iproduct;
@@ -5,6 +6,9 @@ use rapid_qoi::{Colors, Qoi};
 use std::{fs, path::Path};
 
 const TDF_HEADER_SIZE: usize = 7;
+/// The bit of the channel count byte, which marks single channel 16 bit data with holes.
+/// Files written before it was introduced leave it unset.
+const TDF_NODATA_BIT: u8 = 1 << 7;
 
 #[derive(Debug)]
 pub struct TDF {
@@ -12,6 +16,8 @@ pub struct TDF {
     pub channel_count: u32,
     pub mip_level_count: u32,
     pub size: u32,
+    /// Whether texels with the [`NODATA`] value are holes, which are excluded from the mipmaps.
+    pub nodata: bool,
 }
 
 impl TDF {
@@ -24,9 +30,10 @@ impl TDF {
     pub fn decode_alloc(encoded: &[u8], mip_maps: bool) -> Result<(Self, Vec<u8>)> {
         let mut descriptor = TDF {
             pixel_size: encoded[0] as u32,
-            channel_count: encoded[1] as u32,
+            channel_count: (encoded[1] & !TDF_NODATA_BIT) as u32,
             mip_level_count: encoded[2] as u32,
             size: u32::from_be_bytes(encoded[3..7].try_into().unwrap()),
+            nodata: encoded[1] & TDF_NODATA_BIT != 0,
         };
 
         if !mip_maps {
@@ -61,26 +68,14 @@ impl TDF {
             }
         }
 
-        let mut decoded_start = 0;
-
-        for mip_level in 1..descriptor.mip_level_count {
-            let decoded_size = descriptor.decoded_size(mip_level - 1);
-
-            let p_size = (descriptor.size >> (mip_level - 1)) as usize;
-            let c_size = (descriptor.size >> mip_level) as usize;
-            let p_start = decoded_start;
-            let c_start = decoded_start + decoded_size;
-
-            match (descriptor.channel_count, descriptor.pixel_size) {
-                (1, 2) => generate_mipmap::<1, 2>(&mut decoded, p_size, c_size, p_start, c_start),
-                (2, 2) => generate_mipmap::<2, 2>(&mut decoded, p_size, c_size, p_start, c_start),
-                (3, 1) => generate_mipmap::<3, 1>(&mut decoded, p_size, c_size, p_start, c_start),
-                (4, 1) => generate_mipmap::<4, 1>(&mut decoded, p_size, c_size, p_start, c_start),
-                (_, _) => {}
-            }
-
-            decoded_start += decoded_size;
-        }
+        generate_mipmaps(
+            &mut decoded,
+            descriptor.size,
+            descriptor.mip_level_count,
+            descriptor.channel_count,
+            descriptor.pixel_size,
+            descriptor.nodata,
+        );
 
         Ok((descriptor, decoded))
     }
@@ -95,7 +90,7 @@ impl TDF {
         let mut encoded = vec![0; TDF_HEADER_SIZE + decoded_size];
 
         encoded[0] = self.pixel_size as u8;
-        encoded[1] = self.channel_count as u8;
+        encoded[1] = self.channel_count as u8 | if self.nodata { TDF_NODATA_BIT } else { 0 };
         encoded[2] = self.mip_level_count as u8;
         encoded[3..7].copy_from_slice(&self.size.to_be_bytes());
 
@@ -107,7 +102,8 @@ impl TDF {
 
         let decoded = &decoded[decoded_start..decoded_start + decoded_size];
 
-        if self.pixel_size == 1 {
+        // QOI only supports three and four channels, thus two channel data is stored uncompressed
+        if self.pixel_size == 1 && self.channel_count != 2 {
             let colors = match self.channel_count {
                 3 => Ok(Colors::Rgb),
                 4 => Ok(Colors::Rgba),
@@ -164,6 +160,41 @@ impl TDF {
     }
 }
 
+/// Regenerates all mip levels of the `decoded` data from its first level.
+/// If the data has nodata, texels with the [`NODATA`] value are excluded from the average.
+pub(crate) fn generate_mipmaps(
+    decoded: &mut [u8],
+    size: u32,
+    mip_level_count: u32,
+    channel_count: u32,
+    pixel_size: u32,
+    nodata: bool,
+) {
+    let mut decoded_start = 0;
+
+    for mip_level in 1..mip_level_count {
+        let decoded_size = ((size >> (mip_level - 1)).pow(2) * pixel_size * channel_count) as usize;
+
+        let p_size = (size >> (mip_level - 1)) as usize;
+        let c_size = (size >> mip_level) as usize;
+        let p_start = decoded_start;
+        let c_start = decoded_start + decoded_size;
+
+        match (channel_count, pixel_size) {
+            (1, 2) if nodata => generate_mipmap_nodata(decoded, p_size, c_size, p_start, c_start),
+            (1, 2) => generate_mipmap::<1, 2>(decoded, p_size, c_size, p_start, c_start),
+            (2, 2) => generate_mipmap::<2, 2>(decoded, p_size, c_size, p_start, c_start),
+            (2, 1) => generate_mipmap::<2, 1>(decoded, p_size, c_size, p_start, c_start),
+            (3, 1) => generate_mipmap::<3, 1>(decoded, p_size, c_size, p_start, c_start),
+            (4, 1) => generate_mipmap::<4, 1>(decoded, p_size, c_size, p_start, c_start),
+            (4, 2) => generate_mipmap::<4, 2>(decoded, p_size, c_size, p_start, c_start),
+            (_, _) => {}
+        }
+
+        decoded_start += decoded_size;
+    }
+}
+
 fn generate_mipmap<const C: usize, const P: usize>(
     decoded: &mut [u8],
     p_size: usize,
@@ -194,3 +225,40 @@ fn generate_mipmap<const C: usize, const P: usize>(
         }
     }
 }
+
+/// Averages the single channel 16 bit data, excluding texels without data.
+/// Only if all four texels have no data, the texel of the next mip level has no data either.
+fn generate_mipmap_nodata(
+    decoded: &mut [u8],
+    p_size: usize,
+    c_size: usize,
+    p_start: usize,
+    c_start: usize,
+) {
+    for (c_y, c_x) in iproduct!(0..c_size, 0..c_size) {
+        let mut sum = 0;
+        let mut count = 0;
+
+        for i in 0..4 {
+            let p_x = (c_x << 1) + (i >> 1);
+            let p_y = (c_y << 1) + (i & 1);
+
+            let index = p_start + 2 * (p_y * p_size + p_x);
+            let value = u16::from_le_bytes([decoded[index], decoded[index + 1]]);
+
+            if value != NODATA {
+                sum += value as u32;
+                count += 1;
+            }
+        }
+
+        let value = if count == 0 {
+            NODATA
+        } else {
+            (sum / count) as u16
+        };
+
+        let index = c_start + 2 * (c_y * c_size + c_x);
+        decoded[index..index + 2].copy_from_slice(&value.to_le_bytes());
+    }
+}
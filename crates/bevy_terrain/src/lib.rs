@@ -37,9 +37,16 @@
 //! project specific problem and thus there does not exist a one-size-fits-all solution.
 //! You can define your own terrain [Material](bevy::pbr::Material) and shader with all the
 //! detail textures tailored to your application.
-//! In the future this plugin will provide modular shader functions to make techniques like splat
-//! mapping, triplane mapping, etc. easier.
-//! Additionally a virtual texturing solution might be integrated to achieve better performance.
+//! This plugin provides built-in materials and modular shader functions to make techniques like
+//! splat mapping and rule-based procedural texturing easier.
+//! See the [`material`] module for more information.
+//! High-resolution textures (e.g. orthophotos), that exceed the resolution of the height data,
+//! can be streamed independently of the terrain geometry using a virtual texture.
+//! See the [`virtual_texture`] module for more information.
+//! Lakes and rivers can be rendered from a water attachment in a separate transparent pass.
+//! See the [`water`](render::water) module for more information.
+//! Vegetation and other objects can be scattered over the loaded terrain on the GPU.
+//! See the [`scatter`] module for more information.
 //!
 //! [^note]: Some of these claims are not yet fully implemented.
 
@@ -49,9 +56,17 @@ use crate::{
     attachment_loader::{finish_loading_attachment_from_disk, start_loading_attachment_from_disk},
     debug::DebugTerrain,
     formats::TDFPlugin,
+    overlay::apply_terrain_overlays,
     render::{
         compute_pipelines::{TerrainComputeNode, TerrainComputePipelines},
-        culling::{queue_terrain_culling_bind_group, CullingBindGroup},
+        culling::{
+            queue_terrain_culling_bind_group, read_culling_statistics, CullingBindGroup,
+            TerrainCullingStatistics,
+        },
+        occlusion::{
+            extract_depth_pyramids_required, prepare_depth_pyramids, DepthPyramid,
+            DepthPyramidPipelines, DepthPyramidsRequired, TerrainDepthDraw, TerrainOcclusionNode,
+        },
         render_pipeline::TerrainPipelineConfig,
         shaders::add_shader,
         terrain_data::{initialize_terrain_data, TerrainData},
@@ -60,6 +75,18 @@ use crate::{
             extract_terrain_view_config, initialize_terrain_view_data, queue_terrain_view_config,
             TerrainViewData,
         },
+        water::{
+            queue_water, queue_water_bind_groups, DrawWater, TerrainWater, WaterBindGroup,
+            WaterPipeline,
+        },
+        TerrainRenderSettings,
+    },
+    scatter::{
+        gpu_scatter::{
+            queue_scatter, queue_scatter_draws, DrawScatter, GpuScatter, TerrainScatterNode,
+        },
+        pipelines::{ScatterComputePipelines, ScatterRenderPipeline},
+        ScatterLayer,
     },
     terrain::{Terrain, TerrainComponents, TerrainConfig},
     terrain_data::{
@@ -74,25 +101,47 @@ use crate::{
             adjust_quadtree, compute_quadtree_request, update_height_under_viewer, Quadtree,
         },
     },
-    terrain_view::{TerrainView, TerrainViewComponents, TerrainViewConfig},
+    terrain_view::{
+        apply_terrain_view_settings, TerrainView, TerrainViewComponents, TerrainViewConfig,
+        TerrainViewSettings,
+    },
+    virtual_texture::{
+        feedback::{
+            prepare_virtual_texture_feedback, read_virtual_texture_feedback, ViewFeedback,
+            VirtualTextureFeedbackNode, VirtualTextureFeedbackPipeline,
+        },
+        finish_loading_virtual_textures,
+        gpu_virtual_texture::{
+            extract_virtual_texture, initialize_gpu_virtual_texture, queue_virtual_texture_updates,
+            GpuVirtualTexture,
+        },
+        update_virtual_textures, VirtualTextureFeedback,
+    },
 };
 use bevy::render::view::NoFrustumCulling;
 use bevy::{
+    core_pipeline::core_3d::{Opaque3d, Transparent3d},
     prelude::*,
     render::{
-        extract_component::ExtractComponentPlugin, main_graph::node::CAMERA_DRIVER,
-        render_graph::RenderGraph, render_resource::*, RenderApp, RenderStage,
+        extract_component::ExtractComponentPlugin, extract_resource::ExtractResourcePlugin,
+        main_graph::node::CAMERA_DRIVER, render_graph::RenderGraph, render_phase::AddRenderCommand,
+        render_resource::*, RenderApp, RenderStage,
     },
 };
 
 pub mod attachment_loader;
 pub mod debug;
 pub mod formats;
+pub mod material;
+pub mod overlay;
+pub mod physics;
 pub mod preprocess;
 pub mod render;
+pub mod scatter;
 pub mod terrain;
 pub mod terrain_data;
 pub mod terrain_view;
+pub mod virtual_texture;
 
 pub mod prelude {
     //! `use bevy_terrain::prelude::*;` to import common components, bundles, and plugins.
@@ -100,14 +149,33 @@ pub mod prelude {
     pub use crate::{
         attachment_loader::AttachmentFromDiskLoader,
         debug::{camera::DebugCamera, TerrainDebugPlugin},
-        preprocess::{config::load_node_config, BaseConfig, Preprocessor, TileConfig},
-        render::render_pipeline::TerrainMaterialPlugin,
+        material::{
+            procedural::{ProceduralMaterial, ProceduralRule, RuleRange},
+            splat::{LayerProjection, SplatLayer, SplatMaterial},
+        },
+        overlay::{OverlayBlend, OverlayPatch, TerrainOverlay},
+        physics::{PhysicsFocus, TerrainColliders, TerrainHeightField, TerrainPhysicsPlugin},
+        preprocess::{
+            config::load_node_config, fallback::FallbackConfig, nodata::Nodata,
+            occlusion::OcclusionConfig, surface::SurfaceConfig, water::WaterConfig, BaseConfig,
+            Preprocessor, TileConfig,
+        },
+        render::{
+            culling::{CullingStatistics, TerrainCullingStatistics},
+            render_pipeline::TerrainMaterialPlugin,
+            water::TerrainWater,
+            TerrainRenderSettings,
+        },
+        scatter::{ScatterDensity, ScatterLayer},
         terrain::{Terrain, TerrainConfig},
         terrain_data::{
             node_atlas::NodeAtlas, quadtree::Quadtree, AttachmentConfig, AttachmentFormat,
             FileFormat,
         },
-        terrain_view::{TerrainView, TerrainViewComponents, TerrainViewConfig},
+        terrain_view::{
+            TerrainView, TerrainViewComponents, TerrainViewConfig, TerrainViewSettings,
+        },
+        virtual_texture::{VirtualTexture, VirtualTextureConfig, VirtualTextureFeedback},
         TerrainBundle, TerrainPlugin,
     };
 }
@@ -146,12 +214,16 @@ impl TerrainBundle {
 pub struct TerrainPlugin {
     /// The number of terrain attachments.
     pub attachment_count: usize,
+    /// The initial render settings, which can be changed at runtime via the
+    /// [`TerrainRenderSettings`] resource.
+    pub render_settings: TerrainRenderSettings,
 }
 
 impl Default for TerrainPlugin {
     fn default() -> Self {
         Self {
             attachment_count: 2,
+            render_settings: default(),
         }
     }
 }
@@ -160,15 +232,33 @@ impl Plugin for TerrainPlugin {
     fn build(&self, app: &mut App) {
         add_shader(app);
 
+        let culling_statistics = TerrainCullingStatistics::default();
+        let virtual_texture_feedback = VirtualTextureFeedback::default();
+
         app.add_plugin(TDFPlugin)
+            .insert_resource(culling_statistics.clone())
+            .insert_resource(virtual_texture_feedback.clone())
+            .insert_resource(self.render_settings)
+            .register_type::<TerrainRenderSettings>()
+            .add_plugin(ExtractResourcePlugin::<TerrainRenderSettings>::default())
             .add_plugin(ExtractComponentPlugin::<Terrain>::default())
             .add_plugin(ExtractComponentPlugin::<TerrainView>::default())
+            .add_plugin(ExtractComponentPlugin::<TerrainWater>::default())
+            .add_plugin(ExtractComponentPlugin::<ScatterLayer>::default())
             .init_resource::<TerrainViewComponents<Quadtree>>()
             .init_resource::<TerrainViewComponents<TerrainViewConfig>>()
+            .register_type::<TerrainViewSettings>()
+            .add_system_to_stage(CoreStage::PostUpdate, apply_terrain_view_settings)
             .add_system_to_stage(
                 CoreStage::Last,
                 finish_loading_attachment_from_disk.before(update_node_atlas),
             )
+            .add_system_to_stage(
+                CoreStage::Last,
+                apply_terrain_overlays
+                    .after(finish_loading_attachment_from_disk)
+                    .before(update_node_atlas),
+            )
             .add_system_to_stage(
                 CoreStage::Last,
                 compute_quadtree_request.before(update_node_atlas),
@@ -182,13 +272,20 @@ impl Plugin for TerrainPlugin {
             .add_system_to_stage(
                 CoreStage::Last,
                 update_height_under_viewer.after(adjust_quadtree),
-            );
+            )
+            .add_system_to_stage(
+                CoreStage::Last,
+                finish_loading_virtual_textures.before(update_virtual_textures),
+            )
+            .add_system_to_stage(CoreStage::Last, update_virtual_textures);
 
         let render_app = app
             .sub_app_mut(RenderApp)
             .insert_resource(TerrainPipelineConfig {
                 attachment_count: self.attachment_count,
             })
+            .insert_resource(culling_statistics)
+            .insert_resource(virtual_texture_feedback)
             .init_resource::<TerrainComputePipelines>()
             .init_resource::<SpecializedComputePipelines<TerrainComputePipelines>>()
             .init_resource::<TerrainComponents<GpuNodeAtlas>>()
@@ -197,6 +294,23 @@ impl Plugin for TerrainPlugin {
             .init_resource::<TerrainViewComponents<TerrainViewData>>()
             .init_resource::<TerrainViewComponents<TerrainViewConfigUniform>>()
             .init_resource::<TerrainViewComponents<CullingBindGroup>>()
+            .init_resource::<DepthPyramidsRequired>()
+            .init_resource::<DepthPyramidPipelines>()
+            .init_resource::<TerrainViewComponents<DepthPyramid>>()
+            .init_resource::<TerrainComponents<TerrainDepthDraw>>()
+            .init_resource::<TerrainComponents<GpuVirtualTexture>>()
+            .init_resource::<VirtualTextureFeedbackPipeline>()
+            .init_resource::<TerrainViewComponents<ViewFeedback>>()
+            .init_resource::<WaterPipeline>()
+            .init_resource::<SpecializedRenderPipelines<WaterPipeline>>()
+            .init_resource::<TerrainViewComponents<WaterBindGroup>>()
+            .init_resource::<ScatterComputePipelines>()
+            .init_resource::<SpecializedComputePipelines<ScatterComputePipelines>>()
+            .init_resource::<ScatterRenderPipeline>()
+            .init_resource::<SpecializedMeshPipelines<ScatterRenderPipeline>>()
+            .init_resource::<TerrainViewComponents<GpuScatter>>()
+            .add_render_command::<Transparent3d, DrawWater>()
+            .add_render_command::<Opaque3d, DrawScatter>()
             .add_system_to_stage(RenderStage::Extract, extract_terrain_view_config)
             .add_system_to_stage(RenderStage::Extract, initialize_gpu_node_atlas)
             .add_system_to_stage(RenderStage::Extract, initialize_gpu_quadtree)
@@ -216,18 +330,58 @@ impl Plugin for TerrainPlugin {
                 RenderStage::Extract,
                 extract_quadtree.after(initialize_gpu_quadtree),
             )
+            .add_system_to_stage(RenderStage::Extract, initialize_gpu_virtual_texture)
+            .add_system_to_stage(
+                RenderStage::Extract,
+                extract_virtual_texture.after(initialize_gpu_virtual_texture),
+            )
+            .add_system_to_stage(RenderStage::Extract, extract_depth_pyramids_required)
+            .add_system_to_stage(RenderStage::Prepare, prepare_depth_pyramids)
+            .add_system_to_stage(
+                RenderStage::Prepare,
+                prepare_virtual_texture_feedback.after(prepare_depth_pyramids),
+            )
             .add_system_to_stage(RenderStage::Queue, queue_quadtree_update)
             .add_system_to_stage(RenderStage::Queue, queue_node_atlas_updates)
             .add_system_to_stage(RenderStage::Queue, queue_terrain_culling_bind_group)
-            .add_system_to_stage(RenderStage::Queue, queue_terrain_view_config);
+            .add_system_to_stage(RenderStage::Queue, queue_terrain_view_config)
+            .add_system_to_stage(RenderStage::Queue, queue_virtual_texture_updates)
+            .add_system_to_stage(RenderStage::Queue, queue_water_bind_groups)
+            .add_system_to_stage(
+                RenderStage::Queue,
+                queue_water.after(queue_water_bind_groups),
+            )
+            .add_system_to_stage(RenderStage::Queue, queue_scatter)
+            .add_system_to_stage(RenderStage::Queue, queue_scatter_draws.after(queue_scatter))
+            .add_system_to_stage(RenderStage::Cleanup, read_culling_statistics)
+            .add_system_to_stage(RenderStage::Cleanup, read_virtual_texture_feedback);
 
         let compute_node = TerrainComputeNode::from_world(&mut render_app.world);
+        let occlusion_node = TerrainOcclusionNode::from_world(&mut render_app.world);
+        let feedback_node = VirtualTextureFeedbackNode::from_world(&mut render_app.world);
+        let scatter_node = TerrainScatterNode::from_world(&mut render_app.world);
 
         let mut render_graph = render_app.world.resource_mut::<RenderGraph>();
         render_graph.add_node("terrain_compute", compute_node);
+        render_graph.add_node("terrain_occlusion", occlusion_node);
+        render_graph.add_node("virtual_texture_feedback", feedback_node);
+        render_graph.add_node("terrain_scatter", scatter_node);
 
         render_graph
-            .add_node_edge("terrain_compute", CAMERA_DRIVER)
+            .add_node_edge("terrain_compute", "terrain_occlusion")
+            .unwrap();
+        // the terrain depth is used by the water pass, the depth pyramids in the next frame
+        render_graph
+            .add_node_edge("terrain_occlusion", CAMERA_DRIVER)
+            .unwrap();
+        render_graph
+            .add_node_edge("terrain_occlusion", "virtual_texture_feedback")
+            .unwrap();
+        render_graph
+            .add_node_edge("terrain_compute", "terrain_scatter")
+            .unwrap();
+        render_graph
+            .add_node_edge("terrain_scatter", CAMERA_DRIVER)
             .unwrap();
     }
 }
@@ -0,0 +1,13 @@
+//! Built-in terrain materials, which can be used with the
+//! [`TerrainMaterialPlugin`](crate::render::render_pipeline::TerrainMaterialPlugin).
+//!
+//! The shader functions of these materials are exposed as reusable WGSL modules, so that custom
+//! materials can import them as well:
+//! - `bevy_terrain::triplanar` samples textures using triplanar or biplanar projections
+//! - `bevy_terrain::splat` blends detail layers according to splat weights
+//!   (requires `bevy_terrain::triplanar` to be imported beforehand)
+//! - `bevy_terrain::procedural` derives layer weights from the height, slope and curvature
+//!   of the terrain according to a list of rules
+
+pub mod procedural;
+pub mod splat;
@@ -0,0 +1,235 @@
+use crate::{
+    material::splat::{LayerProjection, SplatLayer, SplatMaterialUniform, SPLAT_LAYER_COUNT},
+    render::shaders::PROCEDURAL_MATERIAL_SHADER,
+};
+use bevy::{
+    prelude::*,
+    reflect::TypeUuid,
+    render::{render_asset::RenderAssets, render_resource::*},
+};
+
+/// The maximal number of rules of a [`ProceduralMaterial`].
+pub const MAX_PROCEDURAL_RULES: usize = 16;
+
+/// A range of a terrain attribute, in which a [`ProceduralRule`] applies.
+/// Outside of the range the rule fades out over the falloff distance.
+#[derive(Clone, Copy, Debug)]
+pub struct RuleRange {
+    pub min: f32,
+    pub max: f32,
+    pub falloff: f32,
+}
+
+impl RuleRange {
+    /// A range covering all values.
+    pub const ANY: Self = Self {
+        min: f32::MIN,
+        max: f32::MAX,
+        falloff: 0.0,
+    };
+
+    pub fn new(min: f32, max: f32, falloff: f32) -> Self {
+        Self { min, max, falloff }
+    }
+
+    /// A range covering all values above `min`.
+    pub fn above(min: f32, falloff: f32) -> Self {
+        Self::new(min, f32::MAX, falloff)
+    }
+
+    /// A range covering all values below `max`.
+    pub fn below(max: f32, falloff: f32) -> Self {
+        Self::new(f32::MIN, max, falloff)
+    }
+}
+
+impl Default for RuleRange {
+    fn default() -> Self {
+        Self::ANY
+    }
+}
+
+/// A rule, which assigns a detail layer to all regions of the terrain, whose height, slope
+/// and curvature lie inside of the rule's ranges.
+#[derive(Clone, Copy, Debug)]
+pub struct ProceduralRule {
+    /// The index of the detail layer assigned by this rule.
+    pub layer: u32,
+    /// The opacity of this rule.
+    pub strength: f32,
+    /// The height range in world units.
+    pub height: RuleRange,
+    /// The slope range in degrees.
+    pub slope: RuleRange,
+    /// The curvature range in 1/m.
+    /// Positive values correspond to concave and negative ones to convex regions.
+    pub curvature: RuleRange,
+}
+
+impl ProceduralRule {
+    /// Creates a new rule, which assigns the layer to the entire terrain.
+    pub fn new(layer: u32) -> Self {
+        Self {
+            layer,
+            strength: 1.0,
+            height: RuleRange::ANY,
+            slope: RuleRange::ANY,
+            curvature: RuleRange::ANY,
+        }
+    }
+
+    pub fn with_height(mut self, height: RuleRange) -> Self {
+        self.height = height;
+        self
+    }
+
+    pub fn with_slope(mut self, slope: RuleRange) -> Self {
+        self.slope = slope;
+        self
+    }
+
+    pub fn with_curvature(mut self, curvature: RuleRange) -> Self {
+        self.curvature = curvature;
+        self
+    }
+
+    pub fn with_strength(mut self, strength: f32) -> Self {
+        self.strength = strength;
+        self
+    }
+}
+
+/// The gpu representation of a [`ProceduralRule`].
+#[derive(Clone, Copy, Default, ShaderType)]
+pub struct ProceduralRuleUniform {
+    pub min_height: f32,
+    pub max_height: f32,
+    pub height_falloff: f32,
+    pub min_slope: f32,
+    pub max_slope: f32,
+    pub slope_falloff: f32,
+    pub min_curvature: f32,
+    pub max_curvature: f32,
+    pub curvature_falloff: f32,
+    pub layer: u32,
+    pub strength: f32,
+    pub _empty: f32,
+}
+
+impl From<&ProceduralRule> for ProceduralRuleUniform {
+    fn from(rule: &ProceduralRule) -> Self {
+        Self {
+            min_height: rule.height.min,
+            max_height: rule.height.max,
+            height_falloff: rule.height.falloff,
+            min_slope: rule.slope.min.to_radians(),
+            max_slope: rule.slope.max.to_radians(),
+            slope_falloff: rule.slope.falloff.to_radians(),
+            min_curvature: rule.curvature.min,
+            max_curvature: rule.curvature.max,
+            curvature_falloff: rule.curvature.falloff,
+            layer: rule.layer.min(SPLAT_LAYER_COUNT as u32 - 1),
+            strength: rule.strength,
+            _empty: 0.0,
+        }
+    }
+}
+
+/// The gpu representation of the rules of a [`ProceduralMaterial`].
+#[derive(Clone, Default, ShaderType)]
+pub struct ProceduralRulesUniform {
+    pub rules: [ProceduralRuleUniform; MAX_PROCEDURAL_RULES],
+    pub rule_count: u32,
+}
+
+/// The gpu representation of a [`ProceduralMaterial`].
+#[derive(Clone, Default, ShaderType)]
+pub struct ProceduralMaterialUniform {
+    pub splat: SplatMaterialUniform,
+    pub rules: ProceduralRulesUniform,
+}
+
+/// A terrain material, which derives the weights of up to four detail layers at runtime from
+/// the height, slope and curvature of the terrain according to a list of [`ProceduralRule`]s.
+///
+/// In contrast to the [`SplatMaterial`](super::splat::SplatMaterial) it does not require a
+/// preprocessed splat attachment, only the base attachment (height and minmax).
+/// The rules are evaluated in order, each one painting its layer over the previous ones.
+/// Regions not covered by any rule are assigned to the first layer.
+/// Because the rules are uploaded as a uniform, they can be tweaked live by modifying the material asset.
+#[derive(AsBindGroup, TypeUuid, Clone, Debug)]
+#[uuid = "8a4f3c61-2b7e-4d90-a5c8-e6f1d2b39a07"]
+#[uniform(0, ProceduralMaterialUniform)]
+pub struct ProceduralMaterial {
+    /// The rules determining the layer weights. Only the first [`MAX_PROCEDURAL_RULES`] are used.
+    pub rules: Vec<ProceduralRule>,
+    /// The settings of the detail layers.
+    pub layers: [SplatLayer; SPLAT_LAYER_COUNT],
+    /// The blend depth of the height-based blending. Zero disables it.
+    pub height_blend: f32,
+    /// The projection used to map the layers onto the terrain.
+    pub projection: LayerProjection,
+    /// The sharpness of the transitions between the triplanar/biplanar projections.
+    pub projection_sharpness: f32,
+    /// The array texture containing the albedo (rgb) and the height (a) of each layer.
+    #[texture(1, dimension = "2d_array")]
+    #[sampler(2)]
+    pub albedo_layers: Handle<Image>,
+    /// The array texture containing the tangent space normal map of each layer.
+    #[texture(3, dimension = "2d_array")]
+    pub normal_layers: Handle<Image>,
+}
+
+impl ProceduralMaterial {
+    /// Creates a new procedural material without any rules.
+    ///
+    /// Both array textures have to contain one layer per splat channel and should
+    /// use a repeating sampler.
+    pub fn new(albedo_layers: Handle<Image>, normal_layers: Handle<Image>) -> Self {
+        Self {
+            rules: Vec::new(),
+            layers: default(),
+            height_blend: 0.2,
+            projection: LayerProjection::Triplanar,
+            projection_sharpness: 4.0,
+            albedo_layers,
+            normal_layers,
+        }
+    }
+
+    pub fn with_rule(mut self, rule: ProceduralRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+}
+
+impl AsBindGroupShaderType<ProceduralMaterialUniform> for ProceduralMaterial {
+    fn as_bind_group_shader_type(
+        &self,
+        _images: &RenderAssets<Image>,
+    ) -> ProceduralMaterialUniform {
+        let mut rules = ProceduralRulesUniform::default();
+
+        for (uniform, rule) in rules.rules.iter_mut().zip(&self.rules) {
+            *uniform = rule.into();
+        }
+
+        rules.rule_count = self.rules.len().min(MAX_PROCEDURAL_RULES) as u32;
+
+        ProceduralMaterialUniform {
+            splat: SplatMaterialUniform::new(
+                &self.layers,
+                self.height_blend,
+                self.projection,
+                self.projection_sharpness,
+            ),
+            rules,
+        }
+    }
+}
+
+impl Material for ProceduralMaterial {
+    fn fragment_shader() -> ShaderRef {
+        PROCEDURAL_MATERIAL_SHADER.typed().into()
+    }
+}
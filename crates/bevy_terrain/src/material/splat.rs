@@ -0,0 +1,132 @@
+use crate::render::shaders::SPLAT_MATERIAL_SHADER;
+use bevy::{
+    prelude::*,
+    reflect::TypeUuid,
+    render::{render_asset::RenderAssets, render_resource::*},
+};
+
+/// The maximal number of detail layers of a [`SplatMaterial`], one per channel of the
+/// splat attachment.
+pub const SPLAT_LAYER_COUNT: usize = 4;
+
+/// The settings of a single detail layer of a [`SplatMaterial`].
+#[derive(Clone, Copy, Debug)]
+pub struct SplatLayer {
+    /// The distance in world units, after which the layer textures repeat.
+    pub tiling: f32,
+    /// The strength of the detail normal map of the layer.
+    pub normal_strength: f32,
+}
+
+impl Default for SplatLayer {
+    fn default() -> Self {
+        Self {
+            tiling: 10.0,
+            normal_strength: 1.0,
+        }
+    }
+}
+
+/// The projection used to map the detail layers onto the terrain.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LayerProjection {
+    /// Projects the layers onto the horizontal plane, which stretches them on steep slopes.
+    #[default]
+    Planar,
+    /// Blends three projections based on the terrain normal.
+    /// Avoids stretching, but requires three samples per texture.
+    Triplanar,
+    /// Blends only the two dominant projections.
+    /// A cheaper alternative to the triplanar projection.
+    Biplanar,
+}
+
+/// The gpu representation of a [`SplatMaterial`].
+#[derive(Clone, Default, ShaderType)]
+pub struct SplatMaterialUniform {
+    pub tiling: Vec4,
+    pub normal_strength: Vec4,
+    pub height_blend: f32,
+    pub projection: u32,
+    pub projection_sharpness: f32,
+}
+
+/// A terrain material, which blends up to four detail layers according to the weights
+/// stored in a splat attachment.
+///
+/// The material expects the base attachment (height, minmax and optionally normal) followed by
+/// the splat attachment, which stores the weights of the layers linearly in its four channels
+/// (e.g. [`AttachmentFormat::Rgba8Linear`](crate::terrain_data::AttachmentFormat::Rgba8Linear)).
+/// The layers are blended based on their heights, which results in more natural transitions.
+/// On steep terrain the layers can be mapped using a triplanar or biplanar [`LayerProjection`].
+#[derive(AsBindGroup, TypeUuid, Clone, Debug)]
+#[uuid = "1d2e8f2c-5a0c-4b1b-9f43-7c6e1b0f3a52"]
+#[uniform(0, SplatMaterialUniform)]
+pub struct SplatMaterial {
+    /// The settings of the detail layers.
+    pub layers: [SplatLayer; SPLAT_LAYER_COUNT],
+    /// The blend depth of the height-based blending. Zero disables it.
+    pub height_blend: f32,
+    /// The projection used to map the layers onto the terrain.
+    pub projection: LayerProjection,
+    /// The sharpness of the transitions between the triplanar/biplanar projections.
+    pub projection_sharpness: f32,
+    /// The array texture containing the albedo (rgb) and the height (a) of each layer.
+    #[texture(1, dimension = "2d_array")]
+    #[sampler(2)]
+    pub albedo_layers: Handle<Image>,
+    /// The array texture containing the tangent space normal map of each layer.
+    #[texture(3, dimension = "2d_array")]
+    pub normal_layers: Handle<Image>,
+}
+
+impl SplatMaterial {
+    /// Creates a new splat material with default layer settings.
+    ///
+    /// Both array textures have to contain one layer per splat channel and should
+    /// use a repeating sampler.
+    pub fn new(albedo_layers: Handle<Image>, normal_layers: Handle<Image>) -> Self {
+        Self {
+            layers: default(),
+            height_blend: 0.2,
+            projection: LayerProjection::Planar,
+            projection_sharpness: 4.0,
+            albedo_layers,
+            normal_layers,
+        }
+    }
+}
+
+impl SplatMaterialUniform {
+    pub(crate) fn new(
+        layers: &[SplatLayer; SPLAT_LAYER_COUNT],
+        height_blend: f32,
+        projection: LayerProjection,
+        projection_sharpness: f32,
+    ) -> Self {
+        Self {
+            tiling: Vec4::from_array(layers.map(|layer| layer.tiling)),
+            normal_strength: Vec4::from_array(layers.map(|layer| layer.normal_strength)),
+            height_blend,
+            projection: projection as u32,
+            projection_sharpness,
+        }
+    }
+}
+
+impl AsBindGroupShaderType<SplatMaterialUniform> for SplatMaterial {
+    fn as_bind_group_shader_type(&self, _images: &RenderAssets<Image>) -> SplatMaterialUniform {
+        SplatMaterialUniform::new(
+            &self.layers,
+            self.height_blend,
+            self.projection,
+            self.projection_sharpness,
+        )
+    }
+}
+
+impl Material for SplatMaterial {
+    fn fragment_shader() -> ShaderRef {
+        SPLAT_MATERIAL_SHADER.typed().into()
+    }
+}
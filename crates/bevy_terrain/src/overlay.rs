@@ -0,0 +1,542 @@
+//! Runtime overlays, which modify the streamed terrain data without rewriting it on disk.
+//!
+//! A [`TerrainOverlay`] stores a sparse set of [`OverlayPatch`]es (e.g. craters, roads or
+//! building pads), that are composited on top of the attachments of each node as soon as it
+//! has finished loading. Because the composited data is never written back, the patches
+//! are applied again, whenever a node is reloaded after it has been evicted from the
+//! [`NodeAtlas`].
+
+use crate::{
+    formats::tdf::generate_mipmaps,
+    terrain::TerrainConfig,
+    terrain_data::{
+        node_atlas::{LoadingState, NodeAtlas},
+        AtlasAttachment, AttachmentFormat, AttachmentIndex, NodeCoordinate, NodeId,
+    },
+};
+use bevy::{
+    asset::HandleId,
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use itertools::iproduct;
+
+/// The operation used to combine the value of an [`OverlayPatch`] with the underlying data.
+#[derive(Clone, Copy, Debug)]
+pub enum OverlayBlend {
+    /// Replaces the data with the patch value.
+    Replace,
+    /// Adds the (possibly negative) patch value to the data.
+    Add,
+    /// Keeps the minimum of the data and the patch value.
+    Min,
+    /// Keeps the maximum of the data and the patch value.
+    Max,
+}
+
+impl OverlayBlend {
+    #[inline]
+    fn apply(self, value: f32, patch: f32) -> f32 {
+        match self {
+            OverlayBlend::Replace => patch,
+            OverlayBlend::Add => value + patch,
+            OverlayBlend::Min => value.min(patch),
+            OverlayBlend::Max => value.max(patch),
+        }
+    }
+}
+
+/// A rectangular patch of data, that is composited onto a single attachment of the terrain.
+///
+/// The `values` are normalized (`0.0..=1.0` corresponds to the full range of the attachment
+/// format) and are stretched bilinearly over the rectangle from `min` to `max`, which is specified
+/// in the local space of the terrain (x and z).
+/// The channels of the attachment map to the components of the values in order.
+#[derive(Clone, Debug)]
+pub struct OverlayPatch {
+    /// The attachment the patch is applied to.
+    pub attachment: AttachmentIndex,
+    /// The minimum corner of the patch.
+    pub min: Vec2,
+    /// The maximum corner of the patch.
+    pub max: Vec2,
+    /// The resolution of the `values` and the `mask`.
+    pub size: UVec2,
+    /// The row major patch values.
+    pub values: Vec<Vec4>,
+    /// The optional row major blend weights of the patch, used to fade it into the surrounding data.
+    pub mask: Option<Vec<f32>>,
+    /// The operation used to combine the patch with the data.
+    pub blend: OverlayBlend,
+}
+
+impl OverlayPatch {
+    /// Creates a new patch without a mask.
+    pub fn new(
+        attachment: AttachmentIndex,
+        min: Vec2,
+        max: Vec2,
+        size: UVec2,
+        values: Vec<Vec4>,
+        blend: OverlayBlend,
+    ) -> Self {
+        assert_eq!(values.len(), (size.x * size.y) as usize);
+
+        Self {
+            attachment,
+            min,
+            max,
+            size,
+            values,
+            mask: None,
+            blend,
+        }
+    }
+
+    /// Fades the patch into the surrounding data using the blend weights of the mask.
+    pub fn with_mask(mut self, mask: Vec<f32>) -> Self {
+        assert_eq!(mask.len(), self.values.len());
+
+        self.mask = Some(mask);
+        self
+    }
+
+    /// Returns the component wise minimum and maximum of all patch values.
+    fn value_range(&self) -> (Vec4, Vec4) {
+        self.values.iter().fold(
+            (Vec4::splat(f32::MAX), Vec4::splat(f32::MIN)),
+            |(min, max), &value| (min.min(value), max.max(value)),
+        )
+    }
+
+    /// Samples the value and the blend weight of the patch at the `uv` coordinate.
+    fn sample(&self, uv: Vec2) -> (Vec4, f32) {
+        let position = uv.clamp(Vec2::ZERO, Vec2::ONE) * (self.size - 1).as_vec2();
+        let start = position.floor().as_uvec2();
+        let end = (start + 1).min(self.size - 1);
+        let t = position.fract();
+
+        let index = |x: u32, y: u32| (y * self.size.x + x) as usize;
+        let bilinear =
+            |a: Vec4, b: Vec4, c: Vec4, d: Vec4| a.lerp(b, t.x).lerp(c.lerp(d, t.x), t.y);
+
+        let indices = [
+            index(start.x, start.y),
+            index(end.x, start.y),
+            index(start.x, end.y),
+            index(end.x, end.y),
+        ];
+
+        let value = bilinear(
+            self.values[indices[0]],
+            self.values[indices[1]],
+            self.values[indices[2]],
+            self.values[indices[3]],
+        );
+
+        let weight = self.mask.as_ref().map_or(1.0, |mask| {
+            bilinear(
+                Vec4::splat(mask[indices[0]]),
+                Vec4::splat(mask[indices[1]]),
+                Vec4::splat(mask[indices[2]]),
+                Vec4::splat(mask[indices[3]]),
+            )
+            .x
+        });
+
+        (value, weight)
+    }
+}
+
+/// Stores the runtime overlay of a terrain.
+///
+/// Patches are composited onto the cpu side attachment data of each node, before the node
+/// is uploaded into the [`GpuNodeAtlas`](crate::terrain_data::gpu_node_atlas::GpuNodeAtlas).
+/// Patches added to the height attachment additionally widen the minmax attachment of the
+/// affected nodes, so that the culling stays conservative.
+#[derive(Component)]
+pub struct TerrainOverlay {
+    /// The patches of the overlay in the order they are applied.
+    patches: Vec<OverlayPatch>,
+    /// The amount of patches, that have already been applied to all resident nodes.
+    applied_patch_count: usize,
+    /// Stores the amount of patches, that have been composited into each node attachment.
+    composited: HashMap<HandleId, usize>,
+    /// Nodes, whose data has been modified and which have to be sent to the gpu again.
+    modified_nodes: HashSet<NodeId>,
+    /// The attachment containing the height data.
+    height_attachment: Option<AttachmentIndex>,
+    /// The attachment containing the minmax data.
+    minmax_attachment: Option<AttachmentIndex>,
+}
+
+impl Default for TerrainOverlay {
+    /// Creates an empty overlay for a terrain with a base attachment.
+    fn default() -> Self {
+        Self::new(Some(0), Some(1))
+    }
+}
+
+impl TerrainOverlay {
+    /// Creates an empty overlay.
+    ///
+    /// * `height_attachment` - The attachment containing the height data.
+    /// * `minmax_attachment` - The attachment containing the minmax data, which is updated when
+    /// the height data is modified.
+    pub fn new(
+        height_attachment: Option<AttachmentIndex>,
+        minmax_attachment: Option<AttachmentIndex>,
+    ) -> Self {
+        Self {
+            patches: default(),
+            applied_patch_count: 0,
+            composited: default(),
+            modified_nodes: default(),
+            height_attachment,
+            minmax_attachment,
+        }
+    }
+
+    /// Adds a patch to the overlay.
+    ///
+    /// It is applied to all loaded nodes in the next frame and to every node loaded afterwards.
+    pub fn add_patch(&mut self, patch: OverlayPatch) {
+        self.patches.push(patch);
+    }
+
+    /// Returns all patches of the overlay.
+    pub fn patches(&self) -> &[OverlayPatch] {
+        &self.patches
+    }
+
+    /// Composites all patches, that have not been applied yet, onto the attachment of the node.
+    ///
+    /// Returns whether the attachment data has been modified.
+    fn composite(
+        &mut self,
+        images: &mut Assets<Image>,
+        config: &TerrainConfig,
+        attachments: &[AtlasAttachment],
+        node_id: NodeId,
+        attachment_index: AttachmentIndex,
+        handle: &Handle<Image>,
+    ) -> bool {
+        let applied = self.composited.entry(handle.id()).or_insert(0);
+
+        if *applied == self.patches.len() {
+            return false;
+        }
+
+        let image = match images.get_mut(handle) {
+            Some(image) => image,
+            None => return false,
+        };
+
+        let start = *applied;
+        *applied = self.patches.len();
+
+        let attachment = &attachments[attachment_index];
+        let is_minmax = self.minmax_attachment == Some(attachment_index);
+        let mut modified = false;
+
+        for patch in &self.patches[start..] {
+            if patch.attachment == attachment_index {
+                modified |= composite_patch(image, config, attachment, node_id, patch);
+            } else if is_minmax && self.height_attachment == Some(patch.attachment) {
+                modified |= widen_minmax(image, config, attachment, node_id, patch);
+            }
+        }
+
+        if modified {
+            let (channel_count, pixel_size) = texel_layout(attachment.format);
+            // the holes of the height data must not be averaged into the coarser mip levels
+            let nodata =
+                config.nodata.is_enabled() && self.height_attachment == Some(attachment_index);
+
+            generate_mipmaps(
+                &mut image.data,
+                image.texture_descriptor.size.width,
+                image.texture_descriptor.mip_level_count,
+                channel_count,
+                pixel_size,
+                nodata,
+            );
+        }
+
+        modified
+    }
+}
+
+/// Returns the channel count and the size of each channel in bytes of the attachment format.
+/// Three channel attachments are stored with an additional alpha channel.
+fn texel_layout(format: AttachmentFormat) -> (u32, u32) {
+    match format {
+        AttachmentFormat::Rgb8 => (4, 1),
+        AttachmentFormat::Rgba8 => (4, 1),
+        AttachmentFormat::Rgba8Linear => (4, 1),
+        AttachmentFormat::R16 => (1, 2),
+        AttachmentFormat::Rg16 => (2, 2),
+        AttachmentFormat::Rg8 => (2, 1),
+        AttachmentFormat::Rgba16 => (4, 2),
+    }
+}
+
+/// Returns the position of the node and the size of its texels in world units.
+fn node_layout(
+    config: &TerrainConfig,
+    attachment: &AtlasAttachment,
+    node_id: NodeId,
+) -> (Vec2, f32) {
+    let coordinate = NodeCoordinate::from(node_id);
+    let node_size = (config.leaf_node_size << coordinate.lod) as f32;
+    let node_position = Vec2::new(coordinate.x as f32, coordinate.y as f32) * node_size;
+
+    (node_position, node_size / attachment.center_size as f32)
+}
+
+/// Returns the texels in the range, clamped to the attachment texture.
+fn texel_range(attachment: &AtlasAttachment, start: Vec2, end: Vec2) -> Vec<UVec2> {
+    let start = start.max(Vec2::ZERO);
+    let end = end.min(Vec2::splat(attachment.texture_size as f32));
+
+    if start.x >= end.x || start.y >= end.y {
+        return Vec::new();
+    }
+
+    let (start, end) = (start.as_uvec2(), end.as_uvec2());
+
+    iproduct!(start.y..end.y, start.x..end.x)
+        .map(|(y, x)| UVec2::new(x, y))
+        .collect()
+}
+
+/// Determines the texels of the node attachment, whose centers are covered by the rectangle and
+/// maps each texel back to its position inside the rectangle (`uv`).
+fn covered_texels(
+    config: &TerrainConfig,
+    attachment: &AtlasAttachment,
+    node_id: NodeId,
+    min: Vec2,
+    max: Vec2,
+) -> Vec<(UVec2, Vec2)> {
+    let (node_position, texel_size) = node_layout(config, attachment, node_id);
+    let border_size = attachment.border_size as f32;
+
+    let to_texel = |position: Vec2| (position - node_position) / texel_size + border_size - 0.5;
+
+    texel_range(
+        attachment,
+        to_texel(min).ceil(),
+        to_texel(max).floor() + 1.0,
+    )
+    .into_iter()
+    .map(|texel| {
+        let position = node_position + (texel.as_vec2() - border_size + 0.5) * texel_size;
+
+        (texel, (position - min) / (max - min))
+    })
+    .collect()
+}
+
+/// Determines the texels of the node attachment, whose footprints intersect the rectangle.
+fn overlapped_texels(
+    config: &TerrainConfig,
+    attachment: &AtlasAttachment,
+    node_id: NodeId,
+    min: Vec2,
+    max: Vec2,
+) -> Vec<UVec2> {
+    let (node_position, texel_size) = node_layout(config, attachment, node_id);
+    let border_size = attachment.border_size as f32;
+
+    // the footprint of each texel spans from its coordinate to the next one
+    let to_texel = |position: Vec2| (position - node_position) / texel_size + border_size;
+
+    texel_range(attachment, to_texel(min).floor(), to_texel(max).ceil())
+}
+
+fn read_channel(data: &[u8], index: usize, pixel_size: u32) -> f32 {
+    match pixel_size {
+        2 => u16::from_le_bytes([data[index], data[index + 1]]) as f32 / u16::MAX as f32,
+        _ => data[index] as f32 / u8::MAX as f32,
+    }
+}
+
+fn write_channel(data: &mut [u8], index: usize, pixel_size: u32, value: f32) {
+    let value = value.clamp(0.0, 1.0);
+
+    match pixel_size {
+        2 => data[index..index + 2]
+            .copy_from_slice(&((value * u16::MAX as f32).round() as u16).to_le_bytes()),
+        _ => data[index] = (value * u8::MAX as f32).round() as u8,
+    }
+}
+
+/// Blends the patch onto the first mip level of the attachment image.
+fn composite_patch(
+    image: &mut Image,
+    config: &TerrainConfig,
+    attachment: &AtlasAttachment,
+    node_id: NodeId,
+    patch: &OverlayPatch,
+) -> bool {
+    let texels = covered_texels(config, attachment, node_id, patch.min, patch.max);
+    let (channel_count, pixel_size) = texel_layout(attachment.format);
+    let width = image.texture_descriptor.size.width;
+
+    let mut modified = false;
+
+    for (texel, uv) in texels {
+        let (value, weight) = patch.sample(uv);
+
+        for channel in 0..channel_count {
+            let index =
+                (((texel.y * width + texel.x) * channel_count + channel) * pixel_size) as usize;
+
+            let data = read_channel(&image.data, index, pixel_size);
+            let blended = patch.blend.apply(data, value[channel as usize]);
+
+            let value = data + (blended - data) * weight;
+
+            write_channel(&mut image.data, index, pixel_size, value);
+        }
+
+        modified = true;
+    }
+
+    modified
+}
+
+/// Widens the minmax attachment image, so that it contains all heights the patch may produce.
+///
+/// This is a conservative approximation, because the exact heights of the underlying
+/// higher resolution data are not available. Therefore, every texel partially covered
+/// by the patch is widened, even if its center lies outside of it.
+fn widen_minmax(
+    image: &mut Image,
+    config: &TerrainConfig,
+    attachment: &AtlasAttachment,
+    node_id: NodeId,
+    patch: &OverlayPatch,
+) -> bool {
+    let texels = overlapped_texels(config, attachment, node_id, patch.min, patch.max);
+    let (value_min, value_max) = patch.value_range();
+    let width = image.texture_descriptor.size.width;
+
+    let mut modified = false;
+
+    for texel in texels {
+        let index = ((texel.y * width + texel.x) * 4) as usize;
+
+        let min = read_channel(&image.data, index, 2);
+        let max = read_channel(&image.data, index + 2, 2);
+
+        let min = min.min(patch.blend.apply(min, value_min.x));
+        let max = max.max(patch.blend.apply(max, value_max.x));
+
+        write_channel(&mut image.data, index, 2, min);
+        write_channel(&mut image.data, index + 2, 2, max);
+
+        modified = true;
+    }
+
+    modified
+}
+
+/// Composites the overlay patches onto the attachments of all nodes, that have finished loading
+/// this frame, as well as onto the already loaded nodes affected by newly added patches.
+pub(crate) fn apply_terrain_overlays(
+    mut asset_events: EventReader<AssetEvent<Image>>,
+    mut images: ResMut<Assets<Image>>,
+    mut terrain_query: Query<(&mut NodeAtlas, &mut TerrainOverlay, &TerrainConfig)>,
+) {
+    let removed: Vec<HandleId> = asset_events
+        .iter()
+        .filter_map(|event| match event {
+            AssetEvent::Removed { handle } => Some(handle.id()),
+            _ => None,
+        })
+        .collect();
+
+    for (mut node_atlas, mut overlay, config) in terrain_query.iter_mut() {
+        // unloaded images have lost their composited data
+        for id in &removed {
+            overlay.composited.remove(id);
+        }
+
+        // the modified images of the last frame have been synced to the gpu by now
+        for node_id in overlay.modified_nodes.drain().collect::<Vec<_>>() {
+            node_atlas.reupload_node(node_id);
+        }
+
+        if overlay.patches.is_empty() {
+            continue;
+        }
+
+        let NodeAtlas {
+            ref attachments,
+            ref loading_nodes,
+            ref nodes,
+            ref data,
+            ..
+        } = node_atlas.as_ref();
+
+        // composite the patches onto the nodes that are about to be uploaded
+        for (&node_id, loading_node) in loading_nodes {
+            if !loading_node.finished_loading() {
+                continue;
+            }
+
+            let mut modified = false;
+
+            for (&attachment_index, handle) in &loading_node.attachments {
+                modified |= overlay.composite(
+                    &mut images,
+                    config,
+                    attachments,
+                    node_id,
+                    attachment_index,
+                    handle,
+                );
+            }
+
+            // images that were still loaded from a previous request are already present
+            // in the render world, thus the node has to be updated again in the next frame
+            if modified {
+                overlay.modified_nodes.insert(node_id);
+            }
+        }
+
+        if overlay.applied_patch_count == overlay.patches.len() {
+            continue;
+        }
+
+        // composite new patches onto the nodes that are already resident
+        for (&node_id, node) in nodes {
+            if node.state != LoadingState::Loaded {
+                continue;
+            }
+
+            let mut modified = false;
+
+            for (&attachment_index, handle) in &data[node.atlas_index as usize]._attachments {
+                modified |= overlay.composite(
+                    &mut images,
+                    config,
+                    attachments,
+                    node_id,
+                    attachment_index,
+                    handle,
+                );
+            }
+
+            // the image is extracted to the render world in the next frame,
+            // the node has to be updated afterwards
+            if modified {
+                overlay.modified_nodes.insert(node_id);
+            }
+        }
+
+        overlay.applied_patch_count = overlay.patches.len();
+    }
+}
@@ -0,0 +1,300 @@
+//! Generates height-field colliders from the streamed terrain data.
+//!
+//! Each terrain with a [`TerrainColliders`] component spawns one collider entity per lod 0 node
+//! around every [`PhysicsFocus`]. The colliders are built from the cpu side height data of the
+//! best currently loaded node in the [`NodeAtlas`] and are rebuilt as soon as better data
+//! is streamed in or the data is evicted.
+//!
+//! The colliders are described by the backend agnostic [`TerrainHeightField`] component.
+//! Enable the `rapier` feature to automatically convert them into `bevy_rapier3d` colliders.
+
+#[cfg(feature = "rapier")]
+pub mod rapier;
+
+use crate::{
+    skip_none,
+    terrain::TerrainConfig,
+    terrain_data::{
+        calc_node_id,
+        node_atlas::{update_node_atlas, LoadingState, NodeAtlas},
+        AtlasIndex, AttachmentIndex, NodeCoordinate, NodeId,
+    },
+};
+use bevy::{
+    math::Vec3Swizzles,
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+/// Marks an entity (e.g. the player or a vehicle) around which terrain colliders are generated.
+#[derive(Clone, Copy, Component)]
+pub struct PhysicsFocus {
+    /// The radius around the entity, in which colliders are generated.
+    pub radius: f32,
+}
+
+/// A backend agnostic description of a height-field collider.
+///
+/// The entity holding this component is placed at the center of the height field.
+#[derive(Clone, Component)]
+pub struct TerrainHeightField {
+    /// The row major (x first, then z) normalized heights of the grid.
+    pub heights: Vec<f32>,
+    /// The number of samples in x and z direction.
+    pub size: UVec2,
+    /// The distance between two samples in x and z direction and the scale of the heights.
+    pub scale: Vec3,
+    /// The position of the first sample relative to the terrain.
+    pub origin: Vec3,
+}
+
+impl TerrainHeightField {
+    /// Returns the size of the height field in world units.
+    pub fn extent(&self) -> Vec3 {
+        Vec3::new(
+            (self.size.x - 1) as f32 * self.scale.x,
+            self.scale.y,
+            (self.size.y - 1) as f32 * self.scale.z,
+        )
+    }
+
+    /// Returns the position of the center of the height field relative to the terrain.
+    pub fn center(&self) -> Vec3 {
+        let extent = self.extent();
+
+        self.origin + Vec3::new(extent.x / 2.0, 0.0, extent.z / 2.0)
+    }
+}
+
+/// A collider entity of a terrain, together with the node its data originates from.
+struct TerrainCollider {
+    entity: Entity,
+    source: NodeId,
+}
+
+/// Generates the height-field colliders of the terrain.
+///
+/// Stores the collider entities, which are spawned as children of the terrain.
+#[derive(Component)]
+pub struct TerrainColliders {
+    /// The attachment containing the height data.
+    height_attachment: AttachmentIndex,
+    colliders: HashMap<NodeId, TerrainCollider>,
+}
+
+impl Default for TerrainColliders {
+    /// Generates the colliders from the height data of the base attachment.
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl TerrainColliders {
+    /// Generates the colliders from the height data of the `height_attachment`.
+    pub fn new(height_attachment: AttachmentIndex) -> Self {
+        Self {
+            height_attachment,
+            colliders: default(),
+        }
+    }
+}
+
+/// Searches the best currently loaded node, which contains the data of the lod 0 node.
+fn best_loaded_node(
+    node_atlas: &NodeAtlas,
+    lod_count: u32,
+    x: u32,
+    y: u32,
+) -> Option<(NodeId, AtlasIndex)> {
+    (0..lod_count).find_map(|lod| {
+        let node_id = calc_node_id(lod, x >> lod, y >> lod);
+
+        node_atlas
+            .nodes
+            .get(&node_id)
+            .filter(|node| node.state == LoadingState::Loaded)
+            .map(|node| (node_id, node.atlas_index))
+    })
+}
+
+/// Builds the height field of the lod 0 node at (`x`, `y`) from the data of the source node.
+fn build_height_field(
+    config: &TerrainConfig,
+    node_atlas: &NodeAtlas,
+    images: &Assets<Image>,
+    height_attachment: AttachmentIndex,
+    (x, y): (u32, u32),
+    atlas_index: AtlasIndex,
+    source: NodeId,
+) -> Option<TerrainHeightField> {
+    let attachment = &node_atlas.attachments[height_attachment];
+    let handle = node_atlas.data[atlas_index as usize]
+        ._attachments
+        .get(&height_attachment)?;
+    let image = images.get(handle)?;
+
+    let lod = NodeCoordinate::from(source).lod;
+    let center_size = (attachment.center_size >> lod).max(1);
+    let sample_count = center_size + 1;
+
+    // offset of the lod 0 node inside the source node
+    let offset = UVec2::new(x % (1 << lod), y % (1 << lod)) * center_size + attachment.border_size;
+
+    let heights = (0..sample_count)
+        .flat_map(|j| (0..sample_count).map(move |i| (i, j)))
+        .map(|(i, j)| {
+            let position = offset + UVec2::new(i, j);
+            let index = 2 * (position.y * attachment.texture_size + position.x) as usize;
+            let height = u16::from_le_bytes([image.data[index], image.data[index + 1]]);
+
+            height as f32 / u16::MAX as f32
+        })
+        .collect();
+
+    let spacing = config.leaf_node_size as f32 / center_size as f32;
+
+    Some(TerrainHeightField {
+        heights,
+        size: UVec2::splat(sample_count),
+        scale: Vec3::new(spacing, config.height, spacing),
+        origin: Vec3::new(
+            (x as f32 + 0.5 / center_size as f32) * config.leaf_node_size as f32,
+            0.0,
+            (y as f32 + 0.5 / center_size as f32) * config.leaf_node_size as f32,
+        ),
+    })
+}
+
+/// Spawns, updates and despawns the collider entities of all terrains,
+/// depending on the physics foci and the loaded nodes.
+pub(crate) fn update_terrain_colliders(
+    mut commands: Commands,
+    images: Res<Assets<Image>>,
+    focus_query: Query<(&GlobalTransform, &PhysicsFocus)>,
+    mut terrain_query: Query<(
+        Entity,
+        &GlobalTransform,
+        &TerrainConfig,
+        &NodeAtlas,
+        &mut TerrainColliders,
+    )>,
+) {
+    for (terrain, terrain_transform, config, node_atlas, mut colliders) in terrain_query.iter_mut()
+    {
+        let inverse_transform = terrain_transform.compute_matrix().inverse();
+        let leaf_node_size = config.leaf_node_size as f32;
+        let node_count = (config.terrain_size / config.leaf_node_size) as i32;
+
+        let mut demanded_nodes = HashSet::new();
+
+        for (focus_transform, focus) in focus_query.iter() {
+            let position = inverse_transform.transform_point3(focus_transform.translation());
+
+            let start = ((position.xz() - focus.radius) / leaf_node_size)
+                .floor()
+                .as_ivec2()
+                .max(IVec2::ZERO);
+            let end = ((position.xz() + focus.radius) / leaf_node_size)
+                .floor()
+                .as_ivec2()
+                .min(IVec2::splat(node_count - 1));
+
+            for y in start.y..=end.y {
+                for x in start.x..=end.x {
+                    demanded_nodes.insert((x as u32, y as u32));
+                }
+            }
+        }
+
+        let TerrainColliders {
+            height_attachment,
+            ref mut colliders,
+        } = colliders.as_mut();
+
+        // despawn the colliders, that are no longer demanded
+        colliders.retain(|&node_id, collider| {
+            let coordinate = NodeCoordinate::from(node_id);
+            let demanded = demanded_nodes.contains(&(coordinate.x, coordinate.y));
+
+            if !demanded {
+                commands.entity(collider.entity).despawn_recursive();
+            }
+
+            demanded
+        });
+
+        for (x, y) in demanded_nodes {
+            let node_id = calc_node_id(0, x, y);
+            let best_node = best_loaded_node(node_atlas, config.lod_count, x, y);
+
+            let collider = colliders
+                .get(&node_id)
+                .map(|collider| (collider.entity, collider.source));
+
+            match (collider, best_node) {
+                // the collider is up to date
+                (Some((_, current)), Some((source, _))) if current == source => {}
+                // no data is available
+                (collider, None) => {
+                    if let Some((entity, _)) = collider {
+                        commands.entity(entity).despawn_recursive();
+                        colliders.remove(&node_id);
+                    }
+                }
+                // better data is available or the data has been evicted
+                (collider, Some((source, atlas_index))) => {
+                    let height_field = skip_none!(build_height_field(
+                        config,
+                        node_atlas,
+                        &images,
+                        *height_attachment,
+                        (x, y),
+                        atlas_index,
+                        source,
+                    ));
+
+                    let transform = Transform::from_translation(height_field.center());
+
+                    let entity = match collider {
+                        Some((entity, _)) => {
+                            commands.entity(entity).insert((height_field, transform));
+                            entity
+                        }
+                        None => {
+                            let entity = commands
+                                .spawn((
+                                    Name::new(format!("Terrain Collider {x} {y}")),
+                                    height_field,
+                                    TransformBundle::from_transform(transform),
+                                ))
+                                .id();
+                            commands.entity(terrain).add_child(entity);
+                            entity
+                        }
+                    };
+
+                    colliders.insert(node_id, TerrainCollider { entity, source });
+                }
+            }
+        }
+    }
+}
+
+/// Generates height-field colliders around all [`PhysicsFocus`] entities for all terrains
+/// with a [`TerrainColliders`] component.
+pub struct TerrainPhysicsPlugin;
+
+impl Plugin for TerrainPhysicsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_to_stage(
+            CoreStage::Last,
+            update_terrain_colliders.after(update_node_atlas),
+        );
+
+        #[cfg(feature = "rapier")]
+        app.add_system_to_stage(
+            CoreStage::Last,
+            rapier::insert_rapier_colliders.after(update_terrain_colliders),
+        );
+    }
+}
@@ -0,0 +1,35 @@
+//! Converts the [`TerrainHeightField`]s into `bevy_rapier3d` colliders.
+
+use crate::physics::TerrainHeightField;
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+impl TerrainHeightField {
+    /// Creates a rapier height-field collider, which is centered at the height field.
+    pub fn rapier_collider(&self) -> Collider {
+        let (columns, rows) = (self.size.x as usize, self.size.y as usize);
+
+        // rapier expects the heights in column major order, with the rows along the z axis
+        let heights = (0..columns)
+            .flat_map(|column| (0..rows).map(move |row| (row, column)))
+            .map(|(row, column)| self.heights[row * columns + column] * self.scale.y)
+            .collect();
+
+        let mut scale = self.extent();
+        scale.y = 1.0;
+
+        Collider::heightfield(heights, rows, columns, scale)
+    }
+}
+
+/// Inserts a fixed rapier collider into all new or modified height-field entities.
+pub(crate) fn insert_rapier_colliders(
+    mut commands: Commands,
+    height_field_query: Query<(Entity, &TerrainHeightField), Changed<TerrainHeightField>>,
+) {
+    for (entity, height_field) in height_field_query.iter() {
+        commands
+            .entity(entity)
+            .insert((RigidBody::Fixed, height_field.rapier_collider()));
+    }
+}
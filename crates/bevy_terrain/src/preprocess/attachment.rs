@@ -1,24 +1,32 @@
 use crate::{
     preprocess::{
-        down_sample::{down_sample_layer, linear, minmax},
+        down_sample::{down_sample_layer, encode_normal, linear, linear_nodata, minmax, normal},
+        fallback::{merge_fallback, FallbackConfig},
         file_io::{
             format_directory, format_node_path, iterate_directory, load_image, reset_directory,
             save_image,
         },
+        nodata::{Nodata, NODATA},
+        occlusion::bake_occlusion,
         split::split_tiles,
         stitch::stitch_layer,
         BaseConfig, TileConfig, UVec2Utils,
     },
     terrain_data::{AttachmentConfig, NodeCoordinate, NodeId},
+    virtual_texture::VirtualTextureConfig,
     TerrainConfig,
 };
+use bevy::prelude::*;
 use image::{DynamicImage, ImageBuffer, LumaA};
 
+/// Generates the minmax information of the first lod from the height data.
+/// If nodata is enabled, texels without data store an empty interval.
 fn height_to_minmax(
     height_directory: &str,
     minmax_directory: &str,
     height_attachment: &AttachmentConfig,
     minmax_attachment: &AttachmentConfig,
+    nodata: Nodata,
 ) {
     for (height_name, height_path) in iterate_directory(height_directory) {
         let coord = NodeCoordinate::from(height_name.parse::<NodeId>().unwrap());
@@ -38,7 +46,11 @@ fn height_to_minmax(
             |x, y| {
                 let value = height_image.get_pixel(x, y).0[0];
 
-                LumaA([value, value])
+                if nodata.is_enabled() && value == NODATA {
+                    LumaA([u16::MAX, u16::MIN])
+                } else {
+                    LumaA([value, value])
+                }
             },
         ));
 
@@ -46,7 +58,71 @@ fn height_to_minmax(
     }
 }
 
-pub(crate) fn preprocess_base(config: &TerrainConfig, tile: &TileConfig, base: &BaseConfig) {
+/// Generates the normals of the first lod from the height data.
+/// The borders are filled in afterwards by stitching the layer.
+/// If nodata is enabled, neighbours without data are replaced by the center texel.
+fn height_to_normal(
+    config: &TerrainConfig,
+    height_directory: &str,
+    normal_directory: &str,
+    height_attachment: &AttachmentConfig,
+    normal_attachment: &AttachmentConfig,
+    nodata: Nodata,
+) {
+    for (height_name, height_path) in iterate_directory(height_directory) {
+        let coord = NodeCoordinate::from(height_name.parse::<NodeId>().unwrap());
+
+        if coord.lod != 0 {
+            continue;
+        }
+
+        let normal_path = format_node_path(normal_directory, coord.lod, coord.x, coord.y);
+
+        let height_image = load_image(&height_path, height_attachment.file_format).unwrap();
+        let height_image = height_image.as_luma16().unwrap();
+
+        let size = height_image.width() as i32;
+        let texel = |x: i32, y: i32| {
+            height_image
+                .get_pixel(x.clamp(0, size - 1) as u32, y.clamp(0, size - 1) as u32)
+                .0[0]
+        };
+        let height = |x: i32, y: i32, center: (i32, i32)| {
+            let value = match texel(x, y) {
+                NODATA if nodata.is_enabled() => texel(center.0, center.1),
+                value => value,
+            };
+
+            value as f32 / u16::MAX as f32 * config.height
+        };
+
+        let normal_image = DynamicImage::from(ImageBuffer::from_fn(
+            height_image.width(),
+            height_image.height(),
+            |x, y| {
+                let (x, y) = (x as i32, y as i32);
+
+                // central differences, the distance between two samples is one world unit
+                let normal = Vec3::new(
+                    height(x - 1, y, (x, y)) - height(x + 1, y, (x, y)),
+                    2.0,
+                    height(x, y - 1, (x, y)) - height(x, y + 1, (x, y)),
+                );
+
+                encode_normal(normal.normalize())
+            },
+        ));
+
+        save_image(&normal_path, &normal_image, normal_attachment);
+    }
+}
+
+pub(crate) fn preprocess_base(
+    config: &TerrainConfig,
+    tile: &TileConfig,
+    base: &BaseConfig,
+    fallbacks: &[FallbackConfig],
+) {
     let height_attachment = base.height_attachment();
     let minmax_attachment = base.minmax_attachment();
 
@@ -56,7 +132,30 @@ pub(crate) fn preprocess_base(config: &TerrainConfig, tile: &TileConfig, base: &
     reset_directory(&height_directory);
     reset_directory(&minmax_directory);
 
-    let temp = split_tiles(&height_directory, tile, &height_attachment);
+    let mut temp = split_tiles(&height_directory, tile, &height_attachment);
+
+    if base.nodata == Nodata::Fallback {
+        if fallbacks.is_empty() {
+            warn!("The fallback nodata handling has no fallback sources, thus the holes remain.");
+        }
+
+        for fallback in fallbacks {
+            temp = merge_fallback(
+                config,
+                &height_directory,
+                &height_attachment,
+                fallback,
+                temp.0,
+                temp.1,
+            );
+        }
+    }
+
+    let height_filter = if base.nodata.is_enabled() {
+        linear_nodata
+    } else {
+        linear
+    };
 
     let (mut first, mut last) = temp;
 
@@ -65,7 +164,7 @@ pub(crate) fn preprocess_base(config: &TerrainConfig, tile: &TileConfig, base: &
         last = last.div_ceil(2);
 
         down_sample_layer(
-            linear,
+            height_filter,
             &height_directory,
             &height_attachment,
             lod,
@@ -80,6 +179,7 @@ pub(crate) fn preprocess_base(config: &TerrainConfig, tile: &TileConfig, base: &
         &minmax_directory,
         &height_attachment,
         &minmax_attachment,
+        base.nodata,
     );
 
     let (mut first, mut last) = temp;
@@ -98,12 +198,54 @@ pub(crate) fn preprocess_base(config: &TerrainConfig, tile: &TileConfig, base: &
         );
         stitch_layer(&minmax_directory, &minmax_attachment, lod, first, last);
     }
+
+    if base.normals {
+        let normal_attachment = base.normal_attachment();
+        let normal_directory = format_directory(&config.path, "normal");
+
+        reset_directory(&normal_directory);
+
+        height_to_normal(
+            config,
+            &height_directory,
+            &normal_directory,
+            &height_attachment,
+            &normal_attachment,
+            base.nodata,
+        );
+
+        let (mut first, mut last) = temp;
+
+        stitch_layer(&normal_directory, &normal_attachment, 0, first, last);
+
+        for lod in 1..config.lod_count {
+            first = first.div_floor(2);
+            last = last.div_ceil(2);
+
+            down_sample_layer(
+                normal,
+                &normal_directory,
+                &normal_attachment,
+                lod,
+                first,
+                last,
+            );
+            stitch_layer(&normal_directory, &normal_attachment, lod, first, last);
+        }
+    }
+
+    if let Some(occlusion) = &base.occlusion {
+        bake_occlusion(config, base, occlusion, temp.0, temp.1);
+    }
 }
 
-pub(crate) fn preprocess_attachment(
+/// Splits the tiles into the finest lod of the attachment and down samples them
+/// up to its coarsest lod.
+fn preprocess_layers(
     config: &TerrainConfig,
     tile: &TileConfig,
     attachment: &AttachmentConfig,
+    lod_count: u32,
 ) {
     let directory = format_directory(&config.path, &attachment.name);
 
@@ -111,7 +253,7 @@ pub(crate) fn preprocess_attachment(
 
     let (mut first, mut last) = split_tiles(&directory, tile, attachment);
 
-    for lod in 1..config.lod_count {
+    for lod in attachment.min_lod + 1..lod_count.min(attachment.max_lod.saturating_add(1)) {
         first = first.div_floor(2);
         last = last.div_ceil(2);
 
@@ -119,3 +261,26 @@ pub(crate) fn preprocess_attachment(
         stitch_layer(&directory, attachment, lod, first, last);
     }
 }
+
+pub(crate) fn preprocess_attachment(
+    config: &TerrainConfig,
+    tile: &TileConfig,
+    attachment: &AttachmentConfig,
+) {
+    preprocess_layers(config, tile, attachment, config.lod_count);
+}
+
+/// The pages of a virtual texture are processed like an attachment, but with their own
+/// size and lod count.
+pub(crate) fn preprocess_virtual_texture(
+    config: &TerrainConfig,
+    tile: &TileConfig,
+    virtual_texture: &VirtualTextureConfig,
+) {
+    preprocess_layers(
+        config,
+        tile,
+        &virtual_texture.attachment(),
+        virtual_texture.lod_count,
+    );
+}
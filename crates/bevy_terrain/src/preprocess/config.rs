@@ -1,14 +1,26 @@
 use crate::{
-    formats::tc::TC,
+    formats::tc::{TCAttachment, TC},
     preprocess::file_io::{format_directory, iterate_directory},
     terrain_data::NodeId,
     TerrainConfig,
 };
 
 /// Saves. s the node configuration of the terrain, which stores the [`NodeId`]s of all the nodes
-/// of the terrain.
+/// of the terrain and the border sizes of its attachments.
 pub fn save_config(config: &TerrainConfig) {
-    let mut tc = TC { nodes: vec![] };
+    let attachments = config
+        .attachments
+        .iter()
+        .map(|attachment| TCAttachment {
+            name: attachment.name.clone(),
+            border_size: attachment.border_size,
+        })
+        .collect();
+
+    let mut tc = TC {
+        nodes: vec![],
+        attachments,
+    };
     let attachment_directory = format_directory(&config.path, &config.attachments[0].name);
 
     for (name, _) in iterate_directory(&attachment_directory) {
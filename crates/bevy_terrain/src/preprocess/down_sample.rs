@@ -1,6 +1,7 @@
 use crate::{
     preprocess::{
         file_io::{format_node_path, load_image, load_or_create_node, save_image},
+        nodata::NODATA,
         UVec2Utils,
     },
     skip_none,
@@ -34,6 +35,16 @@ impl AveragePixel for Rgba<u8> {
     }
 }
 
+impl AveragePixel for Rgba<u16> {
+    fn average(a: Self, b: Self, c: Self, d: Self) -> Self {
+        let mut value = Rgba([0; 4]);
+        izip!(&mut value.0, &a.0, &b.0, &c.0, &d.0).for_each(|(out, &a, &b, &c, &d)| {
+            *out = ((a as f32 + b as f32 + c as f32 + d as f32) / 4.0) as u16
+        });
+        value
+    }
+}
+
 impl AveragePixel for Luma<u16> {
     fn average(a: Self, b: Self, c: Self, d: Self) -> Self {
         let mut value = Luma([0; 1]);
@@ -44,6 +55,16 @@ impl AveragePixel for Luma<u16> {
     }
 }
 
+impl AveragePixel for LumaA<u8> {
+    fn average(a: Self, b: Self, c: Self, d: Self) -> Self {
+        let mut value = LumaA([0; 2]);
+        izip!(&mut value.0, &a.0, &b.0, &c.0, &d.0).for_each(|(out, &a, &b, &c, &d)| {
+            *out = ((a as f32 + b as f32 + c as f32 + d as f32) / 4.0) as u8
+        });
+        value
+    }
+}
+
 impl AveragePixel for LumaA<u16> {
     fn average(a: Self, b: Self, c: Self, d: Self) -> Self {
         let mut value = LumaA([0; 2]);
@@ -104,7 +125,7 @@ pub(crate) fn linear(
                 attachment.border_size,
             );
         }
-        AttachmentFormat::Rgba8 => {
+        AttachmentFormat::Rgba8 | AttachmentFormat::Rgba8Linear => {
             imageops_linear(
                 parent_image.as_mut_rgba8().unwrap(),
                 child_image.as_rgba8().unwrap(),
@@ -134,9 +155,73 @@ pub(crate) fn linear(
                 attachment.border_size,
             );
         }
+        AttachmentFormat::Rg8 => {
+            imageops_linear(
+                parent_image.as_mut_luma_alpha8().unwrap(),
+                child_image.as_luma_alpha8().unwrap(),
+                child_size,
+                node_x,
+                node_y,
+                attachment.border_size,
+            );
+        }
+        AttachmentFormat::Rgba16 => {
+            imageops_linear(
+                parent_image.as_mut_rgba16().unwrap(),
+                child_image.as_rgba16().unwrap(),
+                child_size,
+                node_x,
+                node_y,
+                attachment.border_size,
+            );
+        }
     }
 }
 
+/// Averages the height data of the child node, excluding texels without data.
+/// Only if all four texels have no data, the parent texel has no data either.
+pub(crate) fn linear_nodata(
+    parent_image: &mut DynamicImage,
+    child_image: &DynamicImage,
+    attachment: &AttachmentConfig,
+    offset: UVec2,
+) {
+    let parent_image = parent_image.as_mut_luma16().unwrap();
+    let child_image = child_image.as_luma16().unwrap();
+
+    let child_size = attachment.center_size >> 1;
+
+    let node_x = offset.x * child_size + attachment.border_size;
+    let node_y = offset.y * child_size + attachment.border_size;
+
+    for (x, y) in iproduct!(0..child_size, 0..child_size) {
+        let mut sum = 0.0;
+        let mut count = 0;
+
+        for (cx, cy) in iproduct!(0..2, 0..2) {
+            let Luma([value]) = *child_image.get_pixel(
+                (x << 1) + cx + attachment.border_size,
+                (y << 1) + cy + attachment.border_size,
+            );
+
+            if value != NODATA {
+                sum += value as f32;
+                count += 1;
+            }
+        }
+
+        let value = if count == 0 {
+            NODATA
+        } else {
+            ((sum / count as f32) as u16).min(NODATA - 1)
+        };
+
+        parent_image.put_pixel(node_x + x, node_y + y, Luma([value]));
+    }
+}
+
+/// Combines the minmax information of the child node.
+/// Texels without data store an empty interval (min > max), which never widens the result.
 pub(crate) fn minmax(
     parent_image: &mut DynamicImage,
     child_image: &DynamicImage,
@@ -171,6 +256,117 @@ pub(crate) fn minmax(
     }
 }
 
+/// Encodes a unit normal into two channels using the octahedral mapping.
+pub(crate) fn encode_normal(normal: Vec3) -> LumaA<u8> {
+    let mut encoded =
+        Vec2::new(normal.x, normal.z) / (normal.x.abs() + normal.y.abs() + normal.z.abs());
+
+    if normal.y < 0.0 {
+        encoded = (1.0 - Vec2::new(encoded.y, encoded.x).abs()) * encoded.signum();
+    }
+
+    let encoded = (encoded * 0.5 + 0.5) * u8::MAX as f32;
+
+    LumaA([encoded.x.round() as u8, encoded.y.round() as u8])
+}
+
+/// Decodes a unit normal stored using the octahedral mapping.
+pub(crate) fn decode_normal(encoded: LumaA<u8>) -> Vec3 {
+    let encoded = Vec2::new(encoded.0[0] as f32, encoded.0[1] as f32) / u8::MAX as f32 * 2.0 - 1.0;
+
+    let mut normal = Vec3::new(
+        encoded.x,
+        1.0 - encoded.x.abs() - encoded.y.abs(),
+        encoded.y,
+    );
+    let t = (-normal.y).max(0.0);
+    normal.x += if normal.x >= 0.0 { -t } else { t };
+    normal.z += if normal.z >= 0.0 { -t } else { t };
+
+    normal.normalize()
+}
+
+/// Averages the normals of the child node, instead of their octahedral encodings,
+/// which would not yield valid normals.
+pub(crate) fn normal(
+    parent_image: &mut DynamicImage,
+    child_image: &DynamicImage,
+    attachment: &AttachmentConfig,
+    offset: UVec2,
+) {
+    let parent_image = parent_image.as_mut_luma_alpha8().unwrap();
+    let child_image = child_image.as_luma_alpha8().unwrap();
+
+    let child_size = attachment.center_size >> 1;
+
+    let node_x = offset.x * child_size + attachment.border_size;
+    let node_y = offset.y * child_size + attachment.border_size;
+
+    for (x, y) in iproduct!(0..child_size, 0..child_size) {
+        let mut normal = Vec3::ZERO;
+
+        for (cx, cy) in iproduct!(0..2, 0..2) {
+            normal += decode_normal(*child_image.get_pixel(
+                (x << 1) + cx + attachment.border_size,
+                (y << 1) + cy + attachment.border_size,
+            ));
+        }
+
+        let value = encode_normal(normal.try_normalize().unwrap_or(Vec3::Y));
+        parent_image.put_pixel(node_x + x, node_y + y, value);
+    }
+}
+
+/// Combines the water information of the child node.
+/// The water flag is averaged into the coverage of the texel, while the water level is
+/// only averaged over the wet texels, so that the dry terrain heights do not lower it.
+/// Completely dry texels keep the average terrain height.
+pub(crate) fn water(
+    parent_image: &mut DynamicImage,
+    child_image: &DynamicImage,
+    attachment: &AttachmentConfig,
+    offset: UVec2,
+) {
+    let parent_image = parent_image.as_mut_luma_alpha16().unwrap();
+    let child_image = child_image.as_luma_alpha16().unwrap();
+
+    let child_size = attachment.center_size >> 1;
+
+    let node_x = offset.x * child_size + attachment.border_size;
+    let node_y = offset.y * child_size + attachment.border_size;
+
+    for (x, y) in iproduct!(0..child_size, 0..child_size) {
+        let mut coverage = 0.0;
+        let mut wet_level = 0.0;
+        let mut dry_level = 0.0;
+        let mut wet_count = 0;
+
+        for (cx, cy) in iproduct!(0..2, 0..2) {
+            let LumaA([flag, level]) = *child_image.get_pixel(
+                (x << 1) + cx + attachment.border_size,
+                (y << 1) + cy + attachment.border_size,
+            );
+
+            coverage += flag as f32;
+            dry_level += level as f32;
+
+            if flag != 0 {
+                wet_level += level as f32;
+                wet_count += 1;
+            }
+        }
+
+        let level = if wet_count == 0 {
+            dry_level / 4.0
+        } else {
+            wet_level / wet_count as f32
+        };
+
+        let value = LumaA([(coverage / 4.0) as u16, level as u16]);
+        parent_image.put_pixel(node_x + x, node_y + y, value);
+    }
+}
+
 pub(crate) fn down_sample_layer(
     filter: Filter,
     directory: &str,
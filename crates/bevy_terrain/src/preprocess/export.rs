@@ -0,0 +1,631 @@
+//! Exports regions of the preprocessed terrain data into standard mesh formats (OBJ and glTF)
+//! and georeferenced rasters (GeoTIFF and PNG).
+
+use crate::{
+    preprocess::{
+        file_io::{format_directory, format_node_path, load_image},
+        BaseConfig, R16Image, Rgb8Image,
+    },
+    terrain_data::{calc_node_id, AttachmentConfig, AttachmentFormat, NodeId},
+    TerrainConfig,
+};
+use anyhow::{anyhow, bail, ensure, Result};
+use bevy::{math::DVec2, prelude::*, utils::HashMap};
+use image::{DynamicImage, GenericImageView, ImageBuffer, ImageOutputFormat, Pixel};
+use std::{
+    fs,
+    io::{BufWriter, Cursor, Write},
+    path::Path,
+};
+use tiff::{
+    encoder::{colortype, colortype::ColorType, TiffEncoder, TiffValue},
+    tags::Tag,
+};
+
+const MODEL_PIXEL_SCALE_TAG: u16 = 33550;
+const MODEL_TIEPOINT_TAG: u16 = 33922;
+const GEO_KEY_DIRECTORY_TAG: u16 = 34735;
+
+/// Describes the region of the terrain, that is exported as a mesh.
+#[derive(Clone, Debug)]
+pub struct MeshExportConfig {
+    /// The minimal corner of the region in world units.
+    pub min: UVec2,
+    /// The maximal corner of the region in world units.
+    pub max: UVec2,
+    /// The lod of the height data, from which the mesh is triangulated.
+    /// Each vertex is spaced one texel of the height attachment at this lod apart,
+    /// i.e. `leaf_node_size * 2^lod / center_size` world units.
+    pub lod: u32,
+    /// The height attachment of the terrain.
+    pub height_attachment: AttachmentConfig,
+    /// The attachment, from which the albedo texture of the mesh is baked (e.g. `dop`).
+    pub albedo_attachment: Option<AttachmentConfig>,
+}
+
+impl MeshExportConfig {
+    /// Creates an export config for the region, that uses the height attachment of the base.
+    pub fn new(base: &BaseConfig, min: UVec2, max: UVec2, lod: u32) -> Self {
+        Self {
+            min,
+            max,
+            lod,
+            height_attachment: base.height_attachment(),
+            albedo_attachment: None,
+        }
+    }
+}
+
+/// A triangulated region of the terrain.
+pub struct TerrainMesh {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    /// The texture coordinates of the albedo texture.
+    pub uvs: Vec<[f32; 2]>,
+    pub indices: Vec<u32>,
+    /// The albedo texture baked from the albedo attachment.
+    pub albedo: Option<Rgb8Image>,
+}
+
+/// Loads the nodes of an attachment on demand.
+struct NodeSampler<'a> {
+    directory: String,
+    attachment: &'a AttachmentConfig,
+    lod: u32,
+    cache: HashMap<NodeId, Option<DynamicImage>>,
+}
+
+impl<'a> NodeSampler<'a> {
+    fn new(config: &TerrainConfig, attachment: &'a AttachmentConfig, lod: u32) -> Result<Self> {
+        ensure!(
+            attachment.contains_lod(lod),
+            "The {} attachment does not exist for lod {lod}.",
+            attachment.name
+        );
+
+        Ok(Self {
+            directory: format_directory(&config.path, &attachment.name),
+            attachment,
+            lod,
+            cache: HashMap::default(),
+        })
+    }
+
+    /// Returns the node and the texel, that corresponds to the texel coordinate of the lod.
+    fn node(&mut self, coordinate: UVec2) -> Result<(&DynamicImage, UVec2)> {
+        let center_size = self.attachment.center_size;
+        let node_coordinate = coordinate / center_size;
+        let texel = coordinate % center_size + self.attachment.border_size;
+
+        let node_id = calc_node_id(self.lod, node_coordinate.x, node_coordinate.y);
+        let (directory, lod, file_format) =
+            (&self.directory, self.lod, self.attachment.file_format);
+
+        let node = self.cache.entry(node_id).or_insert_with(|| {
+            let path = format_node_path(directory, lod, node_coordinate.x, node_coordinate.y);
+            load_image(&path, file_format)
+        });
+
+        match node {
+            Some(node) => Ok((node, texel)),
+            None => Err(anyhow!(
+                "The region is not covered by the {} attachment, the node {node_id} is missing.",
+                self.attachment.name
+            )),
+        }
+    }
+}
+
+/// The number of texels of the given size along each side of the terrain.
+fn texel_count(config: &TerrainConfig, texel_size: f32) -> u32 {
+    (config.terrain_size as f32 / texel_size).floor() as u32
+}
+
+/// Triangulates the region of the terrain from the preprocessed height data and optionally
+/// bakes its albedo texture.
+///
+/// The region is clamped to the size of the terrain.
+pub fn export_mesh(config: &TerrainConfig, export: &MeshExportConfig) -> Result<TerrainMesh> {
+    let node_size = (config.leaf_node_size << export.lod) as f32;
+    let spacing = node_size / export.height_attachment.center_size as f32;
+    // the last vertex has to lie on the last texel inside the terrain
+    let last_texel = UVec2::splat(texel_count(config, spacing).saturating_sub(1));
+    let first = (export.min.as_vec2() / spacing).floor().as_uvec2();
+    let last = (export.max.as_vec2() / spacing)
+        .ceil()
+        .as_uvec2()
+        .min(last_texel);
+
+    ensure!(
+        first.x < last.x && first.y < last.y,
+        "The region of the mesh is empty."
+    );
+
+    let count = last - first + 1;
+    let mut heights = Vec::with_capacity((count.x * count.y) as usize);
+    let mut sampler = NodeSampler::new(config, &export.height_attachment, export.lod)?;
+
+    for y in first.y..=last.y {
+        for x in first.x..=last.x {
+            let (node, texel) = sampler.node(UVec2::new(x, y))?;
+            let node: &R16Image = node
+                .as_luma16()
+                .ok_or(anyhow!("The height attachment has to be stored as R16."))?;
+
+            heights.push(node.get_pixel(texel.x, texel.y).0[0] as f32 / u16::MAX as f32);
+        }
+    }
+
+    let height = |x: u32, y: u32| heights[(y * count.x + x) as usize] * config.height;
+    let extent = (last - first).as_vec2() * spacing;
+
+    let mut positions = Vec::with_capacity(heights.len());
+    let mut normals = Vec::with_capacity(heights.len());
+    let mut uvs = Vec::with_capacity(heights.len());
+
+    for y in 0..count.y {
+        for x in 0..count.x {
+            let position = (first + UVec2::new(x, y)).as_vec2() * spacing;
+
+            // central differences, that are clamped at the edges of the region
+            let (left, right) = (x.saturating_sub(1), (x + 1).min(count.x - 1));
+            let (up, down) = (y.saturating_sub(1), (y + 1).min(count.y - 1));
+            let normal = Vec3::new(
+                (height(left, y) - height(right, y)) / ((right - left) as f32 * spacing),
+                1.0,
+                (height(x, up) - height(x, down)) / ((down - up) as f32 * spacing),
+            )
+            .normalize();
+
+            positions.push([position.x, height(x, y), position.y]);
+            normals.push(normal.to_array());
+            uvs.push((UVec2::new(x, y).as_vec2() * spacing / extent).to_array());
+        }
+    }
+
+    let mut indices = Vec::with_capacity(((count.x - 1) * (count.y - 1) * 6) as usize);
+
+    for y in 0..count.y - 1 {
+        for x in 0..count.x - 1 {
+            let i00 = y * count.x + x;
+            let i10 = i00 + 1;
+            let i01 = i00 + count.x;
+            let i11 = i01 + 1;
+
+            indices.extend_from_slice(&[i00, i01, i10, i10, i01, i11]);
+        }
+    }
+
+    let albedo = match &export.albedo_attachment {
+        Some(attachment) => Some(bake_albedo(
+            config,
+            attachment,
+            export.lod,
+            node_size,
+            first.as_vec2() * spacing,
+            extent,
+        )?),
+        None => None,
+    };
+
+    Ok(TerrainMesh {
+        positions,
+        normals,
+        uvs,
+        indices,
+        albedo,
+    })
+}
+
+/// Bakes the albedo texture of the region at the resolution of the attachment.
+fn bake_albedo(
+    config: &TerrainConfig,
+    attachment: &AttachmentConfig,
+    lod: u32,
+    node_size: f32,
+    origin: Vec2,
+    extent: Vec2,
+) -> Result<Rgb8Image> {
+    let texel_size = node_size / attachment.center_size as f32;
+    let size = (extent / texel_size).ceil().as_uvec2();
+
+    let mut sampler = NodeSampler::new(config, attachment, lod)?;
+    let mut albedo = Rgb8Image::new(size.x, size.y);
+
+    for (x, y, pixel) in albedo.enumerate_pixels_mut() {
+        let position = origin + (Vec2::new(x as f32, y as f32) + 0.5) * texel_size;
+        let coordinate = (position / texel_size).as_uvec2();
+
+        let (node, texel) = sampler.node(coordinate)?;
+        let color = node.get_pixel(texel.x, texel.y).0;
+
+        pixel.0 = [color[0], color[1], color[2]];
+    }
+
+    Ok(albedo)
+}
+
+impl TerrainMesh {
+    /// Saves the mesh as OBJ (`.obj`) or binary glTF (`.glb`), depending on the extension.
+    pub fn save(&self, path: &str) -> Result<()> {
+        match Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some("obj") => self.save_obj(path),
+            Some("glb") => self.save_glb(path),
+            _ => bail!("Unsupported mesh format {path}, expected .obj or .glb."),
+        }
+    }
+
+    /// Saves the mesh as OBJ.
+    /// The albedo texture is saved as PNG next to the mesh and referenced by a material file.
+    pub fn save_obj(&self, path: &str) -> Result<()> {
+        let path = Path::new(path);
+        let name = path
+            .file_stem()
+            .and_then(|name| name.to_str())
+            .ok_or(anyhow!("Invalid mesh path {}.", path.display()))?;
+
+        let mut file = BufWriter::new(fs::File::create(path)?);
+
+        if let Some(albedo) = &self.albedo {
+            let texture_name = format!("{name}_albedo.png");
+            albedo.save(path.with_file_name(&texture_name))?;
+
+            fs::write(
+                path.with_extension("mtl"),
+                format!("newmtl terrain\nKd 1.0 1.0 1.0\nmap_Kd {texture_name}\n"),
+            )?;
+
+            writeln!(file, "mtllib {name}.mtl")?;
+        }
+
+        for [x, y, z] in &self.positions {
+            writeln!(file, "v {x} {y} {z}")?;
+        }
+        for [u, v] in &self.uvs {
+            // the texture origin of OBJ is at the bottom left
+            writeln!(file, "vt {u} {}", 1.0 - v)?;
+        }
+        for [x, y, z] in &self.normals {
+            writeln!(file, "vn {x} {y} {z}")?;
+        }
+
+        if self.albedo.is_some() {
+            writeln!(file, "usemtl terrain")?;
+        }
+
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [triangle[0] + 1, triangle[1] + 1, triangle[2] + 1];
+            writeln!(file, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}")?;
+        }
+
+        file.flush()?;
+
+        Ok(())
+    }
+
+    /// Saves the mesh as binary glTF, including the albedo texture.
+    pub fn save_glb(&self, path: &str) -> Result<()> {
+        let mut buffer = Vec::new();
+        let mut buffer_views = Vec::new();
+
+        // appends the data to the binary buffer and returns the index of its buffer view
+        let mut push_view = |data: &[u8], target: Option<u32>| {
+            let offset = buffer.len();
+            buffer.extend_from_slice(data);
+            buffer.resize((buffer.len() + 3) & !3, 0);
+
+            let target = target.map_or(String::new(), |target| format!(r#","target":{target}"#));
+            buffer_views.push(format!(
+                r#"{{"buffer":0,"byteOffset":{offset},"byteLength":{}{target}}}"#,
+                data.len()
+            ));
+            buffer_views.len() - 1
+        };
+
+        let (min, max) = self.positions.iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), &position| (min.min(Vec3::from(position)), max.max(Vec3::from(position))),
+        );
+
+        let vertex_count = self.positions.len();
+        let positions = push_view(bytemuck::cast_slice(&self.positions), Some(34962));
+        let normals = push_view(bytemuck::cast_slice(&self.normals), Some(34962));
+        let uvs = push_view(bytemuck::cast_slice(&self.uvs), Some(34962));
+        let indices = push_view(bytemuck::cast_slice(&self.indices), Some(34963));
+
+        let accessors = [
+            format!(
+                r#"{{"bufferView":{positions},"componentType":5126,"count":{vertex_count},"type":"VEC3","min":[{},{},{}],"max":[{},{},{}]}}"#,
+                min.x, min.y, min.z, max.x, max.y, max.z
+            ),
+            format!(
+                r#"{{"bufferView":{normals},"componentType":5126,"count":{vertex_count},"type":"VEC3"}}"#
+            ),
+            format!(
+                r#"{{"bufferView":{uvs},"componentType":5126,"count":{vertex_count},"type":"VEC2"}}"#
+            ),
+            format!(
+                r#"{{"bufferView":{indices},"componentType":5125,"count":{},"type":"SCALAR"}}"#,
+                self.indices.len()
+            ),
+        ];
+
+        let material = match &self.albedo {
+            Some(albedo) => {
+                let mut png = Cursor::new(Vec::new());
+                DynamicImage::from(albedo.clone()).write_to(&mut png, ImageOutputFormat::Png)?;
+                let image = push_view(png.get_ref(), None);
+
+                format!(
+                    r#""materials":[{{"pbrMetallicRoughness":{{"baseColorTexture":{{"index":0}},"metallicFactor":0.0,"roughnessFactor":1.0}}}}],"textures":[{{"source":0}}],"images":[{{"bufferView":{image},"mimeType":"image/png"}}],"#
+                )
+            }
+            None => r#""materials":[{"pbrMetallicRoughness":{"metallicFactor":0.0,"roughnessFactor":1.0}}],"#.to_string(),
+        };
+
+        let json = format!(
+            r#"{{"asset":{{"version":"2.0","generator":"bevy_terrain"}},"scene":0,"scenes":[{{"nodes":[0]}}],"nodes":[{{"mesh":0}}],"meshes":[{{"primitives":[{{"attributes":{{"POSITION":0,"NORMAL":1,"TEXCOORD_0":2}},"indices":3,"material":0}}]}}],{material}"accessors":[{}],"bufferViews":[{}],"buffers":[{{"byteLength":{}}}]}}"#,
+            accessors.join(","),
+            buffer_views.join(","),
+            buffer.len()
+        );
+
+        let mut json = json.into_bytes();
+        json.resize((json.len() + 3) & !3, b' ');
+
+        let length = 12 + 8 + json.len() + 8 + buffer.len();
+
+        let mut file = BufWriter::new(fs::File::create(path)?);
+        file.write_all(b"glTF")?;
+        file.write_all(&2u32.to_le_bytes())?;
+        file.write_all(&(length as u32).to_le_bytes())?;
+        file.write_all(&(json.len() as u32).to_le_bytes())?;
+        file.write_all(b"JSON")?;
+        file.write_all(&json)?;
+        file.write_all(&(buffer.len() as u32).to_le_bytes())?;
+        file.write_all(b"BIN\0")?;
+        file.write_all(&buffer)?;
+        file.flush()?;
+
+        Ok(())
+    }
+}
+
+/// Places the terrain in a projected coordinate reference system.
+#[derive(Clone, Copy, Debug)]
+pub struct GeoReference {
+    /// The projected coordinate (easting, northing) of the origin of the terrain, which is its
+    /// north-western corner.
+    pub origin: DVec2,
+    /// The size of a world unit in the units of the coordinate reference system (usually meters).
+    pub unit_size: f64,
+    /// The EPSG code of the projected coordinate reference system
+    /// (e.g. 25833 for ETRS89 / UTM zone 33N).
+    pub epsg: u16,
+}
+
+/// Describes the region of the terrain, that is exported as a raster.
+#[derive(Clone, Debug)]
+pub struct RasterExportConfig {
+    /// The minimal corner of the region in world units.
+    pub min: UVec2,
+    /// The maximal corner of the region in world units.
+    pub max: UVec2,
+    /// The lod of the nodes, which are stitched together.
+    pub lod: u32,
+    /// The attachment, whose nodes are stitched together (e.g. `height` or `dop`).
+    pub attachment: AttachmentConfig,
+}
+
+/// A region of an attachment, reassembled from its nodes.
+pub struct TerrainRaster {
+    /// The stitched nodes without their borders.
+    pub image: DynamicImage,
+    /// The minimal corner of the raster in world units.
+    pub origin: Vec2,
+    /// The size of a texel in world units.
+    pub texel_size: f32,
+}
+
+/// Copies the texels of the region from the nodes, stripping their borders.
+fn stitch_raster<P: Pixel + 'static>(
+    sampler: &mut NodeSampler,
+    first: UVec2,
+    size: UVec2,
+    as_buffer: fn(&DynamicImage) -> Option<&ImageBuffer<P, Vec<P::Subpixel>>>,
+) -> Result<ImageBuffer<P, Vec<P::Subpixel>>> {
+    let attachment = sampler.attachment;
+    let mut raster = ImageBuffer::new(size.x, size.y);
+
+    for (x, y, pixel) in raster.enumerate_pixels_mut() {
+        let (node, texel) = sampler.node(first + UVec2::new(x, y))?;
+        let node = as_buffer(node).ok_or(anyhow!(
+            "The nodes of the {} attachment do not match its format.",
+            attachment.name
+        ))?;
+
+        *pixel = *node.get_pixel(texel.x, texel.y);
+    }
+
+    Ok(raster)
+}
+
+/// Reassembles the region of the attachment from its nodes.
+///
+/// The region is extended to the texels of the attachment, that intersect it.
+pub fn export_raster(config: &TerrainConfig, export: &RasterExportConfig) -> Result<TerrainRaster> {
+    let node_size = (config.leaf_node_size << export.lod) as f32;
+    let texel_size = node_size / export.attachment.center_size as f32;
+    let first = (export.min.as_vec2() / texel_size).floor().as_uvec2();
+    let last = (export.max.as_vec2() / texel_size)
+        .ceil()
+        .as_uvec2()
+        .min(UVec2::splat(texel_count(config, texel_size)));
+
+    ensure!(
+        first.x < last.x && first.y < last.y,
+        "The region of the raster is empty."
+    );
+
+    let size = last - first;
+    let mut sampler = NodeSampler::new(config, &export.attachment, export.lod)?;
+
+    let image: DynamicImage = match export.attachment.format {
+        AttachmentFormat::Rgb8 => {
+            stitch_raster(&mut sampler, first, size, DynamicImage::as_rgb8)?.into()
+        }
+        AttachmentFormat::Rgba8 | AttachmentFormat::Rgba8Linear => {
+            stitch_raster(&mut sampler, first, size, DynamicImage::as_rgba8)?.into()
+        }
+        AttachmentFormat::R16 => {
+            stitch_raster(&mut sampler, first, size, DynamicImage::as_luma16)?.into()
+        }
+        AttachmentFormat::Rg16 => {
+            stitch_raster(&mut sampler, first, size, DynamicImage::as_luma_alpha16)?.into()
+        }
+        AttachmentFormat::Rg8 => {
+            stitch_raster(&mut sampler, first, size, DynamicImage::as_luma_alpha8)?.into()
+        }
+        AttachmentFormat::Rgba16 => {
+            stitch_raster(&mut sampler, first, size, DynamicImage::as_rgba16)?.into()
+        }
+    };
+
+    Ok(TerrainRaster {
+        image,
+        origin: first.as_vec2() * texel_size,
+        texel_size,
+    })
+}
+
+/// Writes the image as TIFF, including the GeoTIFF tags, if it is georeferenced.
+fn save_tiff<C: ColorType>(
+    path: &str,
+    size: UVec2,
+    data: &[C::Inner],
+    transform: Option<(DVec2, f64, u16)>,
+) -> Result<()>
+where
+    [C::Inner]: TiffValue,
+{
+    let mut encoder = TiffEncoder::new(BufWriter::new(fs::File::create(path)?))?;
+    let mut image = encoder.new_image::<C>(size.x, size.y)?;
+
+    if let Some((tie_point, texel_size, epsg)) = transform {
+        let directory = image.encoder();
+
+        directory.write_tag(
+            Tag::Unknown(MODEL_PIXEL_SCALE_TAG),
+            &[texel_size, texel_size, 0.0][..],
+        )?;
+        directory.write_tag(
+            Tag::Unknown(MODEL_TIEPOINT_TAG),
+            &[0.0, 0.0, 0.0, tie_point.x, tie_point.y, 0.0][..],
+        )?;
+        // projected model, pixel is area and the projected coordinate reference system
+        directory.write_tag(
+            Tag::Unknown(GEO_KEY_DIRECTORY_TAG),
+            &[1, 1, 0, 3, 1024, 0, 1, 1, 1025, 0, 1, 1, 3072, 0, 1, epsg][..],
+        )?;
+    }
+
+    image.write_data(data)?;
+
+    Ok(())
+}
+
+impl TerrainRaster {
+    /// Returns the projected coordinate of the north-western corner of the raster and the
+    /// size of a texel in the coordinate reference system.
+    fn transform(&self, georeference: &GeoReference) -> (DVec2, f64, u16) {
+        let origin = self.origin.as_dvec2() * georeference.unit_size;
+        let tie_point = DVec2::new(
+            georeference.origin.x + origin.x,
+            georeference.origin.y - origin.y,
+        );
+
+        (
+            tie_point,
+            self.texel_size as f64 * georeference.unit_size,
+            georeference.epsg,
+        )
+    }
+
+    /// Saves the raster as GeoTIFF (`.tif`) or PNG (`.png`), depending on the extension.
+    ///
+    /// If the raster is georeferenced, the GeoTIFF tags are written, or a world file
+    /// (`.pgw`) is saved next to the PNG.
+    pub fn save(&self, path: &str, georeference: Option<&GeoReference>) -> Result<()> {
+        let transform = georeference.map(|georeference| self.transform(georeference));
+        let size = UVec2::new(self.image.width(), self.image.height());
+
+        match Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some("tif" | "tiff") => match &self.image {
+                DynamicImage::ImageRgb8(image) => {
+                    save_tiff::<colortype::RGB8>(path, size, image.as_raw(), transform)
+                }
+                DynamicImage::ImageRgba8(image) => {
+                    save_tiff::<colortype::RGBA8>(path, size, image.as_raw(), transform)
+                }
+                DynamicImage::ImageLuma16(image) => {
+                    save_tiff::<colortype::Gray16>(path, size, image.as_raw(), transform)
+                }
+                DynamicImage::ImageRgba16(image) => {
+                    save_tiff::<colortype::RGBA16>(path, size, image.as_raw(), transform)
+                }
+                _ => bail!("Two channel rasters can only be saved as PNG."),
+            },
+            Some("png") => {
+                self.image.save(path)?;
+
+                if let Some((tie_point, texel_size, _)) = transform {
+                    // the world file references the center of the first texel
+                    let center = tie_point + DVec2::new(0.5, -0.5) * texel_size;
+
+                    fs::write(
+                        Path::new(path).with_extension("pgw"),
+                        format!(
+                            "{texel_size}\n0.0\n0.0\n{}\n{}\n{}\n",
+                            -texel_size, center.x, center.y
+                        ),
+                    )?;
+                }
+
+                Ok(())
+            }
+            _ => bail!("Unsupported raster format {path}, expected .tif or .png."),
+        }
+    }
+
+    /// Saves a height raster as 32 bit floating point GeoTIFF, which stores the height
+    /// in world units.
+    pub fn save_height(
+        &self,
+        path: &str,
+        height: f32,
+        georeference: Option<&GeoReference>,
+    ) -> Result<()> {
+        let image = self.image.as_luma16().ok_or(anyhow!(
+            "Only height rasters can be saved as floating point."
+        ))?;
+
+        let data: Vec<f32> = image
+            .as_raw()
+            .iter()
+            .map(|&value| value as f32 / u16::MAX as f32 * height)
+            .collect();
+
+        save_tiff::<colortype::Gray32Float>(
+            path,
+            UVec2::new(image.width(), image.height()),
+            &data,
+            georeference.map(|georeference| self.transform(georeference)),
+        )
+    }
+}
@@ -0,0 +1,363 @@
+//! Composes the height data of the terrain from multiple prioritized sources,
+//! by filling the holes of the base attachment from coarser fallback DEMs.
+
+use crate::{
+    preprocess::{
+        file_io::{format_node_path, iterate_directory, load_image, reset_directory, save_image},
+        nodata::NODATA,
+        R16Image, TileConfig, UVec2Utils,
+    },
+    skip_none,
+    terrain_data::{calc_node_id, AttachmentConfig, NodeId},
+    TerrainConfig,
+};
+use anyhow::{anyhow, Result};
+use bevy::{prelude::*, utils::HashMap};
+use image::{DynamicImage, Luma};
+use itertools::iproduct;
+use lru::LruCache;
+use std::{f32::consts::SQRT_2, fs, num::NonZeroUsize};
+
+/// The configuration of a lower priority source of the height data,
+/// which fills the holes of the base attachment.
+///
+/// Its heights have to share the height scale of the base tiles.
+#[derive(Debug)]
+pub struct FallbackConfig {
+    /// The source tile(s), which are named like the tiles of the base (`{name}_{x}_{y}`),
+    /// if the path is a directory.
+    pub tile: TileConfig,
+    /// The size of a texel of the source in world units (e.g. 25 for a 25 m DEM).
+    pub texel_size: f32,
+    /// The distance in world units, over which the higher priority data fades into this source
+    /// at the boundaries of its holes.
+    pub blend_distance: f32,
+}
+
+impl FallbackConfig {
+    pub fn new(tile: TileConfig, texel_size: f32) -> Self {
+        Self {
+            tile,
+            texel_size,
+            blend_distance: 16.0,
+        }
+    }
+
+    /// Returns the paths of the source tiles by their coordinate.
+    ///
+    /// Fails, if the source tiles can not be read, are missing or are not named like `{name}_{x}_{y}`.
+    pub fn tile_paths(&self) -> Result<HashMap<UVec2, String>> {
+        let tile = &self.tile;
+        let metadata = fs::metadata(&tile.path).map_err(|error| {
+            anyhow!("Could not read the fallback source {}: {error}.", tile.path)
+        })?;
+
+        if !metadata.is_dir() {
+            return Ok(HashMap::from_iter([(UVec2::ZERO, tile.path.clone())]));
+        }
+
+        let paths = iterate_directory(&tile.path)
+            .map(|(tile_name, tile_path)| {
+                let coord = parse_coordinate(&tile_name).ok_or(anyhow!(
+                    "The fallback tile {tile_path} is not named like {{name}}_{{x}}_{{y}}."
+                ))?;
+
+                Ok((coord, tile_path))
+            })
+            .collect::<Result<HashMap<_, _>>>()?;
+
+        if paths.is_empty() {
+            return Err(anyhow!(
+                "The fallback source {} contains no tiles.",
+                tile.path
+            ));
+        }
+
+        Ok(paths)
+    }
+}
+
+/// Parses the coordinate of a tile named like `{name}_{x}_{y}`.
+fn parse_coordinate(tile_name: &str) -> Option<UVec2> {
+    let mut parts = tile_name.split('_');
+    parts.next();
+
+    Some(UVec2::new(
+        parts.next()?.parse().ok()?,
+        parts.next()?.parse().ok()?,
+    ))
+}
+
+/// Samples the tiles of a fallback source, by caching the recently used tiles.
+struct FallbackSampler<'a> {
+    fallback: &'a FallbackConfig,
+    paths: HashMap<UVec2, String>,
+    cache: LruCache<UVec2, Option<R16Image>>,
+}
+
+impl<'a> FallbackSampler<'a> {
+    fn new(fallback: &'a FallbackConfig, paths: HashMap<UVec2, String>) -> Self {
+        Self {
+            fallback,
+            paths,
+            cache: LruCache::new(NonZeroUsize::new(16).unwrap()),
+        }
+    }
+
+    /// Returns the first and last node coordinate of the first lod covered by the source.
+    fn node_range(&self, attachment: &AttachmentConfig) -> (UVec2, UVec2) {
+        let min_pos = self
+            .paths
+            .keys()
+            .fold(UVec2::splat(u32::MAX), |min, &coord| min.min(coord));
+        let max_pos = self
+            .paths
+            .keys()
+            .fold(UVec2::splat(u32::MIN), |max, &coord| max.max(coord));
+
+        let tile_size = self.fallback.tile.size as f32 * self.fallback.texel_size;
+        let offset = (min_pos.as_vec2() * tile_size).floor().as_uvec2();
+        let end = ((max_pos + 1).as_vec2() * tile_size).ceil().as_uvec2();
+
+        (
+            offset.div_floor(attachment.center_size),
+            end.div_ceil(attachment.center_size),
+        )
+    }
+
+    /// Returns the height of the texel with the coordinates in texels of the entire source.
+    fn texel(&mut self, x: i32, y: i32) -> Option<u16> {
+        if x < 0 || y < 0 {
+            return None;
+        }
+
+        let size = self.fallback.tile.size;
+        let (x, y) = (x as u32, y as u32);
+        let coord = UVec2::new(x / size, y / size);
+
+        if !self.cache.contains(&coord) {
+            let image = self.paths.get(&coord).and_then(|path| {
+                load_image(path, self.fallback.tile.file_format).map(|image| image.into_luma16())
+            });
+
+            self.cache.put(coord, image);
+        }
+
+        let image = self.cache.get(&coord).unwrap().as_ref()?;
+        let Luma([value]) = *image.get_pixel(x % size, y % size);
+
+        (value != NODATA).then_some(value)
+    }
+
+    /// Bilinearly samples the source at the world position, ignoring texels without data.
+    fn sample(&mut self, position: Vec2) -> Option<f32> {
+        let position = position / self.fallback.texel_size - 0.5;
+        let texel = position.floor();
+        let ratio = position - texel;
+
+        let mut value = 0.0;
+        let mut weight = 0.0;
+
+        for (dx, dy) in iproduct!(0..2, 0..2) {
+            let height = skip_none!(self.texel(texel.x as i32 + dx, texel.y as i32 + dy));
+
+            let texel_weight = (if dx == 0 { 1.0 - ratio.x } else { ratio.x })
+                * (if dy == 0 { 1.0 - ratio.y } else { ratio.y });
+
+            value += height as f32 * texel_weight;
+            weight += texel_weight;
+        }
+
+        (weight > 0.0).then(|| value / weight)
+    }
+}
+
+/// Samples the height data of the first lod, by caching the recently used nodes.
+struct NodeSampler<'a> {
+    directory: &'a str,
+    attachment: &'a AttachmentConfig,
+    cache: LruCache<NodeId, Option<R16Image>>,
+}
+
+impl<'a> NodeSampler<'a> {
+    fn new(directory: &'a str, attachment: &'a AttachmentConfig) -> Self {
+        Self {
+            directory,
+            attachment,
+            cache: LruCache::new(NonZeroUsize::new(16).unwrap()),
+        }
+    }
+
+    /// Returns the height of the texel with the coordinates in texels of the entire lod.
+    /// Texels outside of the terrain are `None`, while texels of missing nodes have no data.
+    fn texel(&mut self, x: i32, y: i32) -> Option<u16> {
+        if x < 0 || y < 0 {
+            return None;
+        }
+
+        let center_size = self.attachment.center_size;
+        let (x, y) = (x as u32, y as u32);
+        let (node_x, node_y) = (x / center_size, y / center_size);
+        let node_id = calc_node_id(0, node_x, node_y);
+
+        if !self.cache.contains(&node_id) {
+            let path = format_node_path(self.directory, 0, node_x, node_y);
+            let image =
+                load_image(&path, self.attachment.file_format).map(|image| image.into_luma16());
+
+            self.cache.put(node_id, image);
+        }
+
+        let value = match self.cache.get(&node_id).unwrap() {
+            Some(image) => {
+                image
+                    .get_pixel(
+                        x % center_size + self.attachment.border_size,
+                        y % center_size + self.attachment.border_size,
+                    )
+                    .0[0]
+            }
+            None => NODATA,
+        };
+
+        Some(value)
+    }
+}
+
+/// Computes the distance of each texel of the square region to the closest texel without data,
+/// clamped to the maximum distance, using a two pass chamfer distance transform.
+fn distance_to_nodata(region: &[Option<u16>], size: usize, max_distance: f32) -> Vec<f32> {
+    let mut distances = region
+        .iter()
+        .map(|value| match value {
+            Some(NODATA) => 0.0,
+            _ => max_distance,
+        })
+        .collect::<Vec<_>>();
+
+    let forward = [
+        (-1, -1, SQRT_2),
+        (0, -1, 1.0),
+        (1, -1, SQRT_2),
+        (-1, 0, 1.0),
+    ];
+    let backward = [(1, 1, SQRT_2), (0, 1, 1.0), (-1, 1, SQRT_2), (1, 0, 1.0)];
+
+    let mut pass = |coords: &mut dyn Iterator<Item = (usize, usize)>,
+                    offsets: &[(i32, i32, f32)]| {
+        for (x, y) in coords {
+            for &(dx, dy, cost) in offsets {
+                let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+
+                if nx < 0 || ny < 0 || nx >= size as i32 || ny >= size as i32 {
+                    continue;
+                }
+
+                let distance = distances[ny as usize * size + nx as usize] + cost;
+                let current = &mut distances[y * size + x];
+                *current = current.min(distance);
+            }
+        }
+    };
+
+    pass(
+        &mut iproduct!(0..size, 0..size).map(|(y, x)| (x, y)),
+        &forward,
+    );
+    pass(
+        &mut iproduct!((0..size).rev(), (0..size).rev()).map(|(y, x)| (x, y)),
+        &backward,
+    );
+
+    distances
+}
+
+/// Merges the fallback source into the height data of the first lod.
+///
+/// Texels without data are filled from the source, while the texels close to the boundaries
+/// of the holes are blended with it, to avoid cliffs between sources of different resolutions.
+/// Returns the first and last node coordinate of the merged height data.
+pub(crate) fn merge_fallback(
+    config: &TerrainConfig,
+    directory: &str,
+    attachment: &AttachmentConfig,
+    fallback: &FallbackConfig,
+    first: UVec2,
+    last: UVec2,
+) -> (UVec2, UVec2) {
+    // the source tiles have been validated, when the fallback was added to the terrain
+    let paths = match fallback.tile_paths() {
+        Ok(paths) => paths,
+        Err(error) => {
+            error!("Skipped the fallback source: {error}");
+            return (first, last);
+        }
+    };
+
+    let mut fallback_sampler = FallbackSampler::new(fallback, paths);
+    let mut node_sampler = NodeSampler::new(directory, attachment);
+
+    // the nodes covered by the source, that lie inside of the terrain
+    let (fallback_first, fallback_last) = fallback_sampler.node_range(attachment);
+    let terrain_last = UVec2::splat(config.terrain_size).div_ceil(attachment.center_size);
+    let first = first.min(fallback_first);
+    let last = last.max(fallback_last.min(terrain_last));
+
+    let merged_directory = format!("{directory}_merged");
+    reset_directory(&merged_directory);
+
+    let padding = fallback.blend_distance.ceil().max(0.0) as u32;
+    let region_size = (attachment.texture_size + 2 * padding) as usize;
+
+    for (x, y) in first.product(last) {
+        let node_path = format_node_path(&merged_directory, 0, x, y);
+        let mut node_image = R16Image::new(attachment.texture_size, attachment.texture_size);
+
+        // the texel coordinates of the first texel of the region, including the border and padding
+        let origin = (UVec2::new(x, y) * attachment.center_size).as_ivec2()
+            - (attachment.border_size + padding) as i32;
+
+        let region = iproduct!(0..region_size as i32, 0..region_size as i32)
+            .map(|(ry, rx)| node_sampler.texel(origin.x + rx, origin.y + ry))
+            .collect::<Vec<_>>();
+        let distances = distance_to_nodata(&region, region_size, fallback.blend_distance);
+
+        let mut has_data = false;
+
+        for (pixel_x, pixel_y, pixel) in node_image.enumerate_pixels_mut() {
+            let index = (pixel_y + padding) as usize * region_size + (pixel_x + padding) as usize;
+            let texel = origin + IVec2::new((pixel_x + padding) as i32, (pixel_y + padding) as i32);
+            let position = texel.as_vec2() + 0.5;
+
+            let value = region[index].unwrap_or(NODATA);
+
+            let value = match fallback_sampler.sample(position) {
+                Some(fallback_value) if value == NODATA => fallback_value,
+                Some(fallback_value) if distances[index] < fallback.blend_distance => {
+                    let ratio = distances[index] / fallback.blend_distance;
+                    fallback_value + (value as f32 - fallback_value) * ratio
+                }
+                _ => value as f32,
+            };
+
+            // the merged texels must not be marked as holes themselves
+            let value = if value == NODATA as f32 {
+                NODATA
+            } else {
+                (value.round() as u16).min(NODATA - 1)
+            };
+
+            has_data |= value != NODATA;
+            *pixel = Luma([value]);
+        }
+
+        if has_data {
+            save_image(&node_path, &DynamicImage::from(node_image), attachment);
+        }
+    }
+
+    fs::remove_dir_all(directory).unwrap();
+    fs::rename(&merged_directory, directory).unwrap();
+
+    (first, last)
+}
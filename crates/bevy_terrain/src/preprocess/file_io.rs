@@ -1,11 +1,13 @@
 use crate::{
     formats::tdf::TDF,
-    preprocess::{R16Image, Rg16Image, Rgb8Image, Rgba8Image},
+    preprocess::{
+        nodata::NODATA, R16Image, Rg16Image, Rg8Image, Rgb8Image, Rgba16Image, Rgba8Image,
+    },
     terrain_data::{calc_node_id, AttachmentConfig, AttachmentFormat, FileFormat},
 };
 use bytemuck::cast_slice;
 use dtm::DTM;
-use image::{io::Reader, DynamicImage};
+use image::{io::Reader, DynamicImage, Luma};
 use rapid_qoi::{Colors, Qoi};
 use std::{
     fs::{self, DirEntry, ReadDir},
@@ -69,6 +71,8 @@ pub fn load_image(path: &str, file_format: FileFormat) -> Option<DynamicImage> {
     }
 }
 
+/// Loads the node or creates an empty one, whose texels are marked as holes, if the
+/// attachment has nodata.
 pub(crate) fn load_or_create_node(path: &str, attachment: &AttachmentConfig) -> DynamicImage {
     if let Some(node_image) = load_image(path, attachment.file_format) {
         node_image
@@ -77,9 +81,16 @@ pub(crate) fn load_or_create_node(path: &str, attachment: &AttachmentConfig) ->
 
         match attachment.format {
             AttachmentFormat::Rgb8 => DynamicImage::from(Rgb8Image::new(size, size)),
-            AttachmentFormat::Rgba8 => DynamicImage::from(Rgba8Image::new(size, size)),
+            AttachmentFormat::Rgba8 | AttachmentFormat::Rgba8Linear => {
+                DynamicImage::from(Rgba8Image::new(size, size))
+            }
+            AttachmentFormat::R16 if attachment.nodata => {
+                DynamicImage::from(R16Image::from_pixel(size, size, Luma([NODATA])))
+            }
             AttachmentFormat::R16 => DynamicImage::from(R16Image::new(size, size)),
             AttachmentFormat::Rg16 => DynamicImage::from(Rg16Image::new(size, size)),
+            AttachmentFormat::Rg8 => DynamicImage::from(Rg8Image::new(size, size)),
+            AttachmentFormat::Rgba16 => DynamicImage::from(Rgba16Image::new(size, size)),
         }
     }
 }
@@ -110,6 +121,10 @@ fn load_tdf(path: &str) -> Option<DynamicImage> {
             let image = Rgba8Image::from_raw(size, size, data).unwrap();
             Some(DynamicImage::from(image))
         }
+        (1, 2) => {
+            let image = Rg8Image::from_raw(size, size, data).unwrap();
+            Some(DynamicImage::from(image))
+        }
         (2, 1) => {
             let data: Vec<u16> = data
                 .chunks_exact(2)
@@ -130,6 +145,16 @@ fn load_tdf(path: &str) -> Option<DynamicImage> {
             let image = Rg16Image::from_raw(size, size, data).unwrap();
             Some(DynamicImage::from(image))
         }
+        (2, 4) => {
+            let data: Vec<u16> = data
+                .chunks_exact(2)
+                .into_iter()
+                .map(|pixel| u16::from_le_bytes(pixel.try_into().unwrap()))
+                .collect();
+
+            let image = Rgba16Image::from_raw(size, size, data).unwrap();
+            Some(DynamicImage::from(image))
+        }
         _ => None,
     }
 }
@@ -167,6 +192,18 @@ fn load_dtm(path: &str) -> Option<DynamicImage> {
                     .unwrap();
             Some(DynamicImage::from(image))
         }
+        4 => {
+            let data: Vec<u16> = data
+                .chunks_exact(2)
+                .into_iter()
+                .map(|pixel| u16::from_le_bytes(pixel.try_into().unwrap()))
+                .collect();
+
+            let image =
+                Rgba16Image::from_raw(descriptor.width as u32, descriptor.height as u32, data)
+                    .unwrap();
+            Some(DynamicImage::from(image))
+        }
         _ => None,
     }
 }
@@ -191,9 +228,11 @@ fn load_qoi(path: &str) -> Option<DynamicImage> {
 fn save_tdf(path: &str, node_image: &DynamicImage, attachment: &AttachmentConfig) {
     let (pixel_size, channel_count) = match attachment.format {
         AttachmentFormat::Rgb8 => (1, 3),
-        AttachmentFormat::Rgba8 => (1, 4),
+        AttachmentFormat::Rgba8 | AttachmentFormat::Rgba8Linear => (1, 4),
         AttachmentFormat::R16 => (2, 1),
         AttachmentFormat::Rg16 => (2, 2),
+        AttachmentFormat::Rg8 => (1, 2),
+        AttachmentFormat::Rgba16 => (2, 4),
     };
 
     let descriptor = TDF {
@@ -201,6 +240,7 @@ fn save_tdf(path: &str, node_image: &DynamicImage, attachment: &AttachmentConfig
         channel_count,
         size: attachment.texture_size,
         mip_level_count: attachment.mip_level_count,
+        nodata: attachment.nodata,
     };
 
     descriptor.save_file(path, node_image.as_bytes()).unwrap();
@@ -218,6 +258,9 @@ fn save_dtm(path: &str, node_image: &DynamicImage, attachment: &AttachmentConfig
             AttachmentFormat::Rgba8 => panic!("Can not save Rgba8 as DTM."),
             AttachmentFormat::R16 => 1,
             AttachmentFormat::Rg16 => 2,
+            AttachmentFormat::Rg8 => panic!("Can not save Rg8 as DTM."),
+            AttachmentFormat::Rgba16 => 4,
+            AttachmentFormat::Rgba8Linear => panic!("Can not save Rgba8Linear as DTM."),
         },
         width: node_image.width(),
         height: node_image.height(),
@@ -235,8 +278,11 @@ fn save_qoi(path: &str, node_image: &DynamicImage, attachment: &AttachmentConfig
         colors: match attachment.format {
             AttachmentFormat::Rgb8 => Colors::Rgb,
             AttachmentFormat::Rgba8 => Colors::Rgba,
+            AttachmentFormat::Rgba8Linear => Colors::Rgba,
             AttachmentFormat::R16 => panic!("Can not save R16 as QOI."),
             AttachmentFormat::Rg16 => panic!("Can not save Rg16 as QOI."),
+            AttachmentFormat::Rg8 => panic!("Can not save Rg8 as QOI."),
+            AttachmentFormat::Rgba16 => panic!("Can not save Rgba16 as QOI."),
         },
     };
 
@@ -3,16 +3,28 @@
 pub mod attachment;
 pub mod config;
 pub mod down_sample;
+pub mod export;
+pub mod fallback;
 pub mod file_io;
+pub mod nodata;
+pub mod occlusion;
 pub mod split;
 pub mod stitch;
+pub mod surface;
+pub mod water;
 
 use crate::{
     preprocess::{
-        attachment::{preprocess_attachment, preprocess_base},
+        attachment::{preprocess_attachment, preprocess_base, preprocess_virtual_texture},
         config::save_config,
+        fallback::FallbackConfig,
+        nodata::Nodata,
+        occlusion::OcclusionConfig,
+        surface::{preprocess_surface, SurfaceConfig},
+        water::{preprocess_water, WaterConfig},
     },
     terrain_data::{AttachmentConfig, AttachmentFormat, FileFormat},
+    virtual_texture::VirtualTextureConfig,
     TerrainConfig,
 };
 use bevy::prelude::*;
@@ -33,12 +45,25 @@ macro_rules! skip_none {
 /// The configuration of the base attachment of the terrain.
 /// The base attachment consists of the height data and the corresponding minmax
 /// information of the terrain.
+/// Optionally it includes a normal, an ambient occlusion and a horizon attachment,
+/// which are generated from the height data.
 #[derive(Copy, Clone)]
 pub struct BaseConfig {
     pub texture_size: u32,
     pub border_size: u32,
     pub mip_level_count: u32,
     pub file_format: FileFormat,
+    /// Whether to generate a normal attachment, which stores the octahedral encoded
+    /// world space normals of the terrain, following the height and minmax attachment.
+    pub normals: bool,
+    /// The configuration of the ambient occlusion baking.
+    /// If set, an ambient occlusion attachment (and optionally a horizon attachment)
+    /// is generated, following the other base attachments.
+    pub occlusion: Option<OcclusionConfig>,
+    /// Determines how texels of the height data without data (e.g. missing tiles) are handled.
+    /// The fallback sources of [`Nodata::Fallback`] are added via
+    /// [`add_fallback_from_disk`](TerrainConfig::add_fallback_from_disk).
+    pub nodata: Nodata,
 }
 
 impl BaseConfig {
@@ -48,9 +73,36 @@ impl BaseConfig {
             border_size: 2,
             mip_level_count,
             file_format: FileFormat::TDF,
+            normals: false,
+            occlusion: None,
+            nodata: Nodata::Ignore,
         }
     }
 
+    /// The number of attachments of the base, including the optional ones.
+    pub fn attachment_count(&self) -> usize {
+        self.attachments().len()
+    }
+
+    /// Returns all attachments of the base in the order in which they are added to the terrain.
+    pub(crate) fn attachments(&self) -> Vec<AttachmentConfig> {
+        let mut attachments = vec![self.height_attachment(), self.minmax_attachment()];
+
+        if self.normals {
+            attachments.push(self.normal_attachment());
+        }
+
+        if let Some(occlusion) = self.occlusion {
+            attachments.push(self.occlusion_attachment());
+
+            if occlusion.horizon {
+                attachments.push(self.horizon_attachment());
+            }
+        }
+
+        attachments
+    }
+
     pub(crate) fn height_attachment(&self) -> AttachmentConfig {
         let mut attachment = AttachmentConfig::new(
             "height".to_string(),
@@ -61,6 +113,7 @@ impl BaseConfig {
         );
 
         attachment.file_format = self.file_format;
+        attachment.nodata = self.nodata.is_enabled();
         attachment
     }
     pub(crate) fn minmax_attachment(&self) -> AttachmentConfig {
@@ -72,6 +125,44 @@ impl BaseConfig {
             AttachmentFormat::Rg16,
         );
 
+        attachment.file_format = self.file_format;
+        attachment
+    }
+    /// The normal attachment is always stored as TDF, because the other formats
+    /// do not support two 8 bit channels.
+    ///
+    /// It has no mip levels, because the generic mip chain averages the octahedral encodings,
+    /// which does not yield valid normals.
+    pub(crate) fn normal_attachment(&self) -> AttachmentConfig {
+        AttachmentConfig::new(
+            "normal".to_string(),
+            self.texture_size,
+            self.border_size,
+            1,
+            AttachmentFormat::Rg8,
+        )
+    }
+    pub(crate) fn occlusion_attachment(&self) -> AttachmentConfig {
+        let mut attachment = AttachmentConfig::new(
+            "occlusion".to_string(),
+            self.texture_size,
+            self.border_size,
+            self.mip_level_count,
+            AttachmentFormat::R16,
+        );
+
+        attachment.file_format = self.file_format;
+        attachment
+    }
+    pub(crate) fn horizon_attachment(&self) -> AttachmentConfig {
+        let mut attachment = AttachmentConfig::new(
+            "horizon".to_string(),
+            self.texture_size,
+            self.border_size,
+            self.mip_level_count,
+            AttachmentFormat::Rgba16,
+        );
+
         attachment.file_format = self.file_format;
         attachment
     }
@@ -94,20 +185,100 @@ pub struct TileConfig {
 #[derive(Default)]
 pub struct Preprocessor {
     pub(crate) base: Option<(TileConfig, BaseConfig)>,
+    /// The lower priority sources of the height data, in the order of decreasing priority.
+    pub(crate) fallbacks: Vec<FallbackConfig>,
+    pub(crate) water: Option<(TileConfig, WaterConfig)>,
+    pub(crate) surface: Option<(TileConfig, SurfaceConfig)>,
     pub(crate) attachments: Vec<(TileConfig, AttachmentConfig)>,
+    pub(crate) virtual_textures: Vec<(TileConfig, VirtualTextureConfig)>,
 }
 
 impl Preprocessor {
+    /// Returns the names of the steps, that are run by the preprocessor, in order.
+    pub fn steps(&self) -> Vec<String> {
+        let mut steps = Vec::new();
+
+        if self.base.is_some() {
+            steps.push("base".to_string());
+        }
+        if self.water.is_some() {
+            steps.push("water".to_string());
+        }
+        if self.surface.is_some() {
+            steps.push("surface".to_string());
+        }
+
+        steps.extend(
+            self.attachments
+                .iter()
+                .map(|(_, attachment)| attachment.name.clone()),
+        );
+        steps.extend(
+            self.virtual_textures
+                .iter()
+                .map(|(_, virtual_texture)| virtual_texture.name.clone()),
+        );
+
+        steps
+    }
+
     /// Preprocesses all attachments of the terrain.
     pub fn preprocess(self, config: &TerrainConfig) {
-        if let Some(base) = self.base {
-            preprocess_base(config, &base.0, &base.1);
+        self.preprocess_with_progress(config, |_, _, _| {});
+    }
+
+    /// Preprocesses all attachments of the terrain.
+    ///
+    /// The progress callback is invoked with the index of the step, the number of steps
+    /// and the name of the step, before each of the [`steps`](Self::steps) starts.
+    pub fn preprocess_with_progress(
+        self,
+        config: &TerrainConfig,
+        mut progress: impl FnMut(usize, usize, &str),
+    ) {
+        let steps = self.steps();
+        let mut steps = steps.iter().enumerate();
+        let count = steps.len();
+        let mut next_step = || {
+            let (index, name) = steps.next().unwrap();
+            progress(index, count, name);
+        };
+
+        if let Some(base) = &self.base {
+            next_step();
+            preprocess_base(config, &base.0, &base.1, &self.fallbacks);
+        }
+
+        if let Some((tile, water)) = &self.water {
+            let (_, base) = self
+                .base
+                .as_ref()
+                .expect("The water attachment requires the base attachment.");
+
+            next_step();
+            preprocess_water(config, tile, base, water);
+        }
+
+        if let Some((tile, surface)) = &self.surface {
+            let (_, base) = self
+                .base
+                .as_ref()
+                .expect("The surface attachment requires the base attachment.");
+
+            next_step();
+            preprocess_surface(config, tile, base, surface);
         }
 
         for (tile, attachment) in self.attachments {
+            next_step();
             preprocess_attachment(config, &tile, &attachment);
         }
 
+        for (tile, virtual_texture) in self.virtual_textures {
+            next_step();
+            preprocess_virtual_texture(config, &tile, &virtual_texture);
+        }
+
         save_config(config);
     }
 }
@@ -136,3 +307,5 @@ pub type Rgb8Image = ImageBuffer<Rgb<u8>, Vec<u8>>;
 pub type Rgba8Image = ImageBuffer<Rgba<u8>, Vec<u8>>;
 pub type R16Image = ImageBuffer<Luma<u16>, Vec<u16>>;
 pub type Rg16Image = ImageBuffer<LumaA<u16>, Vec<u16>>;
+pub type Rg8Image = ImageBuffer<LumaA<u8>, Vec<u8>>;
+pub type Rgba16Image = ImageBuffer<Rgba<u16>, Vec<u16>>;
@@ -0,0 +1,27 @@
+/// The height value, that marks texels without data.
+/// It is reserved, thus source tiles have to mark their texels without data with it and
+/// store valid heights below it. Missing tiles are filled with it as well.
+pub const NODATA: u16 = u16::MAX;
+
+/// Determines how texels of the height data without data are handled.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Nodata {
+    /// All heights are valid, thus missing tiles are treated as terrain at a height of zero
+    /// and texels with the [`NODATA`] value as terrain at the maximum height.
+    #[default]
+    Ignore,
+    /// Texels without data are excluded from the down-sampling and the minmax information
+    /// and discarded by the renderer.
+    Discard,
+    /// Texels without data are filled from the fallback sources in the order of their priority,
+    /// before the height data is down-sampled.
+    /// Texels not covered by any of the sources are discarded.
+    Fallback,
+}
+
+impl Nodata {
+    /// Whether texels with the [`NODATA`] value are treated as holes.
+    pub fn is_enabled(self) -> bool {
+        self != Nodata::Ignore
+    }
+}
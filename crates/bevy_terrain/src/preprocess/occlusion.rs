@@ -0,0 +1,306 @@
+//! Bakes the ambient occlusion and the horizon angles of the terrain from its height data.
+
+use crate::{
+    preprocess::{
+        file_io::{
+            format_directory, format_node_path, iterate_directory, load_image, load_or_create_node,
+            reset_directory, save_image,
+        },
+        nodata::NODATA,
+        stitch::stitch_layer,
+        BaseConfig, R16Image, UVec2Utils,
+    },
+    terrain_data::{calc_node_id, AttachmentConfig, NodeCoordinate, NodeId},
+    TerrainConfig,
+};
+use bevy::prelude::*;
+use image::{Luma, Rgba};
+use lru::LruCache;
+use std::{f32::consts::TAU, num::NonZeroUsize};
+
+/// The configuration of the ambient occlusion baking.
+#[derive(Clone, Copy, Debug)]
+pub struct OcclusionConfig {
+    /// The number of directions in which the horizon is searched, rounded up to a multiple of four.
+    pub direction_count: u32,
+    /// The maximal distance in world units, up to which the terrain occludes itself.
+    pub distance: f32,
+    /// Whether to additionally store the horizon angles in the four cardinal directions,
+    /// which can be used for soft shadowing by the sun.
+    pub horizon: bool,
+}
+
+impl Default for OcclusionConfig {
+    fn default() -> Self {
+        Self {
+            direction_count: 8,
+            distance: 1024.0,
+            horizon: false,
+        }
+    }
+}
+
+/// Samples the height data of all nodes of the terrain, including the ones adjacent to the
+/// node currently being processed, by caching the recently used nodes.
+struct HeightSampler<'a> {
+    directory: &'a str,
+    attachment: &'a AttachmentConfig,
+    height: f32,
+    /// Whether texels without data are treated like the edge of the terrain.
+    nodata: bool,
+    cache: LruCache<NodeId, Option<R16Image>>,
+}
+
+impl<'a> HeightSampler<'a> {
+    fn new(
+        config: &TerrainConfig,
+        directory: &'a str,
+        attachment: &'a AttachmentConfig,
+        nodata: bool,
+    ) -> Self {
+        Self {
+            directory,
+            attachment,
+            height: config.height,
+            nodata,
+            cache: LruCache::new(NonZeroUsize::new(64).unwrap()),
+        }
+    }
+
+    /// Returns the height of the texel with the coordinates in texels of the entire lod.
+    fn texel(&mut self, lod: u32, x: i32, y: i32) -> Option<f32> {
+        if x < 0 || y < 0 {
+            return None;
+        }
+
+        let center_size = self.attachment.center_size;
+        let (x, y) = (x as u32, y as u32);
+        let (node_x, node_y) = (x / center_size, y / center_size);
+        let node_id = calc_node_id(lod, node_x, node_y);
+
+        if !self.cache.contains(&node_id) {
+            let path = format_node_path(self.directory, lod, node_x, node_y);
+            let image =
+                load_image(&path, self.attachment.file_format).map(|image| image.into_luma16());
+
+            self.cache.put(node_id, image);
+        }
+
+        let image = self.cache.get(&node_id).unwrap().as_ref()?;
+        let Luma([value]) = *image.get_pixel(
+            x % center_size + self.attachment.border_size,
+            y % center_size + self.attachment.border_size,
+        );
+
+        if self.nodata && value == NODATA {
+            return None;
+        }
+
+        Some(value as f32 / u16::MAX as f32 * self.height)
+    }
+
+    /// Bilinearly samples the height at the world position using the data of the lod.
+    fn sample(&mut self, lod: u32, position: Vec2) -> Option<f32> {
+        let position = position / (1 << lod) as f32 - 0.5;
+        let texel = position.floor();
+        let ratio = position - texel;
+        let (x, y) = (texel.x as i32, texel.y as i32);
+
+        let top_left = self.texel(lod, x, y)?;
+        let top_right = self.texel(lod, x + 1, y)?;
+        let bottom_left = self.texel(lod, x, y + 1)?;
+        let bottom_right = self.texel(lod, x + 1, y + 1)?;
+
+        let top = top_left + (top_right - top_left) * ratio.x;
+        let bottom = bottom_left + (bottom_right - bottom_left) * ratio.x;
+
+        Some(top + (bottom - top) * ratio.y)
+    }
+}
+
+/// Bakes the ambient occlusion and the horizon attachment of the terrain.
+struct OcclusionBaker<'a> {
+    config: &'a TerrainConfig,
+    occlusion: &'a OcclusionConfig,
+    sampler: HeightSampler<'a>,
+    directions: Vec<Vec2>,
+    occlusion_directory: String,
+    horizon_directory: String,
+    occlusion_attachment: AttachmentConfig,
+    horizon_attachment: AttachmentConfig,
+}
+
+impl<'a> OcclusionBaker<'a> {
+    /// Searches the horizon in the direction and returns the sine of its elevation angle.
+    /// The step size increases with the distance and the samples are taken from
+    /// successively coarser lods.
+    fn horizon(&mut self, lod: u32, position: Vec2, height: f32, direction: Vec2) -> f32 {
+        let spacing = (1 << lod) as f32;
+        let mut distance = spacing;
+        let mut max_slope = 0.0_f32;
+
+        while distance <= self.occlusion.distance {
+            let sample_lod =
+                ((distance / 4.0).log2().floor() as u32).clamp(lod, self.config.lod_count - 1);
+            let sample_position = position + direction * distance;
+
+            // stop at the edge of the terrain or at a hole
+            let sample_height = match self.sampler.sample(sample_lod, sample_position) {
+                Some(sample_height) => sample_height,
+                None => break,
+            };
+
+            max_slope = max_slope.max((sample_height - height) / distance);
+            distance = (distance * 1.5).max(distance + spacing);
+        }
+
+        max_slope / (1.0 + max_slope * max_slope).sqrt()
+    }
+
+    /// Bakes the ambient occlusion (and the horizon angles) of all nodes of the lod.
+    /// Only the center of each node is computed, the borders are filled in by stitching afterwards.
+    fn bake_lod(&mut self, height_directory: &str, lod: u32) {
+        let center_size = self.occlusion_attachment.center_size;
+        let border_size = self.occlusion_attachment.border_size;
+        let direction_count = self.directions.len();
+        let mut horizons = Vec::with_capacity(direction_count);
+
+        for (height_name, _) in iterate_directory(height_directory) {
+            let coord = NodeCoordinate::from(height_name.parse::<NodeId>().unwrap());
+
+            if coord.lod != lod {
+                continue;
+            }
+
+            let occlusion_path =
+                format_node_path(&self.occlusion_directory, coord.lod, coord.x, coord.y);
+            let horizon_path =
+                format_node_path(&self.horizon_directory, coord.lod, coord.x, coord.y);
+
+            let mut occlusion_image =
+                load_or_create_node(&occlusion_path, &self.occlusion_attachment);
+            let mut horizon_image = self
+                .occlusion
+                .horizon
+                .then(|| load_or_create_node(&horizon_path, &self.horizon_attachment));
+
+            for (x, y) in UVec2::ZERO.product(UVec2::splat(center_size)) {
+                let texel = UVec2::new(coord.x, coord.y) * center_size + UVec2::new(x, y);
+                let position = (texel.as_vec2() + 0.5) * (1 << lod) as f32;
+                let height = self
+                    .sampler
+                    .texel(lod, texel.x as i32, texel.y as i32)
+                    .unwrap_or(0.0);
+
+                // reuse the buffer of the horizons for all texels
+                horizons.clear();
+                horizons.extend(
+                    (0..direction_count)
+                        .map(|i| self.horizon(lod, position, height, self.directions[i])),
+                );
+
+                // the cosine weighted visibility of a horizon with the elevation angle θ is 1 - sin²(θ)
+                let visibility = 1.0
+                    - horizons.iter().map(|sin| sin * sin).sum::<f32>() / direction_count as f32;
+
+                occlusion_image.as_mut_luma16().unwrap().put_pixel(
+                    x + border_size,
+                    y + border_size,
+                    Luma([(visibility * u16::MAX as f32) as u16]),
+                );
+
+                if let Some(horizon_image) = &mut horizon_image {
+                    // the horizons in the +x, +z, -x and -z direction
+                    let quarter = direction_count / 4;
+                    let value =
+                        [0, 1, 2, 3].map(|i| (horizons[i * quarter] * u16::MAX as f32) as u16);
+
+                    horizon_image.as_mut_rgba16().unwrap().put_pixel(
+                        x + border_size,
+                        y + border_size,
+                        Rgba(value),
+                    );
+                }
+            }
+
+            save_image(
+                &occlusion_path,
+                &occlusion_image,
+                &self.occlusion_attachment,
+            );
+
+            if let Some(horizon_image) = horizon_image {
+                save_image(&horizon_path, &horizon_image, &self.horizon_attachment);
+            }
+        }
+    }
+}
+
+/// Bakes the ambient occlusion and optionally the horizon attachment for every lod.
+/// Each lod is computed from its own height data, up to the same distance,
+/// which results in lod appropriate and consistent values.
+pub(crate) fn bake_occlusion(
+    config: &TerrainConfig,
+    base: &BaseConfig,
+    occlusion: &OcclusionConfig,
+    first: UVec2,
+    last: UVec2,
+) {
+    let height_attachment = base.height_attachment();
+    let height_directory = format_directory(&config.path, "height");
+
+    let direction_count = (occlusion.direction_count.max(1) + 3) / 4 * 4;
+
+    let mut baker = OcclusionBaker {
+        config,
+        occlusion,
+        sampler: HeightSampler::new(
+            config,
+            &height_directory,
+            &height_attachment,
+            base.nodata.is_enabled(),
+        ),
+        directions: (0..direction_count)
+            .map(|i| Vec2::from_angle(TAU * i as f32 / direction_count as f32))
+            .collect(),
+        occlusion_directory: format_directory(&config.path, "occlusion"),
+        horizon_directory: format_directory(&config.path, "horizon"),
+        occlusion_attachment: base.occlusion_attachment(),
+        horizon_attachment: base.horizon_attachment(),
+    };
+
+    reset_directory(&baker.occlusion_directory);
+
+    if occlusion.horizon {
+        reset_directory(&baker.horizon_directory);
+    }
+
+    let (mut first, mut last) = (first, last);
+
+    for lod in 0..config.lod_count {
+        if lod != 0 {
+            first = first.div_floor(2);
+            last = last.div_ceil(2);
+        }
+
+        baker.bake_lod(&height_directory, lod);
+
+        stitch_layer(
+            &baker.occlusion_directory,
+            &baker.occlusion_attachment,
+            lod,
+            first,
+            last,
+        );
+
+        if occlusion.horizon {
+            stitch_layer(
+                &baker.horizon_directory,
+                &baker.horizon_attachment,
+                lod,
+                first,
+                last,
+            );
+        }
+    }
+}
@@ -31,7 +31,7 @@ fn tile_to_node(
             x,
             y,
         ),
-        AttachmentFormat::Rgba8 => imageops::replace(
+        AttachmentFormat::Rgba8 | AttachmentFormat::Rgba8Linear => imageops::replace(
             node_image.as_mut_rgba8().unwrap(),
             tile_image.as_rgba8().unwrap(),
             x,
@@ -49,9 +49,22 @@ fn tile_to_node(
             x,
             y,
         ),
+        AttachmentFormat::Rg8 => imageops::replace(
+            node_image.as_mut_luma_alpha8().unwrap(),
+            tile_image.as_luma_alpha8().unwrap(),
+            x,
+            y,
+        ),
+        AttachmentFormat::Rgba16 => imageops::replace(
+            node_image.as_mut_rgba16().unwrap(),
+            tile_image.as_rgba16().unwrap(),
+            x,
+            y,
+        ),
     };
 }
 
+/// Splits the tile into the nodes of the finest lod of the attachment.
 fn split_tile(directory: &str, tile: &TileConfig, attachment: &AttachmentConfig, offset: UVec2) {
     let tile_image = load_image(&tile.path, tile.file_format).expect("Could not load tile.");
 
@@ -60,7 +73,7 @@ fn split_tile(directory: &str, tile: &TileConfig, attachment: &AttachmentConfig,
     let last = (offset + tile.size + attachment.border_size).div_ceil(attachment.center_size);
 
     for (x, y) in first.product(last) {
-        let node_path = format_node_path(directory, 0, x, y);
+        let node_path = format_node_path(directory, attachment.min_lod, x, y);
 
         let mut node_image = load_or_create_node(&node_path, attachment);
 
@@ -45,7 +45,7 @@ fn stitch(
                 node_image.put_pixel(x1, y1, *adjacent_image.get_pixel(x2, y2));
             }
         }
-        AttachmentFormat::Rgba8 => {
+        AttachmentFormat::Rgba8 | AttachmentFormat::Rgba8Linear => {
             let node_image = node_image.as_mut_rgba8().unwrap();
             let adjacent_image = adjacent_image.as_rgba8().unwrap();
 
@@ -65,6 +65,22 @@ fn stitch(
             let node_image = node_image.as_mut_luma_alpha16().unwrap();
             let adjacent_image = adjacent_image.as_luma_alpha16().unwrap();
 
+            for (x1, y1, x2, y2) in iter {
+                node_image.put_pixel(x1, y1, *adjacent_image.get_pixel(x2, y2));
+            }
+        }
+        AttachmentFormat::Rg8 => {
+            let node_image = node_image.as_mut_luma_alpha8().unwrap();
+            let adjacent_image = adjacent_image.as_luma_alpha8().unwrap();
+
+            for (x1, y1, x2, y2) in iter {
+                node_image.put_pixel(x1, y1, *adjacent_image.get_pixel(x2, y2));
+            }
+        }
+        AttachmentFormat::Rgba16 => {
+            let node_image = node_image.as_mut_rgba16().unwrap();
+            let adjacent_image = adjacent_image.as_rgba16().unwrap();
+
             for (x1, y1, x2, y2) in iter {
                 node_image.put_pixel(x1, y1, *adjacent_image.get_pixel(x2, y2));
             }
@@ -101,7 +117,7 @@ fn extend(node_image: &mut DynamicImage, attachment: &AttachmentConfig, directio
                 node_image.put_pixel(x1, y1, *node_image.get_pixel(x2, y2));
             }
         }
-        AttachmentFormat::Rgba8 => {
+        AttachmentFormat::Rgba8 | AttachmentFormat::Rgba8Linear => {
             let node_image = node_image.as_mut_rgba8().unwrap();
 
             for (x1, y1, x2, y2) in iter {
@@ -118,6 +134,20 @@ fn extend(node_image: &mut DynamicImage, attachment: &AttachmentConfig, directio
         AttachmentFormat::Rg16 => {
             let node_image = node_image.as_mut_luma_alpha16().unwrap();
 
+            for (x1, y1, x2, y2) in iter {
+                node_image.put_pixel(x1, y1, *node_image.get_pixel(x2, y2));
+            }
+        }
+        AttachmentFormat::Rg8 => {
+            let node_image = node_image.as_mut_luma_alpha8().unwrap();
+
+            for (x1, y1, x2, y2) in iter {
+                node_image.put_pixel(x1, y1, *node_image.get_pixel(x2, y2));
+            }
+        }
+        AttachmentFormat::Rgba16 => {
+            let node_image = node_image.as_mut_rgba16().unwrap();
+
             for (x1, y1, x2, y2) in iter {
                 node_image.put_pixel(x1, y1, *node_image.get_pixel(x2, y2));
             }
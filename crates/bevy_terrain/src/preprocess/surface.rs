@@ -0,0 +1,233 @@
+//! Generates the surface attachment of the terrain, which stores the height of buildings and
+//! vegetation above the ground, from a digital surface model (DSM) and the height data.
+
+use crate::{
+    preprocess::{
+        down_sample::{down_sample_layer, linear, minmax},
+        file_io::{format_directory, format_node_path, load_image, reset_directory, save_image},
+        nodata::NODATA,
+        split::split_tiles,
+        stitch::stitch_layer,
+        BaseConfig, TileConfig, UVec2Utils,
+    },
+    skip_none,
+    terrain_data::{AttachmentConfig, AttachmentFormat, FileFormat},
+    TerrainConfig,
+};
+use bevy::prelude::*;
+use image::{DynamicImage, ImageBuffer, Luma, LumaA};
+use std::fs;
+
+/// The configuration of the surface attachment.
+///
+/// The surface attachment stores the normalized surface model (nDSM), which is the difference
+/// between the digital surface model and the height data of the terrain (the digital terrain model).
+/// It is generated from DSM tiles, that share the height scale of the height tiles,
+/// which is why its layout has to match the height attachment.
+/// The surface is added to the maximum of the minmax attachment, so that the culling of the
+/// terrain tiles accounts for the extruded geometry.
+#[derive(Clone, Copy, Debug)]
+pub struct SurfaceConfig {
+    pub texture_size: u32,
+    pub border_size: u32,
+    pub mip_level_count: u32,
+    pub file_format: FileFormat,
+    /// The minimal height of the surface above the terrain in world units.
+    /// Lower values are considered noise of the surface model and discarded.
+    pub min_height: f32,
+}
+
+impl SurfaceConfig {
+    /// Creates a surface config, that matches the height attachment of the base.
+    pub fn new(base: &BaseConfig) -> Self {
+        Self {
+            texture_size: base.texture_size,
+            border_size: base.border_size,
+            mip_level_count: base.mip_level_count,
+            file_format: base.file_format,
+            min_height: 2.0,
+        }
+    }
+
+    /// Returns the surface attachment.
+    ///
+    /// If no surface model is available, it can be added to the terrain without any data,
+    /// to keep the attachment layout of a material, that extrudes the surface.
+    pub fn attachment(&self) -> AttachmentConfig {
+        let mut attachment = AttachmentConfig::new(
+            "surface".to_string(),
+            self.texture_size,
+            self.border_size,
+            self.mip_level_count,
+            AttachmentFormat::R16,
+        );
+
+        attachment.file_format = self.file_format;
+        attachment
+    }
+
+    /// The temporary attachment, into which the surface model is split.
+    fn dsm_attachment(&self) -> AttachmentConfig {
+        let mut attachment = AttachmentConfig::new(
+            "dsm".to_string(),
+            self.texture_size,
+            self.border_size,
+            self.mip_level_count,
+            AttachmentFormat::R16,
+        );
+
+        attachment.file_format = self.file_format;
+        attachment
+    }
+}
+
+/// Subtracts the height data from the surface model of the first lod.
+fn dsm_to_surface(
+    config: &TerrainConfig,
+    base: &BaseConfig,
+    surface: &SurfaceConfig,
+    first: UVec2,
+    last: UVec2,
+) {
+    let height_attachment = base.height_attachment();
+    let dsm_attachment = surface.dsm_attachment();
+    let surface_attachment = surface.attachment();
+
+    let height_directory = format_directory(&config.path, "height");
+    let dsm_directory = format_directory(&config.path, "dsm");
+    let surface_directory = format_directory(&config.path, "surface");
+
+    let min_height = (surface.min_height / config.height * u16::MAX as f32) as u16;
+
+    for (x, y) in first.product(last) {
+        let height_path = format_node_path(&height_directory, 0, x, y);
+        let dsm_path = format_node_path(&dsm_directory, 0, x, y);
+        let surface_path = format_node_path(&surface_directory, 0, x, y);
+
+        let height_image = skip_none!(load_image(&height_path, height_attachment.file_format));
+        let height_image = height_image.into_luma16();
+        let dsm_image = skip_none!(load_image(&dsm_path, dsm_attachment.file_format));
+        let dsm_image = dsm_image.into_luma16();
+
+        let surface_image = DynamicImage::from(ImageBuffer::from_fn(
+            height_image.width(),
+            height_image.height(),
+            |x, y| {
+                let height = height_image.get_pixel(x, y).0[0];
+                let dsm = dsm_image.get_pixel(x, y).0[0];
+
+                // holes of the terrain have no surface either
+                if base.nodata.is_enabled() && height == NODATA {
+                    return Luma([0]);
+                }
+
+                // texels without surface data are zero and thus result in no surface
+                match dsm.saturating_sub(height) {
+                    value if value < min_height => Luma([0]),
+                    value => Luma([value]),
+                }
+            },
+        ));
+
+        save_image(&surface_path, &surface_image, &surface_attachment);
+    }
+}
+
+/// Adds the surface to the maximum of the minmax information of the first lod.
+/// Texels without data keep their empty interval.
+fn surface_to_minmax(
+    config: &TerrainConfig,
+    base: &BaseConfig,
+    surface: &SurfaceConfig,
+    first: UVec2,
+    last: UVec2,
+) {
+    let minmax_attachment = base.minmax_attachment();
+    let surface_attachment = surface.attachment();
+
+    let minmax_directory = format_directory(&config.path, "minmax");
+    let surface_directory = format_directory(&config.path, "surface");
+
+    for (x, y) in first.product(last) {
+        let minmax_path = format_node_path(&minmax_directory, 0, x, y);
+        let surface_path = format_node_path(&surface_directory, 0, x, y);
+
+        let minmax_image = skip_none!(load_image(&minmax_path, minmax_attachment.file_format));
+        let minmax_image = minmax_image.into_luma_alpha16();
+        let surface_image = skip_none!(load_image(&surface_path, surface_attachment.file_format));
+        let surface_image = surface_image.into_luma16();
+
+        let minmax_image = DynamicImage::from(ImageBuffer::from_fn(
+            minmax_image.width(),
+            minmax_image.height(),
+            |x, y| {
+                let LumaA([min, max]) = *minmax_image.get_pixel(x, y);
+                let surface = surface_image.get_pixel(x, y).0[0];
+
+                if min > max {
+                    LumaA([min, max])
+                } else {
+                    LumaA([min, max.saturating_add(surface)])
+                }
+            },
+        ));
+
+        save_image(&minmax_path, &minmax_image, &minmax_attachment);
+    }
+}
+
+/// Generates the surface attachment from the DSM tiles and includes it in the minmax attachment.
+/// Requires the height attachment to be preprocessed beforehand.
+pub(crate) fn preprocess_surface(
+    config: &TerrainConfig,
+    tile: &TileConfig,
+    base: &BaseConfig,
+    surface: &SurfaceConfig,
+) {
+    let dsm_attachment = surface.dsm_attachment();
+    let surface_attachment = surface.attachment();
+
+    let dsm_directory = format_directory(&config.path, "dsm");
+    let surface_directory = format_directory(&config.path, "surface");
+
+    reset_directory(&dsm_directory);
+    reset_directory(&surface_directory);
+
+    let (mut first, mut last) = split_tiles(&dsm_directory, tile, &dsm_attachment);
+
+    dsm_to_surface(config, base, surface, first, last);
+
+    fs::remove_dir_all(&dsm_directory).unwrap();
+
+    stitch_layer(&surface_directory, &surface_attachment, 0, first, last);
+
+    surface_to_minmax(config, base, surface, first, last);
+
+    let minmax_attachment = base.minmax_attachment();
+    let minmax_directory = format_directory(&config.path, "minmax");
+
+    for lod in 1..config.lod_count {
+        first = first.div_floor(2);
+        last = last.div_ceil(2);
+
+        down_sample_layer(
+            linear,
+            &surface_directory,
+            &surface_attachment,
+            lod,
+            first,
+            last,
+        );
+        stitch_layer(&surface_directory, &surface_attachment, lod, first, last);
+
+        down_sample_layer(
+            minmax,
+            &minmax_directory,
+            &minmax_attachment,
+            lod,
+            first,
+            last,
+        );
+        stitch_layer(&minmax_directory, &minmax_attachment, lod, first, last);
+    }
+}
@@ -0,0 +1,176 @@
+//! Generates the water attachment of the terrain from a water mask and the height data.
+
+use crate::{
+    preprocess::{
+        down_sample::{self, down_sample_layer},
+        file_io::{format_directory, format_node_path, load_image, reset_directory, save_image},
+        split::split_tiles,
+        stitch::stitch_layer,
+        BaseConfig, TileConfig, UVec2Utils,
+    },
+    skip_none,
+    terrain_data::{AttachmentConfig, AttachmentFormat, FileFormat},
+    TerrainConfig,
+};
+use bevy::prelude::*;
+use image::{DynamicImage, ImageBuffer, LumaA};
+use std::fs;
+
+/// The configuration of the water attachment.
+///
+/// The water attachment stores whether there is water (red channel) and the height
+/// of the water surface (green channel).
+/// In the coarser lods the red channel stores the fraction of the texel covered by water
+/// and the green channel the average water level of the wet texels.
+/// It is generated from a water mask (16 bit grayscale tiles, where non-zero values mark water)
+/// and the height data of the terrain, which is why its layout has to match the height attachment.
+#[derive(Clone, Copy, Debug)]
+pub struct WaterConfig {
+    pub texture_size: u32,
+    pub border_size: u32,
+    pub mip_level_count: u32,
+    pub file_format: FileFormat,
+    /// The radius in texels, in which the water level is determined from the lowest
+    /// surrounding water texel.
+    /// The water surface is also extended this far beyond the mask, so that it intersects
+    /// the shore, instead of ending at the edge of the mask.
+    pub level_radius: u32,
+}
+
+impl WaterConfig {
+    /// Creates a water config, that matches the height attachment of the base.
+    pub fn new(base: &BaseConfig) -> Self {
+        Self {
+            texture_size: base.texture_size,
+            border_size: base.border_size,
+            mip_level_count: base.mip_level_count,
+            file_format: base.file_format,
+            level_radius: 2,
+        }
+    }
+
+    pub(crate) fn attachment(&self) -> AttachmentConfig {
+        let mut attachment = AttachmentConfig::new(
+            "water".to_string(),
+            self.texture_size,
+            self.border_size,
+            self.mip_level_count,
+            AttachmentFormat::Rg16,
+        );
+
+        attachment.file_format = self.file_format;
+        attachment
+    }
+
+    /// The temporary attachment, into which the water mask is split.
+    fn mask_attachment(&self) -> AttachmentConfig {
+        let mut attachment = AttachmentConfig::new(
+            "water_mask".to_string(),
+            self.texture_size,
+            self.border_size,
+            self.mip_level_count,
+            AttachmentFormat::R16,
+        );
+
+        attachment.file_format = self.file_format;
+        attachment
+    }
+}
+
+/// Combines the water mask with the height data of the first lod.
+///
+/// Each texel, that has water within the `level_radius`, is set to the height of the
+/// lowest water texel in that window. All other texels are marked as dry and set to the height
+/// of the terrain.
+fn mask_to_water(
+    config: &TerrainConfig,
+    base: &BaseConfig,
+    water: &WaterConfig,
+    first: UVec2,
+    last: UVec2,
+) {
+    let height_attachment = base.height_attachment();
+    let mask_attachment = water.mask_attachment();
+    let water_attachment = water.attachment();
+
+    let height_directory = format_directory(&config.path, "height");
+    let mask_directory = format_directory(&config.path, "water_mask");
+    let water_directory = format_directory(&config.path, "water");
+
+    for (x, y) in first.product(last) {
+        let height_path = format_node_path(&height_directory, 0, x, y);
+        let mask_path = format_node_path(&mask_directory, 0, x, y);
+        let water_path = format_node_path(&water_directory, 0, x, y);
+
+        let height_image = skip_none!(load_image(&height_path, height_attachment.file_format));
+        let height_image = height_image.into_luma16();
+        let mask_image = skip_none!(load_image(&mask_path, mask_attachment.file_format));
+        let mask_image = mask_image.into_luma16();
+
+        let size = height_image.width() as i32;
+        let radius = water.level_radius as i32;
+
+        let water_image = DynamicImage::from(ImageBuffer::from_fn(
+            height_image.width(),
+            height_image.height(),
+            |x, y| {
+                let (x, y) = (x as i32, y as i32);
+
+                let level = (-radius..=radius)
+                    .flat_map(|dy| (-radius..=radius).map(move |dx| (x + dx, y + dy)))
+                    .filter(|&(x, y)| x >= 0 && y >= 0 && x < size && y < size)
+                    .filter(|&(x, y)| mask_image.get_pixel(x as u32, y as u32).0[0] != 0)
+                    .map(|(x, y)| height_image.get_pixel(x as u32, y as u32).0[0])
+                    .min();
+
+                match level {
+                    Some(level) => LumaA([u16::MAX, level]),
+                    None => LumaA([0, height_image.get_pixel(x as u32, y as u32).0[0]]),
+                }
+            },
+        ));
+
+        save_image(&water_path, &water_image, &water_attachment);
+    }
+}
+
+/// Generates the water attachment from the water mask tiles.
+/// Requires the height attachment to be preprocessed beforehand.
+pub(crate) fn preprocess_water(
+    config: &TerrainConfig,
+    tile: &TileConfig,
+    base: &BaseConfig,
+    water: &WaterConfig,
+) {
+    let mask_attachment = water.mask_attachment();
+    let water_attachment = water.attachment();
+
+    let mask_directory = format_directory(&config.path, "water_mask");
+    let water_directory = format_directory(&config.path, "water");
+
+    reset_directory(&mask_directory);
+    reset_directory(&water_directory);
+
+    let (mut first, mut last) = split_tiles(&mask_directory, tile, &mask_attachment);
+
+    mask_to_water(config, base, water, first, last);
+
+    fs::remove_dir_all(&mask_directory).unwrap();
+
+    stitch_layer(&water_directory, &water_attachment, 0, first, last);
+
+    for lod in 1..config.lod_count {
+        first = first.div_floor(2);
+        last = last.div_ceil(2);
+
+        down_sample_layer(
+            down_sample::water,
+            &water_directory,
+            &water_attachment,
+            lod,
+            first,
+            last,
+        );
+        stitch_layer(&water_directory, &water_attachment, lod, first, last);
+    }
+}
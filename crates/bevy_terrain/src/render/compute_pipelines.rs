@@ -5,9 +5,11 @@ use crate::{
         shaders::{PREPARE_INDIRECT_SHADER, REFINE_TILES_SHADER},
         terrain_data::terrain_bind_group_layout,
         terrain_view_data::TerrainViewConfigUniform,
-        terrain_view_data::TerrainViewData,
-        CULL_DATA_LAYOUT, PREPARE_INDIRECT_LAYOUT, REFINE_TILES_LAYOUT,
+        terrain_view_data::{TerrainViewData, STATISTICS_COPIED, STATISTICS_IDLE},
+        TerrainRenderSettings, CULL_DATA_LAYOUT, PREPARE_INDIRECT_LAYOUT, REFINE_TILES_LAYOUT,
+        STATISTICS_BUFFER_SIZE, STATISTICS_OFFSET,
     },
+    skip_none,
     terrain::Terrain,
     DebugTerrain, TerrainComponents, TerrainData, TerrainView, TerrainViewComponents,
 };
@@ -23,6 +25,7 @@ use bevy::{
         renderer::{RenderContext, RenderDevice},
     },
 };
+use std::sync::atomic::Ordering;
 use strum::{EnumCount, IntoEnumIterator};
 use strum_macros::{EnumCount, EnumIter};
 
@@ -41,6 +44,8 @@ bitflags::bitflags! {
 pub struct TerrainComputePipelineFlags: u32 {
     const NONE               = 0;
     const TEST               = (2 << 0);
+    const OCCLUSION_CULLING  = (2 << 1);
+    const HORIZON_CULLING    = (2 << 2);
 }
 }
 
@@ -55,12 +60,35 @@ impl TerrainComputePipelineFlags {
         key
     }
 
+    pub fn from_settings(settings: &TerrainRenderSettings) -> Self {
+        let mut key = TerrainComputePipelineFlags::NONE;
+
+        if settings.occlusion_culling {
+            key |= TerrainComputePipelineFlags::OCCLUSION_CULLING;
+        }
+        if settings.horizon_culling {
+            key |= TerrainComputePipelineFlags::HORIZON_CULLING;
+        }
+
+        key
+    }
+
+    pub fn horizon_culling(&self) -> bool {
+        (self.bits & TerrainComputePipelineFlags::HORIZON_CULLING.bits) != 0
+    }
+
     pub fn shader_defs(&self) -> Vec<String> {
         let mut shader_defs = Vec::new();
 
         if (self.bits & TerrainComputePipelineFlags::TEST.bits) != 0 {
             shader_defs.push("TEST".to_string());
         }
+        if (self.bits & TerrainComputePipelineFlags::OCCLUSION_CULLING.bits) != 0 {
+            shader_defs.push("OCCLUSION_CULLING".to_string());
+        }
+        if self.horizon_culling() {
+            shader_defs.push("HORIZON_CULLING".to_string());
+        }
 
         shader_defs
     }
@@ -72,6 +100,8 @@ pub struct TerrainComputePipelines {
     pub(crate) refine_tiles_layout: BindGroupLayout,
     pub(crate) cull_data_layout: BindGroupLayout,
     pub(crate) terrain_layout: BindGroupLayout,
+    /// Bound in place of the depth pyramid for views without occlusion culling.
+    pub(crate) dummy_depth_pyramid: TextureView,
     prepare_indirect_shader: Handle<Shader>,
     refine_tiles_shader: Handle<Shader>,
 }
@@ -86,6 +116,18 @@ impl FromWorld for TerrainComputePipelines {
         let cull_data_layout = device.create_bind_group_layout(&CULL_DATA_LAYOUT);
         let terrain_layout = terrain_bind_group_layout(&device, config.attachment_count);
 
+        let dummy_depth_pyramid = device
+            .create_texture(&TextureDescriptor {
+                label: Some("dummy_depth_pyramid"),
+                size: Extent3d::default(),
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::R32Float,
+                usage: TextureUsages::TEXTURE_BINDING,
+            })
+            .create_view(&default());
+
         let prepare_indirect_shader = PREPARE_INDIRECT_SHADER.typed();
         let refine_tiles_shader = REFINE_TILES_SHADER.typed();
 
@@ -94,6 +136,7 @@ impl FromWorld for TerrainComputePipelines {
             refine_tiles_layout,
             cull_data_layout,
             terrain_layout,
+            dummy_depth_pyramid,
             prepare_indirect_shader,
             refine_tiles_shader,
         }
@@ -169,9 +212,11 @@ pub struct TerrainComputeNode {
         SResMut<PipelineCache>,
         SResMut<SpecializedComputePipelines<TerrainComputePipelines>>,
         SRes<TerrainComputePipelines>,
+        SRes<TerrainRenderSettings>,
         Option<SRes<DebugTerrain>>,
     )>,
     pipelines: [CachedComputePipelineId; TerrainComputePipelineId::COUNT],
+    flags: TerrainComputePipelineFlags,
 }
 
 impl FromWorld for TerrainComputeNode {
@@ -181,6 +226,7 @@ impl FromWorld for TerrainComputeNode {
             view_query: world.query_filtered(),
             system_state: SystemState::new(world),
             pipelines: [CachedComputePipelineId::INVALID; TerrainComputePipelineId::COUNT],
+            flags: TerrainComputePipelineFlags::NONE,
         }
     }
 }
@@ -223,9 +269,10 @@ impl render_graph::Node for TerrainComputeNode {
         self.terrain_query.update_archetypes(world);
         self.view_query.update_archetypes(world);
 
-        let (mut pipeline_cache, mut pipelines, pipeline, debug) = self.system_state.get_mut(world);
+        let (mut pipeline_cache, mut pipelines, pipeline, settings, debug) =
+            self.system_state.get_mut(world);
 
-        let mut flags = TerrainComputePipelineFlags::NONE;
+        let mut flags = TerrainComputePipelineFlags::from_settings(&settings);
 
         if let Some(debug) = &debug {
             flags |= TerrainComputePipelineFlags::from_debug(debug);
@@ -235,6 +282,8 @@ impl render_graph::Node for TerrainComputeNode {
             self.pipelines[id as usize] =
                 pipelines.specialize(&mut pipeline_cache, &pipeline, (id, flags));
         }
+
+        self.flags = flags;
     }
 
     fn run(
@@ -266,25 +315,54 @@ impl render_graph::Node for TerrainComputeNode {
             Some(pipelines) => pipelines,
         };
 
-        let pass = &mut context
-            .command_encoder
-            .begin_compute_pass(&ComputePassDescriptor::default());
-
-        for terrain in self.terrain_query.iter_manual(world) {
-            let terrain_data = terrain_data.get(&terrain).unwrap();
-            for view in self.view_query.iter_manual(world) {
-                let view_config = view_config_uniforms.get(&(terrain, view)).unwrap();
-                let view_data = terrain_view_data.get(&(terrain, view)).unwrap();
-                let culling_bind_group = culling_bind_groups.get(&(terrain, view)).unwrap();
-
-                TerrainComputeNode::tessellate_terrain(
-                    pass,
-                    pipelines,
-                    view_data,
-                    terrain_data,
-                    &culling_bind_group.value,
-                    view_config.refinement_count,
-                );
+        {
+            let pass = &mut context
+                .command_encoder
+                .begin_compute_pass(&ComputePassDescriptor::default());
+
+            for terrain in self.terrain_query.iter_manual(world) {
+                let terrain_data = terrain_data.get(&terrain).unwrap();
+                for view in self.view_query.iter_manual(world) {
+                    let view_config = view_config_uniforms.get(&(terrain, view)).unwrap();
+                    let view_data = terrain_view_data.get(&(terrain, view)).unwrap();
+                    // lights without an active shadow view are not culled and thus not tessellated
+                    let culling_bind_group = skip_none!(culling_bind_groups.get(&(terrain, view)));
+
+                    TerrainComputeNode::tessellate_terrain(
+                        pass,
+                        pipelines,
+                        view_data,
+                        terrain_data,
+                        &culling_bind_group.value,
+                        view_config.refinement_count,
+                    );
+                }
+            }
+        }
+
+        // copy the culling statistics, so that they can be read back after the submission
+        if self.flags.horizon_culling() {
+            for (terrain_view, view_data) in terrain_view_data.0.iter() {
+                // skip the buffers, which are still being mapped
+                if culling_bind_groups.get(terrain_view).is_some()
+                    && view_data
+                        .statistics_state
+                        .compare_exchange(
+                            STATISTICS_IDLE,
+                            STATISTICS_COPIED,
+                            Ordering::AcqRel,
+                            Ordering::Acquire,
+                        )
+                        .is_ok()
+                {
+                    context.command_encoder.copy_buffer_to_buffer(
+                        &view_data.parameter_buffer,
+                        STATISTICS_OFFSET,
+                        &view_data.statistics_buffer,
+                        0,
+                        STATISTICS_BUFFER_SIZE,
+                    );
+                }
             }
         }
 
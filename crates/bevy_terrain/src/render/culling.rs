@@ -1,9 +1,22 @@
-use crate::{terrain::Terrain, TerrainComputePipelines, TerrainView, TerrainViewComponents};
+use crate::{
+    render::{
+        occlusion::DepthPyramid,
+        terrain_view_data::{
+            TerrainViewData, STATISTICS_COPIED, STATISTICS_IDLE, STATISTICS_MAPPING,
+        },
+        TerrainRenderSettings,
+    },
+    terrain::Terrain,
+    TerrainComputePipelines, TerrainView, TerrainViewComponents,
+};
 use bevy::{
     math::Vec3Swizzles,
+    pbr::LightEntity,
     prelude::*,
     render::{render_resource::*, renderer::RenderDevice, view::ExtractedView},
+    utils::HashMap,
 };
+use std::sync::{atomic::Ordering, Arc, Mutex};
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Default, ShaderType)]
@@ -12,6 +25,14 @@ pub struct CullingData {
     pub(crate) view_proj: Mat4,
     pub(crate) model: Mat4,
     pub(crate) planes: [Vec4; 5],
+    /// The view projection matrix, with which the depth pyramid was rendered.
+    pub(crate) previous_view_proj: Mat4,
+    pub(crate) pyramid_size: Vec2,
+    /// The number of mip levels of the depth pyramid. Zero disables the occlusion culling.
+    pub(crate) pyramid_mip_count: u32,
+    /// Whether the view is the shadow view of a light, which disables the horizon and
+    /// occlusion culling, since tiles hidden from the light's position may still cast shadows.
+    pub(crate) light_view: u32,
 }
 
 #[derive(Component)]
@@ -38,21 +59,46 @@ pub(crate) fn queue_terrain_culling_bind_group(
     device: Res<RenderDevice>,
     compute_pipelines: Res<TerrainComputePipelines>,
     mut culling_bind_groups: ResMut<TerrainViewComponents<CullingBindGroup>>,
+    depth_pyramids: Res<TerrainViewComponents<DepthPyramid>>,
     terrain_query: Query<Entity, With<Terrain>>,
     view_query: Query<(Entity, &ExtractedView), With<TerrainView>>,
+    light_view_query: Query<(&ExtractedView, &LightEntity)>,
+    light_query: Query<(), With<TerrainView>>,
 ) {
-    for (view, extracted_view) in view_query.iter() {
+    // the shadow views of lights are culled in place of their terrain view light
+    let light_views = light_view_query
+        .iter()
+        .filter_map(|(extracted_view, light)| match *light {
+            LightEntity::Directional { light_entity } if light_query.contains(light_entity) => {
+                Some((light_entity, extracted_view, true))
+            }
+            _ => None,
+        });
+
+    let camera_views = view_query
+        .iter()
+        .map(|(view, extracted_view)| (view, extracted_view, false));
+
+    for (view, extracted_view, light_view) in camera_views.chain(light_views) {
         let view_proj =
             extracted_view.projection * extracted_view.transform.compute_matrix().inverse();
 
         let planes = planes(&view_proj);
 
         for terrain in terrain_query.iter() {
+            // only camera views have a depth pyramid of the last frame
+            let depth_pyramid = depth_pyramids.get(&(terrain, view));
+
             let culling_data = CullingData {
                 world_position: extracted_view.transform.translation().xyzx(),
                 view_proj,
                 model: default(),
                 planes,
+                previous_view_proj: depth_pyramid
+                    .map_or(default(), |pyramid| pyramid.previous_view_proj),
+                pyramid_size: depth_pyramid.map_or(default(), |pyramid| pyramid.size.as_vec2()),
+                pyramid_mip_count: depth_pyramid.map_or(0, |pyramid| pyramid.mip_count),
+                light_view: light_view as u32,
             };
 
             let mut buffer = encase::UniformBuffer::new(Vec::new());
@@ -65,10 +111,21 @@ pub(crate) fn queue_terrain_culling_bind_group(
             });
 
             let cull_bind_group = device.create_bind_group(&BindGroupDescriptor {
-                entries: &[BindGroupEntry {
-                    binding: 0,
-                    resource: buffer.as_entire_binding(),
-                }],
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::TextureView(
+                            depth_pyramid
+                                .map_or(&compute_pipelines.dummy_depth_pyramid, |pyramid| {
+                                    &pyramid.pyramid_view
+                                }),
+                        ),
+                    },
+                ],
                 label: None,
                 layout: &compute_pipelines.cull_data_layout,
             });
@@ -82,3 +139,85 @@ pub(crate) fn queue_terrain_culling_bind_group(
         }
     }
 }
+
+/// The statistics of the culling stages of a terrain view in the last frame.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CullingStatistics {
+    /// The number of tiles (of any size) removed by the horizon culling.
+    /// The shadow views of lights are not horizon culled, thus they do not contribute.
+    pub horizon_culled_tiles: u32,
+}
+
+/// The culling statistics of all terrain views, which are read back from the gpu.
+///
+/// This resource is shared between the main and the render world.
+/// The statistics are only gathered while the horizon culling is enabled.
+#[derive(Clone, Default, Resource)]
+pub struct TerrainCullingStatistics(Arc<Mutex<HashMap<(Entity, Entity), CullingStatistics>>>);
+
+impl TerrainCullingStatistics {
+    /// Returns the culling statistics of the terrain view in the last frame.
+    pub fn get(&self, terrain: Entity, view: Entity) -> Option<CullingStatistics> {
+        self.0.lock().unwrap().get(&(terrain, view)).copied()
+    }
+}
+
+/// Reads back the culling statistics, that were copied into the statistics buffers
+/// by the [`TerrainComputeNode`](crate::render::compute_pipelines::TerrainComputeNode).
+///
+/// The buffers are mapped asynchronously, thus the statistics lag behind by a few frames.
+pub(crate) fn read_culling_statistics(
+    device: Res<RenderDevice>,
+    settings: Res<TerrainRenderSettings>,
+    terrain_view_data: Res<TerrainViewComponents<TerrainViewData>>,
+    statistics: Res<TerrainCullingStatistics>,
+) {
+    if !settings.horizon_culling {
+        statistics.0.lock().unwrap().clear();
+        return;
+    }
+
+    for (&terrain_view, view_data) in terrain_view_data.0.iter() {
+        // only map the buffers, which have been written this frame
+        if view_data
+            .statistics_state
+            .compare_exchange(
+                STATISTICS_COPIED,
+                STATISTICS_MAPPING,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .is_err()
+        {
+            continue;
+        }
+
+        let buffer = view_data.statistics_buffer.clone();
+        let state = view_data.statistics_state.clone();
+        let statistics = statistics.0.clone();
+
+        device.map_buffer(
+            &view_data.statistics_buffer.slice(..),
+            MapMode::Read,
+            move |result| {
+                if result.is_ok() {
+                    let horizon_culled_tiles = {
+                        let data = buffer.slice(..).get_mapped_range();
+                        u32::from_le_bytes([data[0], data[1], data[2], data[3]])
+                    };
+
+                    buffer.unmap();
+
+                    statistics.lock().unwrap().insert(
+                        terrain_view,
+                        CullingStatistics {
+                            horizon_culled_tiles,
+                        },
+                    );
+                }
+
+                state.store(STATISTICS_IDLE, Ordering::Release);
+            },
+        );
+    }
+}
@@ -9,15 +9,39 @@ use crate::render::{
     culling::CullingData, terrain_data::TerrainConfigUniform,
     terrain_view_data::TerrainViewConfigUniform,
 };
-use bevy::render::render_resource::*;
+use bevy::{
+    prelude::*,
+    render::{extract_resource::ExtractResource, render_resource::*},
+};
 use std::mem;
 
 pub mod compute_pipelines;
 pub mod culling;
+pub mod occlusion;
 pub mod render_pipeline;
 pub mod shaders;
 pub mod terrain_data;
 pub mod terrain_view_data;
+pub mod water;
+
+/// The optional render passes of the terrain, which trade additional GPU work for less shading
+/// and geometry work.
+///
+/// They can be changed at runtime, e.g. by the [`TerrainDebugPlugin`](crate::debug::TerrainDebugPlugin).
+#[derive(Clone, Copy, Default, Resource, Reflect, ExtractResource)]
+#[reflect(Resource)]
+pub struct TerrainRenderSettings {
+    /// Renders the depth of the terrain before shading it, so that only the visible fragments
+    /// are shaded.
+    pub depth_prepass: bool,
+    /// Culls the tiles, that were occluded by the terrain in the last frame.
+    /// This requires the depth of each camera view to be rendered and reduced into a depth pyramid.
+    pub occlusion_culling: bool,
+    /// Culls the tiles, that are hidden behind the horizon of the terrain.
+    /// While enabled, the [`TerrainCullingStatistics`](crate::render::culling::TerrainCullingStatistics)
+    /// are read back from the gpu.
+    pub horizon_culling: bool,
+}
 
 pub(crate) const TERRAIN_CONFIG_SIZE: BufferAddress =
     mem::size_of::<TerrainConfigUniform>() as BufferAddress;
@@ -28,6 +52,8 @@ pub(crate) const CULL_DATA_BUFFER_SIZE: BufferAddress =
 pub(crate) const TILE_SIZE: BufferAddress = 6 * 4;
 pub(crate) const INDIRECT_BUFFER_SIZE: BufferAddress = 5 * 4;
 pub(crate) const PARAMETER_BUFFER_SIZE: BufferAddress = 7 * 4;
+pub(crate) const STATISTICS_OFFSET: BufferAddress = 4 * 4;
+pub(crate) const STATISTICS_BUFFER_SIZE: BufferAddress = 4;
 
 pub(crate) const PREPARE_INDIRECT_LAYOUT: BindGroupLayoutDescriptor = BindGroupLayoutDescriptor {
     label: None,
@@ -60,6 +86,73 @@ pub(crate) const CULL_DATA_LAYOUT: BindGroupLayoutDescriptor = BindGroupLayoutDe
             },
             count: None,
         },
+        // depth pyramid
+        BindGroupLayoutEntry {
+            binding: 1,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: false },
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        },
+    ],
+};
+
+pub(crate) const COPY_DEPTH_LAYOUT: BindGroupLayoutDescriptor = BindGroupLayoutDescriptor {
+    label: None,
+    entries: &[
+        // depth texture
+        BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Texture {
+                sample_type: TextureSampleType::Depth,
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        },
+        // pyramid mip
+        BindGroupLayoutEntry {
+            binding: 1,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::StorageTexture {
+                access: StorageTextureAccess::WriteOnly,
+                format: TextureFormat::R32Float,
+                view_dimension: TextureViewDimension::D2,
+            },
+            count: None,
+        },
+    ],
+};
+
+pub(crate) const DOWNSAMPLE_DEPTH_LAYOUT: BindGroupLayoutDescriptor = BindGroupLayoutDescriptor {
+    label: None,
+    entries: &[
+        // pyramid mip
+        BindGroupLayoutEntry {
+            binding: 1,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::StorageTexture {
+                access: StorageTextureAccess::WriteOnly,
+                format: TextureFormat::R32Float,
+                view_dimension: TextureViewDimension::D2,
+            },
+            count: None,
+        },
+        // source mip
+        BindGroupLayoutEntry {
+            binding: 2,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: false },
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        },
     ],
 };
 
@@ -130,7 +223,7 @@ pub(crate) const TERRAIN_VIEW_LAYOUT: BindGroupLayoutDescriptor = BindGroupLayou
         // view config
         BindGroupLayoutEntry {
             binding: 0,
-            visibility: ShaderStages::VERTEX_FRAGMENT,
+            visibility: ShaderStages::all(),
             ty: BindingType::Buffer {
                 ty: BufferBindingType::Uniform,
                 has_dynamic_offset: false,
@@ -141,7 +234,7 @@ pub(crate) const TERRAIN_VIEW_LAYOUT: BindGroupLayoutDescriptor = BindGroupLayou
         // quadtree
         BindGroupLayoutEntry {
             binding: 1,
-            visibility: ShaderStages::VERTEX_FRAGMENT,
+            visibility: ShaderStages::all(),
             ty: BindingType::Texture {
                 sample_type: TextureSampleType::Uint,
                 view_dimension: TextureViewDimension::D2Array,
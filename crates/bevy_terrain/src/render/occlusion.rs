@@ -0,0 +1,415 @@
+//! Hierarchical-Z occlusion culling of the terrain tiles.
+//!
+//! After the tiles have been refined, the depth of the terrain is rendered for each camera view
+//! into a separate depth texture, which is then reduced into a depth pyramid.
+//! In the next frame the `refine_tiles` pass projects the bounding boxes of the tiles using the
+//! previous view projection and discards all tiles, that lie behind the depth of the pyramid.
+//! Thus tiles occluded by the terrain itself (e.g. behind ridges) are never drawn.
+//!
+//! The depth pyramids are also used by the feedback pass of the
+//! [`virtual_texture`](crate::virtual_texture)s and the depth textures by the
+//! [`water`](crate::render::water) pass, which is why they are built before the main pass.
+//! Without the occlusion culling, they are only built for the terrains with a
+//! [`VirtualTexture`] or a [`TerrainWater`] component.
+
+use crate::{
+    render::{
+        shaders::DEPTH_PYRAMID_SHADER, terrain_view_data::TerrainViewData, water::TerrainWater,
+        TerrainRenderSettings, COPY_DEPTH_LAYOUT, DOWNSAMPLE_DEPTH_LAYOUT,
+    },
+    skip_none,
+    terrain::Terrain,
+    virtual_texture::VirtualTexture,
+    TerrainComponents, TerrainData, TerrainView, TerrainViewComponents,
+};
+use bevy::{
+    math::Vec4Swizzles,
+    pbr::LightMeta,
+    prelude::*,
+    render::{
+        render_graph::{self},
+        render_resource::*,
+        renderer::{RenderContext, RenderDevice},
+        view::{ExtractedView, ViewUniformOffset},
+        Extract,
+    },
+    utils::HashSet,
+};
+use std::num::NonZeroU32;
+
+/// The terrains, whose depth pyramids are required this frame, either by the occlusion culling,
+/// the feedback pass of their virtual texture or their water pass.
+#[derive(Default, Resource)]
+pub(crate) struct DepthPyramidsRequired(pub(crate) HashSet<Entity>);
+
+impl DepthPyramidsRequired {
+    pub(crate) fn contains(&self, terrain: Entity) -> bool {
+        self.0.contains(&terrain)
+    }
+}
+
+pub(crate) fn extract_depth_pyramids_required(
+    mut required: ResMut<DepthPyramidsRequired>,
+    settings: Extract<Res<TerrainRenderSettings>>,
+    terrain_query: Extract<
+        Query<(Entity, Option<&VirtualTexture>, Option<&TerrainWater>), With<Terrain>>,
+    >,
+) {
+    required.0.clear();
+    required.0.extend(
+        terrain_query
+            .iter()
+            .filter(|(_, virtual_texture, water)| {
+                settings.occlusion_culling || virtual_texture.is_some() || water.is_some()
+            })
+            .map(|(terrain, _, _)| terrain),
+    );
+}
+
+/// The depth-only pipeline and the material bind group used to render the depth of a terrain.
+pub struct TerrainDepthDraw {
+    pub(crate) pipeline: CachedRenderPipelineId,
+    pub(crate) material_bind_group: BindGroup,
+}
+
+/// The pipelines used to build the depth pyramids.
+#[derive(Resource)]
+pub struct DepthPyramidPipelines {
+    copy_depth_layout: BindGroupLayout,
+    downsample_depth_layout: BindGroupLayout,
+    copy_depth_pipeline: CachedComputePipelineId,
+    downsample_depth_pipeline: CachedComputePipelineId,
+}
+
+impl FromWorld for DepthPyramidPipelines {
+    fn from_world(world: &mut World) -> Self {
+        let device = world.resource::<RenderDevice>();
+
+        let copy_depth_layout = device.create_bind_group_layout(&COPY_DEPTH_LAYOUT);
+        let downsample_depth_layout = device.create_bind_group_layout(&DOWNSAMPLE_DEPTH_LAYOUT);
+
+        let mut pipeline_cache = world.resource_mut::<PipelineCache>();
+
+        let copy_depth_pipeline =
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: Some("copy_depth_pipeline".into()),
+                layout: Some(vec![copy_depth_layout.clone()]),
+                shader: DEPTH_PYRAMID_SHADER.typed(),
+                shader_defs: Vec::new(),
+                entry_point: "copy_depth".into(),
+            });
+
+        let downsample_depth_pipeline =
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: Some("downsample_depth_pipeline".into()),
+                layout: Some(vec![downsample_depth_layout.clone()]),
+                shader: DEPTH_PYRAMID_SHADER.typed(),
+                shader_defs: Vec::new(),
+                entry_point: "downsample".into(),
+            });
+
+        Self {
+            copy_depth_layout,
+            downsample_depth_layout,
+            copy_depth_pipeline,
+            downsample_depth_pipeline,
+        }
+    }
+}
+
+/// The depth of the terrain of the last frame of a camera view, reduced into a pyramid of
+/// the furthest depth values.
+pub struct DepthPyramid {
+    /// The size of the first mip level, which matches the viewport.
+    pub(crate) size: UVec2,
+    pub(crate) mip_count: u32,
+    /// The view projection matrix of the current frame.
+    pub(crate) view_proj: Mat4,
+    /// The view projection matrix, with which the current content of the pyramid was rendered.
+    pub(crate) previous_view_proj: Mat4,
+    pub(crate) depth_view: TextureView,
+    pub(crate) pyramid_view: TextureView,
+    copy_depth_bind_group: BindGroup,
+    downsample_depth_bind_groups: Vec<BindGroup>,
+}
+
+impl DepthPyramid {
+    fn new(
+        device: &RenderDevice,
+        pipelines: &DepthPyramidPipelines,
+        size: UVec2,
+        view_proj: Mat4,
+    ) -> Self {
+        let mip_count = 32 - size.max_element().leading_zeros();
+
+        let extent = Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        };
+
+        let depth_view = device
+            .create_texture(&TextureDescriptor {
+                label: Some("terrain_depth_texture"),
+                size: extent,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::Depth32Float,
+                usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            })
+            .create_view(&default());
+
+        let pyramid = device.create_texture(&TextureDescriptor {
+            label: Some("terrain_depth_pyramid"),
+            size: extent,
+            mip_level_count: mip_count,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::R32Float,
+            usage: TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING,
+        });
+
+        let pyramid_view = pyramid.create_view(&default());
+
+        let mip_views = (0..mip_count)
+            .map(|mip_level| {
+                pyramid.create_view(&TextureViewDescriptor {
+                    base_mip_level: mip_level,
+                    mip_level_count: NonZeroU32::new(1),
+                    ..default()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let copy_depth_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: &pipelines.copy_depth_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&depth_view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(&mip_views[0]),
+                },
+            ],
+        });
+
+        let downsample_depth_bind_groups = mip_views
+            .windows(2)
+            .map(|views| {
+                device.create_bind_group(&BindGroupDescriptor {
+                    label: None,
+                    layout: &pipelines.downsample_depth_layout,
+                    entries: &[
+                        BindGroupEntry {
+                            binding: 1,
+                            resource: BindingResource::TextureView(&views[1]),
+                        },
+                        BindGroupEntry {
+                            binding: 2,
+                            resource: BindingResource::TextureView(&views[0]),
+                        },
+                    ],
+                })
+            })
+            .collect();
+
+        Self {
+            size,
+            mip_count,
+            view_proj,
+            previous_view_proj: view_proj,
+            depth_view,
+            pyramid_view,
+            copy_depth_bind_group,
+            downsample_depth_bind_groups,
+        }
+    }
+
+    fn mip_size(&self, mip_level: u32) -> UVec2 {
+        (self.size >> mip_level).max(UVec2::ONE)
+    }
+}
+
+/// Creates, resizes and removes the depth pyramids of all camera views.
+pub(crate) fn prepare_depth_pyramids(
+    device: Res<RenderDevice>,
+    pipelines: Res<DepthPyramidPipelines>,
+    required: Res<DepthPyramidsRequired>,
+    mut depth_pyramids: ResMut<TerrainViewComponents<DepthPyramid>>,
+    terrain_query: Query<Entity, With<Terrain>>,
+    view_query: Query<(Entity, &ExtractedView), With<TerrainView>>,
+) {
+    depth_pyramids
+        .0
+        .retain(|&(terrain, view), _| required.contains(terrain) && view_query.contains(view));
+
+    for terrain in terrain_query.iter() {
+        if !required.contains(terrain) {
+            continue;
+        }
+
+        for (view, extracted_view) in view_query.iter() {
+            let size = extracted_view.viewport.zw();
+
+            if size.min_element() == 0 {
+                continue;
+            }
+
+            let view_proj =
+                extracted_view.projection * extracted_view.transform.compute_matrix().inverse();
+
+            match depth_pyramids.get_mut(&(terrain, view)) {
+                Some(depth_pyramid) if depth_pyramid.size == size => {
+                    depth_pyramid.previous_view_proj = depth_pyramid.view_proj;
+                    depth_pyramid.view_proj = view_proj;
+                }
+                _ => {
+                    let depth_pyramid = DepthPyramid::new(&device, &pipelines, size, view_proj);
+                    depth_pyramids.insert((terrain, view), depth_pyramid);
+                }
+            }
+        }
+    }
+}
+
+/// Renders the depth of the terrain for each camera view and builds the depth pyramids,
+/// which are used for the occlusion culling in the next frame.
+pub struct TerrainOcclusionNode {
+    terrain_query: QueryState<Entity, With<Terrain>>,
+    view_query: QueryState<(Entity, &'static ViewUniformOffset), With<TerrainView>>,
+}
+
+impl FromWorld for TerrainOcclusionNode {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            terrain_query: world.query_filtered(),
+            view_query: world.query_filtered(),
+        }
+    }
+}
+
+impl TerrainOcclusionNode {
+    fn build_depth_pyramid(
+        pass: &mut ComputePass,
+        copy_depth_pipeline: &ComputePipeline,
+        downsample_depth_pipeline: &ComputePipeline,
+        depth_pyramid: &DepthPyramid,
+    ) {
+        let workgroup_count = |size: UVec2| (size + 7) / 8;
+
+        let count = workgroup_count(depth_pyramid.size);
+        pass.set_pipeline(copy_depth_pipeline);
+        pass.set_bind_group(0, &depth_pyramid.copy_depth_bind_group, &[]);
+        pass.dispatch_workgroups(count.x, count.y, 1);
+
+        pass.set_pipeline(downsample_depth_pipeline);
+
+        for (mip_level, bind_group) in depth_pyramid
+            .downsample_depth_bind_groups
+            .iter()
+            .enumerate()
+        {
+            let count = workgroup_count(depth_pyramid.mip_size(mip_level as u32 + 1));
+            pass.set_bind_group(0, bind_group, &[]);
+            pass.dispatch_workgroups(count.x, count.y, 1);
+        }
+    }
+}
+
+impl render_graph::Node for TerrainOcclusionNode {
+    fn update(&mut self, world: &mut World) {
+        self.terrain_query.update_archetypes(world);
+        self.view_query.update_archetypes(world);
+    }
+
+    fn run(
+        &self,
+        _graph: &mut render_graph::RenderGraphContext,
+        context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), render_graph::NodeRunError> {
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let pipelines = world.resource::<DepthPyramidPipelines>();
+        let light_meta = world.resource::<LightMeta>();
+        let depth_draws = world.resource::<TerrainComponents<TerrainDepthDraw>>();
+        let terrain_data = world.resource::<TerrainComponents<TerrainData>>();
+        let terrain_view_data = world.resource::<TerrainViewComponents<TerrainViewData>>();
+        let depth_pyramids = world.resource::<TerrainViewComponents<DepthPyramid>>();
+        let required = world.resource::<DepthPyramidsRequired>();
+
+        let debug = world.get_resource::<DebugTerrain>();
+
+        if required.0.is_empty() || debug.map_or(false, |debug| debug.freeze) {
+            return Ok(());
+        }
+
+        let view_bind_group = match &light_meta.shadow_view_bind_group {
+            Some(bind_group) => bind_group,
+            None => return Ok(()),
+        };
+
+        let (copy_depth_pipeline, downsample_depth_pipeline) = match (
+            pipeline_cache.get_compute_pipeline(pipelines.copy_depth_pipeline),
+            pipeline_cache.get_compute_pipeline(pipelines.downsample_depth_pipeline),
+        ) {
+            (Some(copy_depth_pipeline), Some(downsample_depth_pipeline)) => {
+                (copy_depth_pipeline, downsample_depth_pipeline)
+            }
+            _ => return Ok(()), // some pipelines are not loaded yet
+        };
+
+        for terrain in self.terrain_query.iter_manual(world) {
+            let depth_draw = skip_none!(depth_draws.get(&terrain));
+            let render_pipeline =
+                skip_none!(pipeline_cache.get_render_pipeline(depth_draw.pipeline));
+            let terrain_data = skip_none!(terrain_data.get(&terrain));
+
+            for (view, view_uniform) in self.view_query.iter_manual(world) {
+                let depth_pyramid = skip_none!(depth_pyramids.get(&(terrain, view)));
+                let view_data = skip_none!(terrain_view_data.get(&(terrain, view)));
+
+                {
+                    let mut pass =
+                        context
+                            .command_encoder
+                            .begin_render_pass(&RenderPassDescriptor {
+                                label: Some("terrain_depth_pass"),
+                                color_attachments: &[],
+                                depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                                    view: &depth_pyramid.depth_view,
+                                    depth_ops: Some(Operations {
+                                        load: LoadOp::Clear(0.0),
+                                        store: true,
+                                    }),
+                                    stencil_ops: None,
+                                }),
+                            });
+
+                    pass.set_pipeline(render_pipeline);
+                    pass.set_bind_group(0, view_bind_group, &[view_uniform.offset]);
+                    pass.set_bind_group(1, &view_data.terrain_view_bind_group, &[]);
+                    pass.set_bind_group(2, &terrain_data.terrain_bind_group, &[]);
+                    pass.set_bind_group(3, &depth_draw.material_bind_group, &[]);
+                    pass.draw_indirect(&view_data.indirect_buffer, 0);
+                }
+
+                let pass = &mut context
+                    .command_encoder
+                    .begin_compute_pass(&ComputePassDescriptor::default());
+
+                TerrainOcclusionNode::build_depth_pyramid(
+                    pass,
+                    copy_depth_pipeline,
+                    downsample_depth_pipeline,
+                    depth_pyramid,
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
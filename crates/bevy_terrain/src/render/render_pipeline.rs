@@ -1,15 +1,21 @@
 use crate::{
     render::{
-        shaders::DEFAULT_SHADER,
+        occlusion::{DepthPyramidsRequired, TerrainDepthDraw},
+        shaders::{DEFAULT_SHADER, DEPTH_PREPASS_SHADER},
         terrain_data::{terrain_bind_group_layout, SetTerrainBindGroup},
         terrain_view_data::{DrawTerrainCommand, SetTerrainViewBindGroup},
-        TERRAIN_VIEW_LAYOUT,
+        TerrainRenderSettings, TERRAIN_VIEW_LAYOUT,
     },
-    DebugTerrain, Terrain,
+    skip_none,
+    terrain_data::gpu_node_atlas::GpuNodeAtlas,
+    DebugTerrain, Terrain, TerrainComponents, TerrainView,
 };
 use bevy::{
     core_pipeline::core_3d::Opaque3d,
-    pbr::{MeshPipeline, RenderMaterials, SetMaterialBindGroup, SetMeshViewBindGroup},
+    pbr::{
+        LightEntity, MeshPipeline, RenderMaterials, SetMaterialBindGroup, SetMeshViewBindGroup,
+        SetShadowViewBindGroup, Shadow, ShadowPipeline, ViewLightEntities,
+    },
     prelude::*,
     render::{
         render_phase::{AddRenderCommand, DrawFunctions, RenderPhase, SetItemPipeline},
@@ -85,6 +91,12 @@ pub struct TerrainPipelineFlags: u32 {
     const TEST1              = (1 << 12);
     const TEST2              = (1 << 13);
     const TEST3              = (1 << 14);
+    const SHADOW             = (1 << 15);
+    const DEPTH_PREPASS      = (1 << 16);
+    const DEPTH_ONLY         = (1 << 17);
+    const SURFACE            = (1 << 18);
+    const NORMAL_ATTACHMENT  = (1 << 19);
+    const DEPTH_PYRAMID      = (1 << 20);
 
     const MSAA_RESERVED_BITS = TerrainPipelineFlags::MSAA_MASK_BITS << TerrainPipelineFlags::MSAA_SHIFT_BITS;
 }
@@ -138,6 +150,9 @@ impl TerrainPipelineFlags {
         if debug.sample_grad {
             key |= TerrainPipelineFlags::SAMPLE_GRAD;
         }
+        if debug.surface {
+            key |= TerrainPipelineFlags::SURFACE;
+        }
         if debug.test1 {
             key |= TerrainPipelineFlags::TEST1;
         }
@@ -151,17 +166,81 @@ impl TerrainPipelineFlags {
         key
     }
 
+    pub fn from_settings(settings: &TerrainRenderSettings) -> Self {
+        let mut key = TerrainPipelineFlags::NONE;
+
+        if settings.depth_prepass {
+            key |= TerrainPipelineFlags::DEPTH_PREPASS;
+        }
+
+        key
+    }
+
+    /// Returns the flags used when no [`DebugTerrain`] resource is present.
+    fn from_debug_or_default(debug: Option<&DebugTerrain>) -> Self {
+        match debug {
+            Some(debug) => TerrainPipelineFlags::from_debug(debug),
+            None => {
+                TerrainPipelineFlags::LIGHTING
+                    | TerrainPipelineFlags::SHOW_NODES
+                    | TerrainPipelineFlags::MESH_MORPH
+                    | TerrainPipelineFlags::SAMPLE_GRAD
+            }
+        }
+    }
+
+    /// Disables the surface extrusion for terrains without a surface attachment and
+    /// samples the normals from the normal attachment, if the terrain has one.
+    fn with_attachments(self, gpu_node_atlas: Option<&GpuNodeAtlas>) -> Self {
+        let has_attachment = |name: &str| {
+            gpu_node_atlas.map_or(false, |gpu_node_atlas| gpu_node_atlas.has_attachment(name))
+        };
+
+        let mut key = self;
+
+        if !has_attachment("surface") {
+            key -= TerrainPipelineFlags::SURFACE;
+        }
+        if has_attachment("normal") {
+            key |= TerrainPipelineFlags::NORMAL_ATTACHMENT;
+        }
+
+        key
+    }
+
     pub fn msaa_samples(&self) -> u32 {
         ((self.bits >> Self::MSAA_SHIFT_BITS) & Self::MSAA_MASK_BITS) + 1
     }
 
     pub fn polygon_mode(&self) -> PolygonMode {
-        match (self.bits & TerrainPipelineFlags::WIREFRAME.bits) != 0 {
+        match (self.bits & TerrainPipelineFlags::WIREFRAME.bits) != 0
+            && !self.shadow()
+            && !self.depth_pyramid()
+        {
             true => PolygonMode::Line,
             false => PolygonMode::Fill,
         }
     }
 
+    pub fn shadow(&self) -> bool {
+        (self.bits & TerrainPipelineFlags::SHADOW.bits) != 0
+    }
+
+    /// Whether the depth of the terrain has already been written by a depth prepass.
+    pub fn depth_prepass(&self) -> bool {
+        (self.bits & TerrainPipelineFlags::DEPTH_PREPASS.bits) != 0
+    }
+
+    /// Whether this pipeline only writes the depth of the terrain in the depth prepass.
+    pub fn depth_only(&self) -> bool {
+        (self.bits & TerrainPipelineFlags::DEPTH_ONLY.bits) != 0
+    }
+
+    /// Whether this pipeline renders the depth of the terrain into the depth pyramids of the views.
+    pub fn depth_pyramid(&self) -> bool {
+        (self.bits & TerrainPipelineFlags::DEPTH_PYRAMID.bits) != 0
+    }
+
     pub fn shader_defs(&self) -> Vec<String> {
         let mut shader_defs = Vec::new();
 
@@ -198,6 +277,12 @@ impl TerrainPipelineFlags {
         if (self.bits & TerrainPipelineFlags::SAMPLE_GRAD.bits) != 0 {
             shader_defs.push("SAMPLE_GRAD".to_string());
         }
+        if (self.bits & TerrainPipelineFlags::SURFACE.bits) != 0 {
+            shader_defs.push("SURFACE".to_string());
+        }
+        if (self.bits & TerrainPipelineFlags::NORMAL_ATTACHMENT.bits) != 0 {
+            shader_defs.push("NORMAL_ATTACHMENT".to_string());
+        }
         if (self.bits & TerrainPipelineFlags::TEST1.bits) != 0 {
             shader_defs.push("TEST1".to_string());
         }
@@ -207,6 +292,10 @@ impl TerrainPipelineFlags {
         if (self.bits & TerrainPipelineFlags::TEST3.bits) != 0 {
             shader_defs.push("TEST3".to_string());
         }
+        // the shadow, the depth prepass and the depth pyramid only write the depth of the terrain
+        if self.shadow() || self.depth_only() || self.depth_pyramid() {
+            shader_defs.push("DEPTH_ONLY".to_string());
+        }
 
         shader_defs
     }
@@ -216,6 +305,7 @@ impl TerrainPipelineFlags {
 #[derive(Resource)]
 pub struct TerrainRenderPipeline<M: Material> {
     pub(crate) view_layout: BindGroupLayout,
+    pub(crate) shadow_view_layout: BindGroupLayout,
     pub(crate) terrain_layout: BindGroupLayout,
     pub(crate) terrain_view_layout: BindGroupLayout,
     pub(crate) material_layout: BindGroupLayout,
@@ -232,6 +322,7 @@ impl<M: Material> FromWorld for TerrainRenderPipeline<M> {
         let config = world.resource::<TerrainPipelineConfig>();
 
         let view_layout = mesh_pipeline.view_layout.clone();
+        let shadow_view_layout = world.resource::<ShadowPipeline>().view_layout.clone();
         let terrain_layout = terrain_bind_group_layout(&device, config.attachment_count);
         let terrain_view_layout = device.create_bind_group_layout(&TERRAIN_VIEW_LAYOUT);
         let material_layout = M::bind_group_layout(device);
@@ -250,6 +341,7 @@ impl<M: Material> FromWorld for TerrainRenderPipeline<M> {
 
         Self {
             view_layout,
+            shadow_view_layout,
             terrain_layout,
             terrain_view_layout,
             material_layout,
@@ -271,10 +363,56 @@ where
 
         shader_defs.push("TONEMAP_IN_SHADER".to_string());
 
+        // shadow views only render the depth of the terrain into the shadow map
+        let shadow = key.flags.shadow();
+        // the depth pyramids are rendered from the camera views without any color target
+        let depth_pyramid = key.flags.depth_pyramid();
+
+        // both only require the view uniform, which is bound using the shadow view layout
+        let view_layout = match shadow || depth_pyramid {
+            true => self.shadow_view_layout.clone(),
+            false => self.view_layout.clone(),
+        };
+
+        // the depth prepass, the depth pyramids and the shadow views use a trivial fragment shader,
+        // which does not write any color and only discards the holes in the height data
+        let fragment = match (shadow || depth_pyramid, key.flags.depth_only()) {
+            (true, _) => Some(FragmentState {
+                shader: DEPTH_PREPASS_SHADER.typed(),
+                shader_defs: Vec::new(),
+                entry_point: "shadow".into(),
+                targets: Vec::new(),
+            }),
+            (false, true) => Some(FragmentState {
+                shader: DEPTH_PREPASS_SHADER.typed(),
+                shader_defs: Vec::new(),
+                entry_point: "fragment".into(),
+                targets: vec![Some(ColorTargetState {
+                    format: TextureFormat::bevy_default(),
+                    blend: None,
+                    write_mask: ColorWrites::empty(),
+                })],
+            }),
+            (false, false) => Some(FragmentState {
+                shader: self.fragment_shader.clone(),
+                shader_defs: shader_defs.clone(),
+                entry_point: "fragment".into(),
+                targets: vec![Some(ColorTargetState {
+                    format: TextureFormat::bevy_default(),
+                    blend: Some(BlendState::REPLACE),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+        };
+
+        // after the depth prepass, only the visible fragments are shaded
+        let shaded_after_prepass =
+            key.flags.depth_prepass() && !key.flags.depth_only() && !shadow && !depth_pyramid;
+
         RenderPipelineDescriptor {
             label: None,
             layout: Some(vec![
-                self.view_layout.clone(),
+                view_layout,
                 self.terrain_view_layout.clone(),
                 self.terrain_layout.clone(), // Todo: do this properly for multiple terrains
                 self.material_layout.clone(),
@@ -282,32 +420,26 @@ where
             vertex: VertexState {
                 shader: self.vertex_shader.clone(),
                 entry_point: "vertex".into(),
-                shader_defs: shader_defs.clone(),
+                shader_defs,
                 buffers: Vec::new(),
             },
             primitive: PrimitiveState {
                 front_face: FrontFace::Ccw,
-                cull_mode: Some(Face::Back),
+                cull_mode: if shadow { None } else { Some(Face::Back) },
                 unclipped_depth: false,
                 polygon_mode: key.flags.polygon_mode(),
                 conservative: false,
                 topology: PrimitiveTopology::TriangleStrip,
                 strip_index_format: None,
             },
-            fragment: Some(FragmentState {
-                shader: self.fragment_shader.clone(),
-                shader_defs,
-                entry_point: "fragment".into(),
-                targets: vec![Some(ColorTargetState {
-                    format: TextureFormat::bevy_default(),
-                    blend: Some(BlendState::REPLACE),
-                    write_mask: ColorWrites::ALL,
-                })],
-            }),
+            fragment,
             depth_stencil: Some(DepthStencilState {
                 format: TextureFormat::Depth32Float,
-                depth_write_enabled: true,
-                depth_compare: CompareFunction::Greater,
+                depth_write_enabled: !shaded_after_prepass,
+                depth_compare: match shadow || shaded_after_prepass {
+                    true => CompareFunction::GreaterEqual,
+                    false => CompareFunction::Greater,
+                },
                 stencil: StencilState {
                     front: StencilFaceState::IGNORE,
                     back: StencilFaceState::IGNORE,
@@ -340,12 +472,24 @@ pub(crate) type DrawTerrain<M> = (
     DrawTerrainCommand,
 );
 
+/// The draw function of the terrain in the shadow pass of a light.
+pub(crate) type DrawTerrainShadow<M> = (
+    SetItemPipeline,
+    SetShadowViewBindGroup<0>,
+    SetTerrainViewBindGroup<1>,
+    SetTerrainBindGroup<2>,
+    SetMaterialBindGroup<M, 3>,
+    DrawTerrainCommand,
+);
+
 /// Queses all terrain entities for rendering via the terrain pipeline.
 pub(crate) fn queue_terrain<M: Material>(
     terrain_pipeline: Res<TerrainRenderPipeline<M>>,
     draw_functions: Res<DrawFunctions<Opaque3d>>,
     msaa: Res<Msaa>,
+    settings: Res<TerrainRenderSettings>,
     debug: Option<Res<DebugTerrain>>,
+    gpu_node_atlases: Res<TerrainComponents<GpuNodeAtlas>>,
     render_materials: Res<RenderMaterials<M>>,
     mut pipelines: ResMut<SpecializedRenderPipelines<TerrainRenderPipeline<M>>>,
     mut pipeline_cache: ResMut<PipelineCache>,
@@ -359,22 +503,33 @@ pub(crate) fn queue_terrain<M: Material>(
     for mut opaque_phase in view_query.iter_mut() {
         for (entity, material) in terrain_query.iter() {
             if let Some(material) = render_materials.get(material) {
-                let mut flags = TerrainPipelineFlags::from_msaa_samples(msaa.samples);
-
-                if let Some(debug) = &debug {
-                    flags |= TerrainPipelineFlags::from_debug(debug);
-                } else {
-                    flags |= TerrainPipelineFlags::LIGHTING
-                        | TerrainPipelineFlags::SHOW_NODES
-                        | TerrainPipelineFlags::MESH_MORPH
-                        | TerrainPipelineFlags::SAMPLE_GRAD;
-                }
+                let flags = TerrainPipelineFlags::from_msaa_samples(msaa.samples)
+                    | TerrainPipelineFlags::from_settings(&settings)
+                    | TerrainPipelineFlags::from_debug_or_default(debug.as_deref())
+                        .with_attachments(gpu_node_atlases.get(&entity));
 
                 let key = TerrainPipelineKey {
                     flags,
                     bind_group_data: material.key.clone(),
                 };
 
+                if flags.depth_prepass() {
+                    let key = TerrainPipelineKey {
+                        flags: flags | TerrainPipelineFlags::DEPTH_ONLY,
+                        bind_group_data: material.key.clone(),
+                    };
+
+                    let pipeline =
+                        pipelines.specialize(&mut pipeline_cache, &terrain_pipeline, key);
+
+                    opaque_phase.add(Opaque3d {
+                        entity,
+                        pipeline,
+                        draw_function,
+                        distance: f32::NEG_INFINITY, // draw the depth prepass before the terrain
+                    });
+                }
+
                 let pipeline = pipelines.specialize(&mut pipeline_cache, &terrain_pipeline, key);
 
                 opaque_phase.add(Opaque3d {
@@ -388,6 +543,111 @@ pub(crate) fn queue_terrain<M: Material>(
     }
 }
 
+/// Queues all terrain entities for rendering into the shadow maps of the lights,
+/// which are terrain views themselves.
+pub(crate) fn queue_terrain_shadows<M: Material>(
+    terrain_pipeline: Res<TerrainRenderPipeline<M>>,
+    draw_functions: Res<DrawFunctions<Shadow>>,
+    debug: Option<Res<DebugTerrain>>,
+    gpu_node_atlases: Res<TerrainComponents<GpuNodeAtlas>>,
+    render_materials: Res<RenderMaterials<M>>,
+    mut pipelines: ResMut<SpecializedRenderPipelines<TerrainRenderPipeline<M>>>,
+    mut pipeline_cache: ResMut<PipelineCache>,
+    view_lights: Query<&ViewLightEntities>,
+    mut light_view_query: Query<(&LightEntity, &mut RenderPhase<Shadow>)>,
+    light_query: Query<(), With<TerrainView>>,
+    terrain_query: Query<(Entity, &Handle<M>), With<Terrain>>,
+) where
+    M::Data: PartialEq + Eq + Hash + Clone,
+{
+    let draw_function = draw_functions
+        .read()
+        .get_id::<DrawTerrainShadow<M>>()
+        .unwrap();
+
+    for view_lights in view_lights.iter() {
+        for &light_view in view_lights.lights.iter() {
+            let (light, mut shadow_phase) = skip_none!(light_view_query.get_mut(light_view).ok());
+
+            // only lights that are terrain views render the terrain into their shadow map
+            let light_entity = match *light {
+                LightEntity::Directional { light_entity } => light_entity,
+                _ => continue,
+            };
+
+            if !light_query.contains(light_entity) {
+                continue;
+            }
+
+            for (entity, material) in terrain_query.iter() {
+                if let Some(material) = render_materials.get(material) {
+                    let flags = TerrainPipelineFlags::from_debug_or_default(debug.as_deref())
+                        .with_attachments(gpu_node_atlases.get(&entity))
+                        | TerrainPipelineFlags::SHADOW;
+
+                    let key = TerrainPipelineKey {
+                        flags,
+                        bind_group_data: material.key.clone(),
+                    };
+
+                    let pipeline =
+                        pipelines.specialize(&mut pipeline_cache, &terrain_pipeline, key);
+
+                    shadow_phase.add(Shadow {
+                        entity,
+                        pipeline,
+                        draw_function,
+                        distance: 0.0,
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Specializes the depth-only pipelines, which render the terrain into the depth pyramids
+/// used for the occlusion culling and the virtual texture feedback.
+pub(crate) fn queue_terrain_depth<M: Material>(
+    terrain_pipeline: Res<TerrainRenderPipeline<M>>,
+    debug: Option<Res<DebugTerrain>>,
+    required: Res<DepthPyramidsRequired>,
+    gpu_node_atlases: Res<TerrainComponents<GpuNodeAtlas>>,
+    render_materials: Res<RenderMaterials<M>>,
+    mut pipelines: ResMut<SpecializedRenderPipelines<TerrainRenderPipeline<M>>>,
+    mut pipeline_cache: ResMut<PipelineCache>,
+    mut depth_draws: ResMut<TerrainComponents<TerrainDepthDraw>>,
+    terrain_query: Query<(Entity, &Handle<M>), With<Terrain>>,
+) where
+    M::Data: PartialEq + Eq + Hash + Clone,
+{
+    for (entity, material) in terrain_query.iter() {
+        if !required.contains(entity) {
+            continue;
+        }
+
+        if let Some(material) = render_materials.get(material) {
+            let flags = TerrainPipelineFlags::from_debug_or_default(debug.as_deref())
+                .with_attachments(gpu_node_atlases.get(&entity))
+                | TerrainPipelineFlags::DEPTH_PYRAMID;
+
+            let key = TerrainPipelineKey {
+                flags,
+                bind_group_data: material.key.clone(),
+            };
+
+            let pipeline = pipelines.specialize(&mut pipeline_cache, &terrain_pipeline, key);
+
+            depth_draws.insert(
+                entity,
+                TerrainDepthDraw {
+                    pipeline,
+                    material_bind_group: material.bind_group.clone(),
+                },
+            );
+        }
+    }
+}
+
 /// This plugin adds a custom material for a terrain.
 ///
 /// It can be used to render the terrain using a custom vertex and fragment shader.
@@ -417,9 +677,12 @@ where
                 //     prepare_materials::<M>.after(PrepareAssetLabel::PreAssetPrepare),
                 // )
                 .add_render_command::<Opaque3d, DrawTerrain<M>>()
+                .add_render_command::<Shadow, DrawTerrainShadow<M>>()
                 .init_resource::<TerrainRenderPipeline<M>>()
                 .init_resource::<SpecializedRenderPipelines<TerrainRenderPipeline<M>>>()
-                .add_system_to_stage(RenderStage::Queue, queue_terrain::<M>);
+                .add_system_to_stage(RenderStage::Queue, queue_terrain::<M>)
+                .add_system_to_stage(RenderStage::Queue, queue_terrain_shadows::<M>)
+                .add_system_to_stage(RenderStage::Queue, queue_terrain_depth::<M>);
         }
     }
 }
@@ -0,0 +1,48 @@
+// The depth pyramid stores the furthest (minimal, because of the reversed z) depth
+// of the region covered by each texel.
+
+@group(0) @binding(0)
+var depth_texture: texture_depth_2d;
+@group(0) @binding(1)
+var pyramid_mip: texture_storage_2d<r32float, write>;
+@group(0) @binding(2)
+var source_mip: texture_2d<f32>;
+
+@compute @workgroup_size(8, 8, 1)
+fn copy_depth(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let coords = vec2<i32>(invocation_id.xy);
+
+    if (any(coords >= textureDimensions(pyramid_mip))) {
+        return;
+    }
+
+    let depth = textureLoad(depth_texture, coords, 0);
+
+    textureStore(pyramid_mip, coords, vec4<f32>(depth, 0.0, 0.0, 0.0));
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn downsample(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let coords = vec2<i32>(invocation_id.xy);
+    let size = textureDimensions(pyramid_mip);
+
+    if (any(coords >= size)) {
+        return;
+    }
+
+    let source_size = textureDimensions(source_mip);
+
+    // odd source sizes require an additional row/column to stay conservative
+    let sample_count = 2 + clamp(source_size - 2 * size, vec2<i32>(0), vec2<i32>(1));
+
+    var depth = 1.0;
+
+    for (var y = 0; y < sample_count.y; y = y + 1) {
+        for (var x = 0; x < sample_count.x; x = x + 1) {
+            let source_coords = min(2 * coords + vec2<i32>(x, y), source_size - 1);
+            depth = min(depth, textureLoad(source_mip, source_coords, 0).x);
+        }
+    }
+
+    textureStore(pyramid_mip, coords, vec4<f32>(depth, 0.0, 0.0, 0.0));
+}
@@ -5,4 +5,5 @@ struct Parameters {
     counter: i32,
     child_index: atomic<i32>,
     final_index: atomic<i32>,
+    horizon_culled_tiles: atomic<u32>,
 }
@@ -21,6 +21,7 @@ var<storage, read_write> indirect_buffer: IndirectBuffer;
 fn prepare_root() {
     parameters.counter = 1;
     atomicStore(&parameters.child_index, 1);
+    atomicStore(&parameters.horizon_culled_tiles, 0u);
 
     let size = 1u << (view_config.refinement_count - 1u);
 
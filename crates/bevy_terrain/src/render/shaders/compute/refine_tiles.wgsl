@@ -19,6 +19,17 @@ struct TerrainConfig {
     minmax_offset: f32,
     _empty: u32,
     _empty: u32,
+    height_min_lod: u32,
+    minmax_min_lod: u32,
+    _empty: u32,
+    _empty: u32,
+    height_max_lod: u32,
+    minmax_max_lod: u32,
+    _empty: u32,
+    _empty: u32,
+
+    planet_radius: f32,
+    nodata: u32,
 }
 
 struct CullingData {
@@ -26,6 +37,10 @@ struct CullingData {
     view_proj: mat4x4<f32>,
     model: mat4x4<f32>,
     planes: array<vec4<f32>, 5>,
+    previous_view_proj: mat4x4<f32>,
+    pyramid_size: vec2<f32>,
+    pyramid_mip_count: u32,
+    light_view: u32,
 }
 
 @group(0) @binding(0)
@@ -41,6 +56,8 @@ var<storage, read_write> parameters: Parameters;
 
 @group(1) @binding(0)
 var<uniform> view: CullingData;
+@group(1) @binding(1)
+var depth_pyramid: texture_2d<f32>;
 
  // terrain bindings
 @group(2) @binding(0)
@@ -108,8 +125,137 @@ fn outside_cull(tile: Tile) -> bool {
     return local_position.x > f32(config.terrain_size) || local_position.y > f32(config.terrain_size);
 }
 
+fn occlusion_cull(tile: Tile) -> bool {
+#ifdef OCCLUSION_CULLING
+    if (view.pyramid_mip_count == 0u) {
+        return false;
+    }
+
+    let size = f32(tile.size) * view_config.tile_scale;
+    let local_position = (vec2<f32>(tile.coords) + 0.5) * size;
+    let minmax = minmax(local_position, size);
+
+    // project the bounding box of the tile into the previous frame
+    var uv_min = vec2<f32>(1.0);
+    var uv_max = vec2<f32>(0.0);
+    var nearest_depth = 0.0;
+
+    for (var i: u32 = 0u; i < 8u; i = i + 1u) {
+        let corner = vec4<f32>(local_position.x + size * (f32(i       & 1u) - 0.5),
+                               select(minmax.x, minmax.y, (i >> 1u & 1u) == 1u),
+                               local_position.y + size * (f32(i >> 2u & 1u) - 0.5), 1.0);
+
+        let clip_position = view.previous_view_proj * corner;
+
+        if (clip_position.w <= 0.0) {
+            // the tile intersects the near plane -> don't cull
+            return false;
+        }
+
+        let ndc_position = clip_position.xyz / clip_position.w;
+        let uv = ndc_position.xy * vec2<f32>(0.5, -0.5) + 0.5;
+
+        uv_min = min(uv_min, uv);
+        uv_max = max(uv_max, uv);
+        nearest_depth = max(nearest_depth, ndc_position.z); // reversed z
+    }
+
+    uv_min = clamp(uv_min, vec2<f32>(0.0), vec2<f32>(1.0));
+    uv_max = clamp(uv_max, vec2<f32>(0.0), vec2<f32>(1.0));
+
+    // select the mip level, at which the tile covers at most 2x2 texels
+    let extent = (uv_max - uv_min) * view.pyramid_size;
+    let mip_level = min(u32(ceil(log2(max(max(extent.x, extent.y), 1.0)))), view.pyramid_mip_count - 1u);
+    let mip_size = max(vec2<i32>(view.pyramid_size) >> vec2<u32>(mip_level), vec2<i32>(1));
+
+    let texel_min = min(vec2<i32>(uv_min * vec2<f32>(mip_size)), mip_size - 1);
+    let texel_max = min(vec2<i32>(uv_max * vec2<f32>(mip_size)), mip_size - 1);
+
+    // the pyramid stores the furthest depth of each region
+    let occluder_depth = min(min(textureLoad(depth_pyramid, texel_min,                           i32(mip_level)).x,
+                                 textureLoad(depth_pyramid, vec2<i32>(texel_max.x, texel_min.y), i32(mip_level)).x),
+                             min(textureLoad(depth_pyramid, vec2<i32>(texel_min.x, texel_max.y), i32(mip_level)).x,
+                                 textureLoad(depth_pyramid, texel_max,                           i32(mip_level)).x));
+
+    return nearest_depth < occluder_depth;
+#else
+    return false;
+#endif
+}
+
+fn horizon_cull(tile: Tile) -> bool {
+#ifdef HORIZON_CULLING
+    let size = f32(tile.size) * view_config.tile_scale;
+    let local_position = (vec2<f32>(tile.coords) + 0.5) * size;
+    let minmax = minmax(local_position, size);
+
+    let viewer_position = view.world_position.xz;
+    let viewer_height = view.world_position.y;
+
+    let tile_radius = size * 0.7071068; // half of the diagonal
+    let tile_distance = distance(local_position, viewer_position);
+
+    if (tile_distance <= tile_radius) {
+        // the viewer is above the tile -> don't cull
+        return false;
+    }
+
+    // the tile lies below the geometric horizon of the planet
+    if (config.planet_radius > 0.0) {
+        let viewer_horizon = sqrt(max(viewer_height * (2.0 * config.planet_radius + viewer_height), 0.0));
+        let tile_horizon = sqrt(max(minmax.y * (2.0 * config.planet_radius + minmax.y), 0.0));
+
+        if (tile_distance - tile_radius > viewer_horizon + tile_horizon) {
+            return true;
+        }
+    }
+
+    // the maximal slope from the viewer to any point of the tile
+    let tile_slope = (minmax.y - viewer_height) /
+                     select(tile_distance + tile_radius, tile_distance - tile_radius, minmax.y > viewer_height);
+
+    // the terrain between the viewer and the tile is used as horizon occluder
+    // each occluder covers the cone from the viewer to the tile at its distance
+    for (var i: u32 = 1u; i <= 4u; i = i + 1u) {
+        let t = f32(i) / 5.0;
+
+        let occluder_position = mix(viewer_position, local_position, t);
+        let occluder_distance = t * tile_distance;
+        let occluder_radius = t * tile_radius;
+
+        // the minimal slope from the viewer to the highest point of the occluder,
+        // whose maximum includes the extruded surface (buildings and vegetation)
+        let occluder_height = minmax(occluder_position, max(2.0 * occluder_radius, 1.0)).y;
+        let occluder_slope = (occluder_height - viewer_height) /
+                             select(occluder_distance - occluder_radius, occluder_distance + occluder_radius, occluder_height > viewer_height);
+
+        if (tile_slope < occluder_slope) {
+            return true;
+        }
+    }
+
+    return false;
+#else
+    return false;
+#endif
+}
+
 fn cull(tile: Tile) -> bool {
-    return outside_cull(tile) || frustum_cull(tile);
+    if (outside_cull(tile) || frustum_cull(tile)) {
+        return true;
+    }
+
+    // the position of a light view is meaningless for the horizon, and tiles hidden from it may still cast shadows
+    if (view.light_view == 1u) {
+        return false;
+    }
+
+    if (horizon_cull(tile)) {
+        atomicAdd(&parameters.horizon_culled_tiles, 1u);
+        return true;
+    }
+
+    return occlusion_cull(tile);
 }
 
 fn should_be_divided(tile: Tile) -> bool {
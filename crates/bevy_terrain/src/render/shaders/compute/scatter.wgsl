@@ -0,0 +1,254 @@
+#import bevy_terrain::types
+
+struct TerrainConfig {
+    lod_count: u32,
+    height: f32,
+    leaf_node_size: u32,
+    terrain_size: u32,
+
+    height_size: f32,
+    minmax_size: f32,
+    _empty: u32,
+    _empty: u32,
+    height_scale: f32,
+    minmax_scale: f32,
+    _empty: u32,
+    _empty: u32,
+    height_offset: f32,
+    minmax_offset: f32,
+    _empty: u32,
+    _empty: u32,
+    height_min_lod: u32,
+    minmax_min_lod: u32,
+    _empty: u32,
+    _empty: u32,
+    height_max_lod: u32,
+    minmax_max_lod: u32,
+    _empty: u32,
+    _empty: u32,
+
+    planet_radius: f32,
+    nodata: u32,
+}
+
+struct ScatterData {
+    world_position: vec4<f32>,
+    view_proj: mat4x4<f32>,
+    planes: array<vec4<f32>, 5>,
+    color: vec4<f32>,
+    first_cell: vec2<i32>,
+    cell_count: vec2<u32>,
+    spacing: f32,
+    distance: f32,
+    lod_falloff: f32,
+    min_scale: f32,
+    max_scale: f32,
+    radius: f32,
+    min_height: f32,
+    max_height: f32,
+    max_slope: f32,
+    channel: u32,
+    density_scale: f32,
+    density_offset: f32,
+    density_min_lod: u32,
+    density_max_lod: u32,
+    canopy_scale: f32,
+    canopy_offset: f32,
+    canopy_min_lod: u32,
+    canopy_max_lod: u32,
+    max_instances: u32,
+}
+
+struct Instance {
+    // position (xyz) and scale (w)
+    position: vec4<f32>,
+    // cosine and sine of the rotation around the y axis (xy) and a random value (z)
+    rotation: vec4<f32>,
+}
+
+struct InstanceList {
+    data: array<Instance>,
+}
+
+struct Indirect {
+    count: u32,
+    instance_count: u32,
+    first: u32,
+    base: u32,
+    first_instance: u32,
+    counter: atomic<u32>,
+}
+
+// terrain view bindings
+@group(0) @binding(0)
+var<uniform> view_config: TerrainViewConfig;
+@group(0) @binding(1)
+var quadtree: texture_2d_array<u32>;
+
+// terrain bindings
+@group(1) @binding(0)
+var<uniform> config: TerrainConfig;
+@group(1) @binding(1)
+var atlas_sampler: sampler;
+@group(1) @binding(2)
+var height_atlas: texture_2d_array<f32>;
+@group(1) @binding(3)
+var minmax_atlas: texture_2d_array<f32>;
+
+// scatter bindings (the scatter data is named view, because the terrain functions require it)
+@group(2) @binding(0)
+var<uniform> view: ScatterData;
+@group(2) @binding(1)
+var density_atlas: texture_2d_array<f32>;
+@group(2) @binding(2)
+var canopy_atlas: texture_2d_array<f32>;
+@group(2) @binding(3)
+var<storage, read_write> instances: InstanceList;
+@group(2) @binding(4)
+var<storage, read_write> indirect: Indirect;
+
+#import bevy_terrain::node
+#import bevy_terrain::functions
+
+// Hashes the value into a pseudo random number (PCG).
+fn hash(value: u32) -> u32 {
+    let state = value * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// Returns four random values in the range [0, 1), which are unique for each cell.
+fn random(cell: vec2<i32>) -> vec4<f32> {
+    let seed = hash(bitcast<u32>(cell.x) ^ hash(bitcast<u32>(cell.y)));
+
+    let x = hash(seed);
+    let y = hash(x);
+    let z = hash(y);
+    let w = hash(z);
+
+    return vec4<f32>(vec4<u32>(x, y, z, w) >> vec4<u32>(8u)) / 16777216.0;
+}
+
+fn sample_height(lookup: NodeLookup) -> f32 {
+    let height_coords = lookup.atlas_coords * config.height_scale + config.height_offset;
+    return textureSampleLevel(height_atlas, atlas_sampler, height_coords, lookup.atlas_index, 0.0).x * config.height;
+}
+
+fn sample_density(lod: u32, local_position: vec2<f32>, height: f32, lookup: NodeLookup) -> f32 {
+#ifdef DENSITY_ATTACHMENT
+    let density_lookup = lookup_attachment_node(lod, local_position, view.density_min_lod, view.density_max_lod);
+
+    if (density_lookup.atlas_index == -1) {
+        return 0.0;
+    }
+
+    let density_coords = density_lookup.atlas_coords * view.density_scale + view.density_offset;
+    let density = textureSampleLevel(density_atlas, atlas_sampler, density_coords, density_lookup.atlas_index, 0.0);
+    let mask = select(vec4<f32>(0.0), vec4<f32>(1.0), vec4<u32>(0u, 1u, 2u, 3u) == vec4<u32>(view.channel));
+
+    return dot(density, mask);
+#else
+    let height_coords = lookup.atlas_coords * config.height_scale + config.height_offset;
+    let normal = calculate_normal(height_coords, lookup.atlas_index, lookup.atlas_lod, vec2<f32>(0.0), vec2<f32>(0.0));
+    let slope = acos(clamp(normal.y, -1.0, 1.0));
+
+    return select(0.0, 1.0, height >= view.min_height && height <= view.max_height && slope <= view.max_slope);
+#endif
+}
+
+// Returns the height of the canopy above the terrain or a negative value, if it is not available.
+fn sample_canopy(lod: u32, local_position: vec2<f32>, height: f32) -> f32 {
+    let canopy_lookup = lookup_attachment_node(lod, local_position, view.canopy_min_lod, view.canopy_max_lod);
+
+    if (canopy_lookup.atlas_index == -1) {
+        return -1.0;
+    }
+
+    let canopy_coords = canopy_lookup.atlas_coords * view.canopy_scale + view.canopy_offset;
+    let surface = textureSampleLevel(canopy_atlas, atlas_sampler, canopy_coords, canopy_lookup.atlas_index, 0.0).x;
+
+    return surface * config.height - height;
+}
+
+fn frustum_cull(center: vec3<f32>, radius: f32) -> bool {
+    for (var i = 0; i < 5; i = i + 1) {
+        let plane = view.planes[i];
+
+        if (dot(plane.xyz, center) + plane.w < -radius * length(plane.xyz)) {
+            return true;
+        }
+    }
+
+    return false;
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn scatter(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    if (any(invocation_id.xy >= view.cell_count)) {
+        return;
+    }
+
+    let cell = view.first_cell + vec2<i32>(invocation_id.xy);
+    let values = random(cell);
+
+    let local_position = (vec2<f32>(cell) + values.xy) * view.spacing;
+
+    if (any(local_position < vec2<f32>(0.0)) || any(local_position > vec2<f32>(f32(config.terrain_size)))) {
+        return;
+    }
+
+    if (distance(local_position, view.world_position.xz) > view.distance) {
+        return;
+    }
+
+    let lod = calculate_blend(approximate_world_position(local_position)).lod;
+    let lookup = lookup_node(lod, local_position);
+
+    // candidates inside of the holes of the height data are rejected
+    if (config.nodata != 0u && is_nodata(lookup.atlas_coords * config.height_scale + config.height_offset, lookup.atlas_index)) {
+        return;
+    }
+
+    let height = sample_height(lookup);
+
+    // thin out the candidates with increasing lod, like the quadtree
+    let density = sample_density(lod, local_position, height, lookup) * pow(view.lod_falloff, f32(lod));
+
+    if (values.z >= density) {
+        return;
+    }
+
+    let variation = random(cell + vec2<i32>(0, 65536));
+
+#ifdef CANOPY
+    let canopy = sample_canopy(lod, local_position, height);
+
+    if (canopy < view.min_scale) {
+        return;
+    }
+
+    let scale = min(canopy, view.max_scale);
+#else
+    let scale = mix(view.min_scale, view.max_scale, variation.x);
+#endif
+
+    let position = vec3<f32>(local_position.x, height, local_position.y);
+
+    if (frustum_cull(position + vec3<f32>(0.0, view.radius * scale, 0.0), view.radius * scale)) {
+        return;
+    }
+
+    let index = atomicAdd(&indirect.counter, 1u);
+
+    if (index >= view.max_instances) {
+        return;
+    }
+
+    let angle = variation.y * 6.283185;
+    instances.data[index] = Instance(vec4<f32>(position, scale), vec4<f32>(cos(angle), sin(angle), variation.z, 0.0));
+}
+
+@compute @workgroup_size(1, 1, 1)
+fn finish() {
+    indirect.instance_count = min(atomicLoad(&indirect.counter), view.max_instances);
+}
@@ -0,0 +1,96 @@
+// The feedback pass determines the pages of the virtual texture, which are required to shade
+// the visible terrain at the full resolution, and appends each of them once to the feedback buffer.
+
+struct FeedbackConfig {
+    inverse_view_proj: mat4x4<f32>,
+    view_position: vec3<f32>,
+    pixel_size: f32,
+    terrain_size: f32,
+    texel_size: f32,
+    center_size: f32,
+    lod_count: u32,
+    lod_bias: f32,
+    mip_level: u32,
+    max_requests: u32,
+    page_count: u32,
+}
+
+struct FeedbackBuffer {
+    count: atomic<u32>,
+    pages: array<u32>,
+}
+
+// One bit per page of all lods, starting with the finest one.
+struct PageBitmap {
+    words: array<atomic<u32>>,
+}
+
+@group(0) @binding(0)
+var depth_pyramid: texture_2d<f32>;
+@group(0) @binding(1)
+var<uniform> config: FeedbackConfig;
+@group(0) @binding(2)
+var<storage, read_write> feedback_buffer: FeedbackBuffer;
+@group(0) @binding(3)
+var<storage, read_write> page_bitmap: PageBitmap;
+
+@compute @workgroup_size(8, 8, 1)
+fn feedback(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let coords = vec2<i32>(invocation_id.xy);
+    let size = textureDimensions(depth_pyramid, i32(config.mip_level));
+
+    if (any(coords >= size)) {
+        return;
+    }
+
+    let depth = textureLoad(depth_pyramid, coords, i32(config.mip_level)).x;
+
+    // nothing of the terrain has been rendered here (reversed z)
+    if (depth == 0.0) {
+        return;
+    }
+
+    let uv = (vec2<f32>(coords) + 0.5) / vec2<f32>(size);
+    let ndc = vec4<f32>(2.0 * uv.x - 1.0, 1.0 - 2.0 * uv.y, depth, 1.0);
+    let position = config.inverse_view_proj * ndc;
+    let world_position = position.xyz / position.w;
+    let local_position = world_position.xz;
+
+    if (any(local_position < vec2<f32>(0.0)) || any(local_position >= vec2<f32>(config.terrain_size))) {
+        return;
+    }
+
+    // select the lod, whose texels match the size of a screen pixel
+    let viewer_distance = length(world_position - config.view_position);
+    let texels_per_pixel = viewer_distance * config.pixel_size / config.texel_size;
+    let lod = u32(clamp(floor(log2(max(texels_per_pixel, 1.0)) + config.lod_bias),
+                        0.0, f32(config.lod_count - 1u)));
+
+    let page = vec2<u32>(local_position / config.texel_size / config.center_size) >> vec2<u32>(lod);
+
+    var offset = 0u;
+    for (var i = 0u; i < lod; i = i + 1u) {
+        let count = ((config.page_count - 1u) >> i) + 1u;
+        offset = offset + count * count;
+    }
+
+    let count = ((config.page_count - 1u) >> lod) + 1u;
+
+    if (any(page >= vec2<u32>(count))) {
+        return;
+    }
+
+    // only the first texel, that marks the page in the bitmap, requests it
+    let bit = offset + page.y * count + page.x;
+    let mask = 1u << (bit & 31u);
+
+    if ((atomicOr(&page_bitmap.words[bit >> 5u], mask) & mask) != 0u) {
+        return;
+    }
+
+    let index = atomicAdd(&feedback_buffer.count, 1u);
+
+    if (index < config.max_requests) {
+        feedback_buffer.pages[index] = (lod << 26u) | (page.x << 13u) | page.y;
+    }
+}
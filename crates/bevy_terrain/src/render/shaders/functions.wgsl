@@ -10,6 +10,8 @@ struct VertexOutput {
     @location(0)             local_position: vec2<f32>,
     @location(1)             world_position: vec4<f32>,
     @location(2)             debug_color: vec4<f32>,
+    // greater than zero inside of the triangles adjacent to holes in the height data
+    @location(3)             nodata: f32,
 }
 
 fn vertex_output(local_position: vec2<f32>, height: f32) -> VertexOutput {
@@ -20,6 +22,7 @@ fn vertex_output(local_position: vec2<f32>, height: f32) -> VertexOutput {
     output.local_position = vec2<f32>(local_position);
     output.world_position = world_position;
     output.debug_color = vec4<f32>(0.0);
+    output.nodata = 0.0;
 
     return output;
 }
@@ -30,6 +33,7 @@ struct FragmentInput {
     @location(0)             local_position: vec2<f32>,
     @location(1)             world_position: vec4<f32>,
     @location(2)             debug_color: vec4<f32>,
+    @location(3)             nodata: f32,
 }
 
 struct FragmentOutput {
@@ -106,6 +110,65 @@ fn calculate_normal(coords: vec2<f32>, atlas_index: i32, atlas_lod: u32, ddx: ve
     return normalize(vec3<f32>(right - left, f32(2u << atlas_lod) / config.height, down - up));
 }
 
+// Decodes a world space normal stored in the octahedral encoded normal attachment.
+fn decode_normal(encoded: vec2<f32>) -> vec3<f32> {
+    let f = encoded * 2.0 - 1.0;
+
+    var normal = vec3<f32>(f.x, 1.0 - abs(f.x) - abs(f.y), f.y);
+    let t = max(-normal.y, 0.0);
+    normal.x = normal.x + select(t, -t, normal.x >= 0.0);
+    normal.z = normal.z + select(t, -t, normal.z >= 0.0);
+
+    return normalize(normal);
+}
+
+// Determines the visibility of the sun based on the baked horizon attachment, which stores
+// the sine of the horizon angles in the +x, +z, -x and -z direction.
+// The softness widens the transition between the lit and the shadowed region.
+fn horizon_shadow(horizon: vec4<f32>, light_direction: vec3<f32>, softness: f32) -> f32 {
+    let direction = light_direction.xz;
+    var weights = max(vec4<f32>(direction.x, direction.y, -direction.x, -direction.y), vec4<f32>(0.0));
+    weights = weights / max(dot(weights, vec4<f32>(1.0)), 0.0001);
+
+    let horizon_sine = dot(horizon, weights);
+    let light_sine = light_direction.y / max(length(light_direction), 0.0001);
+
+    return smoothstep(horizon_sine - softness, horizon_sine + softness, light_sine);
+}
+
+// Returns the baked ambient occlusion, which is the cosine weighted visibility of the sky.
+// The occlusion attachment shares the layout of the height attachment, thus it can be sampled
+// with the lookup of the height.
+fn ambient_occlusion(occlusion_atlas: texture_2d_array<f32>, lookup: NodeLookup,
+                     occlusion_scale: f32, occlusion_offset: f32) -> f32 {
+    let occlusion_coords = lookup.atlas_coords * occlusion_scale + occlusion_offset;
+
+    return textureSampleLevel(occlusion_atlas, atlas_sampler, occlusion_coords, lookup.atlas_index, 0.0).x;
+}
+
+// Approximates the curvature (laplacian of the height) in 1/m.
+// Positive values correspond to concave (e.g. valleys) and negative ones to convex (e.g. ridges) regions.
+fn calculate_curvature(coords: vec2<f32>, atlas_index: i32, atlas_lod: u32, ddx: vec2<f32>, ddy: vec2<f32>) -> f32 {
+#ifdef SAMPLE_GRAD
+    let offset = 1.0 / config.height_size;
+    let center = textureSampleGrad(height_atlas, atlas_sampler, coords, atlas_index, ddx, ddy).x;
+    let left   = textureSampleGrad(height_atlas, atlas_sampler, coords + vec2<f32>(-offset,     0.0), atlas_index, ddx, ddy).x;
+    let up     = textureSampleGrad(height_atlas, atlas_sampler, coords + vec2<f32>(    0.0, -offset), atlas_index, ddx, ddy).x;
+    let right  = textureSampleGrad(height_atlas, atlas_sampler, coords + vec2<f32>( offset,     0.0), atlas_index, ddx, ddy).x;
+    let down   = textureSampleGrad(height_atlas, atlas_sampler, coords + vec2<f32>(    0.0,  offset), atlas_index, ddx, ddy).x;
+#else
+    let center = textureSampleLevel(height_atlas, atlas_sampler, coords, atlas_index, 0.0).x;
+    let left   = textureSampleLevel(height_atlas, atlas_sampler, coords, atlas_index, 0.0, vec2<i32>(-1,  0)).x;
+    let up     = textureSampleLevel(height_atlas, atlas_sampler, coords, atlas_index, 0.0, vec2<i32>( 0, -1)).x;
+    let right  = textureSampleLevel(height_atlas, atlas_sampler, coords, atlas_index, 0.0, vec2<i32>( 1,  0)).x;
+    let down   = textureSampleLevel(height_atlas, atlas_sampler, coords, atlas_index, 0.0, vec2<i32>( 0,  1)).x;
+#endif
+
+    let spacing = f32(1u << atlas_lod);
+
+    return (left + up + right + down - 4.0 * center) * config.height / (spacing * spacing);
+}
+
 fn minmax(local_position: vec2<f32>, size: f32) -> vec2<f32> {
     let lod = u32(ceil(log2(size))) + 1u;
 
@@ -123,5 +186,18 @@ fn minmax(local_position: vec2<f32>, size: f32) -> vec2<f32> {
     var min_height = min(min(min_gather.x, min_gather.y), min(min_gather.z, min_gather.w));
     var max_height = max(max(max_gather.x, max_gather.y), max(max_gather.z, max_gather.w));
 
+    // regions without any data store an empty interval
+    if (min_height > max_height) {
+        return vec2<f32>(0.0);
+    }
+
     return vec2(min_height, max_height) * config.height;
 }
+
+// Whether any of the height texels the coordinates are interpolated from has no data.
+fn is_nodata(height_coords: vec2<f32>, atlas_index: i32) -> bool {
+    let gather = textureGather(0, height_atlas, atlas_sampler, height_coords, atlas_index);
+
+    // the reserved nodata value is the maximum of the normalized height
+    return any(gather == vec4<f32>(1.0));
+}
@@ -0,0 +1,81 @@
+#define_import_path bevy_terrain::procedural
+
+// Reusable functions for deriving the weights of the detail layers at runtime from the
+// height, slope and curvature of the terrain according to a list of rules.
+// The resulting weights can be passed to the `splat` function of `bevy_terrain::splat`.
+
+let MAX_PROCEDURAL_RULES: u32 = 16u;
+
+// A rule, which assigns its layer to all regions of the terrain, whose attributes
+// lie inside of its ranges. Outside of a range the rule fades out over the falloff distance.
+struct ProceduralRule {
+    // The height range in world units.
+    min_height: f32,
+    max_height: f32,
+    height_falloff: f32,
+    // The slope range in radians.
+    min_slope: f32,
+    max_slope: f32,
+    slope_falloff: f32,
+    // The curvature range in 1/m.
+    min_curvature: f32,
+    max_curvature: f32,
+    curvature_falloff: f32,
+    // The index of the detail layer assigned by this rule.
+    layer: u32,
+    // The opacity of this rule.
+    strength: f32,
+    _empty: f32,
+}
+
+struct ProceduralRules {
+    rules: array<ProceduralRule, MAX_PROCEDURAL_RULES>,
+    rule_count: u32,
+}
+
+// The attributes of the terrain, which the rules are evaluated against.
+struct TerrainAttributes {
+    height: f32,
+    slope: f32,
+    curvature: f32,
+}
+
+fn terrain_attributes(world_position: vec3<f32>, world_normal: vec3<f32>, curvature: f32) -> TerrainAttributes {
+    let slope = acos(clamp(world_normal.y, -1.0, 1.0));
+
+    return TerrainAttributes(world_position.y, slope, curvature);
+}
+
+// Returns one inside of the range and fades to zero over the falloff distance outside of it.
+fn range_factor(value: f32, lower: f32, upper: f32, falloff: f32) -> f32 {
+    let inside = min(value - lower, upper - value);
+
+    return clamp(inside / max(falloff, 0.0001) + 1.0, 0.0, 1.0);
+}
+
+fn rule_factor(rule: ProceduralRule, attributes: TerrainAttributes) -> f32 {
+    let height = range_factor(attributes.height, rule.min_height, rule.max_height, rule.height_falloff);
+    let slope = range_factor(attributes.slope, rule.min_slope, rule.max_slope, rule.slope_falloff);
+    let curvature = range_factor(attributes.curvature, rule.min_curvature, rule.max_curvature, rule.curvature_falloff);
+
+    return height * slope * curvature * rule.strength;
+}
+
+// Evaluates the rules in order, each one painting its layer over the result of the previous ones.
+// Regions not covered by any rule are assigned to the first layer.
+fn procedural_weights(rules: ProceduralRules, attributes: TerrainAttributes) -> vec4<f32> {
+    // copy the rules into a variable, so that they can be indexed dynamically
+    var rule_list = rules.rules;
+    var weights = vec4<f32>(1.0, 0.0, 0.0, 0.0);
+
+    for (var i = 0u; i < min(rules.rule_count, MAX_PROCEDURAL_RULES); i = i + 1u) {
+        let rule = rule_list[i];
+
+        var layer_weights = vec4<f32>(0.0);
+        layer_weights[min(rule.layer, 3u)] = 1.0;
+
+        weights = mix(weights, layer_weights, rule_factor(rule, attributes));
+    }
+
+    return weights;
+}
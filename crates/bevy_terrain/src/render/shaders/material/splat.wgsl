@@ -0,0 +1,149 @@
+#define_import_path bevy_terrain::splat
+
+// Reusable functions for blending detail layers according to splat weights.
+// The layers are stored in array textures, with the albedo in rgb and the height in the alpha channel
+// and with tangent space normal maps (x -> world x, y -> world z for the planar projection).
+// All samples use explicit gradients, so that they can be taken in non-uniform control flow.
+// Requires `bevy_terrain::triplanar` to be imported beforehand.
+
+// The projection of the layer textures.
+let PROJECTION_PLANAR: u32 = 0u;
+let PROJECTION_TRIPLANAR: u32 = 1u;
+let PROJECTION_BIPLANAR: u32 = 2u;
+
+// The settings of the detail layers.
+struct SplatSettings {
+    // The distance in world units, after which the textures of each layer repeat.
+    tiling: vec4<f32>,
+    // The strength of the detail normal maps of each layer.
+    normal_strength: vec4<f32>,
+    // The blend depth of the height-based blending. Zero disables it.
+    height_blend: f32,
+    // The projection of the layer textures.
+    projection: u32,
+    // The sharpness of the transitions between the triplanar/biplanar projections.
+    projection_sharpness: f32,
+}
+
+// The blended albedo and world normal of the detail layers.
+struct SplatSample {
+    color: vec4<f32>,
+    world_normal: vec3<f32>,
+}
+
+// Samples a detail layer, which repeats every `tiling` world units, using a planar projection onto the xz plane.
+fn sample_layer(layers: texture_2d_array<f32>, layer_sampler: sampler, local_position: vec2<f32>,
+                ddx: vec2<f32>, ddy: vec2<f32>, layer: i32, tiling: f32) -> vec4<f32> {
+    return textureSampleGrad(layers, layer_sampler, local_position / tiling, layer, ddx / tiling, ddy / tiling);
+}
+
+// Unpacks a tangent space normal from a normal map sample.
+fn unpack_normal(sample: vec4<f32>) -> vec3<f32> {
+    let xy = sample.xy * 2.0 - 1.0;
+
+    return vec3<f32>(xy, sqrt(max(1.0 - dot(xy, xy), 0.0)));
+}
+
+// Applies a tangent space detail normal to the world normal of the terrain using whiteout blending.
+fn apply_detail_normal(world_normal: vec3<f32>, detail_normal: vec3<f32>) -> vec3<f32> {
+    // the tangent space of the terrain has its z axis pointing up
+    let normal = world_normal.xzy;
+    let blended = vec3<f32>(normal.xy + detail_normal.xy, normal.z * detail_normal.z);
+
+    return normalize(blended).xzy;
+}
+
+// Sharpens the transitions of the splat weights based on the heights of the layers,
+// so that e.g. stones stick out of the sand instead of fading into it.
+// A sharpness of zero only normalizes the weights.
+fn height_blend(weights: vec4<f32>, heights: vec4<f32>, sharpness: f32) -> vec4<f32> {
+    var blend_weights = weights;
+
+    if (sharpness > 0.0) {
+        let layer_heights = heights + weights;
+        let max_height = max(max(layer_heights.x, layer_heights.y), max(layer_heights.z, layer_heights.w));
+
+        blend_weights = max(layer_heights - max_height + sharpness, vec4<f32>(0.0));
+        blend_weights = select(vec4<f32>(0.0), blend_weights, weights > vec4<f32>(0.0));
+    }
+
+    return blend_weights / max(dot(blend_weights, vec4<f32>(1.0)), 0.0001);
+}
+
+// Samples the albedo of a detail layer using the projection of the settings.
+fn sample_layer_color(layers: texture_2d_array<f32>, layer_sampler: sampler, world_position: vec3<f32>,
+                      ddx: vec3<f32>, ddy: vec3<f32>, world_normal: vec3<f32>, layer: i32,
+                      settings: SplatSettings) -> vec4<f32> {
+    let tiling = settings.tiling[layer];
+    let sharpness = settings.projection_sharpness;
+
+    switch (settings.projection) {
+        case 1u: {
+            return sample_triplanar(layers, layer_sampler, world_position, ddx, ddy, world_normal, layer, tiling, sharpness);
+        }
+        case 2u: {
+            return sample_biplanar(layers, layer_sampler, world_position, ddx, ddy, world_normal, layer, tiling, sharpness);
+        }
+        default: {
+            return sample_layer(layers, layer_sampler, world_position.xz, ddx.xz, ddy.xz, layer, tiling);
+        }
+    }
+}
+
+// Samples the normal map of a detail layer using the projection of the settings and
+// applies it to the world normal.
+fn sample_layer_normal(layers: texture_2d_array<f32>, layer_sampler: sampler, world_position: vec3<f32>,
+                       ddx: vec3<f32>, ddy: vec3<f32>, world_normal: vec3<f32>, layer: i32,
+                       settings: SplatSettings) -> vec3<f32> {
+    let tiling = settings.tiling[layer];
+    let strength = settings.normal_strength[layer];
+    let sharpness = settings.projection_sharpness;
+
+    switch (settings.projection) {
+        case 1u: {
+            return sample_triplanar_normal(layers, layer_sampler, world_position, ddx, ddy, world_normal, layer, tiling, sharpness, strength);
+        }
+        case 2u: {
+            return sample_biplanar_normal(layers, layer_sampler, world_position, ddx, ddy, world_normal, layer, tiling, sharpness, strength);
+        }
+        default: {
+            var detail_normal = unpack_normal(sample_layer(layers, layer_sampler, world_position.xz, ddx.xz, ddy.xz, layer, tiling));
+            detail_normal = vec3<f32>(detail_normal.xy * strength, detail_normal.z);
+
+            return apply_detail_normal(world_normal, detail_normal);
+        }
+    }
+}
+
+// Blends up to four detail layers according to their splat weights.
+// The `ddx` and `ddy` are the screen space derivatives of the world position.
+fn splat(albedo_layers: texture_2d_array<f32>, normal_layers: texture_2d_array<f32>, layer_sampler: sampler,
+         world_position: vec3<f32>, ddx: vec3<f32>, ddy: vec3<f32>, world_normal: vec3<f32>,
+         weights: vec4<f32>, settings: SplatSettings) -> SplatSample {
+    var colors: array<vec4<f32>, 4>;
+    var normals: array<vec3<f32>, 4>;
+    var heights = vec4<f32>(0.0);
+
+    for (var i = 0; i < 4; i = i + 1) {
+        if (weights[i] > 0.0) {
+            colors[i] = sample_layer_color(albedo_layers, layer_sampler, world_position, ddx, ddy, world_normal, i, settings);
+            normals[i] = sample_layer_normal(normal_layers, layer_sampler, world_position, ddx, ddy, world_normal, i, settings);
+            heights[i] = colors[i].a;
+        }
+    }
+
+    let blend_weights = height_blend(weights, heights, settings.height_blend);
+
+    var color = vec4<f32>(0.0);
+    var normal = vec3<f32>(0.0);
+
+    for (var i = 0; i < 4; i = i + 1) {
+        color = color + colors[i] * blend_weights[i];
+        normal = normal + normals[i] * blend_weights[i];
+    }
+
+    color.a = 1.0;
+    normal = normalize(normal + world_normal * 0.0001); // avoid zero length normals
+
+    return SplatSample(color, normal);
+}
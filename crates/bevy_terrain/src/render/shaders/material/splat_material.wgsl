@@ -0,0 +1,199 @@
+#import bevy_terrain::types
+
+// The splat material expects the splat weights in the attachment following the base attachments,
+// i.e. in the third attachment or in the fourth one, if the base includes the normal attachment.
+#ifdef NORMAL_ATTACHMENT
+struct TerrainConfig {
+    lod_count: u32,
+    height: f32,
+    leaf_node_size: u32,
+    terrain_size: u32,
+
+    height_size: f32,
+    minmax_size: f32,
+    normal_size: f32,
+    splat_size: f32,
+    height_scale: f32,
+    minmax_scale: f32,
+    normal_scale: f32,
+    splat_scale: f32,
+    height_offset: f32,
+    minmax_offset: f32,
+    normal_offset: f32,
+    splat_offset: f32,
+    height_min_lod: u32,
+    minmax_min_lod: u32,
+    normal_min_lod: u32,
+    splat_min_lod: u32,
+    height_max_lod: u32,
+    minmax_max_lod: u32,
+    normal_max_lod: u32,
+    splat_max_lod: u32,
+#else
+struct TerrainConfig {
+    lod_count: u32,
+    height: f32,
+    leaf_node_size: u32,
+    terrain_size: u32,
+
+    height_size: f32,
+    minmax_size: f32,
+    splat_size: f32,
+    _empty: f32,
+    height_scale: f32,
+    minmax_scale: f32,
+    splat_scale: f32,
+    _empty: f32,
+    height_offset: f32,
+    minmax_offset: f32,
+    splat_offset: f32,
+    _empty: f32,
+    height_min_lod: u32,
+    minmax_min_lod: u32,
+    splat_min_lod: u32,
+    _empty: u32,
+    height_max_lod: u32,
+    minmax_max_lod: u32,
+    splat_max_lod: u32,
+    _empty: u32,
+#endif
+
+    planet_radius: f32,
+    nodata: u32,
+}
+
+// view bindings
+#import bevy_pbr::mesh_view_bindings
+
+// terrain view bindings
+@group(1) @binding(0)
+var<uniform> view_config: TerrainViewConfig;
+@group(1) @binding(1)
+var quadtree: texture_2d_array<u32>;
+@group(1) @binding(2)
+var<storage> tiles: TileList;
+
+// terrain bindings
+@group(2) @binding(0)
+var<uniform> config: TerrainConfig;
+@group(2) @binding(1)
+var atlas_sampler: sampler;
+@group(2) @binding(2)
+var height_atlas: texture_2d_array<f32>;
+@group(2) @binding(3)
+var minmax_atlas: texture_2d_array<f32>;
+#ifdef NORMAL_ATTACHMENT
+@group(2) @binding(4)
+var normal_atlas: texture_2d_array<f32>;
+@group(2) @binding(5)
+var splat_atlas: texture_2d_array<f32>;
+#else
+@group(2) @binding(4)
+var splat_atlas: texture_2d_array<f32>;
+#endif
+
+// material bindings
+@group(3) @binding(0)
+var<uniform> material: SplatSettings;
+@group(3) @binding(1)
+var albedo_layers: texture_2d_array<f32>;
+@group(3) @binding(2)
+var layer_sampler: sampler;
+@group(3) @binding(3)
+var normal_layers: texture_2d_array<f32>;
+
+#import bevy_pbr::mesh_types
+#import bevy_pbr::pbr_types
+#import bevy_pbr::utils
+#import bevy_pbr::clustered_forward
+#import bevy_pbr::lighting
+#import bevy_pbr::shadows
+#import bevy_pbr::pbr_functions
+
+#import bevy_terrain::node
+#import bevy_terrain::functions
+#import bevy_terrain::debug
+#import bevy_terrain::triplanar
+#import bevy_terrain::splat
+
+struct FragmentData {
+    world_normal: vec3<f32>,
+    weights: vec4<f32>,
+    debug_color: vec4<f32>,
+}
+
+fn lookup_fragment_data(input: FragmentInput, lookup: NodeLookup, ddx: vec2<f32>, ddy: vec2<f32>) -> FragmentData {
+    let atlas_lod = lookup.atlas_lod;
+    let atlas_index = lookup.atlas_index;
+    let atlas_coords = lookup.atlas_coords;
+    let ddx = ddx / f32(1u << atlas_lod);
+    let ddy = ddy / f32(1u << atlas_lod);
+
+    let height_coords = atlas_coords * config.height_scale + config.height_offset;
+    let height_ddx = ddx / config.height_size;
+    let height_ddy = ddy / config.height_size;
+    let splat_coords = atlas_coords * config.splat_scale + config.splat_offset;
+    let splat_ddx = ddx / config.splat_size;
+    let splat_ddy = ddy / config.splat_size;
+
+#ifdef NORMAL_ATTACHMENT
+    // the normals baked during preprocessing follow the height attachment
+    let normal_coords = atlas_coords * config.normal_scale + config.normal_offset;
+    let world_normal = decode_normal(textureSampleLevel(normal_atlas, atlas_sampler, normal_coords, atlas_index, 0.0).xy);
+#else
+    let world_normal = calculate_normal(height_coords, atlas_index, atlas_lod, height_ddx, height_ddy);
+#endif
+    // the weights are stored linearly (`Rgba8Linear`), thus they can be used as is
+    let weights = textureSampleGrad(splat_atlas, atlas_sampler, splat_coords, atlas_index, splat_ddx, splat_ddy);
+
+    var debug_color = vec4<f32>(0.0);
+
+#ifdef SHOW_LOD
+    debug_color = show_lod(atlas_lod, input.world_position.xyz);
+#endif
+
+#ifdef SHOW_UV
+    debug_color = vec4<f32>(atlas_coords.x, atlas_coords.y, 0.0, 1.0);
+#endif
+
+    return FragmentData(world_normal, weights, debug_color);
+}
+
+fn blend_fragment_data(data1: FragmentData, data2: FragmentData, blend_ratio: f32) -> FragmentData {
+    let world_normal = mix(data2.world_normal, data1.world_normal, blend_ratio);
+    let weights = mix(data2.weights, data1.weights, blend_ratio);
+    let debug_color = mix(data2.debug_color, data1.debug_color, blend_ratio);
+
+    return FragmentData(world_normal, weights, debug_color);
+}
+
+fn process_fragment(input: FragmentInput, data: FragmentData) -> Fragment {
+    let world_position = input.world_position.xyz;
+    let ddx = dpdx(world_position);
+    let ddy = dpdy(world_position);
+
+    let layers = splat(albedo_layers, normal_layers, layer_sampler, world_position, ddx, ddy,
+                       normalize(data.world_normal), data.weights, material);
+
+    var color = mix(layers.color, data.debug_color, 0.4 * data.debug_color.a);
+    color = mix(color, vec4<f32>(input.debug_color.xyz, 1.0), input.debug_color.w);
+
+#ifdef LIGHTING
+    var pbr_input: PbrInput = pbr_input_new();
+    pbr_input.material.base_color = color;
+    pbr_input.material.perceptual_roughness = 0.8;
+    pbr_input.material.reflectance = 0.1;
+    pbr_input.frag_coord = input.frag_coord;
+    pbr_input.world_position = input.world_position;
+    pbr_input.world_normal = layers.world_normal;
+    pbr_input.is_orthographic = view.projection[3].w == 1.0;
+    pbr_input.N = layers.world_normal;
+    pbr_input.V = calculate_view(input.world_position, pbr_input.is_orthographic);
+
+    color = tone_mapping(pbr(pbr_input));
+#endif
+
+    return Fragment(color, false);
+}
+
+#import bevy_terrain::fragment
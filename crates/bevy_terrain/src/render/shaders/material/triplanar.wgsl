@@ -0,0 +1,145 @@
+#define_import_path bevy_terrain::triplanar
+
+// Reusable functions for sampling textures with triplanar and biplanar projections,
+// which avoid stretched textures on steep slopes.
+// The projections onto the yz, xz and xy plane are blended based on the world normal of the terrain.
+// All samples use explicit gradients (of the world position), so that they can be taken
+// in non-uniform control flow.
+
+// The weights of the projections onto the yz, xz and xy plane.
+// A higher sharpness results in narrower transitions between the projections.
+fn triplanar_weights(world_normal: vec3<f32>, sharpness: f32) -> vec3<f32> {
+    let weights = pow(abs(world_normal), vec3<f32>(sharpness));
+
+    return weights / (weights.x + weights.y + weights.z);
+}
+
+fn triplanar_unpack_normal(sample: vec4<f32>) -> vec3<f32> {
+    let xy = sample.xy * 2.0 - 1.0;
+
+    return vec3<f32>(xy, sqrt(max(1.0 - dot(xy, xy), 0.0)));
+}
+
+// Samples the texture using three planar projections, which repeat every `tiling` world units.
+fn sample_triplanar(layers: texture_2d_array<f32>, layer_sampler: sampler, world_position: vec3<f32>,
+                    ddx: vec3<f32>, ddy: vec3<f32>, world_normal: vec3<f32>, layer: i32,
+                    tiling: f32, sharpness: f32) -> vec4<f32> {
+    let weights = triplanar_weights(world_normal, sharpness);
+    let position = world_position / tiling;
+    let ddx = ddx / tiling;
+    let ddy = ddy / tiling;
+
+    let x = textureSampleGrad(layers, layer_sampler, position.zy, layer, ddx.zy, ddy.zy);
+    let y = textureSampleGrad(layers, layer_sampler, position.xz, layer, ddx.xz, ddy.xz);
+    let z = textureSampleGrad(layers, layer_sampler, position.xy, layer, ddx.xy, ddy.xy);
+
+    return x * weights.x + y * weights.y + z * weights.z;
+}
+
+// Samples a tangent space normal map using three planar projections and applies it to the
+// world normal using whiteout blending. The `strength` scales the detail normals.
+fn sample_triplanar_normal(layers: texture_2d_array<f32>, layer_sampler: sampler, world_position: vec3<f32>,
+                           ddx: vec3<f32>, ddy: vec3<f32>, world_normal: vec3<f32>, layer: i32,
+                           tiling: f32, sharpness: f32, strength: f32) -> vec3<f32> {
+    let weights = triplanar_weights(world_normal, sharpness);
+    let position = world_position / tiling;
+    let ddx = ddx / tiling;
+    let ddy = ddy / tiling;
+
+    var x = triplanar_unpack_normal(textureSampleGrad(layers, layer_sampler, position.zy, layer, ddx.zy, ddy.zy));
+    var y = triplanar_unpack_normal(textureSampleGrad(layers, layer_sampler, position.xz, layer, ddx.xz, ddy.xz));
+    var z = triplanar_unpack_normal(textureSampleGrad(layers, layer_sampler, position.xy, layer, ddx.xy, ddy.xy));
+
+    // whiteout blending in the tangent space of each projection
+    x = vec3<f32>(x.xy * strength + world_normal.zy, abs(x.z) * world_normal.x);
+    y = vec3<f32>(y.xy * strength + world_normal.xz, abs(y.z) * world_normal.y);
+    z = vec3<f32>(z.xy * strength + world_normal.xy, abs(z.z) * world_normal.z);
+
+    // swizzle the tangent normals back into world space
+    return normalize(x.zyx * weights.x + y.xzy * weights.y + z.xyz * weights.z);
+}
+
+// The two dominant projections and their weights used by the biplanar mapping.
+struct Biplanar {
+    major: vec3<i32>,
+    median: vec3<i32>,
+    weights: vec2<f32>,
+}
+
+fn biplanar(world_normal: vec3<f32>, sharpness: f32) -> Biplanar {
+    let n = abs(world_normal);
+
+    var major = vec3<i32>(2, 0, 1);
+    if (n.x > n.y && n.x > n.z) { major = vec3<i32>(0, 1, 2); }
+    else if (n.y > n.z)         { major = vec3<i32>(1, 2, 0); }
+
+    var minor = vec3<i32>(2, 0, 1);
+    if (n.x < n.y && n.x < n.z) { minor = vec3<i32>(0, 1, 2); }
+    else if (n.y < n.z)         { minor = vec3<i32>(1, 2, 0); }
+
+    let median = vec3<i32>(3) - minor - major;
+
+    // fade out the median projection, before it is swapped with the minor one
+    var weights = vec2<f32>(n[major.x], n[median.x]);
+    weights = clamp((weights - 0.5773) / (1.0 - 0.5773), vec2<f32>(0.0), vec2<f32>(1.0));
+    weights = pow(weights, vec2<f32>(sharpness));
+    weights = weights / max(weights.x + weights.y, 0.0001);
+
+    return Biplanar(major, median, weights);
+}
+
+// Samples the texture using only the two dominant planar projections.
+// This is a cheaper alternative to `sample_triplanar`.
+fn sample_biplanar(layers: texture_2d_array<f32>, layer_sampler: sampler, world_position: vec3<f32>,
+                   ddx: vec3<f32>, ddy: vec3<f32>, world_normal: vec3<f32>, layer: i32,
+                   tiling: f32, sharpness: f32) -> vec4<f32> {
+    let projections = biplanar(world_normal, sharpness);
+    let position = world_position / tiling;
+    let ddx = ddx / tiling;
+    let ddy = ddy / tiling;
+
+    let major = projections.major;
+    let median = projections.median;
+
+    let x = textureSampleGrad(layers, layer_sampler, vec2<f32>(position[major.y], position[major.z]), layer,
+                              vec2<f32>(ddx[major.y], ddx[major.z]), vec2<f32>(ddy[major.y], ddy[major.z]));
+    let y = textureSampleGrad(layers, layer_sampler, vec2<f32>(position[median.y], position[median.z]), layer,
+                              vec2<f32>(ddx[median.y], ddx[median.z]), vec2<f32>(ddy[median.y], ddy[median.z]));
+
+    return x * projections.weights.x + y * projections.weights.y;
+}
+
+// Samples a tangent space normal map using only the two dominant planar projections and applies
+// it to the world normal using whiteout blending.
+fn sample_biplanar_normal(layers: texture_2d_array<f32>, layer_sampler: sampler, world_position: vec3<f32>,
+                          ddx: vec3<f32>, ddy: vec3<f32>, world_normal: vec3<f32>, layer: i32,
+                          tiling: f32, sharpness: f32, strength: f32) -> vec3<f32> {
+    let projections = biplanar(world_normal, sharpness);
+    let position = world_position / tiling;
+    let ddx = ddx / tiling;
+    let ddy = ddy / tiling;
+
+    var normal = vec3<f32>(0.0);
+
+    for (var i = 0; i < 2; i = i + 1) {
+        let axes = select(projections.median, projections.major, i == 0);
+
+        var tangent_normal = triplanar_unpack_normal(textureSampleGrad(layers, layer_sampler,
+                                 vec2<f32>(position[axes.y], position[axes.z]), layer,
+                                 vec2<f32>(ddx[axes.y], ddx[axes.z]), vec2<f32>(ddy[axes.y], ddy[axes.z])));
+
+        // whiteout blending in the tangent space of the projection
+        tangent_normal = vec3<f32>(tangent_normal.xy * strength + vec2<f32>(world_normal[axes.y], world_normal[axes.z]),
+                                   abs(tangent_normal.z) * world_normal[axes.x]);
+
+        // swizzle the tangent normal back into world space
+        var projected = vec3<f32>(0.0);
+        projected[axes.x] = tangent_normal.z;
+        projected[axes.y] = tangent_normal.x;
+        projected[axes.z] = tangent_normal.y;
+
+        normal = normal + projected * projections.weights[i];
+    }
+
+    return normalize(normal);
+}
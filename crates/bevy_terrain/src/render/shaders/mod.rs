@@ -21,9 +21,37 @@ pub(crate) const PREPARE_INDIRECT_SHADER: HandleUntyped =
     HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 242384313596767307);
 pub(crate) const REFINE_TILES_SHADER: HandleUntyped =
     HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 938732132468373352);
+pub(crate) const DEPTH_PYRAMID_SHADER: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 417853096714326581);
+pub(crate) const VIRTUAL_TEXTURE_FEEDBACK_SHADER: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 739510284637195023);
+pub(crate) const SCATTER_COMPUTE_SHADER: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 615284937102648351);
 
 pub(crate) const DEFAULT_SHADER: HandleUntyped =
     HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 556563744564564658);
+const TRIPLANAR_SHADER: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 671294830517362948);
+const SPLAT_SHADER: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 730961254850972617);
+pub(crate) const SPLAT_MATERIAL_SHADER: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 305126987341208865);
+const PROCEDURAL_SHADER: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 148206935716284053);
+pub(crate) const PROCEDURAL_MATERIAL_SHADER: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 592837461029384756);
+
+const VIRTUAL_TEXTURE_SHADER: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 284617395028461937);
+const SURFACE_SHADER: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 362950184736215094);
+
+pub(crate) const DEPTH_PREPASS_SHADER: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 863019572264130849);
+pub(crate) const WATER_SHADER: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 480193726450182736);
+pub(crate) const SCATTER_SHADER: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 893406152874930261);
 
 pub(crate) fn add_shader(app: &mut App) {
     let mut assets = app.world.resource_mut::<Assets<_>>();
@@ -57,6 +85,48 @@ pub(crate) fn add_shader(app: &mut App) {
         DEFAULT_SHADER,
         Shader::from_wgsl(include_str!("render/default.wgsl")),
     );
+    assets.set_untracked(
+        DEPTH_PREPASS_SHADER,
+        Shader::from_wgsl(include_str!("render/depth_prepass.wgsl")),
+    );
+    assets.set_untracked(
+        WATER_SHADER,
+        Shader::from_wgsl(include_str!("render/water.wgsl")),
+    );
+    assets.set_untracked(
+        SCATTER_SHADER,
+        Shader::from_wgsl(include_str!("render/scatter.wgsl")),
+    );
+
+    assets.set_untracked(
+        TRIPLANAR_SHADER,
+        Shader::from_wgsl(include_str!("material/triplanar.wgsl")),
+    );
+    assets.set_untracked(
+        SPLAT_SHADER,
+        Shader::from_wgsl(include_str!("material/splat.wgsl")),
+    );
+    assets.set_untracked(
+        SPLAT_MATERIAL_SHADER,
+        Shader::from_wgsl(include_str!("material/splat_material.wgsl")),
+    );
+    assets.set_untracked(
+        PROCEDURAL_SHADER,
+        Shader::from_wgsl(include_str!("material/procedural.wgsl")),
+    );
+    assets.set_untracked(
+        PROCEDURAL_MATERIAL_SHADER,
+        Shader::from_wgsl(include_str!("material/procedural_material.wgsl")),
+    );
+
+    assets.set_untracked(
+        VIRTUAL_TEXTURE_SHADER,
+        Shader::from_wgsl(include_str!("virtual_texture.wgsl")),
+    );
+    assets.set_untracked(
+        SURFACE_SHADER,
+        Shader::from_wgsl(include_str!("surface.wgsl")),
+    );
 
     assets.set_untracked(
         PREPARE_INDIRECT_SHADER,
@@ -66,4 +136,16 @@ pub(crate) fn add_shader(app: &mut App) {
         REFINE_TILES_SHADER,
         Shader::from_wgsl(include_str!("compute/refine_tiles.wgsl")),
     );
+    assets.set_untracked(
+        DEPTH_PYRAMID_SHADER,
+        Shader::from_wgsl(include_str!("compute/depth_pyramid.wgsl")),
+    );
+    assets.set_untracked(
+        VIRTUAL_TEXTURE_FEEDBACK_SHADER,
+        Shader::from_wgsl(include_str!("compute/virtual_texture_feedback.wgsl")),
+    );
+    assets.set_untracked(
+        SCATTER_COMPUTE_SHADER,
+        Shader::from_wgsl(include_str!("compute/scatter.wgsl")),
+    );
 }
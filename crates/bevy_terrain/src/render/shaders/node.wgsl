@@ -43,3 +43,18 @@ fn lookup_node(lod: u32, local_position: vec2<f32>) -> NodeLookup {
 
     return NodeLookup(atlas_lod, atlas_index, atlas_coords);
 }
+
+// Looks up the best available node containing an attachment, which only exists for a range of lods.
+// Finer lods fall back to the finest lod of the attachment (e.g. `config.albedo_min_lod`).
+// An atlas index of -1 indicates, that only nodes coarser than the attachment's coarsest lod
+// (e.g. `config.albedo_max_lod`) are available at this position, in which case the caller has to
+// fall back to a default value.
+fn lookup_attachment_node(lod: u32, local_position: vec2<f32>, min_lod: u32, max_lod: u32) -> NodeLookup {
+    var lookup = lookup_node(max(lod, min_lod), local_position);
+
+    if (lookup.atlas_lod > max_lod) {
+        lookup.atlas_index = -1;
+    }
+
+    return lookup;
+}
@@ -8,16 +8,27 @@ struct TerrainConfig {
 
     height_size: f32,
     minmax_size: f32,
-    _empty: u32,
+    normal_size: f32,
     _empty: u32,
     height_scale: f32,
     minmax_scale: f32,
-    _empty: u32,
+    normal_scale: f32,
     _empty: u32,
     height_offset: f32,
     minmax_offset: f32,
+    normal_offset: f32,
     _empty: u32,
+    height_min_lod: u32,
+    minmax_min_lod: u32,
+    normal_min_lod: u32,
     _empty: u32,
+    height_max_lod: u32,
+    minmax_max_lod: u32,
+    normal_max_lod: u32,
+    _empty: u32,
+
+    planet_radius: f32,
+    nodata: u32,
 }
 
 // view bindings
@@ -40,6 +51,10 @@ var atlas_sampler: sampler;
 var height_atlas: texture_2d_array<f32>;
 @group(2) @binding(3)
 var minmax_atlas: texture_2d_array<f32>;
+#ifdef NORMAL_ATTACHMENT
+@group(2) @binding(4)
+var normal_atlas: texture_2d_array<f32>;
+#endif
 
 #import bevy_pbr::mesh_types
 #import bevy_pbr::pbr_types
@@ -76,7 +91,13 @@ fn lookup_fragment_data(input: FragmentInput, lookup: NodeLookup, ddx: vec2<f32>
     let height_ddx = ddx / 512.0;
     let height_ddy = ddy / 512.0;
 
+#ifdef NORMAL_ATTACHMENT
+    // the normals baked during preprocessing follow the height attachment
+    let normal_coords = atlas_coords * config.normal_scale + config.normal_offset;
+    let world_normal = decode_normal(textureSampleLevel(normal_atlas, atlas_sampler, normal_coords, atlas_index, 0.0).xy);
+#else
     let world_normal = calculate_normal(height_coords, atlas_index, atlas_lod, height_ddx, height_ddy);
+#endif
 
     var debug_color = vec4<f32>(0.5);
 
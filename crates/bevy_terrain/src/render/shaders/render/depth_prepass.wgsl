@@ -0,0 +1,19 @@
+// The fragment stages of the terrain depth prepass, the depth pyramid and the shadow passes.
+// They write no color and only discard the triangles adjacent to holes in the height data.
+
+@fragment
+fn fragment(@location(3) nodata: f32) -> @location(0) vec4<f32> {
+    if (nodata > 0.0) {
+        discard;
+    }
+
+    return vec4<f32>(0.0);
+}
+
+// used by the passes without color targets (the shadow maps and the depth pyramids)
+@fragment
+fn shadow(@location(3) nodata: f32) {
+    if (nodata > 0.0) {
+        discard;
+    }
+}
@@ -20,6 +20,10 @@
 // The default fragment entry point, which blends the terrain data at the fringe between two lods.
 @fragment
 fn fragment(input: FragmentInput) -> FragmentOutput {
+    if (input.nodata > 0.0) {
+        discard;
+    }
+
     let ddx   = dpdx(input.local_position);
     let ddy   = dpdy(input.local_position);
     let blend = calculate_blend(input.world_position);
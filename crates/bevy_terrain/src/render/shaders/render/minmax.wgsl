@@ -40,8 +40,9 @@ fn vertex(in: VertexInput) -> VertexOutput {
 
     var output = vertex_output(local_position, height);
 
-    let color = show_tiles(tile, output.world_position);
-    output.debug_color = color;
+#ifndef DEPTH_ONLY
+    output.debug_color = show_tiles(tile, output.world_position);
+#endif
 
     return output;
 
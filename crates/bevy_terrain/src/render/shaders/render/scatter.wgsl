@@ -0,0 +1,98 @@
+struct Mesh { flags: u32 }; let mesh = Mesh(1u); // hack for the pbr shaders (the instances receive shadows)
+
+struct ScatterData {
+    world_position: vec4<f32>,
+    view_proj: mat4x4<f32>,
+    planes: array<vec4<f32>, 5>,
+    color: vec4<f32>,
+    first_cell: vec2<i32>,
+    cell_count: vec2<u32>,
+    spacing: f32,
+    distance: f32,
+    lod_falloff: f32,
+    min_scale: f32,
+    max_scale: f32,
+    radius: f32,
+    min_height: f32,
+    max_height: f32,
+    max_slope: f32,
+    channel: u32,
+    density_scale: f32,
+    density_offset: f32,
+    density_min_lod: u32,
+    density_max_lod: u32,
+    canopy_scale: f32,
+    canopy_offset: f32,
+    canopy_min_lod: u32,
+    canopy_max_lod: u32,
+    max_instances: u32,
+}
+
+// view bindings
+#import bevy_pbr::mesh_view_bindings
+
+// scatter bindings
+@group(1) @binding(0)
+var<uniform> scatter: ScatterData;
+
+#import bevy_pbr::mesh_types
+#import bevy_pbr::pbr_types
+#import bevy_pbr::utils
+#import bevy_pbr::clustered_forward
+#import bevy_pbr::lighting
+#import bevy_pbr::shadows
+#import bevy_pbr::pbr_functions
+
+struct ScatterVertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    // position (xyz) and scale (w)
+    @location(3) instance_position: vec4<f32>,
+    // cosine and sine of the rotation around the y axis (xy) and a random value (z)
+    @location(4) instance_rotation: vec4<f32>,
+}
+
+struct ScatterVertexOutput {
+    @builtin(position) frag_coord: vec4<f32>,
+    @location(0)       world_position: vec4<f32>,
+    @location(1)       world_normal: vec3<f32>,
+    @location(2)       variation: f32,
+}
+
+fn rotate(vector: vec3<f32>, rotation: vec2<f32>) -> vec3<f32> {
+    return vec3<f32>(rotation.x * vector.x + rotation.y * vector.z, vector.y, rotation.x * vector.z - rotation.y * vector.x);
+}
+
+@vertex
+fn vertex(in: ScatterVertexInput) -> ScatterVertexOutput {
+    let rotation = in.instance_rotation.xy;
+    let world_position = rotate(in.position, rotation) * in.instance_position.w + in.instance_position.xyz;
+
+    var output: ScatterVertexOutput;
+    output.frag_coord = view.view_proj * vec4<f32>(world_position, 1.0);
+    output.world_position = vec4<f32>(world_position, 1.0);
+    output.world_normal = rotate(in.normal, rotation);
+    output.variation = in.instance_rotation.z;
+
+    return output;
+}
+
+@fragment
+fn fragment(input: ScatterVertexOutput) -> @location(0) vec4<f32> {
+    // vary the brightness of the instances slightly
+    let color = vec4<f32>(scatter.color.rgb * mix(0.8, 1.2, input.variation), scatter.color.a);
+    let world_normal = normalize(input.world_normal);
+
+    var pbr_input: PbrInput = pbr_input_new();
+    pbr_input.material.base_color = color;
+    pbr_input.material.perceptual_roughness = 1.0;
+    pbr_input.material.reflectance = 0.0;
+    pbr_input.frag_coord = input.frag_coord;
+    pbr_input.world_position = input.world_position;
+    pbr_input.world_normal = world_normal;
+    pbr_input.is_orthographic = view.projection[3].w == 1.0;
+    pbr_input.N = world_normal;
+    pbr_input.V = calculate_view(input.world_position, pbr_input.is_orthographic);
+
+    return tone_mapping(pbr(pbr_input));
+}
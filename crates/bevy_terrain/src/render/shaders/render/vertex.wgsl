@@ -4,6 +4,15 @@
 // This will happen once or twice (lod fringe).
 // fn vertex_height(lookup: AtlasLookup) -> f32;
 
+// Whether any of the height texels the vertex is interpolated from has no data.
+fn is_hole(lookup: NodeLookup) -> bool {
+    if (config.nodata == 0u) {
+        return false;
+    }
+
+    return is_nodata(lookup.atlas_coords * config.height_scale + config.height_offset, lookup.atlas_index);
+}
+
 // The default vertex entry point, which blends the height at the fringe between two lods.
 @vertex
 fn vertex(in: VertexInput) -> VertexOutput {
@@ -20,15 +29,24 @@ fn vertex(in: VertexInput) -> VertexOutput {
 
     let lookup = lookup_node(blend.lod, local_position);
     var height = vertex_height(lookup);
+    var hole   = is_hole(lookup);
 
     if (blend.ratio < 1.0) {
         let lookup2 = lookup_node(blend.lod + 1u, local_position);
         let height2 = vertex_height(lookup2);
         height      = mix(height2, height, blend.ratio);
+        hole        = hole || is_hole(lookup2);
     }
 
     var output = vertex_output(local_position, height);
 
+    // the triangles adjacent to holes in the height data are discarded by all fragment stages
+    if (hole) {
+        output.nodata = 1.0;
+    }
+
+    // the depth passes only require the position of the vertex
+#ifndef DEPTH_ONLY
 #ifdef SHOW_TILES
     output.debug_color = show_tiles(tile, output.world_position);
 #endif
@@ -39,6 +57,7 @@ fn vertex(in: VertexInput) -> VertexOutput {
 
 #ifdef TEST2
     output.debug_color = mix(output.debug_color, vec4<f32>(f32(tile_index) / 1000.0, 0.0, 0.0, 1.0), 0.4);
+#endif
 #endif
 
     return output;
@@ -0,0 +1,159 @@
+#import bevy_terrain::types
+
+struct TerrainConfig {
+    lod_count: u32,
+    height: f32,
+    leaf_node_size: u32,
+    terrain_size: u32,
+
+    height_size: f32,
+    minmax_size: f32,
+    _empty: u32,
+    _empty: u32,
+    height_scale: f32,
+    minmax_scale: f32,
+    _empty: u32,
+    _empty: u32,
+    height_offset: f32,
+    minmax_offset: f32,
+    _empty: u32,
+    _empty: u32,
+    height_min_lod: u32,
+    minmax_min_lod: u32,
+    _empty: u32,
+    _empty: u32,
+    height_max_lod: u32,
+    minmax_max_lod: u32,
+    _empty: u32,
+    _empty: u32,
+
+    planet_radius: f32,
+    nodata: u32,
+}
+
+struct Water {
+    shallow_color: vec4<f32>,
+    deep_color: vec4<f32>,
+    foam_color: vec4<f32>,
+    clarity: f32,
+    foam_width: f32,
+    water_scale: f32,
+    water_offset: f32,
+}
+
+// view bindings
+#import bevy_pbr::mesh_view_bindings
+
+// terrain view bindings
+@group(1) @binding(0)
+var<uniform> view_config: TerrainViewConfig;
+@group(1) @binding(1)
+var quadtree: texture_2d_array<u32>;
+@group(1) @binding(2)
+var<storage> tiles: TileList;
+
+// terrain bindings
+@group(2) @binding(0)
+var<uniform> config: TerrainConfig;
+@group(2) @binding(1)
+var atlas_sampler: sampler;
+@group(2) @binding(2)
+var height_atlas: texture_2d_array<f32>;
+@group(2) @binding(3)
+var minmax_atlas: texture_2d_array<f32>;
+
+// water bindings
+@group(3) @binding(0)
+var<uniform> water: Water;
+@group(3) @binding(1)
+var water_atlas: texture_2d_array<f32>;
+@group(3) @binding(2)
+var terrain_depth: texture_depth_2d;
+
+#import bevy_terrain::node
+#import bevy_terrain::functions
+
+struct WaterVertexOutput {
+    @builtin(position) frag_coord: vec4<f32>,
+    @location(0)       local_position: vec2<f32>,
+    @location(1)       world_position: vec4<f32>,
+}
+
+// Returns whether there is water (x) and the height of the water surface (y).
+fn lookup_water(lookup: NodeLookup) -> vec2<f32> {
+    let water_coords = lookup.atlas_coords * water.water_scale + water.water_offset;
+    return textureSampleLevel(water_atlas, atlas_sampler, water_coords, lookup.atlas_index, 0.0).xy;
+}
+
+// Converts the reversed depth of the perspective projection into the distance to the viewer.
+fn linear_depth(depth: f32) -> f32 {
+    return view.projection[3][2] / max(depth, 0.000001);
+}
+
+@vertex
+fn vertex(in: VertexInput) -> WaterVertexOutput {
+    let tile_index = in.vertex_index / view_config.vertices_per_tile;
+    let grid_index = in.vertex_index % view_config.vertices_per_tile;
+
+    let tile = tiles.data[tile_index];
+    let grid_position = calculate_grid_position(grid_index);
+
+    let local_position = calculate_local_position(tile, grid_position);
+    let approximate_position = approximate_world_position(local_position);
+
+    let blend = calculate_blend(approximate_position);
+
+    let lookup = lookup_node(blend.lod, local_position);
+    var level = lookup_water(lookup).y;
+
+    if (blend.ratio < 1.0) {
+        let lookup2 = lookup_node(blend.lod + 1u, local_position);
+        let level2 = lookup_water(lookup2).y;
+        level      = mix(level2, level, blend.ratio);
+    }
+
+    let world_position = vec4<f32>(local_position.x, level * config.height, local_position.y, 1.0);
+
+    var output: WaterVertexOutput;
+    output.frag_coord = view.view_proj * world_position;
+    output.local_position = local_position;
+    output.world_position = world_position;
+
+    return output;
+}
+
+@fragment
+fn fragment(input: WaterVertexOutput) -> @location(0) vec4<f32> {
+    let blend = calculate_blend(input.world_position);
+
+    let lookup = lookup_node(blend.lod, input.local_position);
+    var mask = lookup_water(lookup).x;
+
+    if (blend.ratio < 1.0) {
+        let lookup2 = lookup_node(blend.lod + 1u, input.local_position);
+        mask        = mix(lookup_water(lookup2).x, mask, blend.ratio);
+    }
+
+    if (mask < 0.01) {
+        discard;
+    }
+
+    // the thickness of the water along the view ray
+    let depth = textureLoad(terrain_depth, vec2<i32>(input.frag_coord.xy), 0);
+    let thickness = max(linear_depth(depth) - linear_depth(input.frag_coord.z), 0.0);
+
+    let deep = 1.0 - exp(-thickness / water.clarity);
+    var color = mix(water.shallow_color, water.deep_color, deep);
+
+    // the surface reflects more light at grazing angles
+    let view_direction = normalize(view.world_position.xyz - input.world_position.xyz);
+    let fresnel = pow(1.0 - max(view_direction.y, 0.0), 5.0);
+    color.a = mix(color.a, 1.0, fresnel);
+
+    let foam = 1.0 - smoothstep(0.0, water.foam_width, thickness);
+    color = mix(color, vec4<f32>(water.foam_color.rgb, 1.0), foam * water.foam_color.a);
+
+    color.a = color.a * smoothstep(0.0, 1.0, mask);
+
+    return color;
+}
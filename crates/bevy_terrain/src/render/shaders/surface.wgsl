@@ -0,0 +1,24 @@
+#define_import_path bevy_terrain::surface
+
+// Reusable functions for extruding the surface attachment (the normalized surface model),
+// which stores the height of buildings and vegetation above the terrain.
+// The surface attachment shares the layout of the height attachment, thus it can be sampled
+// with the lookup of the height.
+
+// Returns the height of the surface above the terrain in world units.
+// The nearest texel is loaded, so that the extruded buildings have steep walls
+// instead of sloped ones.
+fn surface_height(surface_atlas: texture_2d_array<f32>, lookup: NodeLookup,
+                  surface_size: f32, surface_scale: f32, surface_offset: f32) -> f32 {
+    let surface_coords = lookup.atlas_coords * surface_scale + surface_offset;
+    let texel = vec2<i32>(surface_coords * surface_size);
+
+    return textureLoad(surface_atlas, texel, lookup.atlas_index, 0).x * config.height;
+}
+
+// Returns the flat normal of the extruded geometry, which is derived from the screen space
+// derivatives of the world position.
+// Has to be called in uniform control flow.
+fn extruded_normal(world_position: vec3<f32>) -> vec3<f32> {
+    return normalize(cross(dpdy(world_position), dpdx(world_position)));
+}
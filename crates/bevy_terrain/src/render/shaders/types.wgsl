@@ -1,6 +1,6 @@
 #define_import_path bevy_terrain::types
 
-struct Mesh { flags: u32 }; let mesh = Mesh(0u); // hack for the pbr shaders
+struct Mesh { flags: u32 }; let mesh = Mesh(1u); // hack for the pbr shaders (the terrain receives shadows)
 
 struct TerrainViewConfig {
     approximate_height: f32,
@@ -0,0 +1,57 @@
+#define_import_path bevy_terrain::virtual_texture
+
+// Samples a virtual texture, which is streamed by the virtual texture subsystem.
+// The page table stores the index of each resident page offset by one (zero marks missing pages)
+// for every lod in its mip levels.
+// All samples use explicit gradients, so that they can be taken in non-uniform control flow.
+
+// The virtual texture, matching the `VirtualTextureUniform`.
+struct VirtualTexture {
+    // The size of the terrain in world units.
+    terrain_size: f32,
+    // The size of the virtual texture at the highest level of detail in texels.
+    size: f32,
+    page_size: f32,
+    center_size: f32,
+    border_size: f32,
+    lod_count: u32,
+}
+
+// Samples the virtual texture at the local position with the best resident page.
+// The derivatives of the local position select the lod.
+// If the required page is not resident yet, a coarser one is used instead.
+fn virtual_texture_sample(page_table: texture_2d<u32>, pages: texture_2d_array<f32>, page_sampler: sampler,
+                          vt: VirtualTexture, local_position: vec2<f32>,
+                          ddx: vec2<f32>, ddy: vec2<f32>) -> vec4<f32> {
+    let texel_scale = vt.size / vt.terrain_size;
+    let texel_position = local_position * texel_scale;
+    let footprint = max(length(ddx), length(ddy)) * texel_scale;
+
+    var lod = u32(clamp(floor(log2(max(footprint, 1.0))), 0.0, f32(vt.lod_count - 1u)));
+    var page = vec2<u32>(0u);
+    var entry = 0u;
+
+    loop {
+        let lod_scale = f32(1u << lod);
+        page = vec2<u32>(texel_position / (vt.center_size * lod_scale));
+        entry = textureLoad(page_table, vec2<i32>(page), i32(lod)).x;
+
+        if (entry != 0u || lod >= vt.lod_count - 1u) {
+            break;
+        }
+
+        lod = lod + 1u;
+    }
+
+    // the coarsest lod is always resident, once it has been loaded
+    if (entry == 0u) {
+        return vec4<f32>(0.0);
+    }
+
+    let lod_scale = f32(1u << lod);
+    let page_position = texel_position / lod_scale - vec2<f32>(page) * vt.center_size;
+    let uv = (page_position + vt.border_size) / vt.page_size;
+    let uv_scale = texel_scale / (lod_scale * vt.page_size);
+
+    return textureSampleGrad(pages, page_sampler, uv, i32(entry - 1u), ddx * uv_scale, ddy * uv_scale);
+}
@@ -26,6 +26,10 @@ pub(crate) struct TerrainConfigUniform {
     attachment_sizes: Vec4,
     attachment_scales: Vec4,
     attachment_offsets: Vec4,
+    attachment_min_lods: UVec4,
+    attachment_max_lods: UVec4,
+    planet_radius: f32,
+    nodata: u32,
 }
 
 impl From<&TerrainConfig> for TerrainConfigUniform {
@@ -34,11 +38,15 @@ impl From<&TerrainConfig> for TerrainConfigUniform {
         let mut sizes = [0.0; 4];
         let mut scales = [1.0; 4];
         let mut offsets = [0.0; 4];
+        let mut min_lods = [0; 4];
+        let mut max_lods = [config.lod_count - 1; 4];
 
         for (i, attachment) in config.attachments.iter().enumerate() {
             sizes[i] = attachment.texture_size as f32;
             scales[i] = attachment.center_size as f32 / attachment.texture_size as f32;
             offsets[i] = attachment.border_size as f32 / attachment.texture_size as f32;
+            min_lods[i] = attachment.min_lod;
+            max_lods[i] = attachment.max_lod.min(config.lod_count - 1);
         }
 
         Self {
@@ -49,6 +57,10 @@ impl From<&TerrainConfig> for TerrainConfigUniform {
             attachment_sizes: Vec4::from_array(sizes),
             attachment_scales: Vec4::from_array(scales),
             attachment_offsets: Vec4::from_array(offsets),
+            attachment_min_lods: UVec4::from_array(min_lods),
+            attachment_max_lods: UVec4::from_array(max_lods),
+            planet_radius: config.planet_radius,
+            nodata: config.nodata.is_enabled() as u32,
         }
     }
 }
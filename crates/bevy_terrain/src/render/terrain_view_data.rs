@@ -1,14 +1,18 @@
 use crate::{
     render::{
         INDIRECT_BUFFER_SIZE, PARAMETER_BUFFER_SIZE, PREPARE_INDIRECT_LAYOUT, REFINE_TILES_LAYOUT,
-        TERRAIN_VIEW_CONFIG_SIZE, TERRAIN_VIEW_LAYOUT, TILE_SIZE,
+        STATISTICS_BUFFER_SIZE, TERRAIN_VIEW_CONFIG_SIZE, TERRAIN_VIEW_LAYOUT, TILE_SIZE,
     },
     terrain::{Terrain, TerrainConfig},
     terrain_view::{TerrainView, TerrainViewConfig},
     TerrainViewComponents,
 };
 use bevy::{
-    ecs::system::{lifetimeless::SRes, SystemParamItem},
+    ecs::system::{
+        lifetimeless::{Read, SQuery, SRes},
+        SystemParamItem,
+    },
+    pbr::LightEntity,
     prelude::*,
     render::{
         render_asset::RenderAssets,
@@ -18,6 +22,7 @@ use bevy::{
         Extract,
     },
 };
+use std::sync::{atomic::AtomicU32, Arc};
 
 #[derive(Clone, Default, ShaderType)]
 pub(crate) struct TerrainViewConfigUniform {
@@ -57,12 +62,26 @@ impl TerrainViewConfigUniform {
     }
 }
 
+/// The statistics buffer can be written by the compute node.
+pub(crate) const STATISTICS_IDLE: u32 = 0;
+/// The statistics have been copied into the statistics buffer and are ready to be mapped.
+pub(crate) const STATISTICS_COPIED: u32 = 1;
+/// The statistics buffer is being mapped.
+pub(crate) const STATISTICS_MAPPING: u32 = 2;
+
 pub struct TerrainViewData {
     pub(crate) indirect_buffer: Buffer,
     pub(crate) view_config_buffer: Buffer,
+    pub(crate) parameter_buffer: Buffer,
+    /// The culling statistics copied from the parameter buffer for the readback.
+    pub(crate) statistics_buffer: Buffer,
+    pub(crate) statistics_state: Arc<AtomicU32>,
     pub(crate) prepare_indirect_bind_group: BindGroup,
     pub(crate) refine_tiles_bind_group: BindGroup,
     pub(crate) terrain_view_bind_group: BindGroup,
+    /// The node count of the quadtree and the tile count, the data was created with.
+    node_count: u32,
+    tile_count: u32,
 }
 
 impl TerrainViewData {
@@ -74,6 +93,7 @@ impl TerrainViewData {
         let indirect_buffer = Self::create_indirect_buffer(device);
         let view_config_buffer = Self::create_view_config_buffer(device);
         let parameter_buffer = Self::create_parameter_buffer(device);
+        let statistics_buffer = Self::create_statistics_buffer(device);
         let (temporary_tile_buffer, final_tile_buffer) =
             Self::create_tile_buffers(device, view_config);
 
@@ -135,9 +155,14 @@ impl TerrainViewData {
         Self {
             indirect_buffer,
             view_config_buffer,
+            parameter_buffer,
+            statistics_buffer,
+            statistics_state: Arc::new(AtomicU32::new(STATISTICS_IDLE)),
             prepare_indirect_bind_group,
             refine_tiles_bind_group,
             terrain_view_bind_group,
+            node_count: view_config.node_count,
+            tile_count: view_config.tile_count,
         }
     }
 
@@ -161,7 +186,16 @@ impl TerrainViewData {
         device.create_buffer(&BufferDescriptor {
             label: "parameter_buffer".into(),
             size: PARAMETER_BUFFER_SIZE,
-            usage: BufferUsages::STORAGE,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_statistics_buffer(device: &RenderDevice) -> Buffer {
+        device.create_buffer(&BufferDescriptor {
+            label: "statistics_buffer".into(),
+            size: STATISTICS_BUFFER_SIZE,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
             mapped_at_creation: false,
         })
     }
@@ -194,18 +228,28 @@ impl TerrainViewData {
     }
 }
 
+/// Initializes the [`TerrainViewData`] of newly created terrains and recreates the ones,
+/// whose quadtree texture or tile buffers have to be resized.
 pub(crate) fn initialize_terrain_view_data(
     device: Res<RenderDevice>,
     images: Res<RenderAssets<Image>>,
     mut terrain_view_data: ResMut<TerrainViewComponents<TerrainViewData>>,
     view_configs: Extract<Res<TerrainViewComponents<TerrainViewConfig>>>,
     view_query: Extract<Query<Entity, With<TerrainView>>>,
-    terrain_query: Extract<Query<Entity, Added<Terrain>>>,
+    terrain_query: Extract<Query<Entity, With<Terrain>>>,
 ) {
     for terrain in terrain_query.iter() {
         for view in view_query.iter() {
             let view_config = view_configs.get(&(terrain, view)).unwrap();
 
+            if let Some(data) = terrain_view_data.get(&(terrain, view)) {
+                if data.node_count == view_config.node_count
+                    && data.tile_count == view_config.tile_count
+                {
+                    continue;
+                }
+            }
+
             terrain_view_data.insert(
                 (terrain, view),
                 TerrainViewData::new(&device, &images, view_config),
@@ -239,18 +283,36 @@ pub(crate) fn queue_terrain_view_config(
     }
 }
 
+/// Returns the terrain view entity of the render view.
+///
+/// Shadow views are spawned by the renderer for each light, thus the
+/// corresponding light entity is used instead.
+#[inline]
+pub(crate) fn terrain_view_entity(view: Entity, light_query: &Query<&LightEntity>) -> Entity {
+    match light_query.get(view) {
+        Ok(LightEntity::Directional { light_entity })
+        | Ok(LightEntity::Spot { light_entity })
+        | Ok(LightEntity::Point { light_entity, .. }) => *light_entity,
+        Err(_) => view,
+    }
+}
+
 pub struct SetTerrainViewBindGroup<const I: usize>;
 
 impl<const I: usize> EntityRenderCommand for SetTerrainViewBindGroup<I> {
-    type Param = SRes<TerrainViewComponents<TerrainViewData>>;
+    type Param = (
+        SRes<TerrainViewComponents<TerrainViewData>>,
+        SQuery<Read<LightEntity>>,
+    );
 
     #[inline]
     fn render<'w>(
         view: Entity,
         terrain: Entity,
-        terrain_view_data: SystemParamItem<'w, '_, Self::Param>,
+        (terrain_view_data, light_query): SystemParamItem<'w, '_, Self::Param>,
         pass: &mut TrackedRenderPass<'w>,
     ) -> RenderCommandResult {
+        let view = terrain_view_entity(view, &light_query);
         let data = terrain_view_data
             .into_inner()
             .get(&(terrain, view))
@@ -264,15 +326,19 @@ impl<const I: usize> EntityRenderCommand for SetTerrainViewBindGroup<I> {
 pub(crate) struct DrawTerrainCommand;
 
 impl EntityRenderCommand for DrawTerrainCommand {
-    type Param = SRes<TerrainViewComponents<TerrainViewData>>;
+    type Param = (
+        SRes<TerrainViewComponents<TerrainViewData>>,
+        SQuery<Read<LightEntity>>,
+    );
 
     #[inline]
     fn render<'w>(
         view: Entity,
         terrain: Entity,
-        terrain_view_data: SystemParamItem<'w, '_, Self::Param>,
+        (terrain_view_data, light_query): SystemParamItem<'w, '_, Self::Param>,
         pass: &mut TrackedRenderPass<'w>,
     ) -> RenderCommandResult {
+        let view = terrain_view_entity(view, &light_query);
         let data = terrain_view_data
             .into_inner()
             .get(&(terrain, view))
@@ -0,0 +1,364 @@
+//! Renders the water surface of a terrain, which is described by its water attachment.
+//!
+//! The water is drawn in a separate transparent pass on top of the terrain.
+//! The tiles of the terrain are displaced to the water level instead of the terrain height.
+//! The thickness of the water is derived from the depth of the terrain, which is rendered before
+//! the main pass (see [`occlusion`](crate::render::occlusion)), and used to fade the water
+//! towards the shore and to add foam along the shoreline.
+
+use crate::{
+    render::{
+        occlusion::DepthPyramid,
+        render_pipeline::{TerrainPipelineConfig, TerrainPipelineFlags},
+        shaders::WATER_SHADER,
+        terrain_data::{terrain_bind_group_layout, SetTerrainBindGroup},
+        terrain_view_data::{DrawTerrainCommand, SetTerrainViewBindGroup},
+        TERRAIN_VIEW_LAYOUT,
+    },
+    skip_none,
+    terrain::{Terrain, TerrainComponents},
+    terrain_data::{gpu_node_atlas::GpuNodeAtlas, AttachmentIndex},
+    TerrainView, TerrainViewComponents,
+};
+use bevy::{
+    core_pipeline::core_3d::Transparent3d,
+    ecs::{
+        query::QueryItem,
+        system::{
+            lifetimeless::{Read, SRes},
+            SystemParamItem,
+        },
+    },
+    pbr::{MeshPipeline, SetMeshViewBindGroup},
+    prelude::*,
+    render::{
+        extract_component::ExtractComponent,
+        render_asset::RenderAssets,
+        render_phase::{
+            DrawFunctions, EntityRenderCommand, RenderCommandResult, RenderPhase, SetItemPipeline,
+            TrackedRenderPass,
+        },
+        render_resource::*,
+        renderer::RenderDevice,
+        texture::BevyDefault,
+    },
+};
+
+/// The water of a terrain.
+///
+/// Insert this component into a terrain entity, to render the water described by the water
+/// attachment (see [`TerrainConfig::add_water_from_disk`](crate::terrain::TerrainConfig::add_water_from_disk)).
+#[derive(Clone, Component)]
+pub struct TerrainWater {
+    /// The index of the water attachment.
+    pub attachment_index: AttachmentIndex,
+    /// The color of shallow water.
+    pub shallow_color: Color,
+    /// The color of deep water.
+    pub deep_color: Color,
+    /// The color of the foam along the shoreline.
+    pub foam_color: Color,
+    /// The thickness of the water in world units, at which it becomes opaque.
+    pub clarity: f32,
+    /// The thickness of the water in world units, up to which foam is added.
+    pub foam_width: f32,
+}
+
+impl TerrainWater {
+    pub fn new(attachment_index: AttachmentIndex) -> Self {
+        Self {
+            attachment_index,
+            shallow_color: Color::rgba(0.1, 0.4, 0.45, 0.3),
+            deep_color: Color::rgba(0.02, 0.1, 0.18, 0.95),
+            foam_color: Color::rgba(0.9, 0.95, 1.0, 0.8),
+            clarity: 8.0,
+            foam_width: 0.5,
+        }
+    }
+}
+
+impl ExtractComponent for TerrainWater {
+    type Query = Read<Self>;
+    type Filter = With<Terrain>;
+
+    #[inline]
+    fn extract_component(item: QueryItem<Self::Query>) -> Self {
+        item.clone()
+    }
+}
+
+/// The water data that is available in the water shader.
+#[derive(Clone, Default, ShaderType)]
+struct WaterUniform {
+    shallow_color: Vec4,
+    deep_color: Vec4,
+    foam_color: Vec4,
+    clarity: f32,
+    foam_width: f32,
+    /// The scale and offset of the atlas coordinates, which skip the border of the water attachment.
+    water_scale: f32,
+    water_offset: f32,
+}
+
+pub(crate) const WATER_LAYOUT: BindGroupLayoutDescriptor = BindGroupLayoutDescriptor {
+    label: Some("water_layout"),
+    entries: &[
+        // water uniform
+        BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::VERTEX_FRAGMENT,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+        // water atlas
+        BindGroupLayoutEntry {
+            binding: 1,
+            visibility: ShaderStages::VERTEX_FRAGMENT,
+            ty: BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: true },
+                view_dimension: TextureViewDimension::D2Array,
+                multisampled: false,
+            },
+            count: None,
+        },
+        // terrain depth
+        BindGroupLayoutEntry {
+            binding: 2,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                sample_type: TextureSampleType::Depth,
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        },
+    ],
+};
+
+/// The pipeline used to render the water of the terrains.
+#[derive(Resource)]
+pub struct WaterPipeline {
+    view_layout: BindGroupLayout,
+    terrain_view_layout: BindGroupLayout,
+    terrain_layout: BindGroupLayout,
+    water_layout: BindGroupLayout,
+}
+
+impl FromWorld for WaterPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let device = world.resource::<RenderDevice>();
+        let mesh_pipeline = world.resource::<MeshPipeline>();
+        let config = world.resource::<TerrainPipelineConfig>();
+
+        Self {
+            view_layout: mesh_pipeline.view_layout.clone(),
+            terrain_view_layout: device.create_bind_group_layout(&TERRAIN_VIEW_LAYOUT),
+            terrain_layout: terrain_bind_group_layout(device, config.attachment_count),
+            water_layout: device.create_bind_group_layout(&WATER_LAYOUT),
+        }
+    }
+}
+
+impl SpecializedRenderPipeline for WaterPipeline {
+    type Key = TerrainPipelineFlags;
+
+    fn specialize(&self, key: Self::Key) -> RenderPipelineDescriptor {
+        let shader_defs = key.shader_defs();
+
+        RenderPipelineDescriptor {
+            label: Some("water_pipeline".into()),
+            layout: Some(vec![
+                self.view_layout.clone(),
+                self.terrain_view_layout.clone(),
+                self.terrain_layout.clone(),
+                self.water_layout.clone(),
+            ]),
+            vertex: VertexState {
+                shader: WATER_SHADER.typed(),
+                entry_point: "vertex".into(),
+                shader_defs: shader_defs.clone(),
+                buffers: Vec::new(),
+            },
+            fragment: Some(FragmentState {
+                shader: WATER_SHADER.typed(),
+                shader_defs,
+                entry_point: "fragment".into(),
+                targets: vec![Some(ColorTargetState {
+                    format: TextureFormat::bevy_default(),
+                    blend: Some(BlendState::ALPHA_BLENDING),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: PrimitiveState {
+                front_face: FrontFace::Ccw,
+                cull_mode: None,
+                unclipped_depth: false,
+                polygon_mode: key.polygon_mode(),
+                conservative: false,
+                topology: PrimitiveTopology::TriangleStrip,
+                strip_index_format: None,
+            },
+            // the water is tested against, but does not write to the depth of the main pass
+            depth_stencil: Some(DepthStencilState {
+                format: TextureFormat::Depth32Float,
+                depth_write_enabled: false,
+                depth_compare: CompareFunction::GreaterEqual,
+                stencil: StencilState {
+                    front: StencilFaceState::IGNORE,
+                    back: StencilFaceState::IGNORE,
+                    read_mask: 0,
+                    write_mask: 0,
+                },
+                bias: DepthBiasState {
+                    constant: 0,
+                    slope_scale: 0.0,
+                    clamp: 0.0,
+                },
+            }),
+            multisample: MultisampleState {
+                count: key.msaa_samples(),
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        }
+    }
+}
+
+/// The bind group of the water of a terrain for a camera view.
+pub struct WaterBindGroup {
+    bind_group: BindGroup,
+}
+
+/// Creates the water bind groups, which reference the terrain depth texture of each camera view.
+pub(crate) fn queue_water_bind_groups(
+    device: Res<RenderDevice>,
+    water_pipeline: Res<WaterPipeline>,
+    images: Res<RenderAssets<Image>>,
+    gpu_node_atlases: Res<TerrainComponents<GpuNodeAtlas>>,
+    depth_pyramids: Res<TerrainViewComponents<DepthPyramid>>,
+    mut water_bind_groups: ResMut<TerrainViewComponents<WaterBindGroup>>,
+    terrain_query: Query<(Entity, &TerrainWater)>,
+    view_query: Query<Entity, With<TerrainView>>,
+) {
+    // the depth textures are recreated when the viewport is resized
+    water_bind_groups.0.clear();
+
+    for (terrain, water) in terrain_query.iter() {
+        let gpu_node_atlas = skip_none!(gpu_node_atlases.get(&terrain));
+        let (attachment, handle) =
+            skip_none!(gpu_node_atlas.attachments.get(water.attachment_index));
+        let water_atlas = skip_none!(images.get(handle));
+
+        let uniform = WaterUniform {
+            shallow_color: water.shallow_color.as_linear_rgba_f32().into(),
+            deep_color: water.deep_color.as_linear_rgba_f32().into(),
+            foam_color: water.foam_color.as_linear_rgba_f32().into(),
+            clarity: water.clarity,
+            foam_width: water.foam_width,
+            water_scale: attachment.center_size as f32 / attachment.texture_size as f32,
+            water_offset: attachment.border_size as f32 / attachment.texture_size as f32,
+        };
+
+        let mut buffer = encase::UniformBuffer::new(Vec::new());
+        buffer.write(&uniform).unwrap();
+
+        let buffer = device.create_buffer_with_data(&BufferInitDescriptor {
+            label: None,
+            contents: &buffer.into_inner(),
+            usage: BufferUsages::UNIFORM,
+        });
+
+        for view in view_query.iter() {
+            let depth_pyramid = skip_none!(depth_pyramids.get(&(terrain, view)));
+
+            let bind_group = device.create_bind_group(&BindGroupDescriptor {
+                label: Some("water_bind_group"),
+                layout: &water_pipeline.water_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::TextureView(&water_atlas.texture_view),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: BindingResource::TextureView(&depth_pyramid.depth_view),
+                    },
+                ],
+            });
+
+            water_bind_groups.insert((terrain, view), WaterBindGroup { bind_group });
+        }
+    }
+}
+
+pub struct SetWaterBindGroup<const I: usize>;
+
+impl<const I: usize> EntityRenderCommand for SetWaterBindGroup<I> {
+    type Param = SRes<TerrainViewComponents<WaterBindGroup>>;
+
+    #[inline]
+    fn render<'w>(
+        view: Entity,
+        terrain: Entity,
+        water_bind_groups: SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        match water_bind_groups.into_inner().get(&(terrain, view)) {
+            Some(water_bind_group) => {
+                pass.set_bind_group(I, &water_bind_group.bind_group, &[]);
+                RenderCommandResult::Success
+            }
+            None => RenderCommandResult::Failure,
+        }
+    }
+}
+
+/// The draw function of the water.
+pub(crate) type DrawWater = (
+    SetItemPipeline,
+    SetMeshViewBindGroup<0>,
+    SetTerrainViewBindGroup<1>,
+    SetTerrainBindGroup<2>,
+    SetWaterBindGroup<3>,
+    DrawTerrainCommand,
+);
+
+/// Queues the water of all terrains into the transparent phase of the camera views.
+pub(crate) fn queue_water(
+    water_pipeline: Res<WaterPipeline>,
+    draw_functions: Res<DrawFunctions<Transparent3d>>,
+    msaa: Res<Msaa>,
+    water_bind_groups: Res<TerrainViewComponents<WaterBindGroup>>,
+    mut pipelines: ResMut<SpecializedRenderPipelines<WaterPipeline>>,
+    mut pipeline_cache: ResMut<PipelineCache>,
+    mut view_query: Query<(Entity, &mut RenderPhase<Transparent3d>), With<TerrainView>>,
+    terrain_query: Query<Entity, With<TerrainWater>>,
+) {
+    let draw_function = draw_functions.read().get_id::<DrawWater>().unwrap();
+
+    let flags = TerrainPipelineFlags::from_msaa_samples(msaa.samples);
+    let pipeline = pipelines.specialize(&mut pipeline_cache, &water_pipeline, flags);
+
+    for (view, mut transparent_phase) in view_query.iter_mut() {
+        for terrain in terrain_query.iter() {
+            if water_bind_groups.get(&(terrain, view)).is_none() {
+                continue;
+            }
+
+            transparent_phase.add(Transparent3d {
+                entity: terrain,
+                pipeline,
+                draw_function,
+                distance: f32::MIN, // draw the water behind all other transparent objects
+            });
+        }
+    }
+}
@@ -0,0 +1,519 @@
+use crate::{
+    render::{
+        culling::planes, render_pipeline::TerrainPipelineFlags, terrain_view_data::TerrainViewData,
+    },
+    scatter::{
+        pipelines::{
+            ScatterComputePipelineId, ScatterComputePipelines, ScatterPipelineFlags,
+            ScatterRenderPipeline,
+        },
+        ScatterDensity, ScatterLayer,
+    },
+    skip_none,
+    terrain::TerrainComponents,
+    terrain_data::gpu_node_atlas::GpuNodeAtlas,
+    DebugTerrain, TerrainData, TerrainView, TerrainViewComponents,
+};
+use bevy::{
+    core_pipeline::core_3d::Opaque3d,
+    ecs::system::{lifetimeless::SRes, SystemParamItem},
+    math::Vec3Swizzles,
+    pbr::SetMeshViewBindGroup,
+    prelude::*,
+    render::{
+        mesh::{GpuBufferInfo, GpuMesh},
+        render_asset::RenderAssets,
+        render_graph::{self},
+        render_phase::{
+            DrawFunctions, EntityRenderCommand, RenderCommandResult, RenderPhase, SetItemPipeline,
+            TrackedRenderPass,
+        },
+        render_resource::*,
+        renderer::{RenderContext, RenderDevice, RenderQueue},
+        view::ExtractedView,
+    },
+};
+
+/// The size of an instance (position and scale, rotation and a random value) in bytes.
+pub(crate) const INSTANCE_SIZE: BufferAddress = 8 * 4;
+/// The size of the indirect draw arguments followed by the instance counter in bytes.
+pub(crate) const INDIRECT_SIZE: BufferAddress = 6 * 4;
+
+/// The scatter data that is available in the scatter shaders.
+#[derive(Clone, Default, ShaderType)]
+struct ScatterData {
+    world_position: Vec4,
+    view_proj: Mat4,
+    planes: [Vec4; 5],
+    color: Vec4,
+    /// The first cell of the candidate grid.
+    first_cell: IVec2,
+    /// The number of cells of the candidate grid in each direction.
+    cell_count: UVec2,
+    spacing: f32,
+    distance: f32,
+    lod_falloff: f32,
+    min_scale: f32,
+    max_scale: f32,
+    radius: f32,
+    min_height: f32,
+    max_height: f32,
+    max_slope: f32,
+    channel: u32,
+    /// The scale and offset of the atlas coordinates, which skip the border of the attachments.
+    density_scale: f32,
+    density_offset: f32,
+    density_min_lod: u32,
+    density_max_lod: u32,
+    canopy_scale: f32,
+    canopy_offset: f32,
+    canopy_min_lod: u32,
+    canopy_max_lod: u32,
+    max_instances: u32,
+}
+
+/// The gpu resources of a [`ScatterLayer`] for a terrain view.
+pub struct GpuScatter {
+    terrain: Entity,
+    mesh: Handle<Mesh>,
+    max_instances: u32,
+    flags: ScatterPipelineFlags,
+    uniform_buffer: Buffer,
+    instance_buffer: Buffer,
+    indirect_buffer: Buffer,
+    compute_bind_group: BindGroup,
+    render_bind_group: BindGroup,
+    scatter_pipeline: CachedComputePipelineId,
+    finish_pipeline: CachedComputePipelineId,
+    workgroup_count: UVec2,
+}
+
+impl GpuScatter {
+    fn new(
+        device: &RenderDevice,
+        images: &RenderAssets<Image>,
+        compute_pipelines: &ScatterComputePipelines,
+        render_pipeline: &ScatterRenderPipeline,
+        gpu_node_atlas: &GpuNodeAtlas,
+        layer: &ScatterLayer,
+    ) -> Option<Self> {
+        let atlas_view = |attachment_index| {
+            let (_, handle) = gpu_node_atlas.attachments.get(attachment_index)?;
+            images.get(handle).map(|image| &image.texture_view)
+        };
+
+        // the height atlas is bound in place of the unused attachments
+        let height_view = atlas_view(0)?;
+        let density_view = match layer.density {
+            ScatterDensity::Attachment {
+                attachment_index, ..
+            } => atlas_view(attachment_index)?,
+            ScatterDensity::Procedural { .. } => height_view,
+        };
+        let canopy_view = match layer.canopy {
+            Some(attachment_index) => atlas_view(attachment_index)?,
+            None => height_view,
+        };
+
+        let uniform_buffer = device.create_buffer(&BufferDescriptor {
+            label: "scatter_uniform_buffer".into(),
+            size: ScatterData::min_size().get(),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let instance_buffer = device.create_buffer(&BufferDescriptor {
+            label: "scatter_instance_buffer".into(),
+            size: INSTANCE_SIZE * layer.max_instances.max(1) as BufferAddress,
+            usage: BufferUsages::STORAGE | BufferUsages::VERTEX,
+            mapped_at_creation: false,
+        });
+
+        let indirect_buffer = device.create_buffer(&BufferDescriptor {
+            label: "scatter_indirect_buffer".into(),
+            size: INDIRECT_SIZE,
+            usage: BufferUsages::STORAGE | BufferUsages::INDIRECT | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let compute_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("scatter_compute_bind_group"),
+            layout: &compute_pipelines.scatter_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(density_view),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::TextureView(canopy_view),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: instance_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: indirect_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let render_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("scatter_render_bind_group"),
+            layout: &render_pipeline.scatter_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        Some(Self {
+            terrain: layer.terrain,
+            mesh: layer.mesh.clone(),
+            max_instances: layer.max_instances,
+            flags: ScatterPipelineFlags::from_layer(layer),
+            uniform_buffer,
+            instance_buffer,
+            indirect_buffer,
+            compute_bind_group,
+            render_bind_group,
+            scatter_pipeline: CachedComputePipelineId::INVALID,
+            finish_pipeline: CachedComputePipelineId::INVALID,
+            workgroup_count: UVec2::ZERO,
+        })
+    }
+
+    /// Writes the scatter data of the layer for the view and resets the indirect buffer.
+    fn update(
+        &mut self,
+        queue: &RenderQueue,
+        gpu_node_atlas: &GpuNodeAtlas,
+        gpu_mesh: &GpuMesh,
+        layer: &ScatterLayer,
+        extracted_view: &ExtractedView,
+    ) {
+        let view_proj =
+            extracted_view.projection * extracted_view.transform.compute_matrix().inverse();
+        let world_position = extracted_view.transform.translation();
+
+        let first_cell = ((world_position.xz() - layer.distance) / layer.spacing)
+            .floor()
+            .as_ivec2();
+        let cell_count = UVec2::splat((2.0 * layer.distance / layer.spacing).ceil() as u32 + 1);
+
+        let mut data = ScatterData {
+            world_position: world_position.extend(1.0),
+            view_proj,
+            planes: planes(&view_proj),
+            color: layer.color.as_linear_rgba_f32().into(),
+            first_cell,
+            cell_count,
+            spacing: layer.spacing,
+            distance: layer.distance,
+            lod_falloff: layer.lod_falloff,
+            min_scale: layer.scale.0,
+            max_scale: layer.scale.1,
+            radius: layer.radius,
+            max_instances: layer.max_instances,
+            ..default()
+        };
+
+        match layer.density {
+            ScatterDensity::Attachment {
+                attachment_index,
+                channel,
+            } => {
+                let (attachment, _) = &gpu_node_atlas.attachments[attachment_index];
+
+                data.channel = channel;
+                data.density_scale = attachment.center_size as f32 / attachment.texture_size as f32;
+                data.density_offset =
+                    attachment.border_size as f32 / attachment.texture_size as f32;
+                data.density_min_lod = attachment.min_lod;
+                data.density_max_lod = attachment.max_lod;
+            }
+            ScatterDensity::Procedural {
+                min_height,
+                max_height,
+                max_slope,
+            } => {
+                data.min_height = min_height;
+                data.max_height = max_height;
+                data.max_slope = max_slope;
+            }
+        }
+
+        if let Some(attachment_index) = layer.canopy {
+            let (attachment, _) = &gpu_node_atlas.attachments[attachment_index];
+
+            data.canopy_scale = attachment.center_size as f32 / attachment.texture_size as f32;
+            data.canopy_offset = attachment.border_size as f32 / attachment.texture_size as f32;
+            data.canopy_min_lod = attachment.min_lod;
+            data.canopy_max_lod = attachment.max_lod;
+        }
+
+        let mut buffer = encase::UniformBuffer::new(Vec::new());
+        buffer.write(&data).unwrap();
+        queue.write_buffer(&self.uniform_buffer, 0, &buffer.into_inner());
+
+        // reset the instance count and the counter
+        let count = match gpu_mesh.buffer_info {
+            GpuBufferInfo::Indexed { count, .. } => count,
+            GpuBufferInfo::NonIndexed { vertex_count } => vertex_count,
+        };
+        let indirect = [count, 0, 0, 0, 0, 0];
+        queue.write_buffer(&self.indirect_buffer, 0, bytemuck::cast_slice(&indirect));
+
+        self.workgroup_count = (cell_count + 7) / 8;
+    }
+}
+
+/// Creates the gpu resources of all scatter layers for each terrain view, updates their scatter
+/// data and specializes their compute pipelines.
+pub(crate) fn queue_scatter(
+    device: Res<RenderDevice>,
+    queue: Res<RenderQueue>,
+    images: Res<RenderAssets<Image>>,
+    meshes: Res<RenderAssets<Mesh>>,
+    compute_pipelines: Res<ScatterComputePipelines>,
+    render_pipeline: Res<ScatterRenderPipeline>,
+    mut pipelines: ResMut<SpecializedComputePipelines<ScatterComputePipelines>>,
+    mut pipeline_cache: ResMut<PipelineCache>,
+    gpu_node_atlases: Res<TerrainComponents<GpuNodeAtlas>>,
+    mut gpu_scatters: ResMut<TerrainViewComponents<GpuScatter>>,
+    layer_query: Query<(Entity, &ScatterLayer)>,
+    view_query: Query<(Entity, &ExtractedView), With<TerrainView>>,
+) {
+    // remove the resources of despawned layers and views
+    gpu_scatters
+        .0
+        .retain(|&(layer, view), _| layer_query.contains(layer) && view_query.contains(view));
+
+    for (entity, layer) in layer_query.iter() {
+        let gpu_node_atlas = skip_none!(gpu_node_atlases.get(&layer.terrain));
+        let gpu_mesh = skip_none!(meshes.get(&layer.mesh));
+
+        for (view, extracted_view) in view_query.iter() {
+            // recreate the resources, if the layer has changed its mesh, size or attachments
+            if let Some(gpu_scatter) = gpu_scatters.get(&(entity, view)) {
+                if gpu_scatter.mesh != layer.mesh
+                    || gpu_scatter.max_instances != layer.max_instances
+                    || gpu_scatter.flags != ScatterPipelineFlags::from_layer(layer)
+                {
+                    gpu_scatters.0.remove(&(entity, view));
+                }
+            }
+
+            if gpu_scatters.get(&(entity, view)).is_none() {
+                let gpu_scatter = skip_none!(GpuScatter::new(
+                    &device,
+                    &images,
+                    &compute_pipelines,
+                    &render_pipeline,
+                    gpu_node_atlas,
+                    layer,
+                ));
+
+                gpu_scatters.insert((entity, view), gpu_scatter);
+            }
+
+            let gpu_scatter = gpu_scatters.get_mut(&(entity, view)).unwrap();
+            gpu_scatter.update(&queue, gpu_node_atlas, gpu_mesh, layer, extracted_view);
+
+            gpu_scatter.scatter_pipeline = pipelines.specialize(
+                &mut pipeline_cache,
+                &compute_pipelines,
+                (ScatterComputePipelineId::Scatter, gpu_scatter.flags),
+            );
+            gpu_scatter.finish_pipeline = pipelines.specialize(
+                &mut pipeline_cache,
+                &compute_pipelines,
+                (ScatterComputePipelineId::Finish, gpu_scatter.flags),
+            );
+        }
+    }
+}
+
+/// Scatters the instances of each layer for each terrain view.
+pub struct TerrainScatterNode;
+
+impl FromWorld for TerrainScatterNode {
+    fn from_world(_world: &mut World) -> Self {
+        Self
+    }
+}
+
+impl render_graph::Node for TerrainScatterNode {
+    fn run(
+        &self,
+        _graph: &mut render_graph::RenderGraphContext,
+        context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), render_graph::NodeRunError> {
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let terrain_data = world.resource::<TerrainComponents<TerrainData>>();
+        let terrain_view_data = world.resource::<TerrainViewComponents<TerrainViewData>>();
+        let gpu_scatters = world.resource::<TerrainViewComponents<GpuScatter>>();
+
+        let debug = world.get_resource::<DebugTerrain>();
+
+        if debug.map_or(false, |debug| debug.freeze) {
+            return Ok(());
+        }
+
+        let pass = &mut context
+            .command_encoder
+            .begin_compute_pass(&ComputePassDescriptor::default());
+
+        for (&(_, view), gpu_scatter) in gpu_scatters.0.iter() {
+            let terrain = gpu_scatter.terrain;
+            let terrain_data = skip_none!(terrain_data.get(&terrain));
+            let view_data = skip_none!(terrain_view_data.get(&(terrain, view)));
+            // some pipelines are not loaded yet
+            let scatter_pipeline =
+                skip_none!(pipeline_cache.get_compute_pipeline(gpu_scatter.scatter_pipeline));
+            let finish_pipeline =
+                skip_none!(pipeline_cache.get_compute_pipeline(gpu_scatter.finish_pipeline));
+            let count = gpu_scatter.workgroup_count;
+
+            pass.set_bind_group(0, &view_data.terrain_view_bind_group, &[]);
+            pass.set_bind_group(1, &terrain_data.terrain_bind_group, &[]);
+            pass.set_bind_group(2, &gpu_scatter.compute_bind_group, &[]);
+
+            pass.set_pipeline(scatter_pipeline);
+            pass.dispatch_workgroups(count.x, count.y, 1);
+
+            pass.set_pipeline(finish_pipeline);
+            pass.dispatch_workgroups(1, 1, 1);
+        }
+
+        Ok(())
+    }
+}
+
+pub struct SetScatterBindGroup<const I: usize>;
+
+impl<const I: usize> EntityRenderCommand for SetScatterBindGroup<I> {
+    type Param = SRes<TerrainViewComponents<GpuScatter>>;
+
+    #[inline]
+    fn render<'w>(
+        view: Entity,
+        layer: Entity,
+        gpu_scatters: SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        match gpu_scatters.into_inner().get(&(layer, view)) {
+            Some(gpu_scatter) => {
+                pass.set_bind_group(I, &gpu_scatter.render_bind_group, &[]);
+                RenderCommandResult::Success
+            }
+            None => RenderCommandResult::Failure,
+        }
+    }
+}
+
+pub(crate) struct DrawScatterCommand;
+
+impl EntityRenderCommand for DrawScatterCommand {
+    type Param = (
+        SRes<TerrainViewComponents<GpuScatter>>,
+        SRes<RenderAssets<Mesh>>,
+    );
+
+    #[inline]
+    fn render<'w>(
+        view: Entity,
+        layer: Entity,
+        (gpu_scatters, meshes): SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let gpu_scatter = match gpu_scatters.into_inner().get(&(layer, view)) {
+            Some(gpu_scatter) => gpu_scatter,
+            None => return RenderCommandResult::Failure,
+        };
+        let gpu_mesh = match meshes.into_inner().get(&gpu_scatter.mesh) {
+            Some(gpu_mesh) => gpu_mesh,
+            None => return RenderCommandResult::Failure,
+        };
+
+        pass.set_vertex_buffer(0, gpu_mesh.vertex_buffer.slice(..));
+        pass.set_vertex_buffer(1, gpu_scatter.instance_buffer.slice(..));
+
+        match &gpu_mesh.buffer_info {
+            GpuBufferInfo::Indexed {
+                buffer,
+                index_format,
+                ..
+            } => {
+                pass.set_index_buffer(buffer.slice(..), 0, *index_format);
+                pass.draw_indexed_indirect(&gpu_scatter.indirect_buffer, 0);
+            }
+            GpuBufferInfo::NonIndexed { .. } => {
+                pass.draw_indirect(&gpu_scatter.indirect_buffer, 0);
+            }
+        }
+
+        RenderCommandResult::Success
+    }
+}
+
+/// The draw function of the scatter layers.
+pub(crate) type DrawScatter = (
+    SetItemPipeline,
+    SetMeshViewBindGroup<0>,
+    SetScatterBindGroup<1>,
+    DrawScatterCommand,
+);
+
+/// Queues the scatter layers into the opaque phase of the camera views.
+pub(crate) fn queue_scatter_draws(
+    render_pipeline: Res<ScatterRenderPipeline>,
+    draw_functions: Res<DrawFunctions<Opaque3d>>,
+    msaa: Res<Msaa>,
+    meshes: Res<RenderAssets<Mesh>>,
+    gpu_scatters: Res<TerrainViewComponents<GpuScatter>>,
+    mut pipelines: ResMut<SpecializedMeshPipelines<ScatterRenderPipeline>>,
+    mut pipeline_cache: ResMut<PipelineCache>,
+    mut view_query: Query<(Entity, &mut RenderPhase<Opaque3d>), With<TerrainView>>,
+    layer_query: Query<(Entity, &ScatterLayer)>,
+) {
+    let draw_function = draw_functions.read().get_id::<DrawScatter>().unwrap();
+
+    let flags = TerrainPipelineFlags::from_msaa_samples(msaa.samples);
+
+    for (view, mut opaque_phase) in view_query.iter_mut() {
+        for (entity, layer) in layer_query.iter() {
+            if gpu_scatters.get(&(entity, view)).is_none() {
+                continue;
+            }
+
+            let gpu_mesh = skip_none!(meshes.get(&layer.mesh));
+
+            let pipeline = match pipelines.specialize(
+                &mut pipeline_cache,
+                &render_pipeline,
+                flags,
+                &gpu_mesh.layout,
+            ) {
+                Ok(pipeline) => pipeline,
+                Err(error) => {
+                    error!("Failed to specialize the scatter pipeline: {error}");
+                    continue;
+                }
+            };
+
+            opaque_phase.add(Opaque3d {
+                entity,
+                pipeline,
+                draw_function,
+                distance: 0.0,
+            });
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! GPU-driven scattering of meshes (e.g. trees, rocks and grass) over the loaded terrain.
+//!
+//! Each [`ScatterLayer`] places instances of a mesh on a jittered grid of candidate positions
+//! around the viewer.
+//! A compute pass evaluates the candidates of each terrain view using the best currently loaded
+//! data of the node atlas: the instance is placed at the terrain height and kept according to the
+//! density, which is either read from an attachment or derived from procedural rules
+//! (height and slope).
+//! To follow the quadtree, the density decreases with the lod of the candidate, so that distant
+//! regions are covered by fewer instances.
+//! Optionally the instances are scaled to the canopy height, which is derived from a surface
+//! attachment (e.g. a digital surface model) minus the terrain height.
+//! The surviving instances are appended to an instance buffer and drawn with a single indirect
+//! draw call per layer and view.
+
+pub mod gpu_scatter;
+pub mod pipelines;
+
+use crate::terrain_data::AttachmentIndex;
+use bevy::{
+    ecs::{query::QueryItem, system::lifetimeless::Read},
+    prelude::*,
+    render::extract_component::ExtractComponent,
+};
+
+/// Determines the probability, with which a candidate position of a [`ScatterLayer`] is populated.
+#[derive(Clone, Copy, Debug)]
+pub enum ScatterDensity {
+    /// The density is read from a channel of an attachment.
+    Attachment {
+        attachment_index: AttachmentIndex,
+        channel: u32,
+    },
+    /// The density is one inside the height range and below the maximal slope and zero otherwise.
+    Procedural {
+        min_height: f32,
+        max_height: f32,
+        /// The maximal slope in radians.
+        max_slope: f32,
+    },
+}
+
+/// A layer of instances of a mesh, that are scattered over a terrain.
+///
+/// Spawn this component as its own entity, to scatter the mesh over the referenced terrain.
+#[derive(Clone, Component)]
+pub struct ScatterLayer {
+    /// The terrain entity, onto which the mesh is scattered.
+    pub terrain: Entity,
+    /// The mesh of the instances, which requires positions and normals.
+    pub mesh: Handle<Mesh>,
+    pub color: Color,
+    pub density: ScatterDensity,
+    /// The distance between two candidate positions in world units.
+    pub spacing: f32,
+    /// The distance from the viewer in world units, up to which instances are placed.
+    pub distance: f32,
+    /// The factor, by which the density is reduced with each lod.
+    pub lod_falloff: f32,
+    /// The range of the random scale of the instances.
+    /// If the instances are scaled to the canopy height, instances lower than the minimum
+    /// are discarded and higher ones clamped to the maximum.
+    pub scale: (f32, f32),
+    /// The attachment storing the surface height (e.g. a digital surface model).
+    /// If set, the instances are scaled to the height of the surface above the terrain,
+    /// thus the mesh should have a height of one.
+    pub canopy: Option<AttachmentIndex>,
+    /// The radius of the bounding sphere of the mesh, used for the frustum culling.
+    pub radius: f32,
+    /// The maximal number of instances per view.
+    pub max_instances: u32,
+}
+
+impl ScatterLayer {
+    pub fn new(terrain: Entity, mesh: Handle<Mesh>, density: ScatterDensity) -> Self {
+        Self {
+            terrain,
+            mesh,
+            color: Color::rgb(0.2, 0.35, 0.1),
+            density,
+            spacing: 4.0,
+            distance: 1000.0,
+            lod_falloff: 0.5,
+            scale: (0.8, 1.2),
+            canopy: None,
+            radius: 1.0,
+            max_instances: 1 << 16,
+        }
+    }
+}
+
+impl ExtractComponent for ScatterLayer {
+    type Query = Read<Self>;
+    type Filter = ();
+
+    #[inline]
+    fn extract_component(item: QueryItem<Self::Query>) -> Self {
+        item.clone()
+    }
+}
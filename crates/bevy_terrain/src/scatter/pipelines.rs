@@ -0,0 +1,294 @@
+use crate::{
+    render::{
+        render_pipeline::{TerrainPipelineConfig, TerrainPipelineFlags},
+        shaders::{SCATTER_COMPUTE_SHADER, SCATTER_SHADER},
+        terrain_data::terrain_bind_group_layout,
+        TERRAIN_VIEW_LAYOUT,
+    },
+    scatter::{
+        gpu_scatter::{INDIRECT_SIZE, INSTANCE_SIZE},
+        ScatterDensity, ScatterLayer,
+    },
+};
+use bevy::{
+    pbr::MeshPipeline,
+    prelude::*,
+    render::{
+        mesh::MeshVertexBufferLayout, render_resource::*, renderer::RenderDevice,
+        texture::BevyDefault,
+    },
+};
+
+const SCATTER_COMPUTE_LAYOUT: BindGroupLayoutDescriptor = BindGroupLayoutDescriptor {
+    label: None,
+    entries: &[
+        // scatter data
+        BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+        // density atlas
+        BindGroupLayoutEntry {
+            binding: 1,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: true },
+                view_dimension: TextureViewDimension::D2Array,
+                multisampled: false,
+            },
+            count: None,
+        },
+        // canopy atlas
+        BindGroupLayoutEntry {
+            binding: 2,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: true },
+                view_dimension: TextureViewDimension::D2Array,
+                multisampled: false,
+            },
+            count: None,
+        },
+        // instances
+        BindGroupLayoutEntry {
+            binding: 3,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: BufferSize::new(INSTANCE_SIZE),
+            },
+            count: None,
+        },
+        // indirect buffer
+        BindGroupLayoutEntry {
+            binding: 4,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: BufferSize::new(INDIRECT_SIZE),
+            },
+            count: None,
+        },
+    ],
+};
+
+const SCATTER_RENDER_LAYOUT: BindGroupLayoutDescriptor = BindGroupLayoutDescriptor {
+    label: None,
+    entries: &[
+        // scatter data
+        BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::VERTEX_FRAGMENT,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+    ],
+};
+
+#[derive(Copy, Clone, Hash, PartialEq, Eq)]
+pub enum ScatterComputePipelineId {
+    /// Evaluates the candidate positions and appends the instances.
+    Scatter,
+    /// Writes the clamped instance count into the indirect buffer.
+    Finish,
+}
+
+bitflags::bitflags! {
+#[repr(transparent)]
+pub struct ScatterPipelineFlags: u32 {
+    const NONE               = 0;
+    const DENSITY_ATTACHMENT = (1 << 0);
+    const CANOPY             = (1 << 1);
+}
+}
+
+impl ScatterPipelineFlags {
+    pub fn from_layer(layer: &ScatterLayer) -> Self {
+        let mut key = ScatterPipelineFlags::NONE;
+
+        if let ScatterDensity::Attachment { .. } = layer.density {
+            key |= ScatterPipelineFlags::DENSITY_ATTACHMENT;
+        }
+        if layer.canopy.is_some() {
+            key |= ScatterPipelineFlags::CANOPY;
+        }
+
+        key
+    }
+
+    pub fn shader_defs(&self) -> Vec<String> {
+        let mut shader_defs = Vec::new();
+
+        if (self.bits & ScatterPipelineFlags::DENSITY_ATTACHMENT.bits) != 0 {
+            shader_defs.push("DENSITY_ATTACHMENT".to_string());
+        }
+        if (self.bits & ScatterPipelineFlags::CANOPY.bits) != 0 {
+            shader_defs.push("CANOPY".to_string());
+        }
+
+        shader_defs
+    }
+}
+
+/// The compute pipelines, which scatter the instances of the layers.
+#[derive(Resource)]
+pub struct ScatterComputePipelines {
+    pub(crate) terrain_view_layout: BindGroupLayout,
+    pub(crate) terrain_layout: BindGroupLayout,
+    pub(crate) scatter_layout: BindGroupLayout,
+}
+
+impl FromWorld for ScatterComputePipelines {
+    fn from_world(world: &mut World) -> Self {
+        let device = world.resource::<RenderDevice>();
+        let config = world.resource::<TerrainPipelineConfig>();
+
+        Self {
+            terrain_view_layout: device.create_bind_group_layout(&TERRAIN_VIEW_LAYOUT),
+            terrain_layout: terrain_bind_group_layout(device, config.attachment_count),
+            scatter_layout: device.create_bind_group_layout(&SCATTER_COMPUTE_LAYOUT),
+        }
+    }
+}
+
+impl SpecializedComputePipeline for ScatterComputePipelines {
+    type Key = (ScatterComputePipelineId, ScatterPipelineFlags);
+
+    fn specialize(&self, key: Self::Key) -> ComputePipelineDescriptor {
+        let entry_point = match key.0 {
+            ScatterComputePipelineId::Scatter => "scatter",
+            ScatterComputePipelineId::Finish => "finish",
+        };
+
+        ComputePipelineDescriptor {
+            label: Some("scatter_compute_pipeline".into()),
+            layout: Some(vec![
+                self.terrain_view_layout.clone(),
+                self.terrain_layout.clone(),
+                self.scatter_layout.clone(),
+            ]),
+            shader: SCATTER_COMPUTE_SHADER.typed(),
+            shader_defs: key.1.shader_defs(),
+            entry_point: entry_point.into(),
+        }
+    }
+}
+
+/// The pipeline used to draw the instances of the layers.
+#[derive(Resource)]
+pub struct ScatterRenderPipeline {
+    pub(crate) view_layout: BindGroupLayout,
+    pub(crate) scatter_layout: BindGroupLayout,
+}
+
+impl FromWorld for ScatterRenderPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let device = world.resource::<RenderDevice>();
+        let mesh_pipeline = world.resource::<MeshPipeline>();
+
+        Self {
+            view_layout: mesh_pipeline.view_layout.clone(),
+            scatter_layout: device.create_bind_group_layout(&SCATTER_RENDER_LAYOUT),
+        }
+    }
+}
+
+impl SpecializedMeshPipeline for ScatterRenderPipeline {
+    type Key = TerrainPipelineFlags;
+
+    fn specialize(
+        &self,
+        key: Self::Key,
+        layout: &MeshVertexBufferLayout,
+    ) -> Result<RenderPipelineDescriptor, SpecializedMeshPipelineError> {
+        let mut shader_defs = key.shader_defs();
+        shader_defs.push("TONEMAP_IN_SHADER".to_string());
+
+        let vertex_layout = layout.get_layout(&[
+            Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
+            Mesh::ATTRIBUTE_NORMAL.at_shader_location(1),
+        ])?;
+
+        let instance_layout = VertexBufferLayout {
+            array_stride: INSTANCE_SIZE,
+            step_mode: VertexStepMode::Instance,
+            attributes: vec![
+                // position and scale
+                VertexAttribute {
+                    format: VertexFormat::Float32x4,
+                    offset: 0,
+                    shader_location: 3,
+                },
+                // rotation
+                VertexAttribute {
+                    format: VertexFormat::Float32x4,
+                    offset: 16,
+                    shader_location: 4,
+                },
+            ],
+        };
+
+        Ok(RenderPipelineDescriptor {
+            label: Some("scatter_pipeline".into()),
+            layout: Some(vec![self.view_layout.clone(), self.scatter_layout.clone()]),
+            vertex: VertexState {
+                shader: SCATTER_SHADER.typed(),
+                entry_point: "vertex".into(),
+                shader_defs: shader_defs.clone(),
+                buffers: vec![vertex_layout, instance_layout],
+            },
+            fragment: Some(FragmentState {
+                shader: SCATTER_SHADER.typed(),
+                shader_defs,
+                entry_point: "fragment".into(),
+                targets: vec![Some(ColorTargetState {
+                    format: TextureFormat::bevy_default(),
+                    blend: Some(BlendState::REPLACE),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: PrimitiveState {
+                front_face: FrontFace::Ccw,
+                cull_mode: Some(Face::Back),
+                unclipped_depth: false,
+                polygon_mode: key.polygon_mode(),
+                conservative: false,
+                topology: PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+            },
+            depth_stencil: Some(DepthStencilState {
+                format: TextureFormat::Depth32Float,
+                depth_write_enabled: true,
+                depth_compare: CompareFunction::Greater,
+                stencil: StencilState {
+                    front: StencilFaceState::IGNORE,
+                    back: StencilFaceState::IGNORE,
+                    read_mask: 0,
+                    write_mask: 0,
+                },
+                bias: DepthBiasState {
+                    constant: 0,
+                    slope_scale: 0.0,
+                    clamp: 0.0,
+                },
+            }),
+            multisample: MultisampleState {
+                count: key.msaa_samples(),
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+        })
+    }
+}
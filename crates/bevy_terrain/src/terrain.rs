@@ -3,9 +3,14 @@
 use crate::terrain_data::NodeId;
 use crate::{
     attachment_loader::{AttachmentFromDisk, AttachmentFromDiskLoader},
-    preprocess::{BaseConfig, Preprocessor, TileConfig},
-    terrain_data::{AtlasAttachment, AttachmentConfig, AttachmentIndex},
+    preprocess::{
+        fallback::FallbackConfig, nodata::Nodata, surface::SurfaceConfig, water::WaterConfig,
+        BaseConfig, Preprocessor, TileConfig,
+    },
+    terrain_data::{AtlasAttachment, AttachmentConfig, AttachmentIndex, MAX_ATTACHMENT_COUNT},
+    virtual_texture::{VirtualTexture, VirtualTextureConfig},
 };
+use anyhow::Result;
 use bevy::utils::HashSet;
 use bevy::{
     ecs::{query::QueryItem, system::lifetimeless::Read},
@@ -67,8 +72,14 @@ pub struct TerrainConfig {
     pub leaf_node_size: u32, // Todo: reconsider this
     /// The size of the terrain.
     pub terrain_size: u32, // Todo: reconsider this
+    /// The radius of the planet the terrain lies on, which is used to cull tiles below
+    /// the geometric horizon. Zero disables the geometric horizon culling.
+    pub planet_radius: f32,
     /// The amount of nodes the can be loaded simultaneously in the node atlas.
     pub node_atlas_size: u32,
+    /// Determines whether holes in the height data are discarded by the renderer.
+    /// It is set from the base attachment.
+    pub nodata: Nodata,
     /// The path to the terrain folder inside the assets directory.
     pub path: String,
     /// The attachments of the terrain.
@@ -89,7 +100,9 @@ impl TerrainConfig {
             height,
             leaf_node_size: 0,
             terrain_size,
+            planet_radius: 0.0,
             node_atlas_size,
+            nodata: Nodata::Ignore,
             path,
             attachments: vec![],
             nodes: HashSet::new(),
@@ -101,7 +114,14 @@ impl TerrainConfig {
     /// Adds an attachment to the terrain.
     ///
     /// The attachment will not be loaded automatically, but the caller has to handle the loading instead.
+    /// Panics, if the terrain already has [`MAX_ATTACHMENT_COUNT`] attachments.
     pub fn add_attachment(&mut self, attachment: AttachmentConfig) -> AttachmentIndex {
+        assert!(
+            self.attachments.len() < MAX_ATTACHMENT_COUNT,
+            "Can not add the {} attachment, a terrain supports at most {MAX_ATTACHMENT_COUNT} attachments.",
+            attachment.name
+        );
+
         self.attachments.push(attachment.into());
         self.attachments.len() - 1
     }
@@ -125,11 +145,15 @@ impl TerrainConfig {
     }
 
     /// Adds the base attachment, which contains a height and minmax information.
+    /// If enabled, the normal, ambient occlusion and horizon attachments are added afterwards.
     ///
     /// This is required by terrains, that use the default render pipeline.
     pub fn add_base_attachment(&mut self, base: BaseConfig) {
-        self.add_attachment(base.height_attachment());
-        self.add_attachment(base.minmax_attachment());
+        self.nodata = base.nodata;
+
+        for attachment in base.attachments() {
+            self.add_attachment(attachment);
+        }
     }
 
     pub fn add_base_attachment_from_disk(
@@ -141,17 +165,99 @@ impl TerrainConfig {
     ) {
         self.leaf_node_size = base.texture_size - 2 * base.border_size;
 
+        for (i, attachment) in base.attachments().iter().enumerate() {
+            loader.attachments.insert(
+                self.attachments.len() + i,
+                AttachmentFromDisk::new(attachment, &self.path),
+            );
+        }
+
+        self.add_base_attachment(base);
+
+        preprocessor.base = Some((tile, base));
+    }
+
+    /// Adds a lower priority source of the height data, which fills the holes of the base attachment,
+    /// if its nodata handling is [`Nodata::Fallback`].
+    ///
+    /// The sources are merged in the order in which they are added, thus they have to be added
+    /// in the order of decreasing priority (e.g. a national DEM before a global one).
+    /// Fails, if the source tiles can not be read or are not named like the tiles of the base.
+    pub fn add_fallback_from_disk(
+        &self,
+        preprocessor: &mut Preprocessor,
+        fallback: FallbackConfig,
+    ) -> Result<()> {
+        fallback.tile_paths()?;
+        preprocessor.fallbacks.push(fallback);
+
+        Ok(())
+    }
+
+    /// Adds the water attachment, which will be loaded from disk automatically.
+    ///
+    /// It is generated from a water mask and the height data of the base attachment.
+    /// The returned index has to be passed to the [`TerrainWater`](crate::render::water::TerrainWater)
+    /// component of the terrain.
+    pub fn add_water_from_disk(
+        &mut self,
+        preprocessor: &mut Preprocessor,
+        loader: &mut AttachmentFromDiskLoader,
+        water: WaterConfig,
+        tile: TileConfig,
+    ) -> AttachmentIndex {
+        let attachment = water.attachment();
+        let attachment_index = self.add_attachment(attachment.clone());
+
         loader.attachments.insert(
-            self.attachments.len(),
-            AttachmentFromDisk::new(&base.height_attachment(), &self.path),
+            attachment_index,
+            AttachmentFromDisk::new(&attachment, &self.path),
         );
+
+        preprocessor.water = Some((tile, water));
+
+        attachment_index
+    }
+
+    /// Adds the surface attachment, which will be loaded from disk automatically.
+    ///
+    /// It is generated from a digital surface model and the height data of the base attachment
+    /// and stores the height of buildings and vegetation above the terrain.
+    /// It is extruded in materials, that import the `bevy_terrain::surface` shader functions,
+    /// unless [`DebugTerrain::surface`](crate::debug::DebugTerrain::surface) is disabled.
+    pub fn add_surface_from_disk(
+        &mut self,
+        preprocessor: &mut Preprocessor,
+        loader: &mut AttachmentFromDiskLoader,
+        surface: SurfaceConfig,
+        tile: TileConfig,
+    ) -> AttachmentIndex {
+        let attachment = surface.attachment();
+        let attachment_index = self.add_attachment(attachment.clone());
+
         loader.attachments.insert(
-            self.attachments.len() + 1,
-            AttachmentFromDisk::new(&base.minmax_attachment(), &self.path),
+            attachment_index,
+            AttachmentFromDisk::new(&attachment, &self.path),
         );
 
-        self.add_base_attachment(base);
+        preprocessor.surface = Some((tile, surface));
 
-        preprocessor.base = Some((tile, base));
+        attachment_index
+    }
+
+    /// Creates a virtual texture for the terrain, whose pages will be loaded from disk automatically.
+    ///
+    /// The returned component has to be inserted into the terrain entity.
+    pub fn add_virtual_texture_from_disk(
+        &self,
+        preprocessor: &mut Preprocessor,
+        virtual_texture: VirtualTextureConfig,
+        tile: TileConfig,
+    ) -> VirtualTexture {
+        preprocessor
+            .virtual_textures
+            .push((tile, virtual_texture.clone()));
+
+        VirtualTexture::new(virtual_texture, self)
     }
 }
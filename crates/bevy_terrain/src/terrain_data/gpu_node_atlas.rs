@@ -35,7 +35,7 @@ impl AtlasAttachment {
             mip_level_count: self.mip_level_count,
             sample_count: 1,
             dimension: TextureDimension::D2,
-            format: self.format,
+            format: self.format.into(),
             usage: TextureUsages::COPY_DST | TextureUsages::TEXTURE_BINDING,
         });
 
@@ -44,7 +44,7 @@ impl AtlasAttachment {
             GpuImage {
                 texture_view: texture.create_view(&TextureViewDescriptor::default()),
                 texture,
-                texture_format: self.format,
+                texture_format: self.format.into(),
                 sampler: device.create_sampler(&SamplerDescriptor::default()),
                 size: Vec2::splat(self.texture_size as f32),
             },
@@ -90,19 +90,27 @@ impl GpuNodeAtlas {
         }
     }
 
+    /// Returns whether the terrain has an attachment with the name.
+    pub(crate) fn has_attachment(&self, name: &str) -> bool {
+        self.attachments
+            .iter()
+            .any(|(attachment, _)| attachment.name == name)
+    }
+
     /// Updates the atlas attachments, by copying over the data of the nodes that have
     /// finished loading this frame.
+    ///
+    /// Attachments, which do not exist for the lod of the node, are skipped.
     fn update(&mut self, command_encoder: &mut CommandEncoder, images: &RenderAssets<Image>) {
         for node in self.loaded_nodes.drain(..) {
             for (attachment, node_handle, atlas_handle) in
-                self.attachments
-                    .iter()
-                    .enumerate()
-                    .map(|(index, (attachment, atlas_handle))| {
-                        let node_handle = node.attachments.get(&index).unwrap();
+                self.attachments.iter().enumerate().filter_map(
+                    |(index, (attachment, atlas_handle))| {
+                        let node_handle = node.attachments.get(&index)?;
 
-                        (attachment, node_handle, atlas_handle)
-                    })
+                        Some((attachment, node_handle, atlas_handle))
+                    },
+                )
             {
                 if let (Some(node_attachment), Some(atlas_attachment)) =
                     (images.get(node_handle), images.get(atlas_handle))
@@ -101,19 +101,26 @@ impl GpuQuadtree {
     }
 }
 
-/// Initializes the [`GpuQuadtree`] of newly created terrains.
+/// Initializes the [`GpuQuadtree`] of newly created terrains and recreates the ones,
+/// whose node count has changed.
 pub(crate) fn initialize_gpu_quadtree(
     device: Res<RenderDevice>,
     mut images: ResMut<RenderAssets<Image>>,
     mut gpu_quadtrees: ResMut<TerrainViewComponents<GpuQuadtree>>,
     quadtrees: Extract<Res<TerrainViewComponents<Quadtree>>>,
     view_query: Extract<Query<Entity, With<TerrainView>>>,
-    terrain_query: Extract<Query<Entity, Added<Terrain>>>,
+    terrain_query: Extract<Query<Entity, With<Terrain>>>,
 ) {
     for terrain in terrain_query.iter() {
         for view in view_query.iter() {
             let quadtree = quadtrees.get(&(terrain, view)).unwrap();
 
+            if let Some(gpu_quadtree) = gpu_quadtrees.get(&(terrain, view)) {
+                if gpu_quadtree.node_count == quadtree.node_count {
+                    continue;
+                }
+            }
+
             gpu_quadtrees.insert(
                 (terrain, view),
                 GpuQuadtree::new(&device, &mut images, &quadtree),
@@ -5,6 +5,9 @@
 //! Each terrain possesses one [`NodeAtlas`](node_atlas::NodeAtlas), which can be configured
 //! to store any [`AtlasAttachment`] required (eg. height, density, albedo, splat, edc.)
 //! These attachments can vary in resolution and texture format.
+//! Additionally an attachment can be restricted to a range of lods (e.g. a high resolution
+//! albedo, which only exists for the finest lods). Its nodes are then only loaded for these lods
+//! and shaders fall back to the nodes of its range using `lookup_attachment_node`.
 //!
 //! To decide which nodes should be currently loaded you can create multiple
 //! [`Quadtree`](quadtree::Quadtree) views that correspond to one node atlas.
@@ -40,6 +43,9 @@ pub const INVALID_LOD: u16 = u16::MAX;
 
 /// Identifier of an attachment inside the node atlas.
 pub type AttachmentIndex = usize;
+/// The maximum number of attachments of a terrain, which is limited by the size of the
+/// terrain config uniform.
+pub const MAX_ATTACHMENT_COUNT: usize = 4;
 
 /// The global coordinate of a node.
 pub struct NodeCoordinate {
@@ -70,6 +76,13 @@ pub fn calc_node_id(lod: u32, x: u32, y: u32) -> NodeId {
     (lod as NodeId & 0x3F) << 26 | (x as NodeId & 0x1FFF) << 13 | y as NodeId & 0x1FFF
 }
 
+/// Returns whether an attachment, which exists from its min up to its max lod,
+/// exists for nodes of the lod.
+#[inline]
+pub(crate) fn contains_lod(min_lod: u32, max_lod: u32, lod: u32) -> bool {
+    (min_lod..=max_lod).contains(&lod)
+}
+
 /// The data format of an attachment.
 #[derive(Encode, Decode, Clone, Copy, Debug)]
 pub enum AttachmentFormat {
@@ -81,6 +94,12 @@ pub enum AttachmentFormat {
     R16,
     /// Two   channels 16 bit
     Rg16,
+    /// Two   channels  8 bit
+    Rg8,
+    /// Four  channels 16 bit
+    Rgba16,
+    /// Four  channels  8 bit, stored linearly instead of sRGB encoded (e.g. for splat weights)
+    Rgba8Linear,
 }
 
 impl From<AttachmentFormat> for TextureFormat {
@@ -90,6 +109,9 @@ impl From<AttachmentFormat> for TextureFormat {
             AttachmentFormat::Rgba8 => TextureFormat::Rgba8UnormSrgb,
             AttachmentFormat::R16 => TextureFormat::R16Unorm,
             AttachmentFormat::Rg16 => TextureFormat::Rg16Unorm,
+            AttachmentFormat::Rg8 => TextureFormat::Rg8Unorm,
+            AttachmentFormat::Rgba16 => TextureFormat::Rgba16Unorm,
+            AttachmentFormat::Rgba8Linear => TextureFormat::Rgba8Unorm,
         }
     }
 }
@@ -111,7 +133,8 @@ impl Default for FileFormat {
 }
 
 impl FileFormat {
-    pub(crate) fn extension(&self) -> &str {
+    /// The file extension of the format.
+    pub fn extension(&self) -> &str {
         match self {
             Self::TDF => "tdf",
             Self::PNG => "png",
@@ -120,6 +143,18 @@ impl FileFormat {
             Self::DTM => "dtm",
         }
     }
+
+    /// Determines the format from a file extension.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_lowercase().as_str() {
+            "tdf" => Some(Self::TDF),
+            "png" => Some(Self::PNG),
+            "tif" | "tiff" => Some(Self::TIF),
+            "qoi" => Some(Self::QOI),
+            "dtm" => Some(Self::DTM),
+            _ => None,
+        }
+    }
 }
 
 /// Configures an attachment.
@@ -137,6 +172,14 @@ pub struct AttachmentConfig {
     pub format: AttachmentFormat,
     /// The file format of the attachment.
     pub file_format: FileFormat,
+    /// The finest lod, for which the attachment exists.
+    /// The tiles of the attachment are provided at the resolution of this lod.
+    pub min_lod: u32,
+    /// The coarsest lod, for which the attachment exists.
+    pub max_lod: u32,
+    /// Whether the texels of this R16 attachment with the [`NODATA`](crate::preprocess::nodata::NODATA)
+    /// value are holes. Missing texels are filled with it and it is excluded from the mipmaps.
+    pub nodata: bool,
 }
 
 impl AttachmentConfig {
@@ -157,8 +200,16 @@ impl AttachmentConfig {
             mip_level_count,
             format,
             file_format: FileFormat::TDF,
+            min_lod: 0,
+            max_lod: u32::MAX,
+            nodata: false,
         }
     }
+
+    /// Returns whether the attachment exists for nodes of the lod.
+    pub fn contains_lod(&self, lod: u32) -> bool {
+        contains_lod(self.min_lod, self.max_lod, lod)
+    }
 }
 
 /// An attachment of a [`NodeAtlas`](node_atlas::NodeAtlas).
@@ -175,7 +226,18 @@ pub struct AtlasAttachment {
     pub(crate) border_size: u32,
     pub mip_level_count: u32,
     /// The format of the attachment.
-    pub(crate) format: TextureFormat,
+    pub(crate) format: AttachmentFormat,
+    /// The finest lod, for which the attachment exists.
+    pub(crate) min_lod: u32,
+    /// The coarsest lod, for which the attachment exists.
+    pub(crate) max_lod: u32,
+}
+
+impl AtlasAttachment {
+    /// Returns whether the attachment exists for nodes of the lod.
+    pub(crate) fn contains_lod(&self, lod: u32) -> bool {
+        contains_lod(self.min_lod, self.max_lod, lod)
+    }
 }
 
 impl From<AttachmentConfig> for AtlasAttachment {
@@ -194,7 +256,9 @@ impl From<AttachmentConfig> for AtlasAttachment {
             center_size: config.center_size,
             border_size: config.border_size,
             mip_level_count: config.mip_level_count,
-            format: config.format.into(),
+            format: config.format,
+            min_lod: config.min_lod,
+            max_lod: config.max_lod,
         }
     }
 }
@@ -1,7 +1,8 @@
 use crate::{
     terrain::{Terrain, TerrainConfig},
     terrain_data::{
-        quadtree::Quadtree, AtlasAttachment, AtlasIndex, AttachmentIndex, NodeId, INVALID_NODE_ID,
+        quadtree::Quadtree, AtlasAttachment, AtlasIndex, AttachmentIndex, NodeCoordinate, NodeId,
+        INVALID_NODE_ID,
     },
     TerrainView, TerrainViewComponents,
 };
@@ -35,7 +36,7 @@ impl LoadingNode {
     }
 
     /// Returns whether all node attachments of the node have finished loading.
-    fn finished_loading(&self) -> bool {
+    pub(crate) fn finished_loading(&self) -> bool {
         self.loading_attachments.is_empty()
     }
 }
@@ -211,13 +212,20 @@ impl NodeAtlas {
                     },
                 );
 
-                // start loading the node
+                // start loading the node, only the attachments existing for its lod are loaded
+                let lod = NodeCoordinate::from(node_id).lod;
+
                 load_events.push(node_id);
                 loading_nodes.insert(
                     node_id,
                     LoadingNode {
                         atlas_index: unused_node.atlas_index,
-                        loading_attachments: (0..attachments.len()).collect(),
+                        loading_attachments: attachments
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, attachment)| attachment.contains_lod(lod))
+                            .map(|(attachment_index, _)| attachment_index)
+                            .collect(),
                         attachments: default(),
                     },
                 );
@@ -230,6 +238,22 @@ impl NodeAtlas {
         // );
     }
 
+    /// Sends the attachments of an already loaded node to the
+    /// [`GpuNodeAtlas`](super::gpu_node_atlas::GpuNodeAtlas) again.
+    ///
+    /// This is used to update the atlas after the cpu side data of the node has been modified.
+    pub(crate) fn reupload_node(&mut self, node_id: NodeId) {
+        if let Some(node) = self.nodes.get(&node_id) {
+            if node.state == LoadingState::Loaded {
+                self.loaded_nodes.push(LoadingNode {
+                    atlas_index: node.atlas_index,
+                    attachments: self.data[node.atlas_index as usize]._attachments.clone(),
+                    loading_attachments: default(),
+                });
+            }
+        }
+    }
+
     /// Checks all nodes that have finished loading, marks them accordingly and prepares the data
     /// to be send to the gpu by the [`GpuNodeAtlas`](super::gpu_node_atlas::GpuNodeAtlas).
     fn update_loaded_nodes(&mut self) {
@@ -152,6 +152,34 @@ impl Quadtree {
         )
     }
 
+    /// Applies the changes of the view config, which affect the node requests.
+    ///
+    /// If the node count changed, all requested nodes are released and the quadtree is resized,
+    /// so that the nodes are requested again during the next traversal.
+    fn update_config(&mut self, view_config: &TerrainViewConfig) {
+        self.load_distance = view_config.load_distance;
+
+        if view_config.node_count == self.node_count {
+            return;
+        }
+
+        for node in self.nodes.iter() {
+            if node.state == RequestState::Requested {
+                self.released_nodes.push(node.node_id);
+            }
+        }
+
+        self.node_count = view_config.node_count;
+
+        let shape = (
+            self.lod_count as usize,
+            self.node_count as usize,
+            self.node_count as usize,
+        );
+        self.data = Array3::default(shape);
+        self.nodes = Array3::default(shape);
+    }
+
     /// Calculates the size of a node.
     #[inline]
     fn node_size(&self, lod: u32) -> u32 {
@@ -255,10 +283,11 @@ impl Quadtree {
     }
 }
 
-/// Traverses all quadtrees and updates the node states,
+/// Applies the view configs to all quadtrees, traverses them and updates the node states,
 /// while selecting newly requested and released nodes.
 pub(crate) fn compute_quadtree_request(
     mut quadtrees: ResMut<TerrainViewComponents<Quadtree>>,
+    view_configs: Res<TerrainViewComponents<TerrainViewConfig>>,
     view_query: Query<(Entity, &GlobalTransform), With<TerrainView>>,
     terrain_query: Query<(Entity, &GlobalTransform), With<Terrain>>,
 ) {
@@ -268,6 +297,10 @@ pub(crate) fn compute_quadtree_request(
             let view_position = view_transform.translation();
             let quadtree = quadtrees.get_mut(&(terrain, view)).unwrap();
 
+            if let Some(view_config) = view_configs.get(&(terrain, view)) {
+                quadtree.update_config(view_config);
+            }
+
             quadtree.compute_requests(view_position);
         }
     }
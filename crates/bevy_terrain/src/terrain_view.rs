@@ -33,6 +33,9 @@ impl<C> FromWorld for TerrainViewComponents<C> {
 }
 
 /// A marker component used to identify a terrain view entity.
+///
+/// Besides cameras, directional lights can be terrain views as well.
+/// The terrain is then tessellated from the position of the light and rendered into its shadow map.
 #[derive(Clone, Copy, Component)]
 pub struct TerrainView;
 
@@ -71,6 +74,8 @@ pub struct TerrainViewConfig {
     pub grid_size: u32,
     /// The distance (measured in multiples of the node size) at which the LOD changes.
     pub view_distance: f32,
+    /// The factor the view distance of the [`TerrainViewSettings`] is scaled by for this view.
+    pub view_distance_scale: f32,
     /// The morph percentage of the mesh.
     pub morph_range: f32,
     /// The blend percentage in the vertex and fragment shader.
@@ -94,8 +99,92 @@ impl Default for TerrainViewConfig {
             tile_scale: 32.0,
             grid_size: 8,
             view_distance: 4.0,
+            view_distance_scale: 1.0,
             morph_range: 0.2,
             blend_range: 0.2,
         }
     }
 }
+
+/// The runtime-editable quality settings of the terrain views.
+///
+/// If this resource is present, all of its fields are applied to the [`TerrainViewConfig`]
+/// of every terrain view, whenever it changes.
+/// It can be registered with an inspector, to tweak the settings at runtime.
+#[derive(Clone, Resource, Reflect)]
+#[reflect(Resource)]
+pub struct TerrainViewSettings {
+    /// The distance (measured in multiples of the node size) until which to request nodes to be loaded.
+    pub load_distance: f32,
+    /// The count of nodes in x and y direction per quadtree layer.
+    pub node_count: u32,
+    /// The size of the tile buffer.
+    pub tile_count: u32,
+    /// The amount of steps the tile list will be refined.
+    pub refinement_count: u32,
+    /// The amount of steps the tiles will be further refined than there are new LOD layers.
+    pub additional_refinement: u32,
+    /// A factor that scales tiles smaller or larger.
+    pub tile_scale: f32,
+    /// The number of rows and columns of the tile grid.
+    pub grid_size: u32,
+    /// The distance (measured in multiples of the node size) at which the LOD changes.
+    pub view_distance: f32,
+    /// The morph percentage of the mesh.
+    pub morph_range: f32,
+    /// The blend percentage in the vertex and fragment shader.
+    pub blend_range: f32,
+}
+
+impl Default for TerrainViewSettings {
+    fn default() -> Self {
+        Self::from_config(&TerrainViewConfig::default())
+    }
+}
+
+impl TerrainViewSettings {
+    /// Creates the settings from the current values of the view config.
+    pub fn from_config(view_config: &TerrainViewConfig) -> Self {
+        Self {
+            load_distance: view_config.load_distance,
+            node_count: view_config.node_count,
+            tile_count: view_config.tile_count,
+            refinement_count: view_config.refinement_count,
+            additional_refinement: view_config.additional_refinement,
+            tile_scale: view_config.tile_scale,
+            grid_size: view_config.grid_size,
+            view_distance: view_config.view_distance / view_config.view_distance_scale,
+            morph_range: view_config.morph_range,
+            blend_range: view_config.blend_range,
+        }
+    }
+
+    /// Overwrites the settings of the view config.
+    /// The view distance is scaled by the view distance scale of the view.
+    pub fn apply(&self, view_config: &mut TerrainViewConfig) {
+        view_config.load_distance = self.load_distance;
+        view_config.node_count = self.node_count.max(1);
+        view_config.tile_count = self.tile_count.max(1);
+        view_config.refinement_count = self.refinement_count;
+        view_config.additional_refinement = self.additional_refinement;
+        view_config.tile_scale = self.tile_scale;
+        view_config.grid_size = self.grid_size.max(2);
+        view_config.view_distance = self.view_distance * view_config.view_distance_scale;
+        view_config.morph_range = self.morph_range;
+        view_config.blend_range = self.blend_range;
+    }
+}
+
+/// Applies the [`TerrainViewSettings`] to all terrain views, whenever they change.
+pub fn apply_terrain_view_settings(
+    settings: Option<Res<TerrainViewSettings>>,
+    mut view_configs: ResMut<TerrainViewComponents<TerrainViewConfig>>,
+) {
+    if let Some(settings) = settings {
+        if settings.is_changed() {
+            for view_config in view_configs.0.values_mut() {
+                settings.apply(view_config);
+            }
+        }
+    }
+}
@@ -0,0 +1,471 @@
+//! The feedback pass of the virtual textures.
+//!
+//! After the depth pyramids are built, the feedback pass reconstructs the world position of each
+//! texel of a coarse pyramid mip and determines the page and lod of the virtual texture,
+//! required to shade it at the full resolution.
+//! Each page is marked in a bitmap of all pages, so that only the first texel requiring it
+//! appends it to a storage buffer, which is copied into a readback buffer and
+//! mapped asynchronously, thus the requests lag behind by a few frames.
+
+use crate::{
+    render::{occlusion::DepthPyramid, shaders::VIRTUAL_TEXTURE_FEEDBACK_SHADER},
+    skip_none,
+    terrain::{Terrain, TerrainComponents},
+    terrain_data::NodeId,
+    terrain_view::{TerrainView, TerrainViewComponents},
+    virtual_texture::{gpu_virtual_texture::GpuVirtualTexture, VirtualTextureFeedback},
+    DebugTerrain,
+};
+use bevy::{
+    prelude::*,
+    render::{
+        render_graph::{self},
+        render_resource::*,
+        renderer::{RenderContext, RenderDevice, RenderQueue},
+        view::ExtractedView,
+    },
+    utils::HashSet,
+};
+use std::{
+    mem,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
+
+/// The maximal number of page requests per terrain view and frame.
+const MAX_REQUESTS: u32 = 4096;
+/// The maximal size of the pyramid mip, from which the requests are generated.
+const FEEDBACK_SIZE: u32 = 128;
+
+const FEEDBACK_CONFIG_SIZE: BufferAddress = mem::size_of::<FeedbackConfig>() as BufferAddress;
+/// The request count followed by the requested pages.
+const FEEDBACK_BUFFER_SIZE: BufferAddress = 4 + 4 * MAX_REQUESTS as BufferAddress;
+
+const FEEDBACK_LAYOUT: BindGroupLayoutDescriptor = BindGroupLayoutDescriptor {
+    label: None,
+    entries: &[
+        // depth pyramid
+        BindGroupLayoutEntry {
+            binding: 0,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: false },
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        },
+        // feedback config
+        BindGroupLayoutEntry {
+            binding: 1,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: BufferSize::new(FEEDBACK_CONFIG_SIZE),
+            },
+            count: None,
+        },
+        // feedback buffer
+        BindGroupLayoutEntry {
+            binding: 2,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: BufferSize::new(FEEDBACK_BUFFER_SIZE),
+            },
+            count: None,
+        },
+        // page bitmap
+        BindGroupLayoutEntry {
+            binding: 3,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: BufferSize::new(4),
+            },
+            count: None,
+        },
+    ],
+};
+
+/// The feedback buffer can be written by the feedback node.
+const FEEDBACK_IDLE: u32 = 0;
+/// The requests have been copied into the readback buffer and are ready to be mapped.
+const FEEDBACK_COPIED: u32 = 1;
+/// The readback buffer is being mapped.
+const FEEDBACK_MAPPING: u32 = 2;
+
+#[derive(Clone, Copy, Default, ShaderType)]
+struct FeedbackConfig {
+    /// The inverse of the view projection matrix, with which the depth pyramid was rendered.
+    inverse_view_proj: Mat4,
+    view_position: Vec3,
+    /// The size of a screen pixel in world units at a distance of one.
+    pixel_size: f32,
+    terrain_size: f32,
+    /// The size of a texel of the virtual texture at the highest level of detail in world units.
+    texel_size: f32,
+    center_size: f32,
+    lod_count: u32,
+    lod_bias: f32,
+    /// The mip level of the depth pyramid, from which the requests are generated.
+    mip_level: u32,
+    max_requests: u32,
+    /// The number of pages along each side of the virtual texture at the highest level of detail.
+    page_count: u32,
+}
+
+/// The compute pipeline of the feedback pass.
+#[derive(Resource)]
+pub struct VirtualTextureFeedbackPipeline {
+    layout: BindGroupLayout,
+    pipeline: CachedComputePipelineId,
+}
+
+impl FromWorld for VirtualTextureFeedbackPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let device = world.resource::<RenderDevice>();
+
+        let layout = device.create_bind_group_layout(&FEEDBACK_LAYOUT);
+
+        let mut pipeline_cache = world.resource_mut::<PipelineCache>();
+
+        let pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some("virtual_texture_feedback_pipeline".into()),
+            layout: Some(vec![layout.clone()]),
+            shader: VIRTUAL_TEXTURE_FEEDBACK_SHADER.typed(),
+            shader_defs: Vec::new(),
+            entry_point: "feedback".into(),
+        });
+
+        Self { layout, pipeline }
+    }
+}
+
+/// The buffers of the feedback pass of a terrain view.
+pub struct ViewFeedback {
+    config_buffer: Buffer,
+    feedback_buffer: Buffer,
+    /// The requests copied from the feedback buffer for the readback.
+    readback_buffer: Buffer,
+    readback_state: Arc<AtomicU32>,
+    /// One bit per page of all lods, which marks the pages already requested this frame.
+    page_bitmap: Buffer,
+    page_bitmap_size: BufferAddress,
+    bind_group: Option<BindGroup>,
+    workgroup_count: UVec2,
+}
+
+impl ViewFeedback {
+    fn new(device: &RenderDevice, page_bitmap_size: BufferAddress) -> Self {
+        let config_buffer = device.create_buffer(&BufferDescriptor {
+            label: "feedback_config_buffer".into(),
+            size: FEEDBACK_CONFIG_SIZE,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let feedback_buffer = device.create_buffer(&BufferDescriptor {
+            label: "feedback_buffer".into(),
+            size: FEEDBACK_BUFFER_SIZE,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let readback_buffer = device.create_buffer(&BufferDescriptor {
+            label: "feedback_readback_buffer".into(),
+            size: FEEDBACK_BUFFER_SIZE,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let page_bitmap = device.create_buffer(&BufferDescriptor {
+            label: "feedback_page_bitmap".into(),
+            size: page_bitmap_size,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            config_buffer,
+            feedback_buffer,
+            readback_buffer,
+            readback_state: Arc::new(AtomicU32::new(FEEDBACK_IDLE)),
+            page_bitmap,
+            page_bitmap_size,
+            bind_group: None,
+            workgroup_count: UVec2::ZERO,
+        }
+    }
+}
+
+/// The size of the bitmap in bytes, that stores one bit per page of all lods.
+fn page_bitmap_size(page_count: u32, lod_count: u32) -> BufferAddress {
+    let bits = (0..lod_count)
+        .map(|lod| {
+            let count = ((page_count - 1) >> lod) as BufferAddress + 1;
+            count * count
+        })
+        .sum::<BufferAddress>();
+
+    (bits + 31) / 32 * 4
+}
+
+/// Creates the feedback buffers of all terrain views with a virtual texture and a depth pyramid
+/// and updates their configs.
+pub(crate) fn prepare_virtual_texture_feedback(
+    device: Res<RenderDevice>,
+    queue: Res<RenderQueue>,
+    pipeline: Res<VirtualTextureFeedbackPipeline>,
+    gpu_virtual_textures: Res<TerrainComponents<GpuVirtualTexture>>,
+    depth_pyramids: Res<TerrainViewComponents<DepthPyramid>>,
+    mut view_feedbacks: ResMut<TerrainViewComponents<ViewFeedback>>,
+    view_query: Query<&ExtractedView, With<TerrainView>>,
+) {
+    view_feedbacks.0.retain(|&(terrain, view), _| {
+        gpu_virtual_textures.0.contains_key(&terrain)
+            && depth_pyramids.0.contains_key(&(terrain, view))
+    });
+
+    for (&(terrain, view), depth_pyramid) in depth_pyramids.0.iter() {
+        let gpu_virtual_texture = skip_none!(gpu_virtual_textures.get(&terrain));
+        let uniform = &gpu_virtual_texture.uniform;
+        let extracted_view = skip_none!(view_query.get(view).ok());
+
+        let page_count = (uniform.size / uniform.center_size).ceil().max(1.0) as u32;
+        let page_bitmap_size = page_bitmap_size(page_count, uniform.lod_count);
+
+        let view_feedback = view_feedbacks
+            .0
+            .entry((terrain, view))
+            .or_insert_with(|| ViewFeedback::new(&device, page_bitmap_size));
+
+        if view_feedback.page_bitmap_size != page_bitmap_size {
+            *view_feedback = ViewFeedback::new(&device, page_bitmap_size);
+        }
+
+        let mut mip_level = 0;
+        while mip_level + 1 < depth_pyramid.mip_count
+            && (depth_pyramid.size >> mip_level).max_element() > FEEDBACK_SIZE
+        {
+            mip_level += 1;
+        }
+
+        let mip_size = (depth_pyramid.size >> mip_level).max(UVec2::ONE);
+
+        let config = FeedbackConfig {
+            inverse_view_proj: depth_pyramid.view_proj.inverse(),
+            view_position: extracted_view.transform.translation(),
+            pixel_size: 2.0 / (extracted_view.projection.y_axis.y * depth_pyramid.size.y as f32),
+            terrain_size: uniform.terrain_size,
+            texel_size: uniform.terrain_size / uniform.size,
+            center_size: uniform.center_size,
+            lod_count: uniform.lod_count,
+            lod_bias: gpu_virtual_texture.lod_bias,
+            mip_level,
+            max_requests: MAX_REQUESTS,
+            page_count,
+        };
+
+        let mut buffer = encase::UniformBuffer::new(Vec::new());
+        buffer.write(&config).unwrap();
+
+        queue.write_buffer(&view_feedback.config_buffer, 0, &buffer.into_inner());
+        // reset the request count and the requested pages
+        queue.write_buffer(&view_feedback.feedback_buffer, 0, &0u32.to_le_bytes());
+        queue.write_buffer(
+            &view_feedback.page_bitmap,
+            0,
+            &vec![0; page_bitmap_size as usize],
+        );
+
+        view_feedback.workgroup_count = (mip_size + 7) / 8;
+        view_feedback.bind_group = Some(device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: &pipeline.layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&depth_pyramid.pyramid_view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: view_feedback.config_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: view_feedback.feedback_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: view_feedback.page_bitmap.as_entire_binding(),
+                },
+            ],
+        }));
+    }
+}
+
+/// Generates the page requests of the virtual textures of each terrain view
+/// and copies them into the readback buffers.
+pub struct VirtualTextureFeedbackNode {
+    terrain_query: QueryState<Entity, With<Terrain>>,
+    view_query: QueryState<Entity, With<TerrainView>>,
+}
+
+impl FromWorld for VirtualTextureFeedbackNode {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            terrain_query: world.query_filtered(),
+            view_query: world.query_filtered(),
+        }
+    }
+}
+
+impl render_graph::Node for VirtualTextureFeedbackNode {
+    fn update(&mut self, world: &mut World) {
+        self.terrain_query.update_archetypes(world);
+        self.view_query.update_archetypes(world);
+    }
+
+    fn run(
+        &self,
+        _graph: &mut render_graph::RenderGraphContext,
+        context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), render_graph::NodeRunError> {
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let pipeline = world.resource::<VirtualTextureFeedbackPipeline>();
+        let view_feedbacks = world.resource::<TerrainViewComponents<ViewFeedback>>();
+
+        let debug = world.get_resource::<DebugTerrain>();
+
+        if debug.map_or(false, |debug| debug.freeze) {
+            return Ok(());
+        }
+
+        let pipeline = match pipeline_cache.get_compute_pipeline(pipeline.pipeline) {
+            Some(pipeline) => pipeline,
+            None => return Ok(()), // the pipeline is not loaded yet
+        };
+
+        {
+            let pass = &mut context
+                .command_encoder
+                .begin_compute_pass(&ComputePassDescriptor::default());
+
+            pass.set_pipeline(pipeline);
+
+            for terrain in self.terrain_query.iter_manual(world) {
+                for view in self.view_query.iter_manual(world) {
+                    let view_feedback = skip_none!(view_feedbacks.get(&(terrain, view)));
+                    let bind_group = skip_none!(view_feedback.bind_group.as_ref());
+                    let count = view_feedback.workgroup_count;
+
+                    pass.set_bind_group(0, bind_group, &[]);
+                    pass.dispatch_workgroups(count.x, count.y, 1);
+                }
+            }
+        }
+
+        // copy the requests, so that they can be read back after the submission
+        for view_feedback in view_feedbacks.0.values() {
+            // skip the buffers, which are still being mapped
+            if view_feedback.bind_group.is_some()
+                && view_feedback
+                    .readback_state
+                    .compare_exchange(
+                        FEEDBACK_IDLE,
+                        FEEDBACK_COPIED,
+                        Ordering::AcqRel,
+                        Ordering::Acquire,
+                    )
+                    .is_ok()
+            {
+                context.command_encoder.copy_buffer_to_buffer(
+                    &view_feedback.feedback_buffer,
+                    0,
+                    &view_feedback.readback_buffer,
+                    0,
+                    FEEDBACK_BUFFER_SIZE,
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads back the page requests, that were copied into the readback buffers
+/// by the [`VirtualTextureFeedbackNode`].
+///
+/// The buffers are mapped asynchronously, thus the requests lag behind by a few frames.
+pub(crate) fn read_virtual_texture_feedback(
+    device: Res<RenderDevice>,
+    view_feedbacks: Res<TerrainViewComponents<ViewFeedback>>,
+    feedback: Res<VirtualTextureFeedback>,
+) {
+    for (&terrain_view, view_feedback) in view_feedbacks.0.iter() {
+        // only map the buffers, which have been written this frame
+        if view_feedback
+            .readback_state
+            .compare_exchange(
+                FEEDBACK_COPIED,
+                FEEDBACK_MAPPING,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .is_err()
+        {
+            continue;
+        }
+
+        let buffer = view_feedback.readback_buffer.clone();
+        let state = view_feedback.readback_state.clone();
+        let feedback = feedback.0.clone();
+
+        device.map_buffer(
+            &view_feedback.readback_buffer.slice(..),
+            MapMode::Read,
+            move |result| {
+                if result.is_ok() {
+                    let requests = {
+                        let data = buffer.slice(..).get_mapped_range();
+                        let words = data
+                            .chunks_exact(4)
+                            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+                            .collect::<Vec<_>>();
+
+                        // the pages beyond the capacity are requested again in a later frame
+                        if words[0] > MAX_REQUESTS {
+                            warn!(
+                                "The virtual texture feedback exceeded {MAX_REQUESTS} requests, {} of them were dropped.",
+                                words[0] - MAX_REQUESTS
+                            );
+                        }
+
+                        let count = words[0].min(MAX_REQUESTS) as usize;
+
+                        words[1..=count]
+                            .iter()
+                            .copied()
+                            .collect::<HashSet<NodeId>>()
+                    };
+
+                    buffer.unmap();
+
+                    feedback
+                        .lock()
+                        .unwrap()
+                        .insert(terrain_view, requests.into_iter().collect());
+                }
+
+                state.store(FEEDBACK_IDLE, Ordering::Release);
+            },
+        );
+    }
+}
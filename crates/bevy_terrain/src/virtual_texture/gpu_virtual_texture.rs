@@ -0,0 +1,248 @@
+use crate::{
+    skip_none,
+    terrain::{Terrain, TerrainComponents},
+    terrain_data::{NodeCoordinate, NodeId},
+    virtual_texture::{LoadedPage, PageIndex, VirtualTexture, VirtualTextureUniform},
+};
+use bevy::{
+    prelude::*,
+    render::{
+        render_asset::RenderAssets,
+        render_resource::*,
+        renderer::{RenderDevice, RenderQueue},
+        texture::GpuImage,
+        Extract, MainWorld,
+    },
+};
+use std::mem;
+
+/// The format of the page table. Each entry stores the index of the page offset by one.
+const PAGE_TABLE_FORMAT: TextureFormat = TextureFormat::R16Uint;
+
+/// Stores the GPU representation of the [`VirtualTexture`] (page table and physical pages)
+/// alongside the data to update it.
+pub struct GpuVirtualTexture {
+    pub(crate) uniform: VirtualTextureUniform,
+    /// The bias added to the lod selected by the feedback pass.
+    pub(crate) lod_bias: f32,
+    pub(crate) page_size: u32,
+    pub(crate) mip_level_count: u32,
+    /// The handle of the page table texture.
+    pub(crate) page_table: Handle<Image>,
+    /// The handle of the array texture storing the resident pages.
+    pub(crate) pages: Handle<Image>,
+    /// Stores the pages, that have finished loading this frame.
+    pub(crate) loaded_pages: Vec<LoadedPage>,
+    /// Stores the changed entries of the page table.
+    pub(crate) page_table_updates: Vec<(NodeId, PageIndex)>,
+}
+
+impl GpuVirtualTexture {
+    /// Creates a new gpu virtual texture and initializes its textures.
+    fn new(
+        device: &RenderDevice,
+        images: &mut RenderAssets<Image>,
+        virtual_texture: &VirtualTexture,
+    ) -> Self {
+        let config = &virtual_texture.config;
+        let page_count = config.page_count();
+        let format = config.format.into();
+
+        let page_table = device.create_texture(&TextureDescriptor {
+            label: Some(&(config.name.to_string() + "_page_table")),
+            size: Extent3d {
+                width: page_count,
+                height: page_count,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: config.lod_count,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: PAGE_TABLE_FORMAT,
+            usage: TextureUsages::COPY_DST | TextureUsages::TEXTURE_BINDING,
+        });
+
+        let pages = device.create_texture(&TextureDescriptor {
+            label: Some(&(config.name.to_string() + "_pages")),
+            size: Extent3d {
+                width: config.page_size,
+                height: config.page_size,
+                depth_or_array_layers: config.cache_size as u32,
+            },
+            mip_level_count: config.mip_level_count,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage: TextureUsages::COPY_DST | TextureUsages::TEXTURE_BINDING,
+        });
+
+        images.insert(
+            virtual_texture.page_table.clone(),
+            GpuImage {
+                texture_view: page_table.create_view(&TextureViewDescriptor::default()),
+                texture: page_table,
+                texture_format: PAGE_TABLE_FORMAT,
+                sampler: device.create_sampler(&SamplerDescriptor::default()),
+                size: Vec2::splat(page_count as f32),
+            },
+        );
+
+        images.insert(
+            virtual_texture.pages.clone(),
+            GpuImage {
+                texture_view: pages.create_view(&TextureViewDescriptor {
+                    dimension: Some(TextureViewDimension::D2Array),
+                    ..default()
+                }),
+                texture: pages,
+                texture_format: format,
+                sampler: device.create_sampler(&SamplerDescriptor {
+                    mag_filter: FilterMode::Linear,
+                    min_filter: FilterMode::Linear,
+                    mipmap_filter: FilterMode::Linear,
+                    ..default()
+                }),
+                size: Vec2::splat(config.page_size as f32),
+            },
+        );
+
+        Self {
+            uniform: virtual_texture.uniform(),
+            lod_bias: config.lod_bias,
+            page_size: config.page_size,
+            mip_level_count: config.mip_level_count,
+            page_table: virtual_texture.page_table.clone(),
+            pages: virtual_texture.pages.clone(),
+            loaded_pages: Vec::new(),
+            page_table_updates: Vec::new(),
+        }
+    }
+
+    /// Updates the physical pages, by copying over the data of the pages that have
+    /// finished loading this frame, and writes the changed entries of the page table.
+    fn update(
+        &mut self,
+        command_encoder: &mut CommandEncoder,
+        queue: &RenderQueue,
+        images: &RenderAssets<Image>,
+    ) {
+        let (page_table, pages) = match (images.get(&self.page_table), images.get(&self.pages)) {
+            (Some(page_table), Some(pages)) => (page_table, pages),
+            _ => {
+                error!("Something went wrong, virtual texture is not available!");
+                return;
+            }
+        };
+
+        for page in self.loaded_pages.drain(..) {
+            if let Some(page_image) = images.get(&page.handle) {
+                for mip_level in 0..self.mip_level_count {
+                    command_encoder.copy_texture_to_texture(
+                        ImageCopyTexture {
+                            texture: &page_image.texture,
+                            mip_level,
+                            origin: Origin3d { x: 0, y: 0, z: 0 },
+                            aspect: TextureAspect::All,
+                        },
+                        ImageCopyTexture {
+                            texture: &pages.texture,
+                            mip_level,
+                            origin: Origin3d {
+                                x: 0,
+                                y: 0,
+                                z: page.page_index as u32,
+                            },
+                            aspect: TextureAspect::All,
+                        },
+                        Extent3d {
+                            width: self.page_size >> mip_level,
+                            height: self.page_size >> mip_level,
+                            depth_or_array_layers: 1,
+                        },
+                    );
+                }
+            } else {
+                error!("Something went wrong, page is not available!")
+            }
+        }
+
+        for (page_id, entry) in self.page_table_updates.drain(..) {
+            let page = NodeCoordinate::from(page_id);
+
+            queue.write_texture(
+                ImageCopyTexture {
+                    texture: &page_table.texture,
+                    mip_level: page.lod,
+                    origin: Origin3d {
+                        x: page.x,
+                        y: page.y,
+                        z: 0,
+                    },
+                    aspect: TextureAspect::All,
+                },
+                &entry.to_le_bytes(),
+                ImageDataLayout::default(),
+                Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+    }
+}
+
+/// Initializes the [`GpuVirtualTexture`] of newly created terrains.
+pub(crate) fn initialize_gpu_virtual_texture(
+    device: Res<RenderDevice>,
+    mut images: ResMut<RenderAssets<Image>>,
+    mut gpu_virtual_textures: ResMut<TerrainComponents<GpuVirtualTexture>>,
+    terrain_query: Extract<Query<(Entity, &VirtualTexture), Added<VirtualTexture>>>,
+) {
+    for (terrain, virtual_texture) in terrain_query.iter() {
+        gpu_virtual_textures.insert(
+            terrain,
+            GpuVirtualTexture::new(&device, &mut images, virtual_texture),
+        );
+    }
+}
+
+/// Extracts the pages that have finished loading and the page table updates from all
+/// [`VirtualTexture`]s into the corresponding [`GpuVirtualTexture`]s.
+pub(crate) fn extract_virtual_texture(
+    mut main_world: ResMut<MainWorld>,
+    mut gpu_virtual_textures: ResMut<TerrainComponents<GpuVirtualTexture>>,
+) {
+    let mut terrain_query = main_world.query::<(Entity, &mut VirtualTexture)>();
+
+    for (terrain, mut virtual_texture) in terrain_query.iter_mut(&mut main_world) {
+        let gpu_virtual_texture = skip_none!(gpu_virtual_textures.get_mut(&terrain));
+        mem::swap(
+            &mut virtual_texture.loaded_pages,
+            &mut gpu_virtual_texture.loaded_pages,
+        );
+        mem::swap(
+            &mut virtual_texture.page_table_updates,
+            &mut gpu_virtual_texture.page_table_updates,
+        );
+    }
+}
+
+/// Queues the pages that have finished loading to be copied into the physical pages
+/// and writes the changed entries of the page tables.
+pub(crate) fn queue_virtual_texture_updates(
+    device: Res<RenderDevice>,
+    queue: Res<RenderQueue>,
+    images: Res<RenderAssets<Image>>,
+    mut gpu_virtual_textures: ResMut<TerrainComponents<GpuVirtualTexture>>,
+    terrain_query: Query<Entity, With<Terrain>>,
+) {
+    let mut command_encoder = device.create_command_encoder(&CommandEncoderDescriptor::default());
+
+    for terrain in terrain_query.iter() {
+        let gpu_virtual_texture = skip_none!(gpu_virtual_textures.get_mut(&terrain));
+        gpu_virtual_texture.update(&mut command_encoder, &queue, &images);
+    }
+
+    queue.submit(vec![command_encoder.finish()]);
+}
@@ -0,0 +1,417 @@
+//! Contains a virtual texturing solution for high-resolution terrain textures (e.g. albedo).
+//!
+//! In contrast to the attachments of the [`NodeAtlas`](crate::terrain_data::node_atlas::NodeAtlas),
+//! a virtual texture is not bound to the node granularity and the resolution of the height data.
+//! It is subdivided into its own quadtree of pages, which are streamed independently
+//! of the terrain geometry.
+//!
+//! Each frame the feedback pass determines which pages are visible and at which level of detail,
+//! using the depth pyramid of each view
+//! (see [`occlusion`](crate::render::occlusion) for more information).
+//! These requests are read back to the cpu, where the [`VirtualTexture`] loads the missing pages
+//! from disk and evicts the least recently used ones.
+//! The resident pages are stored in an array texture (the physical pages) and the page table
+//! maps every page of each lod to its layer inside of it.
+//! The pages of the coarsest lod are always resident, so that the shader can fall back to
+//! coarser pages, while finer ones are still loading.
+//!
+//! To use a virtual texture in a terrain material, bind the [`VirtualTexture::page_table`]
+//! (`u_int` texture) and the [`VirtualTexture::pages`] (`2d_array` texture) alongside the
+//! [`VirtualTextureUniform`] and sample it with the `bevy_terrain::virtual_texture` shader module.
+
+pub mod feedback;
+pub mod gpu_virtual_texture;
+
+use crate::{
+    terrain::TerrainConfig,
+    terrain_data::{
+        calc_node_id, AttachmentConfig, AttachmentFormat, FileFormat, NodeCoordinate, NodeId,
+    },
+};
+use bevy::{
+    asset::{HandleId, LoadState},
+    prelude::*,
+    reflect::TypeUuid,
+    render::render_resource::*,
+    utils::{HashMap, HashSet},
+};
+use lru::LruCache;
+use std::{
+    cmp::Reverse,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// The index of a page inside of the physical pages.
+pub type PageIndex = u16;
+
+/// Configures a virtual texture.
+#[derive(Clone, Debug)]
+pub struct VirtualTextureConfig {
+    /// The name of the virtual texture.
+    pub name: String,
+    /// The size of the virtual texture at the highest level of detail in texels.
+    /// It covers the entire terrain.
+    pub size: u32,
+    /// The size of a page including its borders in texels.
+    pub page_size: u32,
+    /// The overlapping border size around the page, used to prevent sampling artifacts.
+    pub border_size: u32,
+    pub mip_level_count: u32,
+    /// The number of lods of the virtual texture.
+    pub lod_count: u32,
+    /// The number of pages, that can be resident simultaneously.
+    pub cache_size: PageIndex,
+    /// The maximal number of pages, that start loading each frame.
+    pub max_loads_per_frame: usize,
+    /// The bias added to the lod selected by the feedback pass.
+    pub lod_bias: f32,
+    /// The format of the virtual texture.
+    pub format: AttachmentFormat,
+    /// The file format of the pages.
+    pub file_format: FileFormat,
+}
+
+impl VirtualTextureConfig {
+    pub fn new(
+        name: String,
+        size: u32,
+        page_size: u32,
+        lod_count: u32,
+        cache_size: PageIndex,
+        format: AttachmentFormat,
+    ) -> Self {
+        Self {
+            name,
+            size,
+            page_size,
+            border_size: 2,
+            mip_level_count: 4,
+            lod_count,
+            cache_size,
+            max_loads_per_frame: 16,
+            lod_bias: 0.0,
+            format,
+            file_format: FileFormat::TDF,
+        }
+    }
+
+    /// The none overlapping center size of a page in texels.
+    pub fn center_size(&self) -> u32 {
+        self.page_size - 2 * self.border_size
+    }
+
+    /// The number of pages along each side of the virtual texture at the highest level of detail.
+    /// This is also the size of the page table.
+    pub fn page_count(&self) -> u32 {
+        ((self.size + self.center_size() - 1) / self.center_size()).next_power_of_two()
+    }
+
+    /// The pages are preprocessed and stored like any other attachment.
+    pub(crate) fn attachment(&self) -> AttachmentConfig {
+        let mut attachment = AttachmentConfig::new(
+            self.name.clone(),
+            self.page_size,
+            self.border_size,
+            self.mip_level_count,
+            self.format,
+        );
+
+        attachment.file_format = self.file_format;
+        attachment
+    }
+}
+
+/// The gpu representation of a [`VirtualTexture`], which has to be bound by the materials
+/// using it.
+#[derive(Clone, Copy, Default, ShaderType)]
+pub struct VirtualTextureUniform {
+    /// The size of the terrain in world units.
+    pub terrain_size: f32,
+    /// The size of the virtual texture at the highest level of detail in texels.
+    pub size: f32,
+    pub page_size: f32,
+    pub center_size: f32,
+    pub border_size: f32,
+    pub lod_count: u32,
+}
+
+/// The requested pages of all terrain views, which are read back from the gpu.
+///
+/// This resource is shared between the main and the render world.
+#[derive(Clone, Default, Resource)]
+pub struct VirtualTextureFeedback(pub(crate) Arc<Mutex<HashMap<(Entity, Entity), Vec<NodeId>>>>);
+
+/// The state of a page, that has not finished loading yet.
+struct LoadingPage {
+    handle: Handle<Image>,
+    loaded: bool,
+}
+
+/// A page, that has finished loading this frame, alongside its index inside the physical pages.
+#[derive(Clone)]
+pub(crate) struct LoadedPage {
+    pub(crate) page_index: PageIndex,
+    pub(crate) handle: Handle<Image>,
+}
+
+/// Streams the pages of a virtual texture according to the requests of the feedback pass.
+///
+/// Insert this component next to the [`TerrainBundle`](crate::TerrainBundle) of the terrain.
+#[derive(Component)]
+pub struct VirtualTexture {
+    pub(crate) config: VirtualTextureConfig,
+    pub(crate) terrain_size: u32,
+    /// The directory of the pages relative to the asset folder.
+    path: String,
+    /// The handle of the page table texture.
+    pub(crate) page_table: Handle<Image>,
+    /// The handle of the array texture storing the resident pages.
+    pub(crate) pages: Handle<Image>,
+    /// The pages of the coarsest lod, which are never evicted.
+    pinned_pages: HashMap<NodeId, PageIndex>,
+    /// The resident pages in least recently used order.
+    resident_pages: LruCache<NodeId, PageIndex>,
+    /// The currently loading pages.
+    loading_pages: HashMap<NodeId, LoadingPage>,
+    /// Maps the id of an asset to the corresponding page.
+    handle_mapping: HashMap<HandleId, NodeId>,
+    /// The pages, which do not exist on disk.
+    missing_pages: HashSet<NodeId>,
+    free_pages: Vec<PageIndex>,
+    /// The pages, that have finished loading this frame.
+    /// They will be send to the
+    /// [`GpuVirtualTexture`](gpu_virtual_texture::GpuVirtualTexture) each frame.
+    pub(crate) loaded_pages: Vec<LoadedPage>,
+    /// The changed entries of the page table.
+    pub(crate) page_table_updates: Vec<(NodeId, PageIndex)>,
+}
+
+impl VirtualTexture {
+    /// Creates a new virtual texture, whose pages are loaded from disk.
+    pub fn new(config: VirtualTextureConfig, terrain_config: &TerrainConfig) -> Self {
+        // The textures are created in the render world, thus the handles only have to be unique.
+        static NEXT_HANDLE: AtomicU64 = AtomicU64::new(0);
+
+        let handle = || {
+            HandleUntyped::weak_from_u64(
+                Image::TYPE_UUID,
+                NEXT_HANDLE.fetch_add(1, Ordering::Relaxed),
+            )
+            .typed()
+        };
+
+        Self {
+            path: format!("{}/data/{}", terrain_config.path, config.name),
+            terrain_size: terrain_config.terrain_size,
+            page_table: handle(),
+            pages: handle(),
+            pinned_pages: default(),
+            resident_pages: LruCache::unbounded(),
+            loading_pages: default(),
+            handle_mapping: default(),
+            missing_pages: default(),
+            free_pages: (0..config.cache_size).rev().collect(),
+            loaded_pages: default(),
+            page_table_updates: default(),
+            config,
+        }
+    }
+
+    /// The handle of the page table texture.
+    pub fn page_table(&self) -> Handle<Image> {
+        self.page_table.clone()
+    }
+
+    /// The handle of the array texture storing the resident pages.
+    pub fn pages(&self) -> Handle<Image> {
+        self.pages.clone()
+    }
+
+    /// The uniform of the virtual texture, which has to be bound alongside its textures.
+    pub fn uniform(&self) -> VirtualTextureUniform {
+        VirtualTextureUniform {
+            terrain_size: self.terrain_size as f32,
+            size: self.config.size as f32,
+            page_size: self.config.page_size as f32,
+            center_size: self.config.center_size() as f32,
+            border_size: self.config.border_size as f32,
+            lod_count: self.config.lod_count,
+        }
+    }
+
+    /// Returns whether the page lies inside of the virtual texture.
+    fn contains(&self, page: &NodeCoordinate) -> bool {
+        let page_count = (self.config.page_count() >> page.lod).max(1);
+
+        page.lod < self.config.lod_count && page.x < page_count && page.y < page_count
+    }
+
+    /// Marks the requested pages as recently used and starts loading the missing ones.
+    /// Coarser pages are prioritized, so that the fallback pages are available first.
+    fn request_pages(&mut self, asset_server: &AssetServer, requests: HashSet<NodeId>) {
+        let mut requests = requests
+            .into_iter()
+            .map(NodeCoordinate::from)
+            .filter(|page| self.contains(page))
+            .collect::<Vec<_>>();
+
+        requests.sort_by_key(|page| Reverse(page.lod));
+
+        let mut load_count = 0;
+
+        for page in requests {
+            let page_id = calc_node_id(page.lod, page.x, page.y);
+
+            if self.resident_pages.get(&page_id).is_some()
+                || self.pinned_pages.contains_key(&page_id)
+                || self.loading_pages.contains_key(&page_id)
+                || self.missing_pages.contains(&page_id)
+            {
+                continue;
+            }
+
+            // do not evict more pages than are available, to prevent thrashing
+            if load_count == self.config.max_loads_per_frame
+                || self.loading_pages.len() >= self.free_pages.len() + self.resident_pages.len()
+            {
+                continue;
+            }
+
+            self.load_page(asset_server, page_id);
+            load_count += 1;
+        }
+    }
+
+    fn load_page(&mut self, asset_server: &AssetServer, page_id: NodeId) {
+        let handle: Handle<Image> = asset_server.load(&format!(
+            "{}/{page_id}.{}",
+            self.path,
+            self.config.file_format.extension()
+        ));
+
+        let loaded = asset_server.get_load_state(handle.clone()) == LoadState::Loaded;
+
+        if !loaded {
+            self.handle_mapping.insert(handle.id(), page_id);
+        }
+
+        self.loading_pages
+            .insert(page_id, LoadingPage { handle, loaded });
+    }
+
+    /// Moves the pages, which have finished loading, into the physical pages.
+    fn update_loaded_pages(&mut self, asset_server: &AssetServer) {
+        let lod_count = self.config.lod_count;
+
+        let finished = self
+            .loading_pages
+            .iter()
+            .filter_map(
+                |(&page_id, page)| match asset_server.get_load_state(page.handle.clone()) {
+                    LoadState::Failed => Some((page_id, false)),
+                    _ if page.loaded => Some((page_id, true)),
+                    _ => None,
+                },
+            )
+            .collect::<Vec<_>>();
+
+        for (page_id, loaded) in finished {
+            let page = self.loading_pages.remove(&page_id).unwrap();
+
+            if !loaded {
+                self.handle_mapping.remove(&page.handle.id());
+                self.missing_pages.insert(page_id);
+                continue;
+            }
+
+            let page_index = match self.free_pages.pop() {
+                Some(page_index) => page_index,
+                None => match self.resident_pages.pop_lru() {
+                    Some((evicted_id, page_index)) => {
+                        self.page_table_updates.push((evicted_id, 0));
+                        page_index
+                    }
+                    None => {
+                        error!("The virtual texture cache is too small to fit the coarsest lod.");
+                        continue;
+                    }
+                },
+            };
+
+            if NodeCoordinate::from(page_id).lod == lod_count - 1 {
+                self.pinned_pages.insert(page_id, page_index);
+            } else {
+                self.resident_pages.put(page_id, page_index);
+            }
+
+            // the page table stores the page index offset by one, zero marks missing pages
+            self.page_table_updates.push((page_id, page_index + 1));
+            self.loaded_pages.push(LoadedPage {
+                page_index,
+                handle: page.handle,
+            });
+        }
+    }
+}
+
+/// Marks the pages as loaded and adjusts the format of their textures.
+pub(crate) fn finish_loading_virtual_textures(
+    mut asset_events: EventReader<AssetEvent<Image>>,
+    mut images: ResMut<Assets<Image>>,
+    mut terrain_query: Query<&mut VirtualTexture>,
+) {
+    for event in asset_events.iter() {
+        if let AssetEvent::Created { handle } = event {
+            for mut virtual_texture in terrain_query.iter_mut() {
+                if let Some(page_id) = virtual_texture.handle_mapping.remove(&handle.id()) {
+                    let format = virtual_texture.config.format.into();
+                    let image = images.get_mut(handle).unwrap();
+
+                    image.texture_descriptor.format = format;
+                    image.texture_descriptor.usage |= TextureUsages::COPY_SRC;
+
+                    if let Some(page) = virtual_texture.loading_pages.get_mut(&page_id) {
+                        page.loaded = true;
+                    }
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Streams the pages requested by the feedback pass of all views.
+pub(crate) fn update_virtual_textures(
+    asset_server: Res<AssetServer>,
+    feedback: Res<VirtualTextureFeedback>,
+    mut terrain_query: Query<(Entity, &mut VirtualTexture)>,
+) {
+    let mut feedback = feedback.0.lock().unwrap();
+
+    for (terrain, mut virtual_texture) in terrain_query.iter_mut() {
+        let mut requests = HashSet::new();
+
+        // the coarsest lod is always requested
+        let lod = virtual_texture.config.lod_count - 1;
+        let page_count = (virtual_texture.config.page_count() >> lod).max(1);
+
+        for (x, y) in (0..page_count).flat_map(|x| (0..page_count).map(move |y| (x, y))) {
+            requests.insert(calc_node_id(lod, x, y));
+        }
+
+        feedback.retain(|&(feedback_terrain, _), pages| {
+            if feedback_terrain != terrain {
+                return true;
+            }
+
+            requests.extend(pages.drain(..));
+            false
+        });
+
+        virtual_texture.update_loaded_pages(&asset_server);
+        virtual_texture.request_pages(&asset_server, requests);
+    }
+}
@@ -0,0 +1,212 @@
+use anyhow::{anyhow, Result};
+use reqwest::StatusCode;
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Returns whether the request failed permanently, thus retrying it is pointless.
+/// Rate limited and timed out requests are retried, since they are likely to succeed later.
+fn is_permanent(status: StatusCode) -> bool {
+    status.is_client_error()
+        && status != StatusCode::TOO_MANY_REQUESTS
+        && status != StatusCode::REQUEST_TIMEOUT
+}
+
+fn checksum(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Caches the downloaded files on disk, so that they are only downloaded once.
+///
+/// Each file is stored under the checksum of its url, together with the checksum of its content,
+/// which is verified when the file is read from the cache.
+/// Failed downloads are retried with an exponential backoff.
+pub(crate) struct DownloadCache {
+    directory: PathBuf,
+    client: reqwest::Client,
+    retries: u32,
+    backoff: Duration,
+}
+
+impl DownloadCache {
+    pub(crate) fn new(directory: &str, retries: u32) -> Result<Self> {
+        Self::with_client(
+            directory,
+            reqwest::Client::new(),
+            retries,
+            Duration::from_secs(1),
+        )
+    }
+
+    /// Creates a cache, which downloads with the client and waits for the backoff
+    /// before the first retry.
+    pub(crate) fn with_client(
+        directory: &str,
+        client: reqwest::Client,
+        retries: u32,
+        backoff: Duration,
+    ) -> Result<Self> {
+        fs::create_dir_all(directory)?;
+
+        Ok(Self {
+            directory: PathBuf::from(directory),
+            client,
+            retries,
+            backoff,
+        })
+    }
+
+    /// Returns the content of the url, either from the cache or by downloading it.
+    pub(crate) async fn fetch(&self, url: &str) -> Result<Vec<u8>> {
+        let key = checksum(url.as_bytes());
+        let path = self.directory.join(&key);
+        let checksum_path = path.with_extension("sha256");
+
+        if let Some(data) = Self::load(&path, &checksum_path) {
+            return Ok(data);
+        }
+
+        let data = self.download(url).await?;
+
+        // the file is written to a temporary path first, so that interrupted writes are not cached
+        let temporary_path = path.with_extension("part");
+        fs::write(&temporary_path, &data)?;
+        fs::write(&checksum_path, checksum(&data))?;
+        fs::rename(&temporary_path, &path)?;
+
+        Ok(data)
+    }
+
+    /// Removes all cached files.
+    pub(crate) fn clear(&self) -> Result<()> {
+        fs::remove_dir_all(&self.directory)?;
+
+        Ok(())
+    }
+
+    /// Loads the cached file, if it exists and matches its checksum.
+    fn load(path: &Path, checksum_path: &Path) -> Option<Vec<u8>> {
+        let data = fs::read(path).ok()?;
+        let expected = fs::read_to_string(checksum_path).ok()?;
+
+        if checksum(&data) == expected.trim() {
+            Some(data)
+        } else {
+            let _ = fs::remove_file(path);
+            let _ = fs::remove_file(checksum_path);
+            None
+        }
+    }
+
+    async fn download(&self, url: &str) -> Result<Vec<u8>> {
+        let mut attempt = 0;
+
+        loop {
+            let error = match self.client.get(url).send().await {
+                Ok(response) if is_permanent(response.status()) => {
+                    // the file does not exist or may not be accessed, thus retrying is pointless
+                    return Err(anyhow!("Does not exist ({}): {url}", response.status()));
+                }
+                Ok(response) => match response.error_for_status() {
+                    Ok(response) => match response.bytes().await {
+                        Ok(bytes) => return Ok(bytes.to_vec()),
+                        Err(error) => anyhow!(error),
+                    },
+                    Err(error) => anyhow!(error),
+                },
+                Err(error) => anyhow!(error),
+            };
+
+            if attempt >= self.retries {
+                return Err(error.context(format!(
+                    "Failed to download {url} after {} attempts.",
+                    attempt + 1
+                )));
+            }
+
+            tokio::time::sleep(self.backoff * 2u32.pow(attempt)).await;
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{test_cache, test_directory, TestServer};
+
+    #[tokio::test]
+    async fn cached_files_are_only_downloaded_once() {
+        let server = TestServer::start().await;
+        server.serve("/tile.zip", 200, b"content".to_vec());
+
+        let cache = test_cache(&test_directory("cache_hit"), 2);
+        let url = format!("{}/tile.zip", server.url);
+
+        assert_eq!(cache.fetch(&url).await.unwrap(), b"content");
+        assert_eq!(cache.fetch(&url).await.unwrap(), b"content");
+        assert_eq!(server.requests("/tile.zip"), 1);
+    }
+
+    #[tokio::test]
+    async fn corrupted_files_are_downloaded_again() {
+        let server = TestServer::start().await;
+        server.serve("/tile.zip", 200, b"content".to_vec());
+
+        let cache = test_cache(&test_directory("cache_corrupted"), 2);
+        let url = format!("{}/tile.zip", server.url);
+
+        cache.fetch(&url).await.unwrap();
+        fs::write(cache.directory.join(checksum(url.as_bytes())), b"corrupted").unwrap();
+
+        assert_eq!(cache.fetch(&url).await.unwrap(), b"content");
+        assert_eq!(server.requests("/tile.zip"), 2);
+    }
+
+    #[tokio::test]
+    async fn rate_limited_requests_are_retried() {
+        let server = TestServer::start().await;
+        server.serve("/tile.zip", 429, Vec::new());
+        server.serve("/tile.zip", 408, Vec::new());
+        server.serve("/tile.zip", 200, b"content".to_vec());
+
+        let cache = test_cache(&test_directory("cache_rate_limited"), 2);
+        let url = format!("{}/tile.zip", server.url);
+
+        assert_eq!(cache.fetch(&url).await.unwrap(), b"content");
+        assert_eq!(server.requests("/tile.zip"), 3);
+    }
+
+    #[tokio::test]
+    async fn missing_files_are_not_retried() {
+        let server = TestServer::start().await;
+
+        let cache = test_cache(&test_directory("cache_missing"), 2);
+        let url = format!("{}/tile.zip", server.url);
+
+        assert!(cache.fetch(&url).await.is_err());
+        assert_eq!(server.requests("/tile.zip"), 1);
+    }
+
+    #[tokio::test]
+    async fn cleared_files_are_downloaded_again() {
+        let server = TestServer::start().await;
+        server.serve("/tile.zip", 200, b"content".to_vec());
+
+        let directory = test_directory("cache_cleared");
+        let url = format!("{}/tile.zip", server.url);
+
+        test_cache(&directory, 2).fetch(&url).await.unwrap();
+        test_cache(&directory, 2).clear().unwrap();
+        assert!(!Path::new(&directory).exists());
+
+        assert_eq!(
+            test_cache(&directory, 2).fetch(&url).await.unwrap(),
+            b"content"
+        );
+        assert_eq!(server.requests("/tile.zip"), 2);
+    }
+}
@@ -0,0 +1,142 @@
+use crate::provider::{
+    downscale, parse_tiff_albedo, parse_tiff_height, parse_xyz, unzip, Provider, Tile, TileLayer,
+};
+use anyhow::{anyhow, Result};
+use image::DynamicImage;
+use regex::Regex;
+use std::fs;
+use terrain_settings::{GenericDataset, LayerFormat, LayerKind};
+
+/// Provides the tiles of a dataset described by the config.
+///
+/// The coordinates of each tile are parsed from its name and inserted into the url
+/// templates of the layers.
+pub(crate) struct GenericProvider {
+    dataset: GenericDataset,
+    /// The file listing the names of the tiles.
+    tiles: String,
+    pattern: Regex,
+    /// The size of the height tiles in pixels.
+    tile_size: u32,
+}
+
+impl GenericProvider {
+    pub(crate) fn new(dataset: GenericDataset, terrain_path: &str, tile_size: u32) -> Result<Self> {
+        Ok(Self {
+            tiles: format!("{terrain_path}/{}", dataset.tiles),
+            pattern: Regex::new(&dataset.pattern)?,
+            dataset,
+            tile_size,
+        })
+    }
+
+    fn parse_coordinates(&self, name: &str) -> Result<(u32, u32)> {
+        let captures = self
+            .pattern
+            .captures(name)
+            .ok_or(anyhow!("The tile {name} does not match the pattern."))?;
+        let coordinate = |group: &str| -> Result<u32> {
+            Ok(captures
+                .name(group)
+                .ok_or(anyhow!("The pattern does not contain the group {group}."))?
+                .as_str()
+                .parse()?)
+        };
+
+        Ok((coordinate("x")?, coordinate("y")?))
+    }
+}
+
+impl Provider for GenericProvider {
+    fn gather_tiles(&self) -> Result<Vec<Tile>> {
+        fs::read_to_string(&self.tiles)?
+            .lines()
+            .filter(|name| !name.trim().is_empty())
+            .map(|name| -> Result<Tile> {
+                let name = name.trim();
+                let coordinates = self.parse_coordinates(name)?;
+
+                let layers = self
+                    .dataset
+                    .layers
+                    .iter()
+                    .map(|layer| {
+                        let url = layer
+                            .url
+                            .replace("{x}", &coordinates.0.to_string())
+                            .replace("{y}", &coordinates.1.to_string())
+                            .replace("{tile}", name);
+
+                        TileLayer::new(&layer.name, url, layer.kind)
+                    })
+                    .collect();
+
+                Ok(Tile {
+                    coordinates,
+                    layers,
+                })
+            })
+            .collect()
+    }
+
+    fn tile_extent(&self) -> u32 {
+        self.dataset.tile_extent.unwrap_or(1)
+    }
+
+    fn coordinate_unit(&self) -> f64 {
+        self.dataset.coordinate_unit.unwrap_or(1000.0)
+    }
+
+    fn epsg(&self) -> Option<u32> {
+        self.dataset.epsg
+    }
+
+    fn decode(
+        &self,
+        tile: &Tile,
+        layer: &TileLayer,
+        data: Vec<u8>,
+        height: f32,
+    ) -> Result<DynamicImage> {
+        let config = self
+            .dataset
+            .layers
+            .iter()
+            .find(|config| config.name == layer.name)
+            .ok_or(anyhow!("Unknown layer {}.", layer.name))?;
+
+        let data = match config.format {
+            LayerFormat::Xyz | LayerFormat::Tif => data,
+            LayerFormat::XyzZip => unzip(data, ".xyz")?,
+            LayerFormat::TifZip => unzip(data, ".tif")?,
+        };
+
+        let image = match (config.format, layer.kind) {
+            (LayerFormat::Xyz | LayerFormat::XyzZip, LayerKind::Height) => {
+                let unit = self.coordinate_unit();
+                let origin = (
+                    tile.coordinates.0 as f64 * unit,
+                    tile.coordinates.1 as f64 * unit,
+                );
+                let spacing = unit * self.tile_extent() as f64 / self.tile_size as f64;
+
+                parse_xyz(data, origin, self.tile_size, spacing, height)?
+            }
+            (LayerFormat::Tif | LayerFormat::TifZip, LayerKind::Height) => {
+                parse_tiff_height(data, height)?
+            }
+            (LayerFormat::Tif | LayerFormat::TifZip, LayerKind::Albedo) => parse_tiff_albedo(data)?,
+            (LayerFormat::Xyz | LayerFormat::XyzZip, LayerKind::Albedo) => {
+                return Err(anyhow!(
+                    "The albedo layer {} can not be stored as XYZ.",
+                    layer.name
+                ))
+            }
+        };
+
+        Ok(match layer.kind {
+            LayerKind::Albedo => downscale(image, config.downscale.unwrap_or(1)),
+            LayerKind::Height => image,
+        })
+    }
+}
@@ -1,69 +1,176 @@
+mod cache;
+mod generic;
+mod projection;
+mod provider;
+mod resample;
 mod saxony;
 mod switzerland;
-
-use anyhow::Result;
+#[cfg(test)]
+mod test_server;
+
+use crate::{
+    cache::DownloadCache,
+    generic::GenericProvider,
+    provider::{Provider, Tile, TileLayer},
+    resample::{resample, SourceTiles},
+    saxony::SaxonyProvider,
+    switzerland::SwitzerlandProvider,
+};
+use anyhow::{anyhow, Result};
 use bytemuck::cast_slice;
 use dtm::DTM;
 use futures::{future::join_all, StreamExt};
-use image::{DynamicImage, ImageBuffer, Luma};
+use image::{DynamicImage, ImageBuffer, Luma, RgbImage};
 use indicatif::{ProgressBar, ProgressStyle};
 use rapid_qoi::{Colors, Qoi};
-use std::fs;
-use terrain_settings::{load_settings, Dataset};
+use std::{collections::HashSet, env, fs, path::Path, sync::Arc};
+use terrain_settings::{load_settings, Dataset, LayerKind, Settings, Source};
 
 pub(crate) type ImageGray = ImageBuffer<Luma<u16>, Vec<u16>>;
 
-#[derive(Clone, Debug)]
-enum Tile {
-    Switzerland {
-        dtm_url: String,
-        dop_url: String,
-    },
-    Saxony {
-        dtm_url: String,
-        dop_url: String,
-        dsm_url: String,
-    },
-}
-
+/// The height value, that marks texels without data in the downloaded and resampled tiles.
+/// It matches the reserved nodata value of the terrain preprocessing.
+pub(crate) const NODATA: u16 = u16::MAX;
+
+/// Downloads and parses the tiles of the selected dataset.
+///
+/// Tiles, whose output already exists, are skipped unless `--force` is passed.
+/// The tiles, that failed to process, are written to the failure list of the terrain,
+/// which can be re-run with `--retry-failed`.
+/// With `--no-cache` the download cache is removed, once all tiles were processed successfully.
+///
+/// If the terrain specifies a target grid, each of its sources is downloaded into its own
+/// directory and afterwards resampled onto the grid.
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let force = args.iter().any(|arg| arg == "--force");
+    let retry_failed = args.iter().any(|arg| arg == "--retry-failed");
+    let no_cache = args.iter().any(|arg| arg == "--no-cache");
+
     let settings = load_settings()?;
     let path = settings.terrain_path.clone() + "/source";
+    let failure_path = format!("{}/failed_tiles.txt", settings.terrain_path);
+    let cache = Arc::new(DownloadCache::new(
+        &format!("{}/cache", settings.terrain_path),
+        settings.download_retries,
+    )?);
+
+    let sources = if settings.sources.is_empty() {
+        vec![Source {
+            name: "default".to_string(),
+            priority: 0,
+            dataset: settings.dataset.clone(),
+        }]
+    } else {
+        settings.sources.clone()
+    };
 
-    let tiles = match settings.dataset {
-        Dataset::None => {
-            panic!("No dataset selected.")
-        }
-        Dataset::Saxony { urls } => {
-            fs::create_dir_all(format!("{path}/dtm"))?;
-            fs::create_dir_all(format!("{path}/dop"))?;
-            fs::create_dir_all(format!("{path}/dsm"))?;
+    if settings.grid.is_none() && sources.len() > 1 {
+        return Err(anyhow!("Merging multiple sources requires a target grid.").into());
+    }
 
-            saxony::gather_tiles(&format!("{}/{}", settings.terrain_path, urls))
+    let mut downloads = Vec::new();
+
+    for source in sources {
+        let provider = create_provider(source.dataset, &settings)?;
+        let tiles = provider.gather_tiles()?;
+
+        // without a target grid, the tiles are used as they are
+        let path = match settings.grid {
+            Some(_) => format!("{}/sources/{}", settings.terrain_path, source.name),
+            None => path.clone(),
+        };
+
+        for layer in tiles.iter().flat_map(|tile| &tile.layers) {
+            fs::create_dir_all(format!("{path}/{}", layer.name))?;
         }
-        Dataset::Switzerland { urls_dtm, urls_dop } => {
-            fs::create_dir_all(format!("{path}/dtm"))?;
-            fs::create_dir_all(format!("{path}/dop"))?;
 
-            switzerland::gather_tiles(
-                &format!("{}/{}", settings.terrain_path, urls_dtm),
-                &format!("{}/{}", settings.terrain_path, urls_dop),
-            )
+        // the origin is determined from all tiles, so that the positions of retried tiles match
+        let mut origin = (u32::MAX, u32::MIN);
+
+        for tile in &tiles {
+            let coordinates = tile.coordinates;
+            origin = (origin.0.min(coordinates.0), origin.1.max(coordinates.1));
         }
-    }?;
 
-    let mut origin = (u32::MAX, u32::MIN);
+        downloads.push(SourceTiles {
+            name: source.name,
+            priority: source.priority,
+            provider,
+            tiles,
+            path,
+            origin,
+        });
+    }
 
-    for tile in &tiles {
-        let coords = match tile {
-            Tile::Switzerland { dtm_url, .. } => switzerland::parse_coordinates(dtm_url)?,
-            Tile::Saxony { dtm_url, .. } => saxony::parse_coordinates(dtm_url)?,
-        };
+    let (_, failed) = download_tiles(
+        &downloads,
+        cache.clone(),
+        &failure_path,
+        settings.height,
+        force,
+        retry_failed,
+        settings.parallel_downloads,
+    )
+    .await?;
+
+    if let Some(grid) = &settings.grid {
+        resample(
+            &downloads,
+            grid,
+            settings.tile_size,
+            settings.height,
+            &path,
+            force,
+            std::thread::available_parallelism()?.get(),
+        )
+        .await?;
+    }
 
-        origin = (origin.0.min(coords.0), origin.1.max(coords.1));
+    // the downloads are only kept, while some tiles still have to be retried
+    if no_cache && failed == 0 {
+        cache.clear()?;
     }
 
+    Ok(())
+}
+
+/// Downloads and parses the tiles of all sources.
+///
+/// The tiles, that failed to process, are written to the failure list, which is read again
+/// to select the tiles, if only the failed ones are retried.
+/// Returns the number of processed and failed tiles.
+#[allow(clippy::too_many_arguments)]
+async fn download_tiles(
+    downloads: &[SourceTiles],
+    cache: Arc<DownloadCache>,
+    failure_path: &str,
+    height: f32,
+    force: bool,
+    retry_failed: bool,
+    parallel_downloads: usize,
+) -> Result<(usize, usize)> {
+    let tiles = downloads
+        .iter()
+        .flat_map(|source| source.tiles.iter().map(move |tile| (source, tile)))
+        .collect::<Vec<_>>();
+
+    let tiles = if retry_failed {
+        let failed_tiles = fs::read_to_string(failure_path)?;
+        let failed_tiles = failed_tiles
+            .lines()
+            .filter_map(|line| line.split(": ").next())
+            .collect::<HashSet<_>>();
+
+        tiles
+            .into_iter()
+            .filter(|(source, tile)| failed_tiles.contains(source.tile_id(tile).as_str()))
+            .collect()
+    } else {
+        tiles
+    };
+
     println!("Started downloading and parsing {} tiles.", tiles.len());
     let bar = ProgressBar::new(tiles.len() as u64);
     bar.set_style(
@@ -72,36 +179,51 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     );
     bar.tick();
 
-    let results = futures::stream::iter(tiles.iter().map(|tile| {
-        tokio::spawn(process_tile(
+    let results = futures::stream::iter(tiles.iter().map(|&(source, tile)| {
+        let task = tokio::spawn(process_tile(
+            source.provider.clone(),
             tile.clone(),
-            path.clone(),
-            origin,
-            settings.height,
+            source.path.clone(),
+            source.origin,
+            height,
+            cache.clone(),
+            force,
             bar.clone(),
-        ))
+        ));
+
+        // the results complete out of order, thus each one is paired with its tile
+        async move { (source, tile, task.await) }
     }))
-    .buffer_unordered(settings.parallel_downloads)
+    .buffer_unordered(parallel_downloads)
     .collect::<Vec<_>>()
     .await;
 
     bar.finish();
 
+    // each failed tile is listed together with all of its failed layers
     let mut failed_tiles = Vec::new();
 
-    for (result, url) in results.into_iter().zip(tiles.iter()) {
-        match result {
-            Ok(result) => {
-                if let Err(error) = result {
-                    failed_tiles.push(url);
+    for (source, tile, result) in results {
+        let failed_layers = match result {
+            Ok(failed_layers) => failed_layers
+                .into_iter()
+                .map(|(layer, error)| {
                     println!("{error}");
-                }
-            }
-
+                    layer
+                })
+                .collect(),
             Err(error) => {
-                failed_tiles.push(url);
                 println!("{error}");
+                tile.layers.iter().map(|layer| layer.name.clone()).collect()
             }
+        };
+
+        if !failed_layers.is_empty() {
+            failed_tiles.push(format!(
+                "{}: {}",
+                source.tile_id(tile),
+                failed_layers.join(", ")
+            ));
         }
     }
 
@@ -111,56 +233,124 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         tiles.len()
     );
 
-    if !failed_tiles.is_empty() {
+    if failed_tiles.is_empty() {
+        let _ = fs::remove_file(failure_path);
+    } else {
         println!("\nThe following tiles failed to process.");
 
-        for tile in failed_tiles {
-            println!("{:?}", tile);
+        for failed_tile in &failed_tiles {
+            println!("{failed_tile}");
         }
+
+        fs::write(failure_path, failed_tiles.join("\n"))?;
+
+        println!("\nThe failed tiles have been written to {failure_path}, run the download tool with --retry-failed to retry them.");
     }
 
-    Ok(())
+    Ok((tiles.len(), failed_tiles.len()))
+}
+
+fn create_provider(dataset: Dataset, settings: &Settings) -> Result<Arc<dyn Provider>> {
+    Ok(match dataset {
+        Dataset::None => return Err(anyhow!("No dataset selected.")),
+        Dataset::Saxony { urls } => Arc::new(SaxonyProvider {
+            urls: format!("{}/{}", settings.terrain_path, urls),
+        }),
+        Dataset::Switzerland { urls_dtm, urls_dop } => Arc::new(SwitzerlandProvider {
+            urls_dtm: format!("{}/{}", settings.terrain_path, urls_dtm),
+            urls_dop: format!("{}/{}", settings.terrain_path, urls_dop),
+        }),
+        Dataset::Generic(dataset) => Arc::new(GenericProvider::new(
+            dataset,
+            &settings.terrain_path,
+            settings.tile_size,
+        )?),
+    })
 }
 
+/// Processes all layers of the tile and returns the names of the failed layers with their errors.
+#[allow(clippy::too_many_arguments)]
 async fn process_tile(
+    provider: Arc<dyn Provider>,
     tile: Tile,
     path: String,
     origin: (u32, u32),
     height: f32,
+    cache: Arc<DownloadCache>,
+    force: bool,
     bar: ProgressBar,
-) -> Result<()> {
-    let tasks = match tile {
-        Tile::Switzerland { dtm_url, dop_url } => {
-            let task_dtm = tokio::spawn(switzerland::process_dtm(
-                dtm_url,
-                path.clone(),
-                origin,
-                height,
-            ));
-            let task_dop = tokio::spawn(switzerland::process_dop(dop_url, path.clone(), origin));
+) -> Vec<(String, anyhow::Error)> {
+    let position = tile.position(origin, provider.tile_extent());
 
-            join_all([task_dop, task_dtm]).await
-        }
-        Tile::Saxony {
-            dtm_url,
-            dop_url,
-            dsm_url,
-        } => {
-            let task_dtm = tokio::spawn(saxony::process_dtm(dtm_url, path.clone(), origin, height));
-            let task_dop = tokio::spawn(saxony::process_dop(dop_url, path.clone(), origin));
-            let task_dsm = tokio::spawn(saxony::process_dsm(dsm_url, path.clone(), origin, height));
-
-            join_all([task_dtm, task_dop, task_dsm]).await
-        }
-    };
+    let tasks = tile.layers.iter().map(|layer| {
+        tokio::spawn(process_layer(
+            provider.clone(),
+            tile.clone(),
+            layer.clone(),
+            path.clone(),
+            position,
+            height,
+            cache.clone(),
+            force,
+        ))
+    });
+
+    let mut failed_layers = Vec::new();
 
-    for task in tasks {
-        task??;
+    for (layer, result) in tile.layers.iter().zip(join_all(tasks).await) {
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(error)) => failed_layers.push((layer.name.clone(), error)),
+            Err(error) => failed_layers.push((layer.name.clone(), error.into())),
+        }
     }
 
     bar.inc(1);
 
-    Ok(())
+    failed_layers
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn process_layer(
+    provider: Arc<dyn Provider>,
+    tile: Tile,
+    layer: TileLayer,
+    path: String,
+    position: (u32, u32),
+    height: f32,
+    cache: Arc<DownloadCache>,
+    force: bool,
+) -> Result<()> {
+    if !needs_output(&path, &layer.name, position, layer.extension(), force) {
+        return Ok(());
+    }
+
+    let data = cache.fetch(&layer.url).await?;
+    let image = provider.decode(&tile, &layer, data, height)?;
+
+    match layer.kind {
+        LayerKind::Height => save_height(&image, &path, &layer.name, position),
+        LayerKind::Albedo => save_albedo(&image, &path, &layer.name, position),
+    }
+}
+
+/// Returns the path of the output tile.
+pub(crate) fn output_path(path: &str, name: &str, position: (u32, u32), extension: &str) -> String {
+    format!(
+        "{path}/{name}/{name}_{}_{}.{extension}",
+        position.0, position.1
+    )
+}
+
+/// Returns whether the output tile should be (re)generated.
+pub(crate) fn needs_output(
+    path: &str,
+    name: &str,
+    position: (u32, u32),
+    extension: &str,
+    force: bool,
+) -> bool {
+    force || !Path::new(&output_path(path, name, position, extension)).exists()
 }
 
 pub(crate) fn save_albedo(
@@ -169,7 +359,9 @@ pub(crate) fn save_albedo(
     name: &str,
     position: (u32, u32),
 ) -> Result<()> {
-    let path = format!("{path}/{name}/{name}_{}_{}.qoi", position.0, position.1);
+    let path = output_path(path, name, position, "qoi");
+    // the tile is written to a temporary path first, so that interrupted writes are not skipped
+    let temporary_path = format!("{path}.part");
 
     let bytes = Qoi {
         width: image.width(),
@@ -178,7 +370,8 @@ pub(crate) fn save_albedo(
     }
     .encode_alloc(cast_slice(image.as_bytes()))?;
 
-    fs::write(path, &bytes)?;
+    fs::write(&temporary_path, &bytes)?;
+    fs::rename(temporary_path, path)?;
 
     Ok(())
 }
@@ -189,7 +382,8 @@ pub(crate) fn save_height(
     name: &str,
     position: (u32, u32),
 ) -> Result<()> {
-    let path = format!("{path}/{name}/{name}_{}_{}.dtm", position.0, position.1);
+    let path = output_path(path, name, position, "dtm");
+    let temporary_path = format!("{path}.part");
 
     DTM {
         pixel_size: 2,
@@ -197,7 +391,222 @@ pub(crate) fn save_height(
         width: image.width(),
         height: image.height(),
     }
-    .encode_file(path, cast_slice(image.as_bytes()))?;
+    .encode_file(&temporary_path, cast_slice(image.as_bytes()))?;
+    fs::rename(temporary_path, path)?;
 
     Ok(())
 }
+
+/// Loads a tile, that has been saved by [`save_height`] or [`save_albedo`].
+pub(crate) fn load_tile(path: &str, kind: LayerKind) -> Result<DynamicImage> {
+    match kind {
+        LayerKind::Height => {
+            let (descriptor, data) = DTM::decode_file(path)?;
+            let data = data
+                .chunks_exact(2)
+                .map(|pixel| u16::from_le_bytes([pixel[0], pixel[1]]))
+                .collect();
+
+            Ok(DynamicImage::from(
+                ImageGray::from_raw(descriptor.width, descriptor.height, data)
+                    .ok_or(anyhow!("Could not create image from the tile {path}."))?,
+            ))
+        }
+        LayerKind::Albedo => {
+            let (descriptor, data) = Qoi::decode_alloc(&fs::read(path)?)?;
+
+            Ok(DynamicImage::from(
+                RgbImage::from_raw(descriptor.width, descriptor.height, data)
+                    .ok_or(anyhow!("Could not create image from the tile {path}."))?,
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{test_cache, test_directory, tiff_fixture, zip_fixture, TestServer};
+    use itertools::iproduct;
+    use terrain_settings::{GenericDataset, GenericLayer, LayerFormat};
+
+    const HEIGHT: f32 = 1000.0;
+
+    /// Serves the fixture layers of the tile at the coordinates.
+    fn serve_tile(server: &TestServer, (x, y): (u32, u32)) {
+        // the height tile consists of 2x2 points spaced 500 m apart
+        let points = iproduct!(0..2, 0..2)
+            .map(|(i, j)| format!("{} {} 100.0", x * 1000 + i * 500, y * 1000 + j * 500))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        server.serve(
+            &format!("/dtm_{x}_{y}.zip"),
+            200,
+            zip_fixture("tile.xyz", points.as_bytes()),
+        );
+        server.serve(
+            &format!("/dop_{x}_{y}.tif"),
+            200,
+            tiff_fixture(2, 2, &[128; 12]),
+        );
+    }
+
+    /// Creates a source of a generic dataset, whose layers are downloaded from the server.
+    fn test_source(server: &TestServer, directory: &str, tiles: &[(u32, u32)]) -> SourceTiles {
+        let names = tiles
+            .iter()
+            .map(|(x, y)| format!("tile_{x}_{y}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        fs::write(format!("{directory}/tiles.txt"), names).unwrap();
+
+        let dataset = GenericDataset {
+            tiles: "tiles.txt".to_string(),
+            pattern: r"tile_(?P<x>\d+)_(?P<y>\d+)".to_string(),
+            tile_extent: None,
+            coordinate_unit: None,
+            epsg: None,
+            layers: vec![
+                GenericLayer {
+                    name: "dtm".to_string(),
+                    url: format!("{}/dtm_{{x}}_{{y}}.zip", server.url),
+                    kind: LayerKind::Height,
+                    format: LayerFormat::XyzZip,
+                    downscale: None,
+                },
+                GenericLayer {
+                    name: "dop".to_string(),
+                    url: format!("{}/dop_{{x}}_{{y}}.tif", server.url),
+                    kind: LayerKind::Albedo,
+                    format: LayerFormat::Tif,
+                    downscale: None,
+                },
+            ],
+        };
+
+        let provider: Arc<dyn Provider> =
+            Arc::new(GenericProvider::new(dataset, directory, 2).unwrap());
+        let tiles = provider.gather_tiles().unwrap();
+        let path = format!("{directory}/source");
+
+        for layer in tiles.iter().flat_map(|tile| &tile.layers) {
+            fs::create_dir_all(format!("{path}/{}", layer.name)).unwrap();
+        }
+
+        let origin = tiles.iter().fold((u32::MAX, u32::MIN), |origin, tile| {
+            (
+                origin.0.min(tile.coordinates.0),
+                origin.1.max(tile.coordinates.1),
+            )
+        });
+
+        SourceTiles {
+            name: "default".to_string(),
+            priority: 0,
+            provider,
+            tiles,
+            path,
+            origin,
+        }
+    }
+
+    #[tokio::test]
+    async fn present_tiles_are_skipped_unless_forced() {
+        let server = TestServer::start().await;
+        serve_tile(&server, (0, 0));
+
+        let directory = test_directory("skip_present");
+        let source = test_source(&server, &directory, &[(0, 0)]);
+        let failure_path = format!("{directory}/failed_tiles.txt");
+        let dop_path = output_path(&source.path, "dop", (0, 0), "qoi");
+        let sources = [source];
+
+        let download = |force| {
+            download_tiles(
+                &sources,
+                Arc::new(test_cache(&format!("{directory}/cache"), 0)),
+                &failure_path,
+                HEIGHT,
+                force,
+                false,
+                1,
+            )
+        };
+
+        assert_eq!(download(false).await.unwrap(), (1, 0));
+        assert!(load_tile(&dop_path, LayerKind::Albedo).is_ok());
+
+        // present tiles are neither downloaded nor parsed again
+        fs::write(&dop_path, b"stale").unwrap();
+        download(false).await.unwrap();
+        assert_eq!(fs::read(&dop_path).unwrap(), b"stale");
+
+        // forced tiles are parsed again, but their downloads are taken from the cache
+        download(true).await.unwrap();
+        assert!(load_tile(&dop_path, LayerKind::Albedo).is_ok());
+        assert_eq!(server.requests("/dop_0_0.tif"), 1);
+        assert_eq!(server.requests("/dtm_0_0.zip"), 1);
+    }
+
+    #[tokio::test]
+    async fn failed_tiles_are_recorded_and_retried() {
+        let server = TestServer::start().await;
+        serve_tile(&server, (0, 0));
+
+        let directory = test_directory("retry_failed");
+        let source = test_source(&server, &directory, &[(0, 0), (1, 0)]);
+        let failed_tile = format!("{}: dtm, dop", source.tile_id(&source.tiles[1]));
+        let failure_path = format!("{directory}/failed_tiles.txt");
+        let sources = [source];
+
+        let download = |force, retry_failed| {
+            download_tiles(
+                &sources,
+                Arc::new(test_cache(&format!("{directory}/cache"), 0)),
+                &failure_path,
+                HEIGHT,
+                force,
+                retry_failed,
+                1,
+            )
+        };
+
+        // the layers of the second tile are missing
+        assert_eq!(download(false, false).await.unwrap(), (2, 1));
+        assert_eq!(fs::read_to_string(&failure_path).unwrap(), failed_tile);
+
+        // only the failed tile is retried, even though all tiles are forced
+        serve_tile(&server, (1, 0));
+        assert_eq!(download(true, true).await.unwrap(), (1, 0));
+        assert!(!Path::new(&failure_path).exists());
+        assert_eq!(server.requests("/dtm_1_0.zip"), 2);
+    }
+
+    #[tokio::test]
+    async fn only_the_failed_layers_are_recorded() {
+        let server = TestServer::start().await;
+        // the first request of the albedo layer fails
+        server.serve("/dop_0_0.tif", 404, Vec::new());
+        serve_tile(&server, (0, 0));
+
+        let directory = test_directory("failed_layers");
+        let source = test_source(&server, &directory, &[(0, 0)]);
+        let failed_tile = format!("{}: dop", source.tile_id(&source.tiles[0]));
+        let failure_path = format!("{directory}/failed_tiles.txt");
+
+        let result = download_tiles(
+            &[source],
+            Arc::new(test_cache(&format!("{directory}/cache"), 0)),
+            &failure_path,
+            HEIGHT,
+            false,
+            false,
+            1,
+        )
+        .await;
+
+        assert_eq!(result.unwrap(), (1, 1));
+        assert_eq!(fs::read_to_string(&failure_path).unwrap(), failed_tile);
+    }
+}
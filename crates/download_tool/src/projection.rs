@@ -0,0 +1,284 @@
+use anyhow::{anyhow, Result};
+
+/// A map projection, identified by its EPSG code.
+///
+/// Only the projections of the supported datasets are implemented.
+/// All geographic coordinates are treated as WGS84, thus the small datum shift between
+/// ETRS89 and WGS84 is ignored.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Projection {
+    /// Geographic longitude and latitude in degrees (EPSG:4326 and EPSG:4258).
+    Geographic,
+    /// The universal transverse mercator projection of the northern hemisphere
+    /// (EPSG:25828-25838 and EPSG:32601-32660).
+    Utm { zone: u32 },
+    /// The Swiss LV95 projection (EPSG:2056).
+    Lv95,
+}
+
+impl Projection {
+    pub(crate) fn from_epsg(epsg: u32) -> Result<Self> {
+        match epsg {
+            4326 | 4258 => Ok(Projection::Geographic),
+            25828..=25838 => Ok(Projection::Utm { zone: epsg - 25800 }),
+            32601..=32660 => Ok(Projection::Utm { zone: epsg - 32600 }),
+            2056 => Ok(Projection::Lv95),
+            _ => Err(anyhow!("The projection EPSG:{epsg} is not supported.")),
+        }
+    }
+
+    /// Converts the projected coordinates into longitude and latitude (in degrees).
+    pub(crate) fn to_geographic(self, point: (f64, f64)) -> (f64, f64) {
+        match self {
+            Projection::Geographic => point,
+            Projection::Utm { zone } => utm_to_geographic(zone, point),
+            Projection::Lv95 => lv95_to_geographic(point),
+        }
+    }
+
+    /// Converts the longitude and latitude (in degrees) into projected coordinates.
+    pub(crate) fn from_geographic(self, point: (f64, f64)) -> (f64, f64) {
+        match self {
+            Projection::Geographic => point,
+            Projection::Utm { zone } => geographic_to_utm(zone, point),
+            Projection::Lv95 => geographic_to_lv95(point),
+        }
+    }
+}
+
+/// Transforms the point from one projection into another.
+pub(crate) fn transform(from: Projection, to: Projection, point: (f64, f64)) -> (f64, f64) {
+    if from == to {
+        point
+    } else {
+        to.from_geographic(from.to_geographic(point))
+    }
+}
+
+// The transverse mercator projection is evaluated with the Krüger series up to the third order,
+// which is accurate to a few millimeters inside of a zone.
+// https://en.wikipedia.org/wiki/Universal_Transverse_Mercator_coordinate_system
+
+const SEMI_MAJOR_AXIS: f64 = 6_378_137.0;
+const FLATTENING: f64 = 1.0 / 298.257_222_101;
+const SCALE: f64 = 0.9996;
+const FALSE_EASTING: f64 = 500_000.0;
+
+struct TransverseMercator {
+    n: f64,
+    a: f64,
+    alpha: [f64; 3],
+    beta: [f64; 3],
+    delta: [f64; 3],
+}
+
+impl TransverseMercator {
+    fn new() -> Self {
+        let n = FLATTENING / (2.0 - FLATTENING);
+        let (n2, n3) = (n * n, n * n * n);
+
+        Self {
+            n,
+            a: SEMI_MAJOR_AXIS / (1.0 + n) * (1.0 + n2 / 4.0 + n2 * n2 / 64.0),
+            alpha: [
+                n / 2.0 - 2.0 / 3.0 * n2 + 5.0 / 16.0 * n3,
+                13.0 / 48.0 * n2 - 3.0 / 5.0 * n3,
+                61.0 / 240.0 * n3,
+            ],
+            beta: [
+                n / 2.0 - 2.0 / 3.0 * n2 + 37.0 / 96.0 * n3,
+                1.0 / 48.0 * n2 + 1.0 / 15.0 * n3,
+                17.0 / 480.0 * n3,
+            ],
+            delta: [
+                2.0 * n - 2.0 / 3.0 * n2 - 2.0 * n3,
+                7.0 / 3.0 * n2 - 8.0 / 5.0 * n3,
+                56.0 / 15.0 * n3,
+            ],
+        }
+    }
+}
+
+fn central_meridian(zone: u32) -> f64 {
+    (zone as f64 * 6.0 - 183.0).to_radians()
+}
+
+fn geographic_to_utm(zone: u32, (longitude, latitude): (f64, f64)) -> (f64, f64) {
+    let tm = TransverseMercator::new();
+    let latitude = latitude.to_radians();
+    let longitude = longitude.to_radians() - central_meridian(zone);
+
+    let e = 2.0 * tm.n.sqrt() / (1.0 + tm.n);
+    let t = (latitude.sin().atanh() - e * (e * latitude.sin()).atanh()).sinh();
+    let xi = (t / longitude.cos()).atan();
+    let eta = (longitude.sin() / (1.0 + t * t).sqrt()).atanh();
+
+    let mut easting = eta;
+    let mut northing = xi;
+
+    for (j, alpha) in tm.alpha.iter().enumerate() {
+        let j = 2.0 * (j + 1) as f64;
+        easting += alpha * (j * xi).cos() * (j * eta).sinh();
+        northing += alpha * (j * xi).sin() * (j * eta).cosh();
+    }
+
+    (
+        FALSE_EASTING + SCALE * tm.a * easting,
+        SCALE * tm.a * northing,
+    )
+}
+
+fn utm_to_geographic(zone: u32, (easting, northing): (f64, f64)) -> (f64, f64) {
+    let tm = TransverseMercator::new();
+    let xi = northing / (SCALE * tm.a);
+    let eta = (easting - FALSE_EASTING) / (SCALE * tm.a);
+
+    let mut xi_prime = xi;
+    let mut eta_prime = eta;
+
+    for (j, beta) in tm.beta.iter().enumerate() {
+        let j = 2.0 * (j + 1) as f64;
+        xi_prime -= beta * (j * xi).sin() * (j * eta).cosh();
+        eta_prime -= beta * (j * xi).cos() * (j * eta).sinh();
+    }
+
+    let chi = (xi_prime.sin() / eta_prime.cosh()).asin();
+
+    let mut latitude = chi;
+
+    for (j, delta) in tm.delta.iter().enumerate() {
+        latitude += delta * (2.0 * (j + 1) as f64 * chi).sin();
+    }
+
+    let longitude = central_meridian(zone) + (eta_prime.sinh() / xi_prime.cos()).atan();
+
+    (longitude.to_degrees(), latitude.to_degrees())
+}
+
+// The Swiss projection is evaluated with the approximate formulas published by swisstopo,
+// which are accurate to about one meter.
+// https://www.swisstopo.admin.ch/en/maps-data-online/calculation-services.html
+
+fn geographic_to_lv95((longitude, latitude): (f64, f64)) -> (f64, f64) {
+    // the auxiliary values are in units of 10000 arc seconds
+    let phi = (latitude * 3600.0 - 169_028.66) / 10_000.0;
+    let lambda = (longitude * 3600.0 - 26_782.5) / 10_000.0;
+
+    let easting = 2_600_072.37 + 211_455.93 * lambda
+        - 10_938.51 * lambda * phi
+        - 0.36 * lambda * phi * phi
+        - 44.54 * lambda.powi(3);
+    let northing = 1_200_147.07 + 308_807.95 * phi + 3_745.25 * lambda * lambda + 76.63 * phi * phi
+        - 194.56 * lambda * lambda * phi
+        + 119.79 * phi.powi(3);
+
+    (easting, northing)
+}
+
+fn lv95_to_geographic((easting, northing): (f64, f64)) -> (f64, f64) {
+    // the auxiliary values are in units of 1000 km
+    let y = (easting - 2_600_000.0) / 1_000_000.0;
+    let x = (northing - 1_200_000.0) / 1_000_000.0;
+
+    let lambda =
+        2.677_909_4 + 4.728_982 * y + 0.791_484 * y * x + 0.130_6 * y * x * x - 0.043_6 * y.powi(3);
+    let phi = 16.902_389_2 + 3.238_272 * x
+        - 0.270_978 * y * y
+        - 0.002_528 * x * x
+        - 0.044_7 * y * y * x
+        - 0.014_0 * x.powi(3);
+
+    // converts from units of 10000 arc seconds into degrees
+    (lambda * 100.0 / 36.0, phi * 100.0 / 36.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: (f64, f64), expected: (f64, f64), tolerance: f64) {
+        assert!(
+            (actual.0 - expected.0).abs() <= tolerance
+                && (actual.1 - expected.1).abs() <= tolerance,
+            "{actual:?} differs from {expected:?} by more than {tolerance}"
+        );
+    }
+
+    /// Converts degrees, minutes and seconds into degrees.
+    fn degrees(degrees: f64, minutes: f64, seconds: f64) -> f64 {
+        degrees + minutes / 60.0 + seconds / 3600.0
+    }
+
+    #[test]
+    fn utm_round_trip() {
+        let utm = Projection::from_epsg(25833).unwrap();
+        assert_eq!(utm, Projection::Utm { zone: 33 });
+
+        for point in [
+            (500_000.0, 0.0),
+            (389_918.0, 5_819_699.0),
+            (710_000.0, 6_100_000.0),
+        ] {
+            let geographic = utm.to_geographic(point);
+            assert_close(utm.from_geographic(geographic), point, 0.01);
+        }
+    }
+
+    #[test]
+    fn utm_known_points() {
+        let utm = Projection::from_epsg(25833).unwrap();
+
+        // on the central meridian, the northing is the scaled meridian arc of GRS80
+        assert_close(utm.from_geographic((15.0, 0.0)), (500_000.0, 0.0), 0.001);
+        assert_close(
+            utm.from_geographic((15.0, 52.0)),
+            (500_000.0, 5_761_038.212),
+            0.01,
+        );
+        // three degrees west of the central meridian (sixth order Krüger series)
+        assert_close(
+            utm.from_geographic((12.0, 51.0)),
+            (289_511.143, 5_654_109.179),
+            0.01,
+        );
+        assert_close(
+            utm.to_geographic((289_511.143, 5_654_109.179)),
+            (12.0, 51.0),
+            1e-7,
+        );
+    }
+
+    #[test]
+    fn lv95_round_trip() {
+        let lv95 = Projection::from_epsg(2056).unwrap();
+        assert_eq!(lv95, Projection::Lv95);
+
+        for point in [
+            (2_600_000.0, 1_200_000.0),
+            (2_700_000.0, 1_100_000.0),
+            (2_500_000.0, 1_250_000.0),
+        ] {
+            // both directions are only accurate to about one meter
+            let geographic = lv95.to_geographic(point);
+            assert_close(lv95.from_geographic(geographic), point, 2.5);
+        }
+    }
+
+    #[test]
+    fn lv95_known_points() {
+        let lv95 = Projection::from_epsg(2056).unwrap();
+
+        // the example of the approximate formulas published by swisstopo
+        let geographic = (degrees(8.0, 43.0, 49.79), degrees(46.0, 2.0, 38.87));
+        assert_close(
+            lv95.from_geographic(geographic),
+            (2_700_000.0, 1_100_000.0),
+            1.0,
+        );
+        assert_close(
+            lv95.to_geographic((2_700_000.0, 1_100_000.0)),
+            geographic,
+            1e-5,
+        );
+    }
+}
@@ -0,0 +1,295 @@
+use crate::{ImageGray, NODATA};
+use anyhow::{anyhow, Result};
+use image::{io::Reader, DynamicImage, Rgb, RgbImage};
+use itertools::iproduct;
+use std::io::{BufRead, BufReader, Cursor, Read};
+use terrain_settings::LayerKind;
+use tiff::{
+    decoder::{Decoder, DecodingResult},
+    tags::Tag,
+};
+use zip::ZipArchive;
+
+/// A layer of a tile, which is downloaded from a single url.
+#[derive(Clone, Debug)]
+pub(crate) struct TileLayer {
+    /// The name of the layer, which is the name of its source directory (e.g. `dtm`).
+    pub(crate) name: String,
+    pub(crate) url: String,
+    pub(crate) kind: LayerKind,
+}
+
+impl TileLayer {
+    pub(crate) fn new(name: &str, url: String, kind: LayerKind) -> Self {
+        Self {
+            name: name.to_string(),
+            url,
+            kind,
+        }
+    }
+
+    /// The extension of the output tile.
+    pub(crate) fn extension(&self) -> &str {
+        match self.kind {
+            LayerKind::Height => "dtm",
+            LayerKind::Albedo => "qoi",
+        }
+    }
+}
+
+/// A tile of a dataset, consisting of all its layers.
+#[derive(Clone, Debug)]
+pub(crate) struct Tile {
+    /// The coordinates of the tile in the coordinate units of the dataset.
+    /// The y coordinate increases northwards.
+    pub(crate) coordinates: (u32, u32),
+    pub(crate) layers: Vec<TileLayer>,
+}
+
+impl Tile {
+    /// The position of the tile in the grid of its dataset, which starts at the north-western tile.
+    pub(crate) fn position(&self, origin: (u32, u32), extent: u32) -> (u32, u32) {
+        (
+            (self.coordinates.0 - origin.0) / extent,
+            (origin.1 - self.coordinates.1) / extent,
+        )
+    }
+}
+
+/// Provides the tiles of a dataset and decodes their downloaded layers.
+pub(crate) trait Provider: Send + Sync {
+    /// Lists all tiles of the dataset.
+    fn gather_tiles(&self) -> Result<Vec<Tile>>;
+
+    /// The extent of a tile in coordinate units, which is the spacing between the
+    /// coordinates of adjacent tiles.
+    fn tile_extent(&self) -> u32;
+
+    /// The size of a coordinate unit in the units of the projection.
+    /// The coordinates of a tile are those of its south-western corner.
+    fn coordinate_unit(&self) -> f64 {
+        1000.0
+    }
+
+    /// The EPSG code of the projection of the dataset, if it is known.
+    fn epsg(&self) -> Option<u32>;
+
+    /// The height, that corresponds to the maximum value of the decoded height layers.
+    fn max_height(&self, height: f32) -> f32 {
+        height
+    }
+
+    /// Decodes the downloaded data of the layer of the tile.
+    /// Height layers are decoded into 16 bit grayscale images scaled by the height
+    /// and albedo layers into RGB images.
+    fn decode(
+        &self,
+        tile: &Tile,
+        layer: &TileLayer,
+        data: Vec<u8>,
+        height: f32,
+    ) -> Result<DynamicImage>;
+}
+
+/// Extracts the first file with the extension from the zip archive.
+pub(crate) fn unzip(buffer: Vec<u8>, extension: &str) -> Result<Vec<u8>> {
+    let mut archive = ZipArchive::new(Cursor::new(buffer))?;
+
+    let file_name = archive
+        .file_names()
+        .find(|name| name.ends_with(extension))
+        .ok_or(anyhow!("File not found in archive."))?
+        .to_string();
+
+    let mut file = archive.by_name(&file_name)?;
+
+    let mut buffer = Vec::new();
+    file.read_to_end(&mut buffer)?;
+
+    Ok(buffer)
+}
+
+/// Parses an ASCII grid of `x y z` points with the given size (in pixels) and spacing (in meters),
+/// whose south-western corner lies at the origin (in meters).
+/// Points outside of the grid are skipped.
+/// The TIFF tag, in which GDAL declares the nodata value of an image as text.
+const GDAL_NODATA: u16 = 42113;
+
+/// Scales the height in meters to the value of a height layer.
+/// Valid heights stay below the reserved [`NODATA`] value.
+pub(crate) fn encode_height(height: f32, max_height: f32) -> u16 {
+    (height / max_height * u16::MAX as f32)
+        .round()
+        .clamp(0.0, (NODATA - 1) as f32) as u16
+}
+
+pub(crate) fn parse_xyz(
+    buffer: Vec<u8>,
+    origin: (f64, f64),
+    size: u32,
+    spacing: f64,
+    max_height: f32,
+) -> Result<DynamicImage> {
+    let dimension = size as usize;
+    // the points missing from the file have no data
+    let mut data = vec![NODATA; dimension * dimension];
+
+    let reader = BufReader::new(Cursor::new(buffer));
+
+    for line in reader.lines() {
+        let line = line?;
+        let coordinates = line
+            .split_whitespace()
+            .map(|value| value.parse::<f64>())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if coordinates.len() < 3 {
+            continue;
+        }
+
+        let x = ((coordinates[0] - origin.0) / spacing).floor();
+        let y = ((coordinates[1] - origin.1) / spacing).floor();
+
+        if x < 0.0 || y < 0.0 || x >= size as f64 || y >= size as f64 {
+            continue;
+        }
+
+        let (x, y) = (x as usize, dimension - 1 - y as usize);
+        data[y * dimension + x] = encode_height(coordinates[2] as f32, max_height);
+    }
+
+    Ok(DynamicImage::from(
+        ImageGray::from_vec(size, size, data)
+            .ok_or(anyhow!("Could not create image from the parsed data."))?,
+    ))
+}
+
+/// Parses a TIFF image storing floating point heights.
+pub(crate) fn parse_tiff_height(buffer: Vec<u8>, max_height: f32) -> Result<DynamicImage> {
+    let reader = BufReader::new(Cursor::new(buffer));
+    let mut decoder = Decoder::new(reader)?;
+    let (width, height) = decoder.dimensions()?;
+
+    let nodata = decoder
+        .get_tag_ascii_string(Tag::Unknown(GDAL_NODATA))
+        .ok()
+        .and_then(|value| value.trim_matches(char::from(0)).trim().parse::<f32>().ok());
+
+    if let DecodingResult::F32(data) = decoder.read_image()? {
+        let data = data
+            .into_iter()
+            .map(|value| {
+                if !value.is_finite() || Some(value) == nodata {
+                    NODATA
+                } else {
+                    encode_height(value, max_height)
+                }
+            })
+            .collect();
+        Ok(DynamicImage::from(
+            ImageGray::from_raw(width, height, data)
+                .ok_or(anyhow!("Could not create image from the parsed data."))?,
+        ))
+    } else {
+        Err(anyhow!("Incompatible image format."))
+    }
+}
+
+/// Parses a TIFF image storing RGB colors, including YCbCr encoded ones.
+pub(crate) fn parse_tiff_albedo(buffer: Vec<u8>) -> Result<DynamicImage> {
+    let reader = BufReader::new(Cursor::new(buffer));
+    let mut decoder = Decoder::new(reader)?;
+    let (width, height) = decoder.dimensions()?;
+
+    if let DecodingResult::U8(data) = decoder.read_image()? {
+        Ok(DynamicImage::from(
+            RgbImage::from_raw(width, height, data)
+                .ok_or(anyhow!("Could not create image from the parsed data."))?,
+        ))
+    } else {
+        Err(anyhow!("Incompatible image format."))
+    }
+}
+
+/// Parses an image storing RGB colors in any format supported by the image crate.
+pub(crate) fn parse_image_albedo(buffer: Vec<u8>) -> Result<DynamicImage> {
+    let mut reader = Reader::new(Cursor::new(buffer)).with_guessed_format()?;
+    reader.no_limits();
+
+    Ok(DynamicImage::from(reader.decode()?.into_rgb8()))
+}
+
+/// Downscales the albedo by averaging the colors of `factor` x `factor` pixels.
+pub(crate) fn downscale(image: DynamicImage, factor: u32) -> DynamicImage {
+    if factor <= 1 {
+        return image;
+    }
+
+    let image = image.into_rgb8();
+    let size = (image.width() / factor, image.height() / factor);
+    let count = (factor * factor) as f32;
+
+    let mut downscaled_image = RgbImage::new(size.0, size.1);
+
+    for (x, y) in iproduct!(0..size.0, 0..size.1) {
+        let mut color = [0.0; 3];
+
+        for (dx, dy) in iproduct!(0..factor, 0..factor) {
+            let pixel = image.get_pixel(x * factor + dx, y * factor + dy).0;
+
+            for (channel, value) in color.iter_mut().zip(pixel) {
+                *channel += value as f32;
+            }
+        }
+
+        let pixel = Rgb(color.map(|channel| (channel / count) as u8));
+        downscaled_image.put_pixel(x, y, pixel);
+    }
+
+    DynamicImage::from(downscaled_image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tiff::encoder::{colortype, TiffEncoder};
+
+    #[test]
+    fn points_outside_of_the_grid_are_skipped() {
+        let buffer = b"0 0 100\n1 1 200\n-1 0 300\n2 1 400\n0 2 500\n".to_vec();
+        let image = parse_xyz(buffer, (0.0, 0.0), 2, 1.0, 1000.0).unwrap();
+        let image = image.as_luma16().unwrap();
+
+        assert_eq!(image.get_pixel(0, 1).0[0], encode_height(100.0, 1000.0));
+        assert_eq!(image.get_pixel(1, 0).0[0], encode_height(200.0, 1000.0));
+        assert_eq!(image.get_pixel(0, 0).0[0], NODATA);
+        assert_eq!(image.get_pixel(1, 1).0[0], NODATA);
+    }
+
+    #[test]
+    fn zero_heights_are_valid() {
+        let buffer = b"0 0 0\n".to_vec();
+        let image = parse_xyz(buffer, (0.0, 0.0), 1, 1.0, 1000.0).unwrap();
+
+        assert_eq!(image.as_luma16().unwrap().get_pixel(0, 0).0[0], 0);
+    }
+
+    #[test]
+    fn declared_tiff_nodata_is_marked() {
+        let mut buffer = Cursor::new(Vec::new());
+        let mut encoder = TiffEncoder::new(&mut buffer).unwrap();
+        let mut image = encoder.new_image::<colortype::Gray32Float>(2, 1).unwrap();
+        image
+            .encoder()
+            .write_tag(Tag::Unknown(GDAL_NODATA), "-9999")
+            .unwrap();
+        image.write_data(&[-9999.0, 0.0]).unwrap();
+
+        let image = parse_tiff_height(buffer.into_inner(), 1000.0).unwrap();
+        let image = image.as_luma16().unwrap();
+
+        assert_eq!(image.get_pixel(0, 0).0[0], NODATA);
+        assert_eq!(image.get_pixel(1, 0).0[0], 0);
+    }
+}
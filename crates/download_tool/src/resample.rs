@@ -0,0 +1,753 @@
+use crate::{
+    load_tile, output_path,
+    projection::{transform, Projection},
+    provider::{encode_height, Provider, Tile},
+    save_albedo, save_height, ImageGray, NODATA,
+};
+use anyhow::{anyhow, Result};
+use futures::StreamExt;
+use image::{DynamicImage, Rgb, RgbImage};
+use indicatif::{ProgressBar, ProgressStyle};
+use itertools::iproduct;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use terrain_settings::{LayerKind, TargetGrid};
+
+/// The spacing (in texels) of the lattice, on which the projection is evaluated exactly.
+/// In between, the projected coordinates are interpolated bilinearly.
+const LATTICE_SPACING: u32 = 16;
+
+/// The maximum number of samples per axis, that are averaged when a source is minified.
+const MAX_SAMPLES: u32 = 8;
+
+/// A dataset, whose tiles have been downloaded into its own directory.
+pub(crate) struct SourceTiles {
+    pub(crate) name: String,
+    pub(crate) priority: i32,
+    pub(crate) provider: Arc<dyn Provider>,
+    pub(crate) tiles: Vec<Tile>,
+    pub(crate) path: String,
+    /// The coordinates of the north-western tile, relative to which the tiles are positioned.
+    pub(crate) origin: (u32, u32),
+}
+
+impl SourceTiles {
+    /// The name of the source and the coordinates of the tile, which identify it in the failure list.
+    pub(crate) fn tile_id(&self, tile: &Tile) -> String {
+        format!(
+            "{}/{}_{}",
+            self.name, tile.coordinates.0, tile.coordinates.1
+        )
+    }
+}
+
+/// A layer of a source, whose tiles are indexed by their position in the tile grid.
+struct SourceLayer {
+    kind: LayerKind,
+    projection: Projection,
+    max_height: f32,
+    /// The extent of a tile in the units of the projection.
+    tile_extent: f64,
+    /// The size of a texel in the units of the projection.
+    texel_size: f64,
+    /// The resolution of a tile in texels.
+    resolution: u32,
+    /// The south-western corner of the tile grid.
+    anchor: (f64, f64),
+    tiles: HashMap<(i64, i64), String>,
+}
+
+impl SourceLayer {
+    fn new(
+        source: &SourceTiles,
+        name: &str,
+        kind: LayerKind,
+        target: Projection,
+        height: f32,
+    ) -> Result<Option<Self>> {
+        let provider = &source.provider;
+        let projection = provider
+            .epsg()
+            .map(Projection::from_epsg)
+            .transpose()?
+            .unwrap_or(target);
+        let unit = provider.coordinate_unit();
+        let tile_extent = provider.tile_extent() as f64 * unit;
+
+        let mut anchor = None;
+        let mut tiles = HashMap::new();
+
+        for tile in &source.tiles {
+            let layer = match tile.layers.iter().find(|layer| layer.name == name) {
+                Some(layer) => layer,
+                None => continue,
+            };
+
+            let path = output_path(
+                &source.path,
+                name,
+                tile.position(source.origin, provider.tile_extent()),
+                layer.extension(),
+            );
+
+            // tiles, that failed to download, are left out
+            if !Path::new(&path).exists() {
+                continue;
+            }
+
+            let corner = (
+                tile.coordinates.0 as f64 * unit,
+                tile.coordinates.1 as f64 * unit,
+            );
+            let anchor = *anchor.get_or_insert(corner);
+            let key = (
+                ((corner.0 - anchor.0) / tile_extent).round() as i64,
+                ((corner.1 - anchor.1) / tile_extent).round() as i64,
+            );
+
+            tiles.insert(key, path);
+        }
+
+        let anchor = match anchor {
+            Some(anchor) => anchor,
+            None => return Ok(None),
+        };
+
+        // all tiles of a layer share the same resolution
+        let resolution = load_tile(tiles.values().next().unwrap(), kind)?.width();
+
+        Ok(Some(Self {
+            kind,
+            projection,
+            max_height: provider.max_height(height),
+            tile_extent,
+            texel_size: tile_extent / resolution as f64,
+            resolution,
+            anchor,
+            tiles,
+        }))
+    }
+
+    /// The bounds of all tiles in the target projection.
+    fn bounds(&self, target: Projection) -> (f64, f64, f64, f64) {
+        let mut bounds = (f64::MAX, f64::MAX, f64::MIN, f64::MIN);
+
+        // the edges of the tiles are sampled, since they are curved in the target projection
+        let steps = 4;
+
+        for (&(x, y), (i, j)) in iproduct!(self.tiles.keys(), iproduct!(0..=steps, 0..=steps)) {
+            let point = (
+                self.anchor.0 + (x as f64 + i as f64 / steps as f64) * self.tile_extent,
+                self.anchor.1 + (y as f64 + j as f64 / steps as f64) * self.tile_extent,
+            );
+            let point = transform(self.projection, target, point);
+
+            bounds = (
+                bounds.0.min(point.0),
+                bounds.1.min(point.1),
+                bounds.2.max(point.0),
+                bounds.3.max(point.1),
+            );
+        }
+
+        bounds
+    }
+
+    /// The key of the tile and the texel within it, that contain the global texel.
+    /// The global texels are counted eastwards and northwards from the anchor.
+    fn locate(&self, x: i64, y: i64) -> ((i64, i64), (u32, u32)) {
+        let resolution = self.resolution as i64;
+        let key = (x.div_euclid(resolution), y.div_euclid(resolution));
+        let texel = (
+            x.rem_euclid(resolution) as u32,
+            (resolution - 1 - y.rem_euclid(resolution)) as u32,
+        );
+
+        (key, texel)
+    }
+
+    /// Reads the texel, which is `None` where the source has no data.
+    /// Heights are returned in meters.
+    fn texel(&self, image: &DynamicImage, (x, y): (u32, u32)) -> Option<[f32; 3]> {
+        match self.kind {
+            LayerKind::Height => {
+                let value = image.as_luma16()?.get_pixel(x, y).0[0];
+                (value != NODATA)
+                    .then(|| [value as f32 / u16::MAX as f32 * self.max_height, 0.0, 0.0])
+            }
+            LayerKind::Albedo => Some(image.as_rgb8()?.get_pixel(x, y).0.map(|value| value as f32)),
+        }
+    }
+}
+
+/// Samples a source layer for the texels of a target tile.
+struct SourceSampler<'a> {
+    layer: &'a SourceLayer,
+    /// The coordinates of the lattice points in the projection of the source.
+    lattice: Vec<(f64, f64)>,
+    lattice_size: usize,
+    /// The number of samples per axis, that are averaged for each target texel.
+    samples: u32,
+    cache: HashMap<(i64, i64), Option<DynamicImage>>,
+}
+
+impl<'a> SourceSampler<'a> {
+    fn new(layer: &'a SourceLayer, target: &TargetTile) -> Self {
+        let lattice_size = (target.size / LATTICE_SPACING + 2) as usize;
+
+        let lattice = iproduct!(0..lattice_size, 0..lattice_size)
+            .map(|(j, i)| {
+                let point = target.world(
+                    (i as u32 * LATTICE_SPACING) as f64,
+                    (j as u32 * LATTICE_SPACING) as f64,
+                );
+                transform(target.projection, layer.projection, point)
+            })
+            .collect::<Vec<_>>();
+
+        // the footprint of a target texel in source texels determines the filter size
+        let distance =
+            |a: (f64, f64), b: (f64, f64)| ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt();
+        let footprint = distance(lattice[0], lattice[1])
+            .max(distance(lattice[0], lattice[lattice_size]))
+            / LATTICE_SPACING as f64
+            / layer.texel_size;
+        let samples = (footprint.ceil() as u32).clamp(1, MAX_SAMPLES);
+
+        Self {
+            layer,
+            lattice,
+            lattice_size,
+            samples,
+            cache: HashMap::new(),
+        }
+    }
+
+    /// Interpolates the source coordinates of the target texel position.
+    fn project(&self, x: f64, y: f64) -> (f64, f64) {
+        let (x, y) = (x / LATTICE_SPACING as f64, y / LATTICE_SPACING as f64);
+        let (i, j) = (x.floor() as usize, y.floor() as usize);
+        let (i, j) = (i.min(self.lattice_size - 2), j.min(self.lattice_size - 2));
+        let (u, v) = (x - i as f64, y - j as f64);
+
+        let point = |i: usize, j: usize| self.lattice[j * self.lattice_size + i];
+        let lerp =
+            |a: (f64, f64), b: (f64, f64), t: f64| (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t);
+
+        lerp(
+            lerp(point(i, j), point(i + 1, j), u),
+            lerp(point(i, j + 1), point(i + 1, j + 1), u),
+            v,
+        )
+    }
+
+    /// The tiles of the source, that may be sampled for the target tile.
+    fn footprint_tiles(&self) -> Vec<&'a String> {
+        let layer = self.layer;
+        let (min, max) = self.lattice.iter().fold(
+            ((f64::MAX, f64::MAX), (f64::MIN, f64::MIN)),
+            |(min, max), point| {
+                (
+                    (min.0.min(point.0), min.1.min(point.1)),
+                    (max.0.max(point.0), max.1.max(point.1)),
+                )
+            },
+        );
+        let key = |point: (f64, f64)| {
+            (
+                ((point.0 - layer.anchor.0) / layer.tile_extent).floor() as i64,
+                ((point.1 - layer.anchor.1) / layer.tile_extent).floor() as i64,
+            )
+        };
+        let (min, max) = (key(min), key(max));
+
+        iproduct!(min.0..=max.0, min.1..=max.1)
+            .filter_map(|key| layer.tiles.get(&key))
+            .collect()
+    }
+
+    fn fetch(&mut self, x: i64, y: i64) -> Option<[f32; 3]> {
+        let layer = self.layer;
+        let (key, texel) = layer.locate(x, y);
+
+        let image = self
+            .cache
+            .entry(key)
+            .or_insert_with(|| {
+                let path = layer.tiles.get(&key)?;
+                load_tile(path, layer.kind).ok()
+            })
+            .as_ref()?;
+
+        layer.texel(image, texel)
+    }
+
+    /// Filters the source bilinearly at the point, ignoring texels without data.
+    fn bilinear(&mut self, (x, y): (f64, f64)) -> Option<([f32; 3], f32)> {
+        let layer = self.layer;
+        let x = (x - layer.anchor.0) / layer.texel_size - 0.5;
+        let y = (y - layer.anchor.1) / layer.texel_size - 0.5;
+        let (base_x, base_y) = (x.floor(), y.floor());
+        let (u, v) = ((x - base_x) as f32, (y - base_y) as f32);
+
+        let mut value = [0.0; 3];
+        let mut weight = 0.0;
+
+        for (dx, dy) in iproduct!(0..2, 0..2) {
+            let texel_weight =
+                (if dx == 0 { 1.0 - u } else { u }) * (if dy == 0 { 1.0 - v } else { v });
+
+            if texel_weight == 0.0 {
+                continue;
+            }
+
+            if let Some(texel) = self.fetch(base_x as i64 + dx, base_y as i64 + dy) {
+                for (channel, texel) in value.iter_mut().zip(texel) {
+                    *channel += texel * texel_weight;
+                }
+                weight += texel_weight;
+            }
+        }
+
+        (weight > 0.0).then_some((value, weight))
+    }
+
+    /// Averages the samples within the footprint of the target texel.
+    fn sample(&mut self, x: u32, y: u32) -> Option<[f32; 3]> {
+        let mut value = [0.0; 3];
+        let mut weight = 0.0;
+
+        for (i, j) in iproduct!(0..self.samples, 0..self.samples) {
+            let point = self.project(
+                x as f64 + (i as f64 + 0.5) / self.samples as f64,
+                y as f64 + (j as f64 + 0.5) / self.samples as f64,
+            );
+
+            if let Some((sample, sample_weight)) = self.bilinear(point) {
+                for (channel, sample) in value.iter_mut().zip(sample) {
+                    *channel += sample;
+                }
+                weight += sample_weight;
+            }
+        }
+
+        (weight > 0.0).then(|| value.map(|channel| channel / weight))
+    }
+}
+
+/// A tile of the target grid.
+struct TargetTile {
+    projection: Projection,
+    /// The north-western corner of the tile.
+    corner: (f64, f64),
+    texel_size: f64,
+    size: u32,
+    position: (u32, u32),
+}
+
+impl TargetTile {
+    /// The projected coordinates of the texel position, which is counted eastwards and southwards.
+    fn world(&self, x: f64, y: f64) -> (f64, f64) {
+        (
+            self.corner.0 + x * self.texel_size,
+            self.corner.1 - y * self.texel_size,
+        )
+    }
+}
+
+/// Reprojects and resamples the downloaded layers of all sources onto the target grid and
+/// merges them by priority.
+///
+/// The projection is evaluated on a coarse lattice and interpolated in between.
+/// Sources, that are coarser than the grid, are filtered bilinearly, while finer ones are
+/// averaged over the footprint of each target texel.
+/// Where sources overlap, the one with the highest priority is used, unless it has no data.
+pub(crate) async fn resample(
+    sources: &[SourceTiles],
+    grid: &TargetGrid,
+    tile_size: u32,
+    height: f32,
+    path: &str,
+    force: bool,
+    parallelism: usize,
+) -> Result<()> {
+    let target = Projection::from_epsg(grid.epsg)?;
+
+    let mut sources = sources.iter().collect::<Vec<_>>();
+    sources.sort_by_key(|source| -source.priority);
+
+    let mut layer_kinds = Vec::<(String, LayerKind)>::new();
+
+    for layer in sources
+        .iter()
+        .flat_map(|source| &source.tiles)
+        .flat_map(|tile| &tile.layers)
+    {
+        if !layer_kinds.iter().any(|(name, _)| name == &layer.name) {
+            layer_kinds.push((layer.name.clone(), layer.kind));
+        }
+    }
+
+    let mut layers = Vec::new();
+
+    for (name, kind) in layer_kinds {
+        let mut source_layers = Vec::new();
+
+        for source in &sources {
+            if let Some(layer) = SourceLayer::new(source, &name, kind, target, height)? {
+                source_layers.push(layer);
+            }
+        }
+
+        layers.push((name, kind, Arc::new(source_layers)));
+    }
+
+    // all layers share the same grid, so that their tiles line up
+    let bounds = layers
+        .iter()
+        .flat_map(|(_, _, source_layers)| source_layers.iter())
+        .map(|layer| layer.bounds(target))
+        .reduce(|a, b| (a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3)))
+        .ok_or(anyhow!("None of the sources have been downloaded."))?;
+
+    let origin = grid.origin.map_or((bounds.0, bounds.3), |[x, y]| (x, y));
+    let extent = tile_size as f64 * grid.texel_size;
+
+    let start = (
+        ((bounds.0 - origin.0) / extent).floor().max(0.0) as u32,
+        ((origin.1 - bounds.3) / extent).floor().max(0.0) as u32,
+    );
+    let end = (
+        ((bounds.2 - origin.0) / extent).ceil().max(0.0) as u32,
+        ((origin.1 - bounds.1) / extent).ceil().max(0.0) as u32,
+    );
+
+    let tasks = layers
+        .iter()
+        .flat_map(|(name, kind, source_layers)| {
+            iproduct!(start.0..end.0, start.1..end.1).map(move |position| {
+                let tile = TargetTile {
+                    projection: target,
+                    corner: (
+                        origin.0 + position.0 as f64 * extent,
+                        origin.1 - position.1 as f64 * extent,
+                    ),
+                    texel_size: grid.texel_size,
+                    size: tile_size,
+                    position,
+                };
+
+                (name.clone(), *kind, source_layers.clone(), tile)
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for (name, _, _) in &layers {
+        fs::create_dir_all(format!("{path}/{name}"))?;
+        fs::create_dir_all(format!("{}/{name}", inputs_path(path)))?;
+    }
+
+    println!("\nStarted resampling {} tiles.", tasks.len());
+    let bar = ProgressBar::new(tasks.len() as u64);
+    bar.set_style(
+        ProgressStyle::default_bar()
+            .template("{wide_bar} {pos}/{len} tiles resampled | Elapsed: {elapsed}, ETA: {eta}")?,
+    );
+    bar.tick();
+
+    let results =
+        futures::stream::iter(tasks.into_iter().map(|(name, kind, source_layers, tile)| {
+            let path = path.to_string();
+            let bar = bar.clone();
+
+            tokio::task::spawn_blocking(move || {
+                let result =
+                    resample_tile(&source_layers, &tile, &path, &name, kind, height, force);
+                bar.inc(1);
+                result
+            })
+        }))
+        .buffer_unordered(parallelism)
+        .collect::<Vec<_>>()
+        .await;
+
+    bar.finish();
+
+    for result in results {
+        result??;
+    }
+
+    Ok(())
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).ok()?.modified().ok()
+}
+
+/// Hashes everything the resampled tile depends on, i.e. the target grid, the source layers
+/// in the order of their priority and the modification times of their tiles.
+fn input_hash(samplers: &[SourceSampler], tile: &TargetTile, height: f32) -> String {
+    let mut hasher = Sha256::new();
+
+    hasher.update(format!(
+        "{:?} {:?} {} {} {height}\n",
+        tile.projection, tile.corner, tile.texel_size, tile.size
+    ));
+
+    for sampler in samplers {
+        let layer = sampler.layer;
+
+        hasher.update(format!(
+            "{:?} {} {} {:?}\n",
+            layer.projection, layer.max_height, layer.tile_extent, layer.anchor
+        ));
+
+        for path in sampler.footprint_tiles() {
+            let modified = modified(path)
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|duration| duration.as_nanos());
+
+            hasher.update(format!("{path} {modified:?}\n"));
+        }
+    }
+
+    format!("{:x}", hasher.finalize())
+}
+
+/// The directory of the input hashes, which is kept apart from the tiles,
+/// since the preprocessing reads every file of the tile directories.
+fn inputs_path(path: &str) -> String {
+    format!("{path}/.inputs")
+}
+
+/// Removes the output tile and its input hash, which are stale.
+fn remove_output(output: &str, hash_path: &str) {
+    let _ = fs::remove_file(output);
+    let _ = fs::remove_file(hash_path);
+}
+
+fn resample_tile(
+    source_layers: &[SourceLayer],
+    tile: &TargetTile,
+    path: &str,
+    name: &str,
+    kind: LayerKind,
+    height: f32,
+    force: bool,
+) -> Result<()> {
+    let extension = match kind {
+        LayerKind::Height => "dtm",
+        LayerKind::Albedo => "qoi",
+    };
+
+    let mut samplers = source_layers
+        .iter()
+        .map(|layer| SourceSampler::new(layer, tile))
+        .collect::<Vec<_>>();
+
+    let output = output_path(path, name, tile.position, extension);
+    let hash_path = output_path(&inputs_path(path), name, tile.position, "sha256");
+
+    if samplers
+        .iter()
+        .all(|sampler| sampler.footprint_tiles().is_empty())
+    {
+        remove_output(&output, &hash_path);
+        return Ok(());
+    }
+
+    // the tile is only resampled again, if its sources, their tiles or the grid have changed since
+    let hash = input_hash(&samplers, tile, height);
+
+    if !force && Path::new(&output).exists() {
+        if let Ok(previous) = fs::read_to_string(&hash_path) {
+            if previous.trim() == hash {
+                return Ok(());
+            }
+        }
+    }
+
+    let mut values = Vec::with_capacity((tile.size * tile.size) as usize);
+    let mut has_data = false;
+
+    for (y, x) in iproduct!(0..tile.size, 0..tile.size) {
+        let value = samplers.iter_mut().find_map(|sampler| sampler.sample(x, y));
+
+        has_data |= value.is_some();
+        values.push(value);
+    }
+
+    if !has_data {
+        remove_output(&output, &hash_path);
+        return Ok(());
+    }
+
+    match kind {
+        LayerKind::Height => {
+            let data = values
+                .iter()
+                .map(|value| match value {
+                    Some(value) => encode_height(value[0], height),
+                    None => NODATA,
+                })
+                .collect();
+            let image = ImageGray::from_raw(tile.size, tile.size, data)
+                .ok_or(anyhow!("Could not create image from the resampled data."))?;
+
+            save_height(&DynamicImage::from(image), path, name, tile.position)?;
+        }
+        LayerKind::Albedo => {
+            let mut image = RgbImage::new(tile.size, tile.size);
+
+            for (pixel, value) in image.pixels_mut().zip(values) {
+                let value = value.unwrap_or([0.0; 3]);
+                *pixel = Rgb(value.map(|channel| channel.round().clamp(0.0, 255.0) as u8));
+            }
+
+            save_albedo(&DynamicImage::from(image), path, name, tile.position)?;
+        }
+    }
+
+    // the hash is written last, so that interrupted writes are resampled again
+    fs::write(&hash_path, hash)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{provider::TileLayer, test_server::test_directory};
+
+    /// Heights are stored with a precision of one meter.
+    const HEIGHT: f32 = u16::MAX as f32;
+
+    /// A dataset in the grid projection consisting of a single 1 km tile.
+    struct TestProvider;
+
+    impl Provider for TestProvider {
+        fn gather_tiles(&self) -> Result<Vec<Tile>> {
+            unreachable!()
+        }
+
+        fn tile_extent(&self) -> u32 {
+            1
+        }
+
+        fn epsg(&self) -> Option<u32> {
+            Some(2056)
+        }
+
+        fn decode(&self, _: &Tile, _: &TileLayer, _: Vec<u8>, _: f32) -> Result<DynamicImage> {
+            unreachable!()
+        }
+    }
+
+    /// Creates a source, whose downloaded height tile consists of 2x2 texels.
+    fn test_source(directory: &str, name: &str, priority: i32, heights: [u16; 4]) -> SourceTiles {
+        let path = format!("{directory}/sources/{name}");
+        fs::create_dir_all(format!("{path}/dtm")).unwrap();
+
+        let image = ImageGray::from_raw(2, 2, heights.to_vec()).unwrap();
+        save_height(&DynamicImage::from(image), &path, "dtm", (0, 0)).unwrap();
+
+        SourceTiles {
+            name: name.to_string(),
+            priority,
+            provider: Arc::new(TestProvider),
+            tiles: vec![Tile {
+                coordinates: (0, 0),
+                layers: vec![TileLayer::new("dtm", String::new(), LayerKind::Height)],
+            }],
+            path,
+            origin: (0, 0),
+        }
+    }
+
+    /// A grid, whose single tile matches the tiles of the sources.
+    fn test_grid() -> TargetGrid {
+        TargetGrid {
+            epsg: 2056,
+            texel_size: 500.0,
+            origin: Some([0.0, 1000.0]),
+        }
+    }
+
+    /// Resamples the sources onto the grid and returns the heights.
+    async fn resample_heights(directory: &str, sources: &[SourceTiles]) -> Vec<u16> {
+        let path = format!("{directory}/source");
+
+        resample(sources, &test_grid(), 2, HEIGHT, &path, false, 1)
+            .await
+            .unwrap();
+
+        let tile = load_tile(&output_path(&path, "dtm", (0, 0), "dtm"), LayerKind::Height);
+        tile.unwrap().into_luma16().into_raw()
+    }
+
+    #[tokio::test]
+    async fn sources_are_merged_by_priority() {
+        let directory = test_directory("resample_priority");
+        let sources = [
+            test_source(&directory, "low", 0, [10, 20, 30, 40]),
+            test_source(&directory, "high", 1, [50, 60, 70, 80]),
+        ];
+
+        assert_eq!(
+            resample_heights(&directory, &sources).await,
+            [50, 60, 70, 80]
+        );
+    }
+
+    #[tokio::test]
+    async fn nodata_is_filled_by_lower_priorities() {
+        let directory = test_directory("resample_nodata");
+        let sources = [
+            test_source(&directory, "low", 0, [10, 20, NODATA, 40]),
+            test_source(&directory, "high", 1, [0, NODATA, NODATA, 80]),
+        ];
+
+        // genuine heights of 0 m are kept, while the holes of both sources remain
+        assert_eq!(
+            resample_heights(&directory, &sources).await,
+            [0, 20, NODATA, 80]
+        );
+    }
+
+    #[tokio::test]
+    async fn changed_inputs_invalidate_the_resampled_tiles() {
+        let directory = test_directory("resample_invalidation");
+        let path = format!("{directory}/source");
+        let output = output_path(&path, "dtm", (0, 0), "dtm");
+
+        let sources = [
+            test_source(&directory, "low", 0, [10, 20, 30, 40]),
+            test_source(&directory, "high", 1, [50, 60, 70, 80]),
+        ];
+        resample_heights(&directory, &sources).await;
+
+        // unchanged inputs are not resampled again
+        fs::write(&output, b"stale").unwrap();
+        resample(&sources, &test_grid(), 2, HEIGHT, &path, false, 1)
+            .await
+            .unwrap();
+        assert_eq!(fs::read(&output).unwrap(), b"stale");
+
+        // swapping the priorities changes the order of the sources
+        let sources = [
+            test_source(&directory, "low", 2, [10, 20, 30, 40]),
+            test_source(&directory, "high", 1, [50, 60, 70, 80]),
+        ];
+
+        assert_eq!(
+            resample_heights(&directory, &sources).await,
+            [10, 20, 30, 40]
+        );
+    }
+}
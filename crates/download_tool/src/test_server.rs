@@ -0,0 +1,157 @@
+//! A minimal HTTP server, which serves fixture files to the tests of the download tool.
+
+use crate::cache::DownloadCache;
+use std::{
+    collections::HashMap,
+    fs,
+    io::{Cursor, Write},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tiff::encoder::{colortype, TiffEncoder};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+/// The responses of each path, which are served in order, while the last one is repeated.
+type Responses = HashMap<String, Vec<(u16, Vec<u8>)>>;
+
+/// Serves the registered responses on a local port and counts the requests of each path.
+/// Paths without a response are answered with 404.
+pub(crate) struct TestServer {
+    /// The base url of the server (e.g. `http://127.0.0.1:1234`).
+    pub(crate) url: String,
+    responses: Arc<Mutex<Responses>>,
+    requests: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+impl TestServer {
+    pub(crate) async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        let responses = Arc::new(Mutex::new(Responses::new()));
+        let requests = Arc::new(Mutex::new(HashMap::new()));
+
+        {
+            let responses = responses.clone();
+            let requests = requests.clone();
+
+            tokio::spawn(async move {
+                while let Ok((mut stream, _)) = listener.accept().await {
+                    let responses = responses.clone();
+                    let requests = requests.clone();
+
+                    tokio::spawn(async move {
+                        let mut request = Vec::new();
+                        let mut buffer = [0; 1024];
+
+                        while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                            match stream.read(&mut buffer).await {
+                                Ok(0) | Err(_) => return,
+                                Ok(count) => request.extend_from_slice(&buffer[..count]),
+                            }
+                        }
+
+                        let request = String::from_utf8_lossy(&request);
+                        let path = request.split_whitespace().nth(1).unwrap_or("/");
+
+                        *requests
+                            .lock()
+                            .unwrap()
+                            .entry(path.to_string())
+                            .or_default() += 1;
+
+                        let (status, body) = match responses.lock().unwrap().get_mut(path) {
+                            Some(queue) if queue.len() > 1 => queue.remove(0),
+                            Some(queue) => queue[0].clone(),
+                            None => (404, Vec::new()),
+                        };
+
+                        let header = format!(
+                            "HTTP/1.1 {status} Fixture\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                            body.len()
+                        );
+
+                        let _ = stream.write_all(header.as_bytes()).await;
+                        let _ = stream.write_all(&body).await;
+                        let _ = stream.shutdown().await;
+                    });
+                }
+            });
+        }
+
+        Self {
+            url,
+            responses,
+            requests,
+        }
+    }
+
+    /// Appends a response to the ones served for the path.
+    pub(crate) fn serve(&self, path: &str, status: u16, body: Vec<u8>) {
+        self.responses
+            .lock()
+            .unwrap()
+            .entry(path.to_string())
+            .or_default()
+            .push((status, body));
+    }
+
+    /// The number of requests of the path.
+    pub(crate) fn requests(&self, path: &str) -> usize {
+        self.requests
+            .lock()
+            .unwrap()
+            .get(path)
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+/// Creates an empty temporary directory for the test.
+pub(crate) fn test_directory(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("download_tool_{}_{name}", std::process::id()));
+
+    let _ = fs::remove_dir_all(&path);
+    fs::create_dir_all(&path).unwrap();
+
+    path.to_string_lossy().into_owned()
+}
+
+/// Creates a download cache in the directory, which bypasses the proxy of the environment
+/// and retries failed downloads without a noticeable backoff.
+pub(crate) fn test_cache(directory: &str, retries: u32) -> DownloadCache {
+    DownloadCache::with_client(
+        directory,
+        reqwest::Client::builder().no_proxy().build().unwrap(),
+        retries,
+        Duration::from_millis(1),
+    )
+    .unwrap()
+}
+
+/// Creates a zip archive containing a single file.
+pub(crate) fn zip_fixture(name: &str, content: &[u8]) -> Vec<u8> {
+    let mut archive = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+
+    archive.start_file(name, options).unwrap();
+    archive.write_all(content).unwrap();
+
+    archive.finish().unwrap().into_inner()
+}
+
+/// Creates a TIFF image storing RGB colors.
+pub(crate) fn tiff_fixture(width: u32, height: u32, data: &[u8]) -> Vec<u8> {
+    let mut buffer = Cursor::new(Vec::new());
+
+    TiffEncoder::new(&mut buffer)
+        .unwrap()
+        .write_image::<colortype::RGB8>(width, height, data)
+        .unwrap();
+
+    buffer.into_inner()
+}
@@ -0,0 +1,524 @@
+//! Inspects, converts and verifies the nodes of a preprocessed terrain.
+//!
+//! Usage: `terrain_inspect <terrain_directory> <command>`, where the terrain directory
+//! contains the `config.tc` file and the `data` directory.
+//!
+//! Commands:
+//! - `list [lod]` - lists the nodes of the terrain (of a single lod)
+//! - `stats` - prints the node count and the disk usage of each attachment per lod
+//! - `dump <attachment> <node> <output.png>` - saves a single node of an attachment as an image
+//! - `convert <attachment> <file_format> [mip_level_count]` - converts all nodes of an attachment
+//!   into another file format (tdf, png, tif, qoi or dtm)
+//! - `verify` - checks that every node has all attachments and decodes correctly
+//! - `export <lod> <min_x> <min_y> <max_x> <max_y> <height> <output.obj|output.glb> [albedo]` -
+//!   exports the region (in world units) as a triangulated mesh, where height is the height
+//!   of the terrain and albedo the attachment (e.g. `dop`), from which the texture is baked
+//! - `raster <attachment> <lod> <min_x> <min_y> <max_x> <max_y> <output.tif|output.png> [options]` -
+//!   reassembles the region (in world units) of the attachment from its nodes, with the options
+//!   `--height <height>` (saves the height as 32 bit floating point GeoTIFF) and
+//!   `--georeference <easting> <northing> <epsg>` (the projected coordinate of the terrain origin)
+//!
+//! Nodes are specified either by their id or by their coordinate (`lod/x/y`).
+
+mod terrain;
+
+use crate::terrain::{attachment_format, parse_node_id, TerrainData};
+use anyhow::{anyhow, bail, ensure, Result};
+use bevy::math::{DVec2, UVec2};
+use bevy_terrain::{
+    prelude::*,
+    preprocess::{
+        export::{export_mesh, export_raster, GeoReference, MeshExportConfig, RasterExportConfig},
+        file_io::{load_image, save_image},
+    },
+    terrain_data::NodeCoordinate,
+};
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    panic::{self, catch_unwind},
+    path::Path,
+    process::ExitCode,
+};
+
+const USAGE: &str = "Usage: terrain_inspect <terrain_directory> <list [lod] | stats | dump <attachment> <node> <output.png> | convert <attachment> <file_format> [mip_level_count] | verify | export <lod> <min_x> <min_y> <max_x> <max_y> <height> <output.obj|output.glb> [albedo] | raster <attachment> <lod> <min_x> <min_y> <max_x> <max_y> <output.tif|output.png> [--height <height>] [--georeference <easting> <northing> <epsg>]>";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    let result = match args[..] {
+        [path, "list"] => list(path, None),
+        [path, "list", lod] => lod
+            .parse()
+            .map_err(Into::into)
+            .and_then(|lod| list(path, Some(lod))),
+        [path, "stats"] => stats(path),
+        [path, "dump", attachment, node, output] => dump(path, attachment, node, output),
+        [path, "convert", attachment, file_format] => convert(path, attachment, file_format, None),
+        [path, "convert", attachment, file_format, mip_level_count] => mip_level_count
+            .parse()
+            .map_err(Into::into)
+            .and_then(|count| convert(path, attachment, file_format, Some(count))),
+        [path, "verify"] => verify(path),
+        [path, "export", ref region @ .., output] if region.len() == 6 || region.len() == 7 => {
+            export(path, region, output)
+        }
+        [path, "raster", attachment, ref args @ ..] if args.len() >= 6 => {
+            raster(path, attachment, args)
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(error) => {
+            eprintln!("Error: {error}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn list(path: &str, lod: Option<u32>) -> Result<bool> {
+    let terrain = TerrainData::load(path)?;
+
+    for &node_id in &terrain.nodes {
+        let coordinate = NodeCoordinate::from(node_id);
+
+        if lod.is_some() && lod != Some(coordinate.lod) {
+            continue;
+        }
+
+        let attachments = terrain
+            .attachments
+            .iter()
+            .filter(|attachment| attachment.nodes.contains_key(&node_id))
+            .map(|attachment| attachment.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        println!(
+            "{node_id}\t{}/{}/{}\t{attachments}",
+            coordinate.lod, coordinate.x, coordinate.y
+        );
+    }
+
+    Ok(true)
+}
+
+fn stats(path: &str) -> Result<bool> {
+    let terrain = TerrainData::load(path)?;
+
+    let mut node_counts = BTreeMap::<u32, usize>::new();
+
+    for &node_id in &terrain.nodes {
+        *node_counts
+            .entry(NodeCoordinate::from(node_id).lod)
+            .or_default() += 1;
+    }
+
+    println!("Terrain {path} with {} nodes.", terrain.nodes.len());
+
+    for (lod, count) in &node_counts {
+        println!("lod {lod:>2}: {count} nodes");
+    }
+
+    for attachment in &terrain.attachments {
+        let mut usage = BTreeMap::<u32, (usize, u64)>::new();
+
+        for (&node_id, node) in &attachment.nodes {
+            let entry = usage.entry(NodeCoordinate::from(node_id).lod).or_default();
+            entry.0 += 1;
+            entry.1 += node.size;
+        }
+
+        let total = usage.values().map(|&(_, size)| size).sum::<u64>();
+
+        println!(
+            "\nAttachment {} with {} nodes and {}.",
+            attachment.name,
+            attachment.nodes.len(),
+            format_size(total)
+        );
+
+        for (lod, (count, size)) in &usage {
+            println!("lod {lod:>2}: {count} nodes, {}", format_size(*size));
+        }
+    }
+
+    Ok(true)
+}
+
+fn dump(path: &str, attachment: &str, node: &str, output: &str) -> Result<bool> {
+    let terrain = TerrainData::load(path)?;
+    let attachment = terrain.attachment(attachment)?;
+    let node_id = parse_node_id(node)?;
+
+    let image = attachment.load_node(node_id)?;
+    image.save(output)?;
+
+    println!(
+        "Saved the node {node_id} of the {} attachment ({}x{} {:?}) to {output}.",
+        attachment.name,
+        image.width(),
+        image.height(),
+        image.color()
+    );
+
+    Ok(true)
+}
+
+fn convert(
+    path: &str,
+    attachment: &str,
+    file_format: &str,
+    mip_level_count: Option<u32>,
+) -> Result<bool> {
+    let terrain = TerrainData::load(path)?;
+    let attachment = terrain.attachment(attachment)?;
+    let file_format = FileFormat::from_extension(file_format)
+        .ok_or(anyhow!("Unknown file format {file_format}."))?;
+
+    for (&node_id, node) in &attachment.nodes {
+        let image = attachment.load_node(node_id)?;
+
+        // the mip level count is stored in the header of a TDF file and can not be
+        // determined from the other formats
+        let mip_level_count = match (mip_level_count, node.file_format) {
+            (Some(mip_level_count), _) => mip_level_count,
+            (None, FileFormat::TDF) => tdf_mip_level_count(&node.path)?,
+            (None, _) if matches!(file_format, FileFormat::TDF) => {
+                bail!("Converting to TDF requires the mip level count of the attachment.")
+            }
+            (None, _) => 1,
+        };
+
+        let mut config = AttachmentConfig::new(
+            attachment.name.clone(),
+            image.width(),
+            0,
+            mip_level_count,
+            attachment_format(&image)?,
+        );
+        config.file_format = file_format;
+
+        // the encoders panic on unsupported formats
+        catch_unwind(|| save_image(&node.path, &image, &config)).map_err(|_| {
+            anyhow!(
+                "Could not save the node {node_id} of the {} attachment as {}.",
+                attachment.name,
+                file_format.extension()
+            )
+        })?;
+
+        if file_format.extension() != node.file_format.extension() {
+            fs::remove_file(Path::new(&node.path).with_extension(node.file_format.extension()))?;
+        }
+    }
+
+    println!(
+        "Converted {} nodes of the {} attachment to {}.",
+        attachment.nodes.len(),
+        attachment.name,
+        file_format.extension()
+    );
+
+    Ok(true)
+}
+
+fn verify(path: &str) -> Result<bool> {
+    let terrain = TerrainData::load(path)?;
+    let mut errors = 0;
+
+    // the decoders panic on corrupted files, which are reported instead
+    panic::set_hook(Box::new(|_| {}));
+
+    for attachment in &terrain.attachments {
+        // virtual textures are stored alongside the attachments, but use their own nodes
+        if !attachment
+            .nodes
+            .keys()
+            .any(|node_id| terrain.nodes.contains(node_id))
+        {
+            println!(
+                "Skipped the {} directory, whose nodes are not part of the node configuration.",
+                attachment.name
+            );
+            continue;
+        }
+
+        // attachments may only exist for a range of lods
+        let lods = attachment.lod_range().unwrap();
+        let mut texture_size = None;
+
+        for &node_id in &terrain.nodes {
+            let coordinate = NodeCoordinate::from(node_id);
+
+            if !lods.contains(&coordinate.lod) {
+                continue;
+            }
+
+            let node = match attachment.nodes.get(&node_id) {
+                Some(node) => node,
+                None => {
+                    println!(
+                        "The node {node_id} ({}/{}/{}) is missing the {} attachment.",
+                        coordinate.lod, coordinate.x, coordinate.y, attachment.name
+                    );
+                    errors += 1;
+                    continue;
+                }
+            };
+
+            let image = match catch_unwind(|| load_image(&node.path, node.file_format)) {
+                Ok(Some(image)) => image,
+                _ => {
+                    println!(
+                        "The node {node_id} of the {} attachment could not be decoded.",
+                        attachment.name
+                    );
+                    errors += 1;
+                    continue;
+                }
+            };
+
+            let size = *texture_size.get_or_insert(image.width());
+
+            if image.width() != size || image.height() != size {
+                println!(
+                    "The node {node_id} of the {} attachment has a size of {}x{} instead of {size}x{size}.",
+                    attachment.name,
+                    image.width(),
+                    image.height()
+                );
+                errors += 1;
+            }
+        }
+
+        for &node_id in attachment.nodes.keys() {
+            if !terrain.nodes.contains(&node_id) {
+                println!(
+                    "The node {node_id} of the {} attachment is not part of the node configuration.",
+                    attachment.name
+                );
+                errors += 1;
+            }
+        }
+    }
+
+    let _ = panic::take_hook();
+
+    println!(
+        "Verified {} nodes of {} attachments, found {errors} errors.",
+        terrain.nodes.len(),
+        terrain.attachments.len()
+    );
+
+    Ok(errors == 0)
+}
+
+fn export(path: &str, args: &[&str], output: &str) -> Result<bool> {
+    let terrain = TerrainData::load(path)?;
+    let numbers = args[..5]
+        .iter()
+        .map(|arg| arg.parse::<u32>())
+        .collect::<Result<Vec<_>, _>>()?;
+    let height = args[5].parse::<f32>()?;
+
+    let height_attachment = terrain.attachment("height")?.config()?;
+    let albedo_attachment = match args.get(6) {
+        Some(name) => Some(terrain.attachment(name)?.config()?),
+        None => None,
+    };
+
+    // the node directories are resolved relative to the assets folder, unless the path is absolute,
+    // and the node size of each lod is determined by the height attachment
+    let path = fs::canonicalize(path)?.to_string_lossy().into_owned();
+    let mut config = TerrainConfig::new(0, 0, height, 0, path);
+    config.leaf_node_size = height_attachment.center_size;
+    config.terrain_size = terrain
+        .attachment("height")?
+        .terrain_size(config.leaf_node_size);
+
+    let export = MeshExportConfig {
+        lod: numbers[0],
+        min: UVec2::new(numbers[1], numbers[2]),
+        max: UVec2::new(numbers[3], numbers[4]),
+        height_attachment,
+        albedo_attachment,
+    };
+
+    let mesh = export_mesh(&config, &export)?;
+    mesh.save(output)?;
+
+    println!(
+        "Exported {} vertices and {} triangles to {output}.",
+        mesh.positions.len(),
+        mesh.indices.len() / 3
+    );
+
+    Ok(true)
+}
+
+fn raster(path: &str, attachment: &str, args: &[&str]) -> Result<bool> {
+    let terrain = TerrainData::load(path)?;
+    let numbers = args[..5]
+        .iter()
+        .map(|arg| arg.parse::<u32>())
+        .collect::<Result<Vec<_>, _>>()?;
+    let output = args[5];
+
+    let mut height = None;
+    let mut georeference = None;
+
+    match args[6..] {
+        [] => {}
+        ["--height", value] => height = Some(value.parse::<f32>()?),
+        ["--georeference", easting, northing, epsg] => {
+            georeference = Some(GeoReference {
+                origin: DVec2::new(easting.parse()?, northing.parse()?),
+                unit_size: 1.0,
+                epsg: epsg.parse()?,
+            })
+        }
+        ["--height", value, "--georeference", easting, northing, epsg]
+        | ["--georeference", easting, northing, epsg, "--height", value] => {
+            height = Some(value.parse::<f32>()?);
+            georeference = Some(GeoReference {
+                origin: DVec2::new(easting.parse()?, northing.parse()?),
+                unit_size: 1.0,
+                epsg: epsg.parse()?,
+            })
+        }
+        _ => bail!("Invalid raster options, expected --height <height> and/or --georeference <easting> <northing> <epsg>."),
+    }
+
+    // the node size of each lod is determined by the height attachment
+    let path = fs::canonicalize(path)?.to_string_lossy().into_owned();
+    let height_attachment = terrain.attachment("height")?;
+    let mut config = TerrainConfig::new(0, 0, 0.0, 0, path);
+    config.leaf_node_size = height_attachment.config()?.center_size;
+    config.terrain_size = height_attachment.terrain_size(config.leaf_node_size);
+
+    let export = RasterExportConfig {
+        lod: numbers[0],
+        min: UVec2::new(numbers[1], numbers[2]),
+        max: UVec2::new(numbers[3], numbers[4]),
+        attachment: terrain.attachment(attachment)?.config()?,
+    };
+
+    let raster = export_raster(&config, &export)?;
+
+    match height {
+        Some(height) => raster.save_height(output, height, georeference.as_ref())?,
+        None => raster.save(output, georeference.as_ref())?,
+    }
+
+    println!(
+        "Exported the {}x{} raster of the {attachment} attachment to {output}.",
+        raster.image.width(),
+        raster.image.height()
+    );
+
+    Ok(true)
+}
+
+/// Reads the mip level count from the header of a TDF file.
+fn tdf_mip_level_count(path: &str) -> Result<u32> {
+    let header = fs::read(Path::new(path).with_extension(FileFormat::TDF.extension()))?;
+    ensure!(header.len() > 2, "The TDF file {path} is corrupted.");
+
+    Ok(header[2] as u32)
+}
+
+fn format_size(size: u64) -> String {
+    match size {
+        size if size >= 1 << 30 => format!("{:.2} GiB", size as f64 / (1u64 << 30) as f64),
+        size if size >= 1 << 20 => format!("{:.2} MiB", size as f64 / (1u64 << 20) as f64),
+        size if size >= 1 << 10 => format!("{:.2} KiB", size as f64 / (1u64 << 10) as f64),
+        size => format!("{size} B"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy_terrain::{
+        formats::tc::{TCAttachment, TC},
+        terrain_data::calc_node_id,
+    };
+    use image::{DynamicImage, ImageBuffer, Luma};
+
+    /// Creates a terrain of 2x2 nodes at lod 0, with a texture size of 8 and a border size of 2.
+    fn test_terrain(name: &str) -> String {
+        let path = env::temp_dir().join(format!("terrain_inspect_{}_{name}", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        let directory = format!("{path}/data/height");
+
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&directory).unwrap();
+
+        let mut nodes = Vec::new();
+
+        for (x, y) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let node_id = calc_node_id(0, x, y);
+            let image = ImageBuffer::from_fn(8, 8, |_, _| Luma([u16::MAX / 2]));
+
+            DynamicImage::ImageLuma16(image)
+                .save(format!("{directory}/{node_id}.png"))
+                .unwrap();
+            nodes.push(node_id);
+        }
+
+        let tc = TC {
+            nodes,
+            attachments: vec![TCAttachment {
+                name: "height".to_string(),
+                border_size: 2,
+            }],
+        };
+        tc.save_file(format!("{path}/config.tc")).unwrap();
+
+        path
+    }
+
+    #[test]
+    fn export_mesh_of_the_whole_terrain() {
+        let path = test_terrain("export");
+        let output = format!("{path}/mesh.obj");
+
+        // the region exceeds the terrain of 8x8 world units and is clamped to its last texel
+        let args = ["0", "0", "0", "16", "16", "10.0"];
+        assert!(export(&path, &args, &output).unwrap());
+
+        let mesh = fs::read_to_string(&output).unwrap();
+        let vertices: Vec<&str> = mesh.lines().filter(|line| line.starts_with("v ")).collect();
+        let triangles = mesh.lines().filter(|line| line.starts_with("f ")).count();
+
+        assert_eq!(vertices.len(), 8 * 8);
+        assert_eq!(triangles, 7 * 7 * 2);
+        assert_eq!(vertices.last().unwrap().split(' ').nth(1), Some("7"));
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn export_requires_the_border_size() {
+        let path = test_terrain("border");
+        TC {
+            nodes: vec![calc_node_id(0, 0, 0)],
+            attachments: vec![],
+        }
+        .save_file(format!("{path}/config.tc"))
+        .unwrap();
+
+        let args = ["0", "0", "0", "4", "4", "10.0"];
+        assert!(export(&path, &args, &format!("{path}/mesh.obj")).is_err());
+
+        fs::remove_dir_all(path).unwrap();
+    }
+}
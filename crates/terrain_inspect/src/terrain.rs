@@ -0,0 +1,237 @@
+//! Gathers the nodes of a preprocessed terrain from disk.
+
+use anyhow::{anyhow, ensure, Result};
+use bevy_terrain::{
+    formats::tc::TC,
+    prelude::*,
+    preprocess::file_io::load_image,
+    terrain_data::{calc_node_id, NodeCoordinate, NodeId},
+};
+use image::DynamicImage;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    ops::RangeInclusive,
+    path::Path,
+};
+
+/// A node of an attachment stored on disk.
+pub struct NodeFile {
+    /// The path to the node without its extension.
+    pub path: String,
+    pub file_format: FileFormat,
+    /// The size of the file in bytes.
+    pub size: u64,
+}
+
+/// The nodes of an attachment, which are stored in the `data/<name>` directory of the terrain.
+pub struct AttachmentData {
+    pub name: String,
+    /// The border size saved in the `config.tc` file, which is missing for terrains,
+    /// that have been preprocessed by older versions.
+    pub border_size: Option<u32>,
+    pub nodes: BTreeMap<NodeId, NodeFile>,
+}
+
+impl AttachmentData {
+    fn load(name: String, border_size: Option<u32>, directory: &Path) -> Result<Self> {
+        let mut nodes = BTreeMap::new();
+
+        for entry in fs::read_dir(directory)? {
+            let path = entry?.path();
+
+            // skips hidden and foreign files
+            let node_id = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(stem) => match stem.parse::<NodeId>() {
+                    Ok(node_id) => node_id,
+                    Err(_) => continue,
+                },
+                None => continue,
+            };
+            let file_format = match path
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .and_then(FileFormat::from_extension)
+            {
+                Some(file_format) => file_format,
+                None => continue,
+            };
+
+            let size = fs::metadata(&path)?.len();
+            let path = path.with_extension("").to_string_lossy().into_owned();
+
+            nodes.insert(
+                node_id,
+                NodeFile {
+                    path,
+                    file_format,
+                    size,
+                },
+            );
+        }
+
+        Ok(Self {
+            name,
+            border_size,
+            nodes,
+        })
+    }
+
+    /// The range of lods, for which the attachment stores nodes.
+    pub fn lod_range(&self) -> Option<RangeInclusive<u32>> {
+        let lods = self
+            .nodes
+            .keys()
+            .map(|&node_id| NodeCoordinate::from(node_id).lod);
+
+        let (min, max) = lods.fold((u32::MAX, u32::MIN), |(min, max), lod| {
+            (min.min(lod), max.max(lod))
+        });
+
+        (min <= max).then_some(min..=max)
+    }
+
+    /// The size of the terrain, that is covered by the nodes of the finest lod of the attachment,
+    /// where each node of lod 0 spans the leaf node size.
+    pub fn terrain_size(&self, leaf_node_size: u32) -> u32 {
+        let min_lod = self.lod_range().map_or(0, |lods| *lods.start());
+
+        self.nodes
+            .keys()
+            .map(|&node_id| NodeCoordinate::from(node_id))
+            .filter(|coordinate| coordinate.lod == min_lod)
+            .map(|coordinate| (coordinate.x.max(coordinate.y) + 1) * (leaf_node_size << min_lod))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Reconstructs the config of the attachment from its first node and the border size
+    /// saved in the `config.tc` file.
+    pub fn config(&self) -> Result<AttachmentConfig> {
+        let border_size = self.border_size.ok_or(anyhow!(
+            "The border size of the {} attachment is unknown, preprocess the terrain again.",
+            self.name
+        ))?;
+        let (&node_id, node) = self
+            .nodes
+            .iter()
+            .next()
+            .ok_or(anyhow!("The {} attachment has no nodes.", self.name))?;
+        let image = self.load_node(node_id)?;
+        let lods = self.lod_range().unwrap();
+
+        let mut config = AttachmentConfig::new(
+            self.name.clone(),
+            image.width(),
+            border_size,
+            1,
+            attachment_format(&image)?,
+        );
+        config.file_format = node.file_format;
+        config.min_lod = *lods.start();
+        config.max_lod = *lods.end();
+
+        Ok(config)
+    }
+
+    /// Loads the image of the node.
+    pub fn load_node(&self, node_id: NodeId) -> Result<DynamicImage> {
+        let node = self.nodes.get(&node_id).ok_or(anyhow!(
+            "The node {node_id} of the {} attachment does not exist.",
+            self.name
+        ))?;
+
+        load_image(&node.path, node.file_format).ok_or(anyhow!(
+            "Could not decode the node {node_id} of the {} attachment.",
+            self.name
+        ))
+    }
+}
+
+/// A preprocessed terrain consisting of the node configuration and all attachments on disk.
+pub struct TerrainData {
+    pub path: String,
+    /// The nodes stored in the `config.tc` file of the terrain.
+    pub nodes: BTreeSet<NodeId>,
+    pub attachments: Vec<AttachmentData>,
+}
+
+impl TerrainData {
+    /// Loads the terrain from its directory, which contains the `config.tc` file and
+    /// the `data` directory.
+    pub fn load(path: &str) -> Result<Self> {
+        let config_path = format!("{path}/config.tc");
+        let data_directory = format!("{path}/data");
+
+        ensure!(
+            Path::new(&config_path).exists(),
+            "The terrain {path} has not been preprocessed, {config_path} does not exist."
+        );
+
+        let tc = TC::load_file(&config_path)?;
+        let nodes = tc.nodes.into_iter().collect();
+
+        let mut attachments = Vec::new();
+
+        for entry in fs::read_dir(&data_directory)? {
+            let entry = entry?;
+
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let border_size = tc
+                .attachments
+                .iter()
+                .find(|attachment| attachment.name == name)
+                .map(|attachment| attachment.border_size);
+
+            attachments.push(AttachmentData::load(name, border_size, &entry.path())?);
+        }
+
+        attachments.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(Self {
+            path: path.to_string(),
+            nodes,
+            attachments,
+        })
+    }
+
+    pub fn attachment(&self, name: &str) -> Result<&AttachmentData> {
+        self.attachments
+            .iter()
+            .find(|attachment| attachment.name == name)
+            .ok_or(anyhow!(
+                "The terrain {} has no attachment named {name}.",
+                self.path
+            ))
+    }
+}
+
+/// Parses a node either from its id or from its coordinate in the form `lod/x/y`.
+pub fn parse_node_id(node: &str) -> Result<NodeId> {
+    let coordinate: Vec<&str> = node.split('/').collect();
+
+    match coordinate[..] {
+        [id] => Ok(id.parse()?),
+        [lod, x, y] => Ok(calc_node_id(lod.parse()?, x.parse()?, y.parse()?)),
+        _ => Err(anyhow!(
+            "Invalid node {node}, expected a node id or a coordinate (lod/x/y)."
+        )),
+    }
+}
+
+/// Determines the attachment format of a decoded node.
+pub fn attachment_format(image: &DynamicImage) -> Result<AttachmentFormat> {
+    match image {
+        DynamicImage::ImageRgb8(_) => Ok(AttachmentFormat::Rgb8),
+        DynamicImage::ImageRgba8(_) => Ok(AttachmentFormat::Rgba8),
+        DynamicImage::ImageLuma16(_) => Ok(AttachmentFormat::R16),
+        DynamicImage::ImageLumaA16(_) => Ok(AttachmentFormat::Rg16),
+        DynamicImage::ImageLumaA8(_) => Ok(AttachmentFormat::Rg8),
+        DynamicImage::ImageRgba16(_) => Ok(AttachmentFormat::Rgba16),
+        _ => Err(anyhow!("Unsupported color type {:?}.", image.color())),
+    }
+}
@@ -0,0 +1,403 @@
+//! The declarative description of a terrain, that should be preprocessed.
+
+use anyhow::{anyhow, ensure, Result};
+use bevy_terrain::{prelude::*, terrain_data::MAX_ATTACHMENT_COUNT};
+use serde::Deserialize;
+use std::{fs, path::Path};
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub enum FileFormatEntry {
+    TDF,
+    PNG,
+    TIF,
+    QOI,
+    DTM,
+}
+
+impl From<FileFormatEntry> for FileFormat {
+    fn from(format: FileFormatEntry) -> Self {
+        match format {
+            FileFormatEntry::TDF => FileFormat::TDF,
+            FileFormatEntry::PNG => FileFormat::PNG,
+            FileFormatEntry::TIF => FileFormat::TIF,
+            FileFormatEntry::QOI => FileFormat::QOI,
+            FileFormatEntry::DTM => FileFormat::DTM,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub enum AttachmentFormatEntry {
+    Rgb8,
+    Rgba8,
+    R16,
+    Rg16,
+    Rg8,
+    Rgba16,
+    Rgba8Linear,
+}
+
+impl From<AttachmentFormatEntry> for AttachmentFormat {
+    fn from(format: AttachmentFormatEntry) -> Self {
+        match format {
+            AttachmentFormatEntry::Rgb8 => AttachmentFormat::Rgb8,
+            AttachmentFormatEntry::Rgba8 => AttachmentFormat::Rgba8,
+            AttachmentFormatEntry::R16 => AttachmentFormat::R16,
+            AttachmentFormatEntry::Rg16 => AttachmentFormat::Rg16,
+            AttachmentFormatEntry::Rg8 => AttachmentFormat::Rg8,
+            AttachmentFormatEntry::Rgba16 => AttachmentFormat::Rgba16,
+            AttachmentFormatEntry::Rgba8Linear => AttachmentFormat::Rgba8Linear,
+        }
+    }
+}
+
+/// The source tile(s) of an attachment.
+#[derive(Deserialize, Debug)]
+pub struct TileEntry {
+    path: String,
+    size: u32,
+    file_format: FileFormatEntry,
+}
+
+impl TileEntry {
+    fn to_config(&self) -> Result<TileConfig> {
+        ensure!(
+            Path::new(&self.path).exists(),
+            "The source tiles {} do not exist.",
+            self.path
+        );
+
+        Ok(TileConfig {
+            path: self.path.clone(),
+            size: self.size,
+            file_format: self.file_format.into(),
+        })
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub enum NodataEntry {
+    Ignore,
+    Discard,
+    Fallback,
+}
+
+impl From<NodataEntry> for Nodata {
+    fn from(nodata: NodataEntry) -> Self {
+        match nodata {
+            NodataEntry::Ignore => Nodata::Ignore,
+            NodataEntry::Discard => Nodata::Discard,
+            NodataEntry::Fallback => Nodata::Fallback,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OcclusionEntry {
+    direction_count: Option<u32>,
+    distance: Option<f32>,
+    horizon: Option<bool>,
+}
+
+/// A lower priority source of the height data.
+#[derive(Deserialize, Debug)]
+pub struct FallbackEntry {
+    tile: TileEntry,
+    texel_size: f32,
+    blend_distance: Option<f32>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BaseEntry {
+    tile: TileEntry,
+    texture_size: u32,
+    mip_level_count: u32,
+    border_size: Option<u32>,
+    file_format: Option<FileFormatEntry>,
+    normals: Option<bool>,
+    occlusion: Option<OcclusionEntry>,
+    nodata: Option<NodataEntry>,
+    /// The fallback sources in the order of decreasing priority,
+    /// which are required by the fallback nodata handling.
+    #[serde(default)]
+    fallbacks: Vec<FallbackEntry>,
+}
+
+impl BaseEntry {
+    /// Creates the base config, without its tile and fallback sources.
+    fn to_config(&self) -> BaseConfig {
+        let mut base = BaseConfig::new(self.texture_size, self.mip_level_count);
+        base.border_size = self.border_size.unwrap_or(base.border_size);
+        base.file_format = self.file_format.map_or(base.file_format, Into::into);
+        base.normals = self.normals.unwrap_or(base.normals);
+        base.occlusion = self.occlusion.as_ref().map(|entry| {
+            let default = OcclusionConfig::default();
+
+            OcclusionConfig {
+                direction_count: entry.direction_count.unwrap_or(default.direction_count),
+                distance: entry.distance.unwrap_or(default.distance),
+                horizon: entry.horizon.unwrap_or(default.horizon),
+            }
+        });
+        base.nodata = self.nodata.map_or(base.nodata, Into::into);
+
+        base
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct WaterEntry {
+    tile: TileEntry,
+    level_radius: Option<u32>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SurfaceEntry {
+    tile: TileEntry,
+    min_height: Option<f32>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AttachmentEntry {
+    tile: TileEntry,
+    name: String,
+    format: AttachmentFormatEntry,
+    texture_size: u32,
+    mip_level_count: u32,
+    border_size: Option<u32>,
+    file_format: Option<FileFormatEntry>,
+    min_lod: Option<u32>,
+    max_lod: Option<u32>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct VirtualTextureEntry {
+    tile: TileEntry,
+    name: String,
+    format: AttachmentFormatEntry,
+    size: u32,
+    page_size: u32,
+    lod_count: u32,
+    border_size: Option<u32>,
+    mip_level_count: Option<u32>,
+    file_format: Option<FileFormatEntry>,
+}
+
+/// Describes the terrain and all of its attachments, together with their source tiles.
+///
+/// The base attachment is required by the water and the surface attachment.
+#[derive(Deserialize, Debug)]
+pub struct TerrainDescription {
+    /// The directory, into which the preprocessed nodes are written.
+    path: String,
+    /// The size of the terrain in world units.
+    terrain_size: u32,
+    lod_count: u32,
+    height: f32,
+    node_atlas_size: Option<u32>,
+    base: Option<BaseEntry>,
+    water: Option<WaterEntry>,
+    surface: Option<SurfaceEntry>,
+    #[serde(default)]
+    attachments: Vec<AttachmentEntry>,
+    #[serde(default)]
+    virtual_textures: Vec<VirtualTextureEntry>,
+}
+
+impl TerrainDescription {
+    pub fn load(path: &str) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .map_err(|error| anyhow!("Could not read the description {path}: {error}."))?;
+
+        Ok(toml::from_str(&contents)?)
+    }
+
+    /// Creates the terrain config and the preprocessor containing all described attachments.
+    pub fn to_preprocessor(&self) -> Result<(TerrainConfig, Preprocessor)> {
+        let mut preprocessor = Preprocessor::default();
+        // the loader is only required by the terrain config and discarded afterwards
+        let mut loader = AttachmentFromDiskLoader::default();
+
+        let mut config = TerrainConfig::new(
+            self.terrain_size,
+            self.lod_count,
+            self.height,
+            self.node_atlas_size.unwrap_or(1028),
+            self.path.clone(),
+        );
+
+        // the terrain config uniform only provides the data of a limited number of attachments
+        let attachment_count = self
+            .base
+            .as_ref()
+            .map_or(0, |entry| entry.to_config().attachment_count())
+            + self.water.is_some() as usize
+            + self.surface.is_some() as usize
+            + self.attachments.len();
+
+        ensure!(
+            attachment_count <= MAX_ATTACHMENT_COUNT,
+            "The terrain has {attachment_count} attachments, but at most {MAX_ATTACHMENT_COUNT} are supported."
+        );
+
+        let base = match &self.base {
+            Some(entry) => {
+                let base = entry.to_config();
+
+                if base.nodata == Nodata::Fallback {
+                    ensure!(
+                        !entry.fallbacks.is_empty(),
+                        "The fallback nodata handling requires at least one fallback source."
+                    );
+
+                    for fallback_entry in &entry.fallbacks {
+                        let mut fallback = FallbackConfig::new(
+                            fallback_entry.tile.to_config()?,
+                            fallback_entry.texel_size,
+                        );
+                        fallback.blend_distance = fallback_entry
+                            .blend_distance
+                            .unwrap_or(fallback.blend_distance);
+
+                        config.add_fallback_from_disk(&mut preprocessor, fallback)?;
+                    }
+                }
+
+                config.add_base_attachment_from_disk(
+                    &mut preprocessor,
+                    &mut loader,
+                    base,
+                    entry.tile.to_config()?,
+                );
+
+                Some(base)
+            }
+            None => None,
+        };
+
+        if let Some(entry) = &self.water {
+            let base = base.ok_or(anyhow!("The water attachment requires a base attachment."))?;
+
+            let mut water = WaterConfig::new(&base);
+            water.level_radius = entry.level_radius.unwrap_or(water.level_radius);
+
+            config.add_water_from_disk(
+                &mut preprocessor,
+                &mut loader,
+                water,
+                entry.tile.to_config()?,
+            );
+        }
+
+        if let Some(entry) = &self.surface {
+            let base = base.ok_or(anyhow!(
+                "The surface attachment requires a base attachment."
+            ))?;
+
+            let mut surface = SurfaceConfig::new(&base);
+            surface.min_height = entry.min_height.unwrap_or(surface.min_height);
+
+            config.add_surface_from_disk(
+                &mut preprocessor,
+                &mut loader,
+                surface,
+                entry.tile.to_config()?,
+            );
+        }
+
+        for entry in &self.attachments {
+            let mut attachment = AttachmentConfig::new(
+                entry.name.clone(),
+                entry.texture_size,
+                entry.border_size.unwrap_or(2),
+                entry.mip_level_count,
+                entry.format.into(),
+            );
+            attachment.file_format = entry.file_format.map_or(attachment.file_format, Into::into);
+            attachment.min_lod = entry.min_lod.unwrap_or(attachment.min_lod);
+            attachment.max_lod = entry.max_lod.unwrap_or(attachment.max_lod);
+
+            config.add_attachment_from_disk(
+                &mut preprocessor,
+                &mut loader,
+                attachment,
+                entry.tile.to_config()?,
+            );
+        }
+
+        for entry in &self.virtual_textures {
+            let mut virtual_texture = VirtualTextureConfig::new(
+                entry.name.clone(),
+                entry.size,
+                entry.page_size,
+                entry.lod_count,
+                0,
+                entry.format.into(),
+            );
+            virtual_texture.border_size = entry.border_size.unwrap_or(virtual_texture.border_size);
+            virtual_texture.mip_level_count = entry
+                .mip_level_count
+                .unwrap_or(virtual_texture.mip_level_count);
+            virtual_texture.file_format = entry
+                .file_format
+                .map_or(virtual_texture.file_format, Into::into);
+
+            // the virtual texture component is only required by the renderer
+            config.add_virtual_texture_from_disk(
+                &mut preprocessor,
+                virtual_texture,
+                entry.tile.to_config()?,
+            );
+        }
+
+        Ok((config, preprocessor))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DESCRIPTION: &str = r#"
+        path = "terrains/test"
+        terrain_size = 1024
+        lod_count = 4
+        height = 100.0
+
+        [base]
+        texture_size = 516
+        mip_level_count = 1
+        normals = true
+        tile = { path = "terrains/test/source/height", size = 1024, file_format = "PNG" }
+
+        [base.occlusion]
+        horizon = true
+    "#;
+
+    #[test]
+    fn reject_more_than_four_attachments() {
+        // height, minmax, normal, occlusion and horizon
+        let description: TerrainDescription = toml::from_str(DESCRIPTION).unwrap();
+        let error = description.to_preprocessor().err().unwrap();
+
+        assert_eq!(
+            error.to_string(),
+            "The terrain has 5 attachments, but at most 4 are supported."
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "a terrain supports at most 4 attachments")]
+    fn panic_when_adding_a_fifth_attachment() {
+        let mut config = TerrainConfig::new(1024, 4, 100.0, 0, "terrains/test".to_string());
+
+        let mut base = BaseConfig::new(516, 1);
+        base.normals = true;
+        base.occlusion = Some(OcclusionConfig {
+            horizon: true,
+            ..OcclusionConfig::default()
+        });
+
+        config.add_base_attachment(base);
+    }
+}
@@ -0,0 +1,70 @@
+//! Preprocesses the source tiles of a terrain into streamable nodes, without starting the renderer.
+//!
+//! Usage: `terrain_preprocess <description.toml>`
+//!
+//! Exits with status `1`, if the description is invalid and with status `2`, if the
+//! preprocessing failed.
+
+mod description;
+
+use crate::description::TerrainDescription;
+use std::{
+    env,
+    panic::{catch_unwind, AssertUnwindSafe},
+    process::ExitCode,
+    time::Instant,
+};
+
+fn main() -> ExitCode {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("Usage: terrain_preprocess <description.toml>");
+            return ExitCode::from(1);
+        }
+    };
+
+    let (config, preprocessor) = match TerrainDescription::load(&path)
+        .and_then(|description| description.to_preprocessor())
+    {
+        Ok(result) => result,
+        Err(error) => {
+            eprintln!("Invalid terrain description {path}: {error}");
+            return ExitCode::from(1);
+        }
+    };
+
+    println!(
+        "Started preprocessing the terrain data into {}.",
+        config.path
+    );
+    let start = Instant::now();
+    let mut step_start = Instant::now();
+
+    // the preprocessing panics on io errors, which are reported as a failure
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        preprocessor.preprocess_with_progress(&config, |index, count, name| {
+            if index > 0 {
+                println!("Finished in {:?}.", step_start.elapsed());
+            }
+
+            println!("[{}/{count}] Preprocessing {name} ...", index + 1);
+            step_start = Instant::now();
+        });
+    }));
+
+    match result {
+        Ok(()) => {
+            println!("Finished in {:?}.", step_start.elapsed());
+            println!(
+                "Time elapsed during preprocessing is: {:?}.",
+                start.elapsed()
+            );
+            ExitCode::SUCCESS
+        }
+        Err(_) => {
+            eprintln!("Failed to preprocess the terrain data.");
+            ExitCode::from(2)
+        }
+    }
+}
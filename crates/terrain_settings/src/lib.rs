@@ -1,6 +1,10 @@
 use anyhow::{anyhow, Result};
 use serde::Deserialize;
-use std::{env, fs};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
 
 #[derive(Deserialize, Debug)]
 struct TerrainEntry {
@@ -17,9 +21,27 @@ struct TerrainEntry {
     node_count: Option<u32>,
     load_distance: Option<f32>,
     view_distance: Option<f32>,
+    tile_scale: Option<f32>,
+    grid_size: Option<u32>,
+    morph_range: Option<f32>,
+    blend_range: Option<f32>,
+    horizon_culling: Option<bool>,
     urls_saxony: Option<String>,
     urls_switzerland_dtm: Option<String>,
     urls_switzerland_dop: Option<String>,
+    dataset: Option<GenericDataset>,
+    grid: Option<TargetGrid>,
+    sources: Option<Vec<SourceEntry>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SourceEntry {
+    name: String,
+    priority: Option<i32>,
+    urls_saxony: Option<String>,
+    urls_switzerland_dtm: Option<String>,
+    urls_switzerland_dop: Option<String>,
+    dataset: Option<GenericDataset>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -27,21 +49,103 @@ struct TerrainSettings {
     terrain_dir: String,
     preprocess: Option<bool>,
     parallel_downloads: Option<usize>,
+    download_retries: Option<u32>,
     terrain: String,
     terrains: Vec<TerrainEntry>,
 }
 
+/// The kind of data stored in a layer, which determines its output format.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LayerKind {
+    /// Height data (e.g. `dtm` or `dsm`), saved as DTM.
+    Height,
+    /// Color data (e.g. `dop`), saved as QOI.
+    Albedo,
+}
+
+/// The file format of the downloaded files of a layer.
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum LayerFormat {
+    /// An ASCII grid of `x y z` points.
+    Xyz,
+    /// A zip archive containing an ASCII grid of `x y z` points.
+    XyzZip,
+    /// A (Geo)TIFF image, storing floating point heights or RGB colors.
+    Tif,
+    /// A zip archive containing a (Geo)TIFF image.
+    TifZip,
+}
+
+/// A layer of a generic dataset.
+#[derive(Deserialize, Clone, Debug)]
+pub struct GenericLayer {
+    /// The name of the layer, which is the name of its source directory (e.g. `dtm`).
+    pub name: String,
+    /// The url template of the files, where `{x}` and `{y}` are replaced by the
+    /// coordinates of the tile and `{tile}` by its name.
+    pub url: String,
+    pub kind: LayerKind,
+    pub format: LayerFormat,
+    /// The factor by which the layer is downscaled.
+    pub downscale: Option<u32>,
+}
+
+/// A dataset, which is described by the config instead of being implemented by the
+/// download tool.
+#[derive(Deserialize, Clone, Debug)]
+pub struct GenericDataset {
+    /// The file listing the names of all tiles, relative to the terrain directory.
+    pub tiles: String,
+    /// The regex, which parses the coordinates of a tile from its name,
+    /// using the named groups `x` and `y`.
+    pub pattern: String,
+    /// The extent of a tile in coordinate units.
+    pub tile_extent: Option<u32>,
+    /// The size of a coordinate unit in the units of the projection (meters for projected ones).
+    pub coordinate_unit: Option<f64>,
+    /// The EPSG code of the projection of the dataset.
+    /// If omitted, the dataset is assumed to share the projection of the target grid.
+    pub epsg: Option<u32>,
+    pub layers: Vec<GenericLayer>,
+}
+
+/// The grid, onto which all sources of a terrain are reprojected and resampled.
+#[derive(Deserialize, Clone, Debug)]
+pub struct TargetGrid {
+    /// The EPSG code of the projection of the grid.
+    pub epsg: u32,
+    /// The size of a texel in meters.
+    pub texel_size: f64,
+    /// The north-western corner of the grid in projected coordinates.
+    /// If omitted, the grid starts at the north-western corner of all sources.
+    pub origin: Option<[f64; 2]>,
+}
+
+/// A dataset, which is merged with the other sources of the terrain.
+#[derive(Clone)]
+pub struct Source {
+    /// The name of the source, which is the name of its download directory.
+    pub name: String,
+    /// Where sources overlap, the one with the highest priority is used.
+    pub priority: i32,
+    pub dataset: Dataset,
+}
+
 #[derive(Clone)]
 pub enum Dataset {
     None,
     Saxony { urls: String },
     Switzerland { urls_dtm: String, urls_dop: String },
+    Generic(GenericDataset),
 }
 
 pub struct Settings {
     pub terrain_path: String,
     pub preprocess: bool,
     pub parallel_downloads: usize,
+    pub download_retries: u32,
     pub node_atlas_size: u32,
     pub height: f32,
     pub lod_count: u32,
@@ -54,7 +158,31 @@ pub struct Settings {
     pub node_count: u32,
     pub load_distance: f32,
     pub view_distance: f32,
+    pub tile_scale: f32,
+    pub grid_size: u32,
+    pub morph_range: f32,
+    pub blend_range: f32,
+    pub horizon_culling: bool,
     pub dataset: Dataset,
+    pub grid: Option<TargetGrid>,
+    pub sources: Vec<Source>,
+}
+
+fn parse_dataset(
+    urls_saxony: Option<String>,
+    urls_switzerland_dtm: Option<String>,
+    urls_switzerland_dop: Option<String>,
+    dataset: Option<GenericDataset>,
+) -> Dataset {
+    if let Some(dataset) = dataset {
+        Dataset::Generic(dataset)
+    } else if let Some(urls) = urls_saxony {
+        Dataset::Saxony { urls }
+    } else if let (Some(urls_dtm), Some(urls_dop)) = (urls_switzerland_dtm, urls_switzerland_dop) {
+        Dataset::Switzerland { urls_dtm, urls_dop }
+    } else {
+        Dataset::None
+    }
 }
 
 impl TryFrom<TerrainSettings> for Settings {
@@ -70,29 +198,59 @@ impl TryFrom<TerrainSettings> for Settings {
                 settings.terrain
             ))?;
 
-        let dataset = if entry.urls_saxony.is_some() {
-            Dataset::Saxony {
-                urls: entry.urls_saxony.unwrap(),
-            }
-        } else if entry.urls_switzerland_dtm.is_some() && entry.urls_switzerland_dop.is_some() {
-            Dataset::Switzerland {
-                urls_dtm: entry.urls_switzerland_dtm.unwrap(),
-                urls_dop: entry.urls_switzerland_dop.unwrap(),
-            }
-        } else {
-            Dataset::None
+        let dataset = parse_dataset(
+            entry.urls_saxony,
+            entry.urls_switzerland_dtm,
+            entry.urls_switzerland_dop,
+            entry.dataset,
+        );
+
+        let mut sources = entry
+            .sources
+            .unwrap_or_default()
+            .into_iter()
+            .map(|source| Source {
+                name: source.name,
+                priority: source.priority.unwrap_or(0),
+                dataset: parse_dataset(
+                    source.urls_saxony,
+                    source.urls_switzerland_dtm,
+                    source.urls_switzerland_dop,
+                    source.dataset,
+                ),
+            })
+            .collect::<Vec<_>>();
+        sources.sort_by_key(|source| -source.priority);
+
+        // the defaults are derived from the source with the highest priority,
+        // if the terrain does not specify a dataset itself
+        let default_dataset = match (&dataset, sources.first()) {
+            (Dataset::None, Some(source)) => &source.dataset,
+            _ => &dataset,
         };
 
-        let (height, tile_size, enable_dsm) = match dataset {
+        let (height, tile_size, enable_dsm) = match default_dataset {
             Dataset::None => (1000.0, 1000, false),
+            Dataset::Generic(dataset) => (
+                1000.0,
+                1000,
+                dataset.layers.iter().any(|layer| layer.name == "dsm"),
+            ),
             Dataset::Saxony { .. } => (1250.0, 2000, true),
             Dataset::Switzerland { .. } => (2500.0, 500, false),
         };
+        let enable_dsm = enable_dsm
+            || sources.iter().any(|source| match &source.dataset {
+                Dataset::Saxony { .. } => true,
+                Dataset::Generic(dataset) => dataset.layers.iter().any(|layer| layer.name == "dsm"),
+                _ => false,
+            });
 
         Ok(Self {
             terrain_path: format!("{}/{}", settings.terrain_dir, entry.name),
             preprocess: settings.preprocess.unwrap_or(false),
             parallel_downloads: settings.parallel_downloads.unwrap_or(2),
+            download_retries: settings.download_retries.unwrap_or(3),
             node_atlas_size: entry.node_atlas_size.unwrap_or(1028),
             side_length: entry.side_length,
             height: entry.height.unwrap_or(height),
@@ -105,29 +263,93 @@ impl TryFrom<TerrainSettings> for Settings {
             node_count: entry.node_count.unwrap_or(12),
             load_distance: entry.load_distance.unwrap_or(6.0),
             view_distance: entry.view_distance.unwrap_or(4.0),
+            tile_scale: entry.tile_scale.unwrap_or(32.0),
+            grid_size: entry.grid_size.unwrap_or(8),
+            morph_range: entry.morph_range.unwrap_or(0.2),
+            blend_range: entry.blend_range.unwrap_or(0.2),
+            horizon_culling: entry.horizon_culling.unwrap_or(false),
             dataset,
+            grid: entry.grid,
+            sources,
         })
     }
 }
 
-pub fn load_settings() -> Result<Settings> {
+/// Returns the path of the `config.toml`, which is located either next to the executable
+/// or in the working directory.
+pub fn settings_path() -> Result<PathBuf> {
     let mut path = env::current_exe()?;
     path.pop();
     path.push("config.toml");
 
-    let mut contents = fs::read_to_string(path);
+    if path.exists() {
+        return Ok(path);
+    }
 
-    if contents.is_err() {
-        let mut path = env::current_dir()?;
-        path.push("config.toml");
+    let mut path = env::current_dir()?;
+    path.push("config.toml");
 
-        contents = fs::read_to_string(path);
-    }
+    Ok(path)
+}
+
+pub fn load_settings() -> Result<Settings> {
+    load_settings_from(&settings_path()?)
+}
 
-    let contents = contents?;
+pub fn load_settings_from(path: &Path) -> Result<Settings> {
+    let contents = fs::read_to_string(path)?;
 
     let settings: TerrainSettings = toml::from_str(&contents)?;
     let settings = settings.try_into()?;
 
     Ok(settings)
 }
+
+/// Watches the `config.toml` for changes, by polling its modification time.
+///
+/// Only the quality settings of the terrain views and the horizon culling can be applied
+/// at runtime, all other settings require a restart.
+pub struct SettingsWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    last_poll: Instant,
+    interval: Duration,
+}
+
+impl SettingsWatcher {
+    pub fn new() -> Result<Self> {
+        let path = settings_path()?;
+        let modified = fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+
+        Ok(Self {
+            path,
+            modified,
+            last_poll: Instant::now(),
+            interval: Duration::from_secs(1),
+        })
+    }
+
+    /// Reloads the settings, if the file has been modified since the last reload.
+    /// The file is checked at most once per second.
+    pub fn poll(&mut self) -> Option<Result<Settings>> {
+        if self.last_poll.elapsed() < self.interval {
+            return None;
+        }
+
+        self.last_poll = Instant::now();
+
+        let modified = fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+
+        if modified == self.modified {
+            return None;
+        }
+
+        self.modified = modified;
+
+        Some(load_settings_from(&self.path))
+    }
+}